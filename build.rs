@@ -0,0 +1,691 @@
+//! Build-time configuration of the bootloader
+//!
+//! Reads `config/<variant>.toml` (variant from `ECU_VARIANT`, default
+//! `gridania-telematic`), validates it and generates `config.rs` for the
+//! `config` module and the `memory.x` linker script in `OUT_DIR`.
+//!
+//! The committed configuration may only hold a development key. Root and
+//! production keys are read from the key file named by `BOOTLOADER_KEYS`
+//! (an absolute path outside the repository).
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use serde::Deserialize;
+
+/// Variant built when `ECU_VARIANT` is not set
+const DEFAULT_VARIANT: &str = "gridania-telematic";
+
+/// Longest identification string (XCP GET_ID response payload)
+const MAX_ID_LENGTH: usize = 56;
+
+/// CAN baudrates supported by the FlexCAN bit timing setup
+const SUPPORTED_BAUDRATES: &[u32] = &[125_000, 250_000, 500_000, 1_000_000];
+
+/// Size of the mailbox frame (`src/bootloader/mailbox.rs`)
+const MIN_MAILBOX_LENGTH: u32 = 32;
+
+/// Size of the stage 0 handoff record (`src/bootloader/boot_handoff.rs`)
+const MIN_HANDOFF_LENGTH: u32 = 64;
+
+/// Size of the measurement log record (`src/bootloader/measurement.rs`)
+const MIN_MEASUREMENT_LENGTH: u32 = 256;
+
+/// Highest key ID (`src/bootloader/keystore.rs`)
+const MAX_KEY_ID: u8 = 31;
+
+/// Number of key slots (`src/bootloader/keystore.rs`)
+const KEY_SLOT_COUNT: usize = 3;
+
+/// Signed image header (`src/bootloader/image.rs`), in front of a staged
+/// bootloader update and at the end of the bootloader partition
+const IMAGE_HEADER_LENGTH: u32 = 256;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    ecu: Ecu,
+    can: Can,
+    timeout: Timeout,
+    boot: Boot,
+    handoff: Handoff,
+    self_update: SelfUpdate,
+    #[serde(default)]
+    key: Vec<Key>,
+    secure_boot: SecureBoot,
+    security: Security,
+    memory: Memory,
+    partition: Vec<Partition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Ecu {
+    name: String,
+    mcu: String,
+    software_version: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Can {
+    baudrate: u32,
+    extended_ids: bool,
+    tx_id: u32,
+    rx_id: u32,
+    tx_timeout_ms: u32,
+    init_timeout_ms: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Timeout {
+    reset_timeout_ms: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Boot {
+    backdoor_window_ms: u32,
+    max_watchdog_resets: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Handoff {
+    deinit_can: bool,
+    deinit_clock: bool,
+    disable_watchdog: bool,
+    restore_security: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelfUpdate {
+    security_version: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Key {
+    slot: String,
+    id: u8,
+    public_key: String,
+}
+
+/// Key file named by `BOOTLOADER_KEYS`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    key: Vec<Key>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecureBoot {
+    flavor: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Security {
+    seed_personalization: String,
+    lockout_delay_ms: u32,
+    extended: SeedKey,
+    programming: SeedKey,
+    eol: SeedKey,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeedKey {
+    mask: u32,
+    rotation: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Memory {
+    ram_origin: u32,
+    ram_length: u32,
+    mailbox_length: u32,
+    handoff_length: u32,
+    measurement_length: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Partition {
+    name: String,
+    kind: String,
+    start: u32,
+    end: u32,
+    flags: Vec<String>,
+    erase_size: u32,
+}
+
+fn main() {
+    let variant = env::var("ECU_VARIANT").unwrap_or_else(|_| String::from(DEFAULT_VARIANT));
+    let path = PathBuf::from("config").join(format!("{variant}.toml"));
+    
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rerun-if-env-changed=ECU_VARIANT");
+    println!("cargo:rerun-if-env-changed=BOOTLOADER_KEYS");
+    
+    let text = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("cannot read configuration {}: {e}", path.display()));
+    let mut config: Config = toml::from_str(&text)
+        .unwrap_or_else(|e| panic!("invalid configuration {}: {e}", path.display()));
+    
+    if let Some(key) = config.key.iter().find(|key| key.slot != "development") {
+        panic!(
+            "invalid configuration {}: key {}: {} keys must come from BOOTLOADER_KEYS, not the repository",
+            path.display(),
+            key.id,
+            key.slot
+        );
+    }
+    
+    if let Ok(keys_path) = env::var("BOOTLOADER_KEYS") {
+        println!("cargo:rerun-if-changed={keys_path}");
+        let text = fs::read_to_string(&keys_path)
+            .unwrap_or_else(|e| panic!("cannot read key file {keys_path}: {e}"));
+        let keys: KeyFile = toml::from_str(&text)
+            .unwrap_or_else(|e| panic!("invalid key file {keys_path}: {e}"));
+        config.key.extend(keys.key);
+    }
+    
+    if let Err(message) = validate(&config) {
+        panic!("invalid configuration {}: {message}", path.display());
+    }
+    
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out.join("config.rs"), generate_config(&config, &variant)).unwrap();
+    fs::write(out.join("memory.x"), generate_memory_x(&config)).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+}
+
+/// Check the configuration for values the bootloader cannot work with
+fn validate(config: &Config) -> Result<(), String> {
+    for (field, value) in [
+        ("ecu.name", &config.ecu.name),
+        ("ecu.mcu", &config.ecu.mcu),
+        ("ecu.software_version", &config.ecu.software_version),
+    ] {
+        if value.is_empty() || value.len() > MAX_ID_LENGTH || !value.is_ascii() {
+            return Err(format!("{field} must be 1-{MAX_ID_LENGTH} ASCII characters"));
+        }
+    }
+    
+    let can = &config.can;
+    if !SUPPORTED_BAUDRATES.contains(&can.baudrate) {
+        return Err(format!("can.baudrate {} is not one of {SUPPORTED_BAUDRATES:?}", can.baudrate));
+    }
+    
+    let max_id = if can.extended_ids { 0x1FFF_FFFF } else { 0x7FF };
+    if can.tx_id > max_id || can.rx_id > max_id {
+        return Err(format!("CAN identifiers must not exceed 0x{max_id:X}"));
+    }
+    
+    if can.tx_id == can.rx_id {
+        return Err(String::from("can.tx_id and can.rx_id must differ"));
+    }
+    
+    if can.tx_timeout_ms == 0 || can.init_timeout_ms == 0 || config.timeout.reset_timeout_ms == 0 {
+        return Err(String::from("timeouts must not be zero"));
+    }
+    
+    if config.boot.max_watchdog_resets == 0 {
+        return Err(String::from("boot.max_watchdog_resets must not be zero"));
+    }
+    
+    validate_keys(&config.key)?;
+    
+    let flavor = &config.secure_boot.flavor;
+    if boot_flavor(flavor).is_none() {
+        return Err(format!("secure_boot.flavor {flavor} is not one of strict, serial, parallel, none"));
+    }
+    if flavor != "none" && secure_boot_partition(&config.partition).is_none() {
+        return Err(String::from("secure boot needs a stage0 or bootloader partition at address 0"));
+    }
+    
+    let security = &config.security;
+    if security.seed_personalization.is_empty() || !security.seed_personalization.is_ascii() {
+        return Err(String::from("security.seed_personalization must be non-empty ASCII"));
+    }
+    
+    for (level, seed_key) in [
+        ("extended", &security.extended),
+        ("programming", &security.programming),
+        ("eol", &security.eol),
+    ] {
+        if seed_key.mask == 0 || seed_key.rotation == 0 || seed_key.rotation >= 32 {
+            return Err(format!("security.{level} needs a non-zero mask and a rotation of 1-31"));
+        }
+    }
+    
+    if config.memory.ram_length == 0 || config.memory.ram_origin.checked_add(config.memory.ram_length).is_none() {
+        return Err(String::from("memory.ram_length is invalid"));
+    }
+    
+    let mailbox_length = config.memory.mailbox_length;
+    if mailbox_length < MIN_MAILBOX_LENGTH || !mailbox_length.is_multiple_of(4) || mailbox_length >= config.memory.ram_length {
+        return Err(format!(
+            "memory.mailbox_length must be a multiple of 4, at least {MIN_MAILBOX_LENGTH} and below memory.ram_length"
+        ));
+    }
+    
+    let handoff_length = config.memory.handoff_length;
+    if handoff_length < MIN_HANDOFF_LENGTH || !handoff_length.is_multiple_of(4) || mailbox_length + handoff_length >= config.memory.ram_length {
+        return Err(format!(
+            "memory.handoff_length must be a multiple of 4, at least {MIN_HANDOFF_LENGTH} and leave RAM after the mailbox"
+        ));
+    }
+    
+    let measurement_length = config.memory.measurement_length;
+    if measurement_length < MIN_MEASUREMENT_LENGTH
+        || !measurement_length.is_multiple_of(4)
+        || mailbox_length + handoff_length + measurement_length >= config.memory.ram_length
+    {
+        return Err(format!(
+            "memory.measurement_length must be a multiple of 4, at least {MIN_MEASUREMENT_LENGTH} and leave RAM after the handoff record"
+        ));
+    }
+    
+    validate_partitions(&config.partition)
+}
+
+/// Check the partition table
+fn validate_partitions(partitions: &[Partition]) -> Result<(), String> {
+    for partition in partitions {
+        let name = &partition.name;
+        
+        if partition_kind(&partition.kind).is_none() {
+            return Err(format!("partition {name}: unknown kind {}", partition.kind));
+        }
+        
+        if let Some(flag) = partition.flags.iter().find(|flag| partition_flag(flag).is_none()) {
+            return Err(format!("partition {name}: unknown flag {flag}"));
+        }
+        
+        if partition.start >= partition.end {
+            return Err(format!("partition {name}: start must be below end"));
+        }
+        
+        let erase_size = partition.erase_size;
+        if !erase_size.is_power_of_two() || partition.start % erase_size != 0 || partition.end % erase_size != 0 {
+            return Err(format!("partition {name}: bounds must be aligned to the erase size"));
+        }
+    }
+    
+    for (i, a) in partitions.iter().enumerate() {
+        if let Some(b) = partitions[i + 1..].iter().find(|b| a.start < b.end && b.start < a.end) {
+            return Err(format!("partitions {} and {} overlap", a.name, b.name));
+        }
+    }
+    
+    for kind in ["bootloader", "application"] {
+        if partitions.iter().filter(|partition| partition.kind == kind).count() != 1 {
+            return Err(format!("exactly one {kind} partition is required"));
+        }
+    }
+    
+    let bootloader = partitions.iter().find(|partition| partition.kind == "bootloader").unwrap();
+    if bootloader.flags.iter().any(|flag| flag == "writable") {
+        return Err(String::from("the bootloader partition must not be writable"));
+    }
+    
+    if bootloader.end - bootloader.start <= IMAGE_HEADER_LENGTH {
+        return Err(String::from("the bootloader partition must hold the bootloader and its image header"));
+    }
+    
+    let mut stage0 = partitions.iter().filter(|partition| partition.kind == "stage0");
+    if let Some(stage0) = stage0.next() {
+        if stage0.flags.iter().any(|flag| flag == "writable") || !stage0.flags.iter().any(|flag| flag == "executable") {
+            return Err(String::from("the stage0 partition must be executable and not writable"));
+        }
+        
+        // The core fetches the reset vector from the start of the program flash
+        if stage0.start != 0 {
+            return Err(String::from("the stage0 partition must start at address 0"));
+        }
+    }
+    if stage0.next().is_some() {
+        return Err(String::from("at most one stage0 partition is allowed"));
+    }
+    
+    let application = partitions.iter().find(|partition| partition.kind == "application").unwrap();
+    if !application.flags.iter().any(|flag| flag == "executable") {
+        return Err(String::from("the application partition must be executable"));
+    }
+    
+    let mut staging = partitions.iter().filter(|partition| partition.kind == "staging");
+    if let Some(staging) = staging.next() {
+        if !staging.flags.iter().any(|flag| flag == "writable") || staging.flags.iter().any(|flag| flag == "executable") {
+            return Err(String::from("the staging partition must be writable and not executable"));
+        }
+        
+        if staging.end - staging.start < bootloader.end - bootloader.start + IMAGE_HEADER_LENGTH {
+            return Err(String::from("the staging partition must hold the bootloader partition and the update header"));
+        }
+        
+        if staging.erase_size != bootloader.erase_size {
+            return Err(String::from("the staging and bootloader partitions must have the same erase size"));
+        }
+    }
+    if staging.next().is_some() {
+        return Err(String::from("at most one staging partition is allowed"));
+    }
+    
+    let mut scratch = partitions.iter().filter(|partition| partition.kind == "scratch");
+    if let Some(scratch) = scratch.next() {
+        if scratch.flags.iter().any(|flag| flag == "writable" || flag == "executable") {
+            return Err(String::from("the scratch partition must be neither writable nor executable"));
+        }
+        
+        // A delta update may rewrite the whole application
+        if scratch.end - scratch.start < application.end - application.start {
+            return Err(String::from("the scratch partition must hold the application partition"));
+        }
+    }
+    if scratch.next().is_some() {
+        return Err(String::from("at most one scratch partition is allowed"));
+    }
+    
+    Ok(())
+}
+
+/// Check the built-in key store
+fn validate_keys(keys: &[Key]) -> Result<(), String> {
+    for key in keys {
+        if key_slot(&key.slot).is_none() {
+            return Err(format!("key {}: unknown slot {}", key.id, key.slot));
+        }
+        
+        if key.id > MAX_KEY_ID {
+            return Err(format!("key {}: IDs must not exceed {MAX_KEY_ID}", key.id));
+        }
+        
+        if parse_public_key(&key.public_key).is_none() {
+            return Err(format!(
+                "key {}: public_key must be an uncompressed P-256 key (130 hex digits starting with 04)",
+                key.id
+            ));
+        }
+    }
+    
+    for (i, a) in keys.iter().enumerate() {
+        if let Some(b) = keys[i + 1..].iter().find(|b| a.slot == b.slot || a.id == b.id) {
+            return Err(format!("keys {} and {} share a slot or an ID", a.id, b.id));
+        }
+    }
+    
+    if keys.is_empty() {
+        return Err(String::from("no keys, configure a development key or set BOOTLOADER_KEYS"));
+    }
+    
+    let has_slot = |slot: &str| keys.iter().any(|key| key.slot == slot);
+    if has_slot("production") && !has_slot("root") {
+        return Err(String::from("a production key needs a root key to install key updates"));
+    }
+    
+    Ok(())
+}
+
+/// Index of a key slot in the key store
+fn key_slot(slot: &str) -> Option<usize> {
+    match slot {
+        "root" => Some(0),
+        "production" => Some(1),
+        "development" => Some(2),
+        _ => None,
+    }
+}
+
+/// Rust name of a secure boot flavor
+fn boot_flavor(flavor: &str) -> Option<&'static str> {
+    match flavor {
+        "strict" => Some("Strict"),
+        "serial" => Some("Serial"),
+        "parallel" => Some("Parallel"),
+        "none" => Some("NoBoot"),
+        _ => None,
+    }
+}
+
+/// Partition at the reset vector covered by secure boot
+fn secure_boot_partition(partitions: &[Partition]) -> Option<&Partition> {
+    partitions
+        .iter()
+        .find(|partition| partition.start == 0 && (partition.kind == "stage0" || partition.kind == "bootloader"))
+}
+
+/// Decode an uncompressed P-256 public key given as hex
+fn parse_public_key(text: &str) -> Option<Vec<u8>> {
+    if text.len() != 130 || !text.is_ascii() || !text.starts_with("04") {
+        return None;
+    }
+    
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Rust name of a partition kind
+fn partition_kind(kind: &str) -> Option<&'static str> {
+    match kind {
+        "bootloader" => Some("Bootloader"),
+        "application" => Some("Application"),
+        "calibration" => Some("Calibration"),
+        "nv-data" => Some("NvData"),
+        "staging" => Some("Staging"),
+        "stage0" => Some("Stage0"),
+        "scratch" => Some("Scratch"),
+        _ => None,
+    }
+}
+
+/// Rust name of a partition flag
+fn partition_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "writable" => Some("PARTITION_FLAG_WRITABLE"),
+        "executable" => Some("PARTITION_FLAG_EXECUTABLE"),
+        "readable" => Some("PARTITION_FLAG_READABLE"),
+        _ => None,
+    }
+}
+
+/// Generate the `config` module
+fn generate_config(config: &Config, variant: &str) -> String {
+    let mut out = String::new();
+    let can = &config.can;
+    let security = &config.security;
+    let id_flag = if can.extended_ids { " | 0x8000_0000" } else { "" };
+    
+    writeln!(out, "// Generated by build.rs from config/{variant}.toml, do not edit").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "use crate::bootloader::keystore::*;").unwrap();
+    writeln!(out, "use crate::bootloader::partition::*;").unwrap();
+    writeln!(out, "use crate::hal::s32k148::csec::BootFlavor;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// ECU variant the bootloader was built for").unwrap();
+    writeln!(out, "pub const ECU_VARIANT: &str = {variant:?};").unwrap();
+    writeln!(out, "/// Bootloader identification").unwrap();
+    writeln!(out, "pub const ECU_NAME: &str = {:?};", config.ecu.name).unwrap();
+    writeln!(out, "/// Microcontroller identification").unwrap();
+    writeln!(out, "pub const MCU_NAME: &str = {:?};", config.ecu.mcu).unwrap();
+    writeln!(out, "/// Bootloader software version").unwrap();
+    writeln!(out, "pub const SOFTWARE_VERSION: &str = {:?};", config.ecu.software_version).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// CAN transmit identifier (bit 31 marks an extended identifier)").unwrap();
+    writeln!(out, "pub const CAN_TX_MSG_ID: u32 = 0x{:X}{id_flag};", can.tx_id).unwrap();
+    writeln!(out, "/// CAN receive identifier (bit 31 marks an extended identifier)").unwrap();
+    writeln!(out, "pub const CAN_RX_MSG_ID: u32 = 0x{:X}{id_flag};", can.rx_id).unwrap();
+    writeln!(out, "/// CAN baudrate in bits per second").unwrap();
+    writeln!(out, "pub const CAN_BAUDRATE: u32 = {};", can.baudrate).unwrap();
+    writeln!(out, "/// CAN transmit timeout in milliseconds").unwrap();
+    writeln!(out, "pub const CAN_MSG_TX_TIMEOUT_MS: u32 = {};", can.tx_timeout_ms).unwrap();
+    writeln!(out, "/// CAN initialization timeout in milliseconds").unwrap();
+    writeln!(out, "pub const CAN_INIT_TIMEOUT_MS: u32 = {};", can.init_timeout_ms).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Reset timeout of the programming session in milliseconds").unwrap();
+    writeln!(out, "pub const RESET_TIMEOUT_MS: u32 = {};", config.timeout.reset_timeout_ms).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Backdoor window after an external reset in milliseconds").unwrap();
+    writeln!(out, "pub const BACKDOOR_WINDOW_MS: u32 = {};", config.boot.backdoor_window_ms).unwrap();
+    writeln!(out, "/// Consecutive watchdog resets after which the application is not started").unwrap();
+    writeln!(out, "pub const MAX_WATCHDOG_RESETS: u8 = {};", config.boot.max_watchdog_resets).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// De-initialize CAN before starting the application").unwrap();
+    writeln!(out, "pub const HANDOFF_DEINIT_CAN: bool = {};", config.handoff.deinit_can).unwrap();
+    writeln!(out, "/// Restore the reset clock configuration before starting the application").unwrap();
+    writeln!(out, "pub const HANDOFF_DEINIT_CLOCK: bool = {};", config.handoff.deinit_clock).unwrap();
+    writeln!(out, "/// Disable the watchdog before starting the application").unwrap();
+    writeln!(out, "pub const HANDOFF_DISABLE_WATCHDOG: bool = {};", config.handoff.disable_watchdog).unwrap();
+    writeln!(out, "/// Restore the security level passed by the application in the mailbox").unwrap();
+    writeln!(out, "pub const HANDOFF_RESTORE_SECURITY: bool = {};", config.handoff.restore_security).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Security version of this bootloader, updates must not be lower").unwrap();
+    writeln!(out, "pub const BOOTLOADER_SECURITY_VERSION: u32 = {};", config.self_update.security_version).unwrap();
+    writeln!(out, "/// Key store in effect until the first key update (root, production, development)").unwrap();
+    writeln!(out, "pub const BUILTIN_KEYS: [Option<KeyEntry>; KEY_SLOT_COUNT] = [").unwrap();
+    for slot in 0..KEY_SLOT_COUNT {
+        match config.key.iter().find(|key| key_slot(&key.slot) == Some(slot)) {
+            Some(key) => {
+                let public_key = parse_public_key(&key.public_key).unwrap();
+                writeln!(out, "    Some(KeyEntry {{ id: {}, public_key: {:?} }}),", key.id, public_key).unwrap();
+            },
+            None => writeln!(out, "    None,").unwrap(),
+        }
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    let secure_boot_size = match config.secure_boot.flavor.as_str() {
+        "none" => 0,
+        _ => secure_boot_partition(&config.partition).map_or(0, |partition| partition.end - partition.start),
+    };
+    writeln!(out, "/// SHE secure boot flavor defined with the BOOT_MAC_KEY").unwrap();
+    writeln!(out, "pub const SECURE_BOOT_FLAVOR: BootFlavor = BootFlavor::{};", boot_flavor(&config.secure_boot.flavor).unwrap()).unwrap();
+    writeln!(out, "/// Bytes from the start of the program flash covered by BOOT_MAC").unwrap();
+    writeln!(out, "pub const SECURE_BOOT_SIZE: u32 = 0x{secure_boot_size:X};").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Personalization string for the seed DRBG").unwrap();
+    writeln!(out, "pub const SEED_PERSONALIZATION: &[u8] = b{:?};", security.seed_personalization).unwrap();
+    writeln!(out, "/// Delay after exceeding the number of security access attempts").unwrap();
+    writeln!(out, "pub const SECURITY_LOCKOUT_DELAY_MS: u32 = {};", security.lockout_delay_ms).unwrap();
+    for (name, seed_key) in [
+        ("EXTENDED", &security.extended),
+        ("PROGRAMMING", &security.programming),
+        ("EOL", &security.eol),
+    ] {
+        let level = name.to_lowercase();
+        writeln!(out, "/// XOR mask of the default {level} seed/key algorithm").unwrap();
+        writeln!(out, "pub const SECURITY_{name}_MASK: u32 = 0x{:08X};", seed_key.mask).unwrap();
+        writeln!(out, "/// Rotation of the default {level} seed/key algorithm").unwrap();
+        writeln!(out, "pub const SECURITY_{name}_ROTATION: u32 = {};", seed_key.rotation).unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "/// First address of RAM").unwrap();
+    writeln!(out, "pub const RAM_START: u32 = 0x{:08X};", config.memory.ram_origin).unwrap();
+    writeln!(out, "/// First address after RAM").unwrap();
+    writeln!(out, "pub const RAM_END: u32 = 0x{:08X};", config.memory.ram_origin + config.memory.ram_length).unwrap();
+    writeln!(out, "/// Address of the application/bootloader mailbox (no-init RAM at the start of RAM)").unwrap();
+    writeln!(out, "pub const MAILBOX_START: u32 = 0x{:08X};", config.memory.ram_origin).unwrap();
+    writeln!(out, "/// Length of the mailbox region in bytes").unwrap();
+    writeln!(out, "pub const MAILBOX_LENGTH: u32 = 0x{:X};", config.memory.mailbox_length).unwrap();
+    writeln!(out, "/// Address of the stage 0 handoff record (no-init RAM after the mailbox)").unwrap();
+    writeln!(out, "pub const HANDOFF_START: u32 = 0x{:08X};", config.memory.ram_origin + config.memory.mailbox_length).unwrap();
+    writeln!(out, "/// Length of the handoff region in bytes").unwrap();
+    writeln!(out, "pub const HANDOFF_LENGTH: u32 = 0x{:X};", config.memory.handoff_length).unwrap();
+    writeln!(out, "/// Address of the measurement log handed to the application (no-init RAM after the handoff record)").unwrap();
+    writeln!(out, "pub const MEASUREMENT_START: u32 = 0x{:08X};", config.memory.ram_origin + config.memory.mailbox_length + config.memory.handoff_length).unwrap();
+    writeln!(out, "/// Length of the measurement region in bytes").unwrap();
+    writeln!(out, "pub const MEASUREMENT_LENGTH: u32 = 0x{:X};", config.memory.measurement_length).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Partition table").unwrap();
+    writeln!(out, "pub static PARTITION_TABLE: &[Partition] = &[").unwrap();
+    for partition in &config.partition {
+        let flags: Vec<&str> = partition.flags.iter().filter_map(|flag| partition_flag(flag)).collect();
+        let flags = if flags.is_empty() { String::from("0") } else { flags.join(" | ") };
+        
+        writeln!(out, "    Partition {{").unwrap();
+        writeln!(out, "        name: {:?},", partition.name).unwrap();
+        writeln!(out, "        kind: PartitionKind::{},", partition_kind(&partition.kind).unwrap()).unwrap();
+        writeln!(out, "        start: 0x{:08X},", partition.start).unwrap();
+        writeln!(out, "        end: 0x{:08X},", partition.end).unwrap();
+        writeln!(out, "        flags: {flags},").unwrap();
+        writeln!(out, "        erase_size: {},", partition.erase_size).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    
+    out
+}
+
+/// Generate the linker memory layout
+///
+/// The last `IMAGE_HEADER_LENGTH` bytes of the bootloader partition hold
+/// the image header checked by stage 0 and are kept out of `FLASH`.
+fn generate_memory_x(config: &Config) -> String {
+    let bootloader = config.partition.iter().find(|partition| partition.kind == "bootloader").unwrap();
+    let application = config.partition.iter().find(|partition| partition.kind == "application").unwrap();
+    let memory = &config.memory;
+    let reserved = memory.mailbox_length + memory.handoff_length + memory.measurement_length;
+    
+    format!(
+        "/* Generated by build.rs, do not edit */\n\
+         MEMORY\n\
+         {{\n  \
+           /* Bootloader partition without the image header at its end */\n  \
+           FLASH (rx) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           /* Mailbox shared with the application, never initialized by either */\n  \
+           MAILBOX (rw) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           /* Record written by stage 0 before it starts the bootloader */\n  \
+           HANDOFF (rw) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           /* Measurement log handed to the application */\n  \
+           MEASUREMENTS (rw) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           RAM (rwx) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n\
+         }}\n\
+         \n\
+         /* Application partition */\n\
+         _app_start = 0x{:08X};\n\
+         \n\
+         PROVIDE(_stack_start = ORIGIN(RAM) + LENGTH(RAM));\n\
+         \n\
+         SECTIONS\n\
+         {{\n  \
+           .bootloader_data (NOLOAD) : ALIGN(4)\n  \
+           {{\n    \
+             KEEP(*(.bootloader_data .bootloader_data.*));\n    \
+             . = ALIGN(4);\n  \
+           }} > MAILBOX\n\
+           \n  \
+           .boot_handoff (NOLOAD) : ALIGN(4)\n  \
+           {{\n    \
+             KEEP(*(.boot_handoff .boot_handoff.*));\n    \
+             . = ALIGN(4);\n  \
+           }} > HANDOFF\n\
+           \n  \
+           .boot_measurements (NOLOAD) : ALIGN(4)\n  \
+           {{\n    \
+             KEEP(*(.boot_measurements .boot_measurements.*));\n    \
+             . = ALIGN(4);\n  \
+           }} > MEASUREMENTS\n\
+         }}\n\
+         INSERT AFTER .bss;\n",
+        bootloader.start,
+        bootloader.end - bootloader.start - IMAGE_HEADER_LENGTH,
+        memory.ram_origin,
+        memory.mailbox_length,
+        memory.ram_origin + memory.mailbox_length,
+        memory.handoff_length,
+        memory.ram_origin + memory.mailbox_length + memory.handoff_length,
+        memory.measurement_length,
+        memory.ram_origin + reserved,
+        memory.ram_length - reserved,
+        application.start,
+    )
+}
\ No newline at end of file
@@ -2,7 +2,7 @@
 #![no_main]
 
 use panic_halt as _;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
 use defmt::info;
 use defmt_rtt as _;
 
@@ -10,6 +10,7 @@ use gridania_telematic_bootloader::bootloader::core::BootLoader;
 use gridania_telematic_bootloader::drivers::clock::Clock;
 use gridania_telematic_bootloader::drivers::gpio::Gpio;
 use gridania_telematic_bootloader::drivers::power::Power;
+use gridania_telematic_bootloader::drivers::systick::{self, SysTickTimer};
 use gridania_telematic_bootloader::drivers::watchdog::Watchdog;
 
 /// Basic bootloader example showing initialization and core functionality
@@ -21,6 +22,9 @@ fn main() -> ! {
     let mut clock = Clock::new();
     clock.init();
     
+    let mut systick = SysTickTimer::new();
+    systick.init(clock.get_system_clock_hz());
+    
     let mut power = Power::new();
     power.init();
     
@@ -43,7 +47,9 @@ fn main() -> ! {
     // Check if valid application exists
     if bootloader.verify_application() {
         info!("Valid application found, starting...");
-        bootloader.start_application();
+        if bootloader.start_application(&mut clock, &mut watchdog, &mut systick).is_err() {
+            info!("Application vector table invalid, staying in bootloader");
+        }
     } else {
         info!("No valid application found, staying in bootloader");
     }
@@ -56,4 +62,10 @@ fn main() -> ! {
         // Service the watchdog
         watchdog.service();
     }
+}
+
+/// Millisecond time base of the bootloader
+#[exception]
+fn SysTick() {
+    systick::tick();
 }
\ No newline at end of file
@@ -51,7 +51,7 @@ fn test_flash_operations(flash: &mut Flash, verification: &FirmwareVerification)
     match flash.erase(test_addr, test_size) {
         Ok(_) => info!("Flash erase successful"),
         Err(e) => {
-            error!("Flash erase failed: {:?}", e);
+            error!("Flash erase failed: {:?}", defmt::Debug2Format(&e));
             return;
         }
     }
@@ -61,7 +61,7 @@ fn test_flash_operations(flash: &mut Flash, verification: &FirmwareVerification)
     match flash.write(test_addr, &test_data) {
         Ok(_) => info!("Flash write successful"),
         Err(e) => {
-            error!("Flash write failed: {:?}", e);
+            error!("Flash write failed: {:?}", defmt::Debug2Format(&e));
             return;
         }
     }
@@ -71,14 +71,14 @@ fn test_flash_operations(flash: &mut Flash, verification: &FirmwareVerification)
     let mut verify_passed = true;
     
     // Directly read from memory address to verify
-    for i in 0..test_data.len() {
+    for (i, &expected) in test_data.iter().enumerate() {
         let value = unsafe { 
             core::ptr::read_volatile((test_addr + i as u32) as *const u8) 
         };
         
-        if value != test_data[i] {
+        if value != expected {
             error!("Verification failed at offset {}: expected 0x{:02X}, got 0x{:02X}", 
-                   i, test_data[i], value);
+                   i, expected, value);
             verify_passed = false;
             break;
         }
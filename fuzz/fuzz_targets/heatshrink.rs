@@ -0,0 +1,49 @@
+//! Heatshrink decoder fuzz target
+//!
+//! Decodes arbitrary input in one piece and split into chunks (the first
+//! byte selects the chunk size). Both must produce the same output and the
+//! same `finish` result, without panics or output beyond 16 bytes per
+//! input bit.
+//!
+//! ```text
+//! cargo +nightly fuzz run heatshrink
+//! ```
+
+#![no_main]
+
+#[path = "../../src/compression/heatshrink.rs"]
+#[allow(dead_code)]
+mod heatshrink;
+
+use heatshrink::{DecodeError, HeatshrinkDecoder};
+use libfuzzer_sys::fuzz_target;
+
+/// Decode `input` passing `chunk_size` bytes at a time
+fn decode(input: &[u8], chunk_size: usize) -> (Vec<u8>, Result<(), DecodeError<()>>) {
+    let mut decoder = HeatshrinkDecoder::new();
+    let mut output = Vec::new();
+    let mut sink = |chunk: &[u8]| -> Result<(), ()> {
+        output.extend_from_slice(chunk);
+        Ok(())
+    };
+    
+    for chunk in input.chunks(chunk_size) {
+        decoder.decode(chunk, &mut sink).unwrap();
+    }
+    let result = decoder.finish(&mut sink);
+    assert_eq!(decoder.decoded_len(), output.len());
+    
+    (output, result)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, input)) = data.split_first() else {
+        return;
+    };
+    
+    let whole = decode(input, input.len().max(1));
+    let chunked = decode(input, split as usize + 1);
+    
+    assert_eq!(whole, chunked);
+    assert!(whole.0.len() <= input.len() * 8 * 16);
+});
\ No newline at end of file
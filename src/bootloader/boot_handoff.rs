@@ -0,0 +1,219 @@
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use sha2::{Digest, Sha256};
+
+/// Marker of a handoff record ("S0HO")
+const HANDOFF_MAGIC: u32 = 0x4F48_3053;
+
+/// Layout version of the handoff record
+pub const HANDOFF_VERSION: u8 = 1;
+
+/// Size of the handoff record in bytes
+pub const HANDOFF_RECORD_LENGTH: usize = 64;
+
+/// Bytes covered by the check value (everything before it)
+const HANDOFF_CHECK_OFFSET: usize = HANDOFF_RECORD_LENGTH - 4;
+
+/// Handoff record in the `.boot_handoff` no-init section
+///
+/// Placed in the `HANDOFF` region of memory.x at `config::HANDOFF_START`,
+/// written by stage 0 right before it starts the bootloader and taken by
+/// the bootloader at startup. The record is:
+///
+/// | Offset | Size | Content                                        |
+/// |--------|------|------------------------------------------------|
+/// | 0      | 4    | magic `HANDOFF_MAGIC` (LE)                     |
+/// | 4      | 1    | version `HANDOFF_VERSION`                      |
+/// | 5      | 1    | slot started (`BootSlot`)                      |
+/// | 6      | 2    | reserved (0)                                   |
+/// | 8      | 4    | reset status, raw `RCM_SRS` (LE)               |
+/// | 12     | 4    | security version of the bootloader (LE)        |
+/// | 16     | 32   | SHA-256 of the bootloader image (measurement)  |
+/// | 48     | 12   | reserved (0)                                   |
+/// | 60     | 4    | first 4 bytes of the SHA-256 of bytes 0-59     |
+///
+/// The module only depends on `sha2`, stage 0 shares it.
+#[link_section = ".boot_handoff"]
+#[used]
+static mut HANDOFF: MaybeUninit<[u8; HANDOFF_RECORD_LENGTH]> = MaybeUninit::uninit();
+
+/// Bootloader slot started by stage 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootSlot {
+    /// The bootloader partition
+    Primary,
+}
+
+impl BootSlot {
+    /// Decode the slot byte
+    fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Primary),
+            _ => None,
+        }
+    }
+    
+    /// Encode the slot byte
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Primary => 0x00,
+        }
+    }
+}
+
+/// What stage 0 found before starting the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootHandoff {
+    /// Slot that was verified and started
+    pub slot: BootSlot,
+    /// `RCM_SRS` as read by stage 0 (the register is sticky until the next reset)
+    pub reset_status: u32,
+    /// Security version from the image header of the bootloader
+    pub security_version: u32,
+    /// SHA-256 of the bootloader image as verified by stage 0
+    pub measurement: [u8; 32],
+}
+
+impl BootHandoff {
+    /// Parse a handoff record, `None` if it is empty, corrupted or of another version
+    pub fn parse(record: &[u8; HANDOFF_RECORD_LENGTH]) -> Option<Self> {
+        let word = |offset: usize| {
+            u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]])
+        };
+        
+        if word(0) != HANDOFF_MAGIC || record[HANDOFF_CHECK_OFFSET..] != Sha256::digest(&record[..HANDOFF_CHECK_OFFSET])[..4] {
+            return None;
+        }
+        
+        if record[4] != HANDOFF_VERSION {
+            return None;
+        }
+        
+        let mut measurement = [0u8; 32];
+        measurement.copy_from_slice(&record[16..48]);
+        
+        Some(Self {
+            slot: BootSlot::from_byte(record[5])?,
+            reset_status: word(8),
+            security_version: word(12),
+            measurement,
+        })
+    }
+    
+    /// Serialize the handoff into a record
+    pub fn to_record(self) -> [u8; HANDOFF_RECORD_LENGTH] {
+        let mut record = [0u8; HANDOFF_RECORD_LENGTH];
+        
+        record[0..4].copy_from_slice(&HANDOFF_MAGIC.to_le_bytes());
+        record[4] = HANDOFF_VERSION;
+        record[5] = self.slot.to_byte();
+        record[8..12].copy_from_slice(&self.reset_status.to_le_bytes());
+        record[12..16].copy_from_slice(&self.security_version.to_le_bytes());
+        record[16..48].copy_from_slice(&self.measurement);
+        
+        let check = Sha256::digest(&record[..HANDOFF_CHECK_OFFSET]);
+        record[HANDOFF_CHECK_OFFSET..].copy_from_slice(&check[..4]);
+        
+        record
+    }
+}
+
+/// Take the record left by stage 0, if any
+///
+/// The record is cleared so that it is only valid for the start it was
+/// written for. Only call this if stage 0 is installed: it writes the
+/// record at every start, otherwise the RAM may be uninitialized.
+pub fn take() -> Option<BootHandoff> {
+    let handoff = BootHandoff::parse(&read_record());
+    write_record(&[0u8; HANDOFF_RECORD_LENGTH]);
+    handoff
+}
+
+/// Leave the record for the bootloader about to be started
+///
+/// Used by stage 0.
+pub fn write(handoff: &BootHandoff) {
+    write_record(&handoff.to_record());
+}
+
+/// Read the raw handoff record
+fn read_record() -> [u8; HANDOFF_RECORD_LENGTH] {
+    // Safety: the record is only accessed from thread mode and any bit
+    // pattern is a valid byte array
+    unsafe { read_volatile(addr_of!(HANDOFF) as *const [u8; HANDOFF_RECORD_LENGTH]) }
+}
+
+/// Write the raw handoff record
+fn write_record(record: &[u8; HANDOFF_RECORD_LENGTH]) {
+    // Safety: the record is only accessed from thread mode
+    unsafe { write_volatile(addr_of_mut!(HANDOFF) as *mut [u8; HANDOFF_RECORD_LENGTH], *record) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn handoff() -> BootHandoff {
+        BootHandoff {
+            slot: BootSlot::Primary,
+            reset_status: 0x0000_0482,
+            security_version: 7,
+            measurement: core::array::from_fn(|i| i as u8 ^ 0xA5),
+        }
+    }
+    
+    /// Record of `handoff()` with `edit` applied and the check value recomputed
+    fn record_with(edit: impl FnOnce(&mut [u8; HANDOFF_RECORD_LENGTH])) -> [u8; HANDOFF_RECORD_LENGTH] {
+        let mut record = handoff().to_record();
+        edit(&mut record);
+        let check = Sha256::digest(&record[..HANDOFF_CHECK_OFFSET]);
+        record[HANDOFF_CHECK_OFFSET..].copy_from_slice(&check[..4]);
+        record
+    }
+    
+    #[test]
+    fn valid_record() {
+        assert_eq!(BootHandoff::parse(&handoff().to_record()), Some(handoff()));
+        
+        let other = BootHandoff {
+            reset_status: 0,
+            security_version: u32::MAX,
+            measurement: [0; 32],
+            ..handoff()
+        };
+        assert_eq!(BootHandoff::parse(&other.to_record()), Some(other));
+    }
+    
+    #[test]
+    fn corrupted_record() {
+        let record = handoff().to_record();
+        
+        // Any flipped bit, check value included, invalidates the record
+        for bit in 0..HANDOFF_RECORD_LENGTH * 8 {
+            let mut corrupted = record;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(BootHandoff::parse(&corrupted), None, "bit {bit}");
+        }
+        
+        assert_eq!(BootHandoff::parse(&[0; HANDOFF_RECORD_LENGTH]), None);
+        assert_eq!(BootHandoff::parse(&[0xFF; HANDOFF_RECORD_LENGTH]), None);
+    }
+    
+    #[test]
+    fn wrong_magic_version_or_slot() {
+        assert_eq!(BootHandoff::parse(&record_with(|record| record[3] ^= 0x80)), None);
+        assert_eq!(BootHandoff::parse(&record_with(|record| record[4] = HANDOFF_VERSION + 1)), None);
+        assert_eq!(BootHandoff::parse(&record_with(|record| record[5] = 0x01)), None);
+    }
+    
+    #[test]
+    fn stale_record() {
+        // The single test using the handoff RAM, tests run in parallel
+        write(&handoff());
+        assert_eq!(take(), Some(handoff()));
+        
+        // The record of the last start is not taken again
+        assert_eq!(take(), None);
+        assert_eq!(read_record(), [0; HANDOFF_RECORD_LENGTH]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,414 @@
+use defmt::{info, warn};
+use crate::bootloader::nvm::{NvStorage, NVM_BOOT_RECORD_OFFSET};
+use crate::config::{BACKDOOR_WINDOW_MS, MAX_WATCHDOG_RESETS};
+use crate::drivers::power::ResetCause;
+
+/// Marker of a valid boot record in non-volatile storage
+const BOOT_RECORD_MAGIC: u16 = 0xB007;
+
+/// Length of the boot record in bytes
+const BOOT_RECORD_LENGTH: usize = 6;
+
+/// What the bootloader does after reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootDecision {
+    /// Start the application
+    RunApplication,
+    /// Keep the backdoor window open for a tester to connect
+    WaitForBackdoor,
+    /// Stay in the bootloader because programming was requested
+    StayForProgramming,
+    /// Stay in the bootloader because the application cannot be started
+    Recovery,
+}
+
+/// State of the backdoor window after reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackdoorState {
+    /// The window is still open and no tester has connected
+    Open,
+    /// A tester connected while the window was open
+    Connected,
+    /// The window elapsed without a tester
+    Expired,
+}
+
+/// Inputs of the boot decision
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BootInputs {
+    /// Cause of the last reset
+    pub reset_cause: ResetCause,
+    /// The application requested programming before resetting
+    pub programming_requested: bool,
+    /// Number of consecutive watchdog or lockup resets, this one included
+    pub consecutive_watchdog_resets: u8,
+    /// The installed application passed verification
+    pub image_valid: bool,
+    /// State of the backdoor window
+    pub backdoor: BackdoorState,
+}
+
+/// Check if a reset cause opens the backdoor window
+///
+/// Only resets from outside the application (power-on, reset pin, brown-out,
+/// debugger) give a tester the chance to connect; application resets start
+/// it again without delay.
+pub fn opens_backdoor(reset_cause: ResetCause) -> bool {
+    matches!(
+        reset_cause,
+        ResetCause::PowerOn | ResetCause::External | ResetCause::LowVoltage | ResetCause::Jtag | ResetCause::Unknown
+    )
+}
+
+/// Decide what to do after reset
+///
+/// In order of priority: an explicit programming request or a tester that
+/// connected during the backdoor window keeps the bootloader running, an
+/// application caught in a watchdog reset loop or failing verification is
+/// never started, and otherwise the application runs once the backdoor
+/// window (if any) has elapsed.
+pub fn decide(inputs: &BootInputs) -> BootDecision {
+    if inputs.programming_requested || inputs.backdoor == BackdoorState::Connected {
+        return BootDecision::StayForProgramming;
+    }
+    
+    if inputs.consecutive_watchdog_resets >= MAX_WATCHDOG_RESETS || !inputs.image_valid {
+        return BootDecision::Recovery;
+    }
+    
+    if opens_backdoor(inputs.reset_cause) && inputs.backdoor == BackdoorState::Open {
+        return BootDecision::WaitForBackdoor;
+    }
+    
+    BootDecision::RunApplication
+}
+
+/// Number of consecutive watchdog resets after a reset with `reset_cause`
+pub fn next_watchdog_resets(reset_cause: ResetCause, previous: u8) -> u8 {
+    match reset_cause {
+        ResetCause::Watchdog | ResetCause::Lockup => previous.saturating_add(1),
+        _ => 0,
+    }
+}
+
+/// Boot state kept across resets
+///
+/// Stored as magic (u16 BE), flags, !flags, watchdog resets, !watchdog
+/// resets. The application sets `BOOT_FLAG_PROGRAMMING_REQUEST` before
+/// resetting into the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootRecord {
+    /// Boot flags (`BOOT_FLAG_*`)
+    pub flags: u8,
+    /// Number of consecutive watchdog or lockup resets
+    pub watchdog_resets: u8,
+}
+
+/// Programming was requested by the application
+pub const BOOT_FLAG_PROGRAMMING_REQUEST: u8 = 1 << 0;
+
+impl BootRecord {
+    /// Record of a device that never stored one
+    pub const fn empty() -> Self {
+        Self { flags: 0, watchdog_resets: 0 }
+    }
+    
+    /// Parse a stored record, `None` if it is corrupted
+    pub fn parse(data: &[u8; BOOT_RECORD_LENGTH]) -> Option<Self> {
+        if *data == [0xFF; BOOT_RECORD_LENGTH] {
+            // Never written
+            return Some(Self::empty());
+        }
+        
+        let magic = u16::from_be_bytes([data[0], data[1]]);
+        if magic != BOOT_RECORD_MAGIC || data[2] != !data[3] || data[4] != !data[5] {
+            return None;
+        }
+        
+        Some(Self { flags: data[2], watchdog_resets: data[4] })
+    }
+    
+    /// Serialize the record
+    pub fn to_bytes(self) -> [u8; BOOT_RECORD_LENGTH] {
+        let magic = BOOT_RECORD_MAGIC.to_be_bytes();
+        [magic[0], magic[1], self.flags, !self.flags, self.watchdog_resets, !self.watchdog_resets]
+    }
+}
+
+/// Boot manager deciding between application and bootloader after reset
+pub struct BootManager {
+    /// Non-volatile storage holding the boot record
+    nv_storage: Option<*mut dyn NvStorage>,
+    /// Inputs of the last decision
+    inputs: BootInputs,
+    /// Time the backdoor window was opened
+    backdoor_start: u32,
+}
+
+impl BootManager {
+    /// Create a new boot manager
+    pub fn new() -> Self {
+        Self {
+            nv_storage: None,
+            inputs: BootInputs {
+                reset_cause: ResetCause::Unknown,
+                programming_requested: false,
+                consecutive_watchdog_resets: 0,
+                image_valid: false,
+                backdoor: BackdoorState::Expired,
+            },
+            backdoor_start: 0,
+        }
+    }
+    
+    /// Register the non-volatile storage for the boot record
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.nv_storage = Some(storage);
+    }
+    
+    /// Make the decision after reset
+    ///
+    /// Consumes a pending programming request and updates the watchdog
+    /// reset counter in the boot record. `programming_requested` adds a
+    /// request that did not come through the boot record (e.g. the mailbox).
+    pub fn start(
+        &mut self,
+        reset_cause: ResetCause,
+        image_valid: bool,
+        programming_requested: bool,
+        now_ms: u32,
+    ) -> BootDecision {
+        let record = self.load_record();
+        let watchdog_resets = next_watchdog_resets(reset_cause, record.watchdog_resets);
+        
+        self.store_record(BootRecord {
+            flags: record.flags & !BOOT_FLAG_PROGRAMMING_REQUEST,
+            watchdog_resets,
+        });
+        
+        self.inputs = BootInputs {
+            reset_cause,
+            programming_requested: programming_requested || record.flags & BOOT_FLAG_PROGRAMMING_REQUEST != 0,
+            consecutive_watchdog_resets: watchdog_resets,
+            image_valid,
+            backdoor: if BACKDOOR_WINDOW_MS > 0 { BackdoorState::Open } else { BackdoorState::Expired },
+        };
+        self.backdoor_start = now_ms;
+        
+        let decision = decide(&self.inputs);
+        info!(
+            "Boot decision {} (reset {}, watchdog resets {}, image valid {})",
+            defmt::Debug2Format(&decision),
+            defmt::Display2Format(&reset_cause),
+            watchdog_resets,
+            image_valid
+        );
+        
+        decision
+    }
+    
+    /// Re-evaluate the decision while the backdoor window is open
+    pub fn poll(&mut self, tester_connected: bool, now_ms: u32) -> BootDecision {
+        if self.inputs.backdoor == BackdoorState::Open {
+            if tester_connected {
+                info!("Tester connected during backdoor window");
+                self.inputs.backdoor = BackdoorState::Connected;
+            } else if now_ms.wrapping_sub(self.backdoor_start) >= BACKDOOR_WINDOW_MS {
+                self.inputs.backdoor = BackdoorState::Expired;
+            }
+        }
+        
+        decide(&self.inputs)
+    }
+    
+    /// Load the boot record from non-volatile storage
+    fn load_record(&self) -> BootRecord {
+        let storage = match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) => unsafe { &*storage },
+            None => return BootRecord::empty(),
+        };
+        
+        let mut data = [0u8; BOOT_RECORD_LENGTH];
+        if storage.read(NVM_BOOT_RECORD_OFFSET, &mut data).is_err() {
+            return BootRecord::empty();
+        }
+        
+        BootRecord::parse(&data).unwrap_or_else(|| {
+            warn!("Corrupted boot record discarded");
+            BootRecord::empty()
+        })
+    }
+    
+    /// Store the boot record in non-volatile storage
+    fn store_record(&mut self, record: BootRecord) {
+        if let Some(storage) = self.nv_storage {
+            // Safety: We know this pointer is valid
+            let result = unsafe { (*storage).write(NVM_BOOT_RECORD_OFFSET, &record.to_bytes()) };
+            if result.is_err() {
+                warn!("Boot record write failed");
+            }
+        }
+    }
+}
+
+impl Default for BootManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const RESET_CAUSES: [ResetCause; 8] = [
+        ResetCause::Unknown,
+        ResetCause::PowerOn,
+        ResetCause::External,
+        ResetCause::Watchdog,
+        ResetCause::Software,
+        ResetCause::Lockup,
+        ResetCause::Jtag,
+        ResetCause::LowVoltage,
+    ];
+    
+    const BACKDOOR_STATES: [BackdoorState; 3] = [
+        BackdoorState::Open,
+        BackdoorState::Connected,
+        BackdoorState::Expired,
+    ];
+    
+    /// Every combination of boot inputs
+    fn all_inputs() -> impl Iterator<Item = BootInputs> {
+        RESET_CAUSES.into_iter().flat_map(|reset_cause| {
+            [false, true].into_iter().flat_map(move |programming_requested| {
+                (0..=u8::MAX).flat_map(move |consecutive_watchdog_resets| {
+                    [false, true].into_iter().flat_map(move |image_valid| {
+                        BACKDOOR_STATES.into_iter().map(move |backdoor| BootInputs {
+                            reset_cause,
+                            programming_requested,
+                            consecutive_watchdog_resets,
+                            image_valid,
+                            backdoor,
+                        })
+                    })
+                })
+            })
+        })
+    }
+    
+    #[test]
+    fn decide_every_input() {
+        let mut count = 0;
+        
+        for inputs in all_inputs() {
+            let decision = decide(&inputs);
+            let looping = inputs.consecutive_watchdog_resets >= MAX_WATCHDOG_RESETS;
+            let external = matches!(
+                inputs.reset_cause,
+                ResetCause::PowerOn | ResetCause::External | ResetCause::LowVoltage | ResetCause::Jtag | ResetCause::Unknown
+            );
+            
+            let expected = if inputs.programming_requested || inputs.backdoor == BackdoorState::Connected {
+                BootDecision::StayForProgramming
+            } else if looping || !inputs.image_valid {
+                BootDecision::Recovery
+            } else if external && inputs.backdoor == BackdoorState::Open {
+                BootDecision::WaitForBackdoor
+            } else {
+                BootDecision::RunApplication
+            };
+            assert_eq!(decision, expected, "{:?}", inputs);
+            
+            // The application is only started when it is valid and not caught in a reset loop
+            if decision == BootDecision::RunApplication {
+                assert!(inputs.image_valid && !looping, "{:?}", inputs);
+                assert!(!inputs.programming_requested, "{:?}", inputs);
+                assert_ne!(inputs.backdoor, BackdoorState::Connected, "{:?}", inputs);
+            }
+            
+            // Application resets never wait for a tester
+            if decision == BootDecision::WaitForBackdoor {
+                assert!(opens_backdoor(inputs.reset_cause), "{:?}", inputs);
+            }
+            
+            count += 1;
+        }
+        
+        assert_eq!(count, RESET_CAUSES.len() * 2 * 256 * 2 * BACKDOOR_STATES.len());
+    }
+    
+    #[test]
+    fn opens_backdoor_every_cause() {
+        for reset_cause in RESET_CAUSES {
+            let application_reset = matches!(
+                reset_cause,
+                ResetCause::Watchdog | ResetCause::Software | ResetCause::Lockup
+            );
+            assert_eq!(opens_backdoor(reset_cause), !application_reset, "{:?}", reset_cause);
+        }
+    }
+    
+    #[test]
+    fn next_watchdog_resets_every_input() {
+        for reset_cause in RESET_CAUSES {
+            for previous in 0..=u8::MAX {
+                let expected = match reset_cause {
+                    ResetCause::Watchdog | ResetCause::Lockup => previous.saturating_add(1),
+                    _ => 0,
+                };
+                assert_eq!(
+                    next_watchdog_resets(reset_cause, previous),
+                    expected,
+                    "{:?} after {}", reset_cause, previous
+                );
+            }
+        }
+        
+        // The counter saturates instead of wrapping back below the limit
+        assert_eq!(next_watchdog_resets(ResetCause::Watchdog, u8::MAX), u8::MAX);
+        assert_eq!(next_watchdog_resets(ResetCause::Lockup, u8::MAX - 1), u8::MAX);
+    }
+    
+    #[test]
+    fn watchdog_reset_loop_ends_in_recovery() {
+        for looping_cause in [ResetCause::Watchdog, ResetCause::Lockup] {
+            let mut resets = 0;
+            
+            for reset in 1..=u32::from(u8::MAX) + 2 {
+                resets = next_watchdog_resets(looping_cause, resets);
+                let inputs = BootInputs {
+                    reset_cause: looping_cause,
+                    programming_requested: false,
+                    consecutive_watchdog_resets: resets,
+                    image_valid: true,
+                    backdoor: BackdoorState::Expired,
+                };
+                
+                let expected = if reset < u32::from(MAX_WATCHDOG_RESETS) {
+                    BootDecision::RunApplication
+                } else {
+                    BootDecision::Recovery
+                };
+                assert_eq!(decide(&inputs), expected, "{:?} reset {}", looping_cause, reset);
+            }
+            
+            // Any other reset starts the application again
+            for reset_cause in RESET_CAUSES {
+                if reset_cause == ResetCause::Watchdog || reset_cause == ResetCause::Lockup {
+                    continue;
+                }
+                
+                let inputs = BootInputs {
+                    reset_cause,
+                    programming_requested: false,
+                    consecutive_watchdog_resets: next_watchdog_resets(reset_cause, resets),
+                    image_valid: true,
+                    backdoor: BackdoorState::Expired,
+                };
+                assert_eq!(decide(&inputs), BootDecision::RunApplication, "{:?}", reset_cause);
+            }
+        }
+    }
+}
\ No newline at end of file
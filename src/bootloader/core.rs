@@ -1,8 +1,28 @@
-use defmt::info;
+use core::convert::Infallible;
+use defmt::{error, info, warn};
 use crate::communication::can::Can;
 use crate::protocol::uds::session::UdsSession;
 use crate::bootloader::flash::Flash;
+use crate::bootloader::partition::{self, Partition, PartitionKind};
+use crate::bootloader::boot_manager::{BootDecision, BootManager};
+use crate::bootloader::handoff::{self, HandoffError, VectorTable};
+use crate::bootloader::mailbox::{self, HandoffRequestType};
+use crate::bootloader::boot_handoff::BootHandoff;
+use crate::bootloader::image::{self, ImageHeader, IMAGE_HEADER_LENGTH};
+use crate::bootloader::lifecycle;
+use crate::bootloader::measurement::{self, MeasuredStage, Measurement, MeasurementLog};
+use crate::bootloader::self_update::{self, UpdateFlash};
+use crate::bootloader::secure_boot::SecureBootState;
+use crate::bootloader::verification;
+use crate::config;
 use crate::bootloader::timeout::TimeoutReset;
+use crate::hal::s32k148::csec::Csec;
+use crate::hal::s32k148::peripherals::SystemReset;
+use crate::drivers::eeprom::Eeprom;
+use crate::drivers::clock::Clock;
+use crate::drivers::power::ResetCause;
+use crate::drivers::systick::{self, SysTickTimer};
+use crate::drivers::watchdog::Watchdog;
 
 /// Core bootloader functionality
 pub struct BootLoader {
@@ -10,6 +30,12 @@ pub struct BootLoader {
     can: Can,
     uds_session: UdsSession,
     timeout_reset: TimeoutReset,
+    csec: Csec,
+    eeprom: Eeprom,
+    boot_manager: BootManager,
+    boot_handoff: Option<BootHandoff>,
+    measurements: MeasurementLog,
+    tester_connected: bool,
 }
 
 impl BootLoader {
@@ -20,6 +46,12 @@ impl BootLoader {
             can: Can::new(),
             uds_session: UdsSession::new(),
             timeout_reset: TimeoutReset::new(),
+            csec: Csec::new(),
+            eeprom: Eeprom::new(),
+            boot_manager: BootManager::new(),
+            boot_handoff: None,
+            measurements: MeasurementLog::new(),
+            tester_connected: false,
         }
     }
     
@@ -30,28 +62,179 @@ impl BootLoader {
         // Initialize flash controller
         self.flash.init();
         
+        // Initialize emulated EEPROM for persistent data
+        self.eeprom.init();
+        
+        // Finish a pending bootloader update before anything else. With
+        // stage 0 installed it copies the update itself: the bootloader
+        // would otherwise rewrite the code it runs from.
+        if !partition::has_stage0() {
+            self.install_bootloader_update();
+        }
+        
+        // Start the measurement log of this start with the bootloader
+        self.measure(Self::measure_bootloader(&self.flash, self.boot_handoff.as_ref()));
+        
         // Initialize CAN communication
         self.can.init();
         
         // Initialize UDS session management
         self.uds_session.init();
         
+        // Program downloads and read the staged bootloader update
+        self.uds_session.register_flash(&mut self.flash);
+        
+        // Seed security access and decrypt downloads with the CSEc, authenticate
+        // attestation reports and provision the secure boot keys with its key slots
+        self.uds_session.register_security_engine(&mut self.csec);
+        self.log_secure_boot();
+        
+        // Persist the security access attempt counter and the device lifecycle across resets
+        self.uds_session.register_nv_storage(&mut self.eeprom);
+        info!("Device lifecycle: {}", defmt::Debug2Format(&lifecycle::load_state(&self.eeprom)));
+        
+        // Keep the programming request and watchdog reset counter across resets
+        self.boot_manager.register_nv_storage(&mut self.eeprom);
+        
         // Initialize timeout reset mechanism
         self.timeout_reset.init();
         
         info!("Bootloader initialization complete");
     }
     
+    /// Continue an armed or interrupted bootloader update
+    ///
+    /// Resets into the new bootloader once it is installed.
+    fn install_bootloader_update(&mut self) {
+        let layout = match partition::update_layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        
+        let keys = verification::load_keys(&self.eeprom);
+        match self_update::resume(
+            &mut self.flash,
+            &mut self.eeprom,
+            &layout,
+            config::BOOTLOADER_SECURITY_VERSION,
+            &|key_id, message, signature| verification::verify_image_signature(&keys, key_id, message, signature),
+        ) {
+            Ok(Some(security_version)) => {
+                info!("Bootloader update installed (security version {}), resetting", security_version);
+                SystemReset::reset();
+            },
+            Ok(None) => {},
+            Err(error) => {
+                error!("Bootloader update failed: {}", defmt::Debug2Format(&error));
+            }
+        }
+    }
+    
+    /// Report the outcome of the CSEc secure boot at this reset
+    fn log_secure_boot(&self) {
+        let status = match self.uds_session.secure_boot_status(config::SECURE_BOOT_FLAVOR) {
+            Some(status) => status,
+            None => return,
+        };
+        match status.state {
+            SecureBootState::Disabled => info!("SHE secure boot not configured"),
+            SecureBootState::Verified => info!("SHE secure boot verified"),
+            SecureBootState::Failed => {
+                warn!("SHE secure boot failed (status 0x{:02X}), boot protected keys locked", status.status_register);
+            }
+        }
+    }
+    
+    /// Keep the record stage 0 left for this start
+    pub fn set_boot_handoff(&mut self, handoff: BootHandoff) {
+        info!(
+            "Started by stage 0 from slot {} (security version {})",
+            defmt::Debug2Format(&handoff.slot),
+            handoff.security_version
+        );
+        self.boot_handoff = Some(handoff);
+    }
+    
+    /// Record stage 0 left for this start, `None` without stage 0
+    pub fn boot_handoff(&self) -> Option<&BootHandoff> {
+        self.boot_handoff.as_ref()
+    }
+    
+    /// Measurements of this start, as handed to the application
+    pub fn measurements(&self) -> &MeasurementLog {
+        &self.measurements
+    }
+    
+    /// Add a measurement to the log and publish the log
+    fn measure(&mut self, measurement: Option<Measurement>) {
+        if let Some(measurement) = measurement {
+            if self.measurements.extend(measurement).is_err() {
+                error!("Measurement log full, {} not recorded", defmt::Debug2Format(&measurement.stage));
+            }
+        }
+        
+        measurement::publish(&self.measurements);
+    }
+    
+    /// Measurement of the running bootloader
+    ///
+    /// Stage 0 hands over the hash it verified. Without it, the image is
+    /// hashed here over the size in its header, or over the whole
+    /// partition if it has no header.
+    fn measure_bootloader(flash: &Flash, handoff: Option<&BootHandoff>) -> Option<Measurement> {
+        if let Some(handoff) = handoff {
+            return Some(Measurement {
+                stage: MeasuredStage::Bootloader,
+                security_version: handoff.security_version,
+                digest: handoff.measurement,
+            });
+        }
+        
+        let bootloader = partition::find(PartitionKind::Bootloader)?;
+        let header_start = bootloader.end - IMAGE_HEADER_LENGTH as u32;
+        
+        let mut data = [0u8; IMAGE_HEADER_LENGTH];
+        flash.read(header_start, &mut data);
+        let size = ImageHeader::parse(&data)
+            .map(|header| header.image_size)
+            .ok()
+            .filter(|size| *size <= header_start - bootloader.start)
+            .unwrap_or(header_start - bootloader.start);
+        
+        Some(Measurement {
+            stage: MeasuredStage::Bootloader,
+            security_version: config::BOOTLOADER_SECURITY_VERSION,
+            digest: image::hash(&|address, buffer| flash.read(address, buffer), bootloader.start, size),
+        })
+    }
+    
+    /// Measurement of the application partition
+    ///
+    /// The application has no image header, the whole partition is hashed.
+    fn measure_application(flash: &Flash) -> Option<Measurement> {
+        let application = partition::find(PartitionKind::Application)?;
+        
+        Some(Measurement {
+            stage: MeasuredStage::Application,
+            security_version: 0,
+            digest: image::hash(&|address, buffer| flash.read(address, buffer), application.start, application.end - application.start),
+        })
+    }
+    
     /// Main task function that should be called periodically
     pub fn task(&mut self) {
         // Process communication data
         if let Some(data) = self.can.receive() {
+            self.tester_connected = true;
+            
             // Handle incoming UDS messages
             let response = self.uds_session.process_message(&data);
             
             // Send response if needed
             if !response.is_empty() {
-                self.can.transmit(&response);
+                if let Err(error) = self.can.transmit(&response) {
+                    warn!("UDS response not sent: {}", defmt::Debug2Format(&error));
+                }
             }
         }
         
@@ -59,22 +242,113 @@ impl BootLoader {
         self.timeout_reset.check();
     }
     
-    /// Verify application checksum to determine if it's valid
+    /// Access the UDS session (e.g. to register customer seed/key algorithms)
+    pub fn uds_session_mut(&mut self) -> &mut UdsSession {
+        &mut self.uds_session
+    }
+    
+    /// Verify application checksum and vector table to determine if it's valid
     pub fn verify_application(&self) -> bool {
-        self.flash.verify_checksum()
+        self.flash.verify_checksum() && self.application_vector_table().is_ok()
     }
     
-    /// Start the application if valid
-    pub fn start_application(&self) -> ! {
-        info!("Starting application...");
+    /// Decide after reset whether to start the application or stay
+    ///
+    /// A programming request the application left in the mailbox is
+    /// answered right away with the pending DiagnosticSessionControl response.
+    pub fn boot_decision(&mut self, reset_cause: ResetCause) -> BootDecision {
+        let image_valid = self.verify_application();
         
-        // Jump to application code
-        // Safety: This jumps to the application entry point
-        unsafe {
-            // Calculate application start address from memory.x
-            let app_start: u32 = 0x00008000; // Example value - get from memory.x
-            let jump_addr: *const fn() -> ! = core::mem::transmute(app_start);
-            (*jump_addr)()
+        // Measure the application before it can be started, the log is then
+        // complete for the application and for attestation from here
+        if image_valid {
+            self.measure(Self::measure_application(&self.flash));
         }
+        
+        let handoff = mailbox::take(reset_cause);
+        let programming_requested = handoff
+            .is_some_and(|handoff| handoff.request == HandoffRequestType::EnterProgramming);
+        
+        let decision = self.boot_manager.start(reset_cause, image_valid, programming_requested, systick::millis());
+        
+        if let Some(handoff) = handoff {
+            let response = self.uds_session.resume_from_handoff(&handoff);
+            if !response.is_empty() && self.can.transmit_to(handoff.tester.response_id, &response).is_err() {
+                warn!("Delayed session control response not sent");
+            }
+        }
+        
+        decision
+    }
+    
+    /// Re-evaluate the boot decision while waiting for a tester
+    pub fn poll_boot_decision(&mut self) -> BootDecision {
+        self.boot_manager.poll(self.tester_connected, systick::millis())
+    }
+    
+    /// Read and validate the vector table of the application partition
+    fn application_vector_table(&self) -> Result<(&'static Partition, VectorTable), HandoffError> {
+        let application = partition::find(PartitionKind::Application).ok_or(HandoffError::InvalidResetVector)?;
+        
+        // Safety: the application partition is memory-mapped flash
+        let table = unsafe { VectorTable::read(application.start) };
+        handoff::validate_vector_table(
+            &table,
+            application.start,
+            application,
+            config::RAM_START,
+            config::RAM_END,
+        )?;
+        
+        Ok((application, table))
+    }
+    
+    /// Start the application if its vector table is valid
+    ///
+    /// Only returns if the vector table is rejected, the bootloader state is
+    /// untouched in that case.
+    pub fn start_application(
+        &mut self,
+        clock: &mut Clock,
+        watchdog: &mut Watchdog,
+        systick: &mut SysTickTimer,
+    ) -> Result<Infallible, HandoffError> {
+        let (application, table) = match self.application_vector_table() {
+            Ok(application) => application,
+            Err(error) => {
+                warn!("Application vector table rejected: {}", defmt::Debug2Format(&error));
+                return Err(error);
+            }
+        };
+        
+        info!("Starting application at 0x{:08X}...", table.reset_handler);
+        
+        // Close the secure boot phase, boot protected keys stay available
+        if let Err(error) = self.uds_session.finish_secure_boot() {
+            warn!("Secure boot phase not finished: {}", defmt::Debug2Format(&error));
+        }
+        
+        // Return the peripherals to their reset state as configured
+        systick.deinit();
+        if config::HANDOFF_DEINIT_CAN {
+            self.can.deinit();
+        }
+        if config::HANDOFF_DEINIT_CLOCK {
+            clock.deinit();
+        }
+        if config::HANDOFF_DISABLE_WATCHDOG {
+            if let Err(error) = watchdog.disable() {
+                warn!("Watchdog not disabled: {}", defmt::Debug2Format(&error));
+            }
+        }
+        
+        // Safety: the vector table was validated and the peripherals are de-initialized
+        unsafe { handoff::jump_to_application(application.start) }
+    }
+}
+
+impl Default for BootLoader {
+    fn default() -> Self {
+        Self::new()
     }
 }
\ No newline at end of file
@@ -1,16 +1,83 @@
 use defmt::{debug, error, info};
-use core::ops::Range;
+use super::partition::{self, PartitionKind};
+use super::self_update::{UpdateError, UpdateFlash};
+
+/// Offset of the application checksum from the application start
+const CHECKSUM_OFFSET: u32 = 0x3F8;
+
+/// Size of the vector table part covered by the application checksum
+const CHECKSUM_COVERED_SIZE: u32 = 0x20;
+
+/// Access to the flash array behind the controller
+///
+/// `PflashPort` is the FTFC of the S32K148. Host tests run the controller
+/// on a RAM image of the flash.
+pub trait FlashPort {
+    /// Read flash memory
+    fn read(&self, address: u32, buffer: &mut [u8]);
+    
+    /// Program erased flash memory
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), FlashError>;
+    
+    /// Erase the sector starting at `address`
+    fn erase_sector(&mut self, address: u32) -> Result<(), FlashError>;
+}
+
+/// Program and data flash of the S32K148, accessed through the FTFC
+pub struct PflashPort;
+
+impl FlashPort for PflashPort {
+    fn read(&self, address: u32, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((address + i as u32) as *const u8) };
+        }
+    }
+    
+    fn program(&mut self, _address: u32, _data: &[u8]) -> Result<(), FlashError> {
+        // Handle actual hardware flash programming here
+        // This is highly device-specific and would involve:
+        // 1. Unlocking flash if needed
+        // 2. Programming the data in appropriate-sized chunks
+        // 3. Verifying the written data
+        // 4. Locking flash when done
+        
+        // Example pseudocode for S32K148:
+        // 1. Check if CCIF is set in FTFC_FSTAT
+        // 2. Clear error flags
+        // 3. Set up FTFC_FCCOB registers for Program Phrase command
+        // 4. Execute command sequence
+        // 5. Verify data was written correctly
+        
+        Ok(())
+    }
+    
+    fn erase_sector(&mut self, address: u32) -> Result<(), FlashError> {
+        debug!("Erasing flash sector at address 0x{:08X}", address);
+        
+        // Handle actual hardware flash erasing here
+        // Similar to program but using erase sector command
+        
+        // Example pseudocode for S32K148:
+        // 1. Check if CCIF is set in FTFC_FSTAT
+        // 2. Clear error flags
+        // 3. Set up FTFC_FCCOB registers for Erase Sector command
+        // 4. Execute command sequence
+        // 5. Verify sector was erased correctly
+        
+        Ok(())
+    }
+}
 
 /// Flash memory controller for S32K148
-pub struct Flash {
+pub struct Flash<P: FlashPort + ?Sized = PflashPort> {
     // Base address for program memory
     base_address: u32,
     // Flash block size for writing
     write_block_size: usize,
-    // Flash sector size for erasing
-    erase_block_size: usize,
     // Current block for buffering write operations
     current_block: Option<FlashBlock>,
+    // Flash array access
+    port: P,
 }
 
 /// Flash memory block for batch operations
@@ -22,14 +89,25 @@ struct FlashBlock {
 impl Flash {
     /// Create a new flash controller instance
     pub fn new() -> Self {
+        Self::with_port(PflashPort)
+    }
+}
+
+impl<P: FlashPort> Flash<P> {
+    /// Create a flash controller on the given flash array
+    ///
+    /// The application checksum is kept relative to the application partition.
+    pub fn with_port(port: P) -> Self {
         Self {
-            base_address: 0x00000000,
+            base_address: partition::application_start(),
             write_block_size: 1024,
-            erase_block_size: 4096,
             current_block: None,
+            port,
         }
     }
-    
+}
+
+impl<P: FlashPort + ?Sized> Flash<P> {
     /// Initialize the flash controller
     pub fn init(&mut self) {
         info!("Initializing flash controller");
@@ -60,13 +138,15 @@ impl Flash {
             return Err(FlashError::InvalidAddress);
         }
         
-        // Calculate sectors to erase
-        let start_sector = address / self.erase_block_size as u32;
-        let end_sector = (address + length - 1) / self.erase_block_size as u32;
+        // Calculate sectors to erase with the granularity of the partition
+        let erase_size = partition::find_containing(address, length)
+            .map_or(partition::PFLASH_SECTOR_SIZE, |partition| partition.erase_size);
+        let start_sector = address / erase_size;
+        let end_sector = (address + length - 1) / erase_size;
         
         // Erase each sector
         for sector in start_sector..=end_sector {
-            self.erase_sector(sector)?;
+            self.erase_sector(sector * erase_size)?;
         }
         
         Ok(())
@@ -77,22 +157,14 @@ impl Flash {
         debug!("Writing application checksum");
         
         // Calculate checksum from application vectors
-        let mut checksum: u32 = 0;
-        
-        // Read vector table entries
-        let vector_table = self.base_address;
-        for offset in (0..0x20).step_by(4) {
-            // Read vector table entry
-            let value = unsafe { core::ptr::read_volatile((vector_table + offset) as *const u32) };
-            checksum = checksum.wrapping_add(value);
-        }
+        let mut checksum = self.vector_table_sum();
         
         // Calculate two's complement
         checksum = !checksum;
         checksum = checksum.wrapping_add(1);
         
-        // Write checksum to designated location (e.g., 0x3F8 offset in vector table)
-        let checksum_address = self.base_address + 0x3F8;
+        // Write checksum to offset 0x3F8 of the application vector table
+        let checksum_address = self.base_address + CHECKSUM_OFFSET;
         let checksum_bytes = checksum.to_le_bytes();
         self.write(checksum_address, &checksum_bytes)?;
         
@@ -104,20 +176,10 @@ impl Flash {
         debug!("Verifying application checksum");
         
         // Calculate sum with stored checksum - should result in 0
-        let mut sum: u32 = 0;
-        
-        // Read vector table entries
-        let vector_table = self.base_address;
-        for offset in (0..0x20).step_by(4) {
-            // Read vector table entry
-            let value = unsafe { core::ptr::read_volatile((vector_table + offset) as *const u32) };
-            sum = sum.wrapping_add(value);
-        }
+        let mut sum = self.vector_table_sum();
         
         // Read stored checksum
-        let checksum_address = self.base_address + 0x3F8;
-        let stored_checksum = unsafe { core::ptr::read_volatile(checksum_address as *const u32) };
-        sum = sum.wrapping_add(stored_checksum);
+        sum = sum.wrapping_add(self.read_word(self.base_address + CHECKSUM_OFFSET));
         
         // If valid, sum should be 0
         sum == 0
@@ -136,19 +198,37 @@ impl Flash {
     /// Get application base address
     pub fn get_app_address(&self) -> u32 {
         // Return the application start address (after bootloader)
-        0x00008000 // Example - adjust based on actual memory map
+        partition::application_start()
     }
     
     // Private helper methods
     
+    fn vector_table_sum(&self) -> u32 {
+        // Sum of the vector table entries covered by the checksum
+        (0..CHECKSUM_COVERED_SIZE)
+            .step_by(4)
+            .fold(0u32, |sum, offset| sum.wrapping_add(self.read_word(self.base_address + offset)))
+    }
+    
+    fn read_word(&self, address: u32) -> u32 {
+        let mut word = [0u8; 4];
+        self.port.read(address, &mut word);
+        u32::from_le_bytes(word)
+    }
+    
     fn is_valid_address_range(&self, address: u32, length: u32) -> bool {
         // Check if the address range is valid for flash operations
-        // Ensure it doesn't overlap with bootloader area
-        let app_start = self.get_app_address();
-        let flash_end = self.base_address + 0x40000; // Example - adjust to actual flash size
-        
-        // Valid if in application area and not exceeding flash
-        (address >= app_start) && (address + length <= flash_end)
+        // Valid if within a single partition other than stage 0 and the bootloader
+        match partition::find_containing(address, length) {
+            Some(partition) => !matches!(partition.kind, PartitionKind::Stage0 | PartitionKind::Bootloader),
+            None => false,
+        }
+    }
+    
+    fn is_update_range(address: u32, length: u32) -> bool {
+        // Only the self-update writes the bootloader, from the staging partition
+        partition::find_containing(address, length)
+            .is_some_and(|partition| matches!(partition.kind, PartitionKind::Bootloader | PartitionKind::Staging))
     }
     
     fn write_with_block_manager(&mut self, address: u32, data: &[u8]) -> Result<(), FlashError> {
@@ -173,11 +253,7 @@ impl Flash {
                     };
                     
                     // Read current flash content
-                    for i in 0..self.write_block_size {
-                        new_block.data[i] = unsafe {
-                            core::ptr::read_volatile((block_address + i as u32) as *const u8)
-                        };
-                    }
+                    self.port.read(block_address, &mut new_block.data[..self.write_block_size]);
                     
                     self.current_block = Some(new_block);
                     self.current_block.as_mut().unwrap()
@@ -208,41 +284,47 @@ impl Flash {
         Ok(())
     }
     
-    fn flush_block(&self, block: &FlashBlock) -> Result<(), FlashError> {
+    fn flush_block(&mut self, block: &FlashBlock) -> Result<(), FlashError> {
         debug!("Flushing flash block at address 0x{:08X}", block.base_address);
-        
-        // Handle actual hardware flash programming here
-        // This is highly device-specific and would involve:
-        // 1. Unlocking flash if needed
-        // 2. Programming the data in appropriate-sized chunks
-        // 3. Verifying the written data
-        // 4. Locking flash when done
-        
-        // Example pseudocode for S32K148:
-        // 1. Check if CCIF is set in FTFC_FSTAT
-        // 2. Clear error flags
-        // 3. Set up FTFC_FCCOB registers for Program Phrase command
-        // 4. Execute command sequence
-        // 5. Verify data was written correctly
-        
-        Ok(())
+        self.port.program(block.base_address, &block.data[..self.write_block_size])
     }
     
-    fn erase_sector(&self, sector: u32) -> Result<(), FlashError> {
-        let sector_address = sector * self.erase_block_size as u32;
-        debug!("Erasing flash sector at address 0x{:08X}", sector_address);
-        
-        // Handle actual hardware flash erasing here
-        // Similar to flush_block but using erase sector command
+    fn erase_sector(&mut self, sector_address: u32) -> Result<(), FlashError> {
+        self.port.erase_sector(sector_address)
+    }
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Flash access of the bootloader self-update
+///
+/// The only path allowed to write the bootloader partition, restricted to
+/// it and the staging partition.
+impl<P: FlashPort + ?Sized> UpdateFlash for Flash<P> {
+    fn read(&self, address: u32, buffer: &mut [u8]) {
+        self.port.read(address, buffer);
+    }
+    
+    fn erase(&mut self, address: u32) -> Result<(), UpdateError> {
+        if !Self::is_update_range(address, 1) {
+            return Err(UpdateError::EraseFailed);
+        }
         
-        // Example pseudocode for S32K148:
-        // 1. Check if CCIF is set in FTFC_FSTAT
-        // 2. Clear error flags
-        // 3. Set up FTFC_FCCOB registers for Erase Sector command
-        // 4. Execute command sequence
-        // 5. Verify sector was erased correctly
+        Flash::erase_sector(self, address).map_err(|_| UpdateError::EraseFailed)
+    }
+    
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), UpdateError> {
+        if !Self::is_update_range(address, data.len() as u32) {
+            return Err(UpdateError::ProgramFailed);
+        }
         
-        Ok(())
+        self.write_with_block_manager(address, data)
+            .and_then(|_| self.finalize())
+            .map_err(|_| UpdateError::ProgramFailed)
     }
 }
 
@@ -254,8 +336,98 @@ pub enum FlashError {
     EraseError,
     VerificationError,
 }
+/// RAM image of the flash for host tests
+///
+/// Unwritten memory reads as erased. Programming a range containing
+/// `fail_at` fails, as a flash write error would.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockFlashPort {
+    memory: std::collections::HashMap<u32, u8>,
+    /// Address whose programming fails
+    pub fail_at: Option<u32>,
+}
 
-/// Helper class for implementing block-based flash operations
-struct FlashBlockManager {
-    current_block: Option<FlashBlock>,
+#[cfg(test)]
+impl FlashPort for MockFlashPort {
+    fn read(&self, address: u32, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.memory.get(&(address + i as u32)).copied().unwrap_or(0xFF);
+        }
+    }
+    
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), FlashError> {
+        if self.fail_at.is_some_and(|fail_at| (address..address + data.len() as u32).contains(&fail_at)) {
+            return Err(FlashError::WriteError);
+        }
+        
+        for (i, &byte) in data.iter().enumerate() {
+            self.memory.insert(address + i as u32, byte);
+        }
+        Ok(())
+    }
+    
+    fn erase_sector(&mut self, address: u32) -> Result<(), FlashError> {
+        let sector = partition::find_containing(address, 1)
+            .map_or(partition::PFLASH_SECTOR_SIZE, |partition| partition.erase_size);
+        self.memory.retain(|&byte_address, _| !(address..address + sector).contains(&byte_address));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Flash with an application vector table of eight words
+    fn programmed_flash() -> Flash<MockFlashPort> {
+        let mut flash = Flash::with_port(MockFlashPort::default());
+        let start = partition::application_start();
+        let vectors: std::vec::Vec<u8> = (0..8u32).flat_map(|i| (0x2001_0000 + i * 0x111).to_le_bytes()).collect();
+        flash.write(start, &vectors).unwrap();
+        flash
+    }
+    
+    #[test]
+    fn checksum_round_trip() {
+        let mut flash = programmed_flash();
+        assert!(!flash.verify_checksum());
+        
+        flash.write_checksum().unwrap();
+        assert!(flash.verify_checksum());
+        
+        // The checksum lies in the application vector table, not in stage 0
+        let mut stored = [0u8; 4];
+        flash.port.read(partition::application_start() + CHECKSUM_OFFSET, &mut stored);
+        assert_ne!(stored, [0xFF; 4]);
+        assert_eq!(flash.read_word(CHECKSUM_OFFSET), 0xFFFF_FFFF);
+    }
+    
+    #[test]
+    fn checksum_covers_the_vector_table() {
+        let mut flash = programmed_flash();
+        flash.write_checksum().unwrap();
+        
+        // Reprogramming the reset vector invalidates the checksum
+        let reset_vector = partition::application_start() + 4;
+        flash.write(reset_vector, &0x0001_0401u32.to_le_bytes()).unwrap();
+        assert!(!flash.verify_checksum());
+    }
+    
+    #[test]
+    fn checksum_write_failure() {
+        let mut flash = programmed_flash();
+        flash.port.fail_at = Some(partition::application_start() + CHECKSUM_OFFSET);
+        
+        assert!(matches!(flash.write_checksum(), Err(FlashError::WriteError)));
+        assert!(!flash.verify_checksum());
+    }
+    
+    #[test]
+    fn protected_partitions_are_not_written() {
+        let mut flash = Flash::with_port(MockFlashPort::default());
+        
+        assert!(matches!(flash.write(CHECKSUM_OFFSET, &[0; 4]), Err(FlashError::InvalidAddress)));
+        assert_eq!(flash.read_word(CHECKSUM_OFFSET), 0xFFFF_FFFF);
+    }
 }
\ No newline at end of file
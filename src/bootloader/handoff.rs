@@ -0,0 +1,235 @@
+use core::ptr::{read_volatile, write_volatile};
+use super::partition::Partition;
+
+/// NVIC interrupt clear-enable registers
+const NVIC_ICER: u32 = 0xE000_E180;
+
+/// NVIC interrupt clear-pending registers
+const NVIC_ICPR: u32 = 0xE000_E280;
+
+/// Number of NVIC enable/pending registers (up to 240 interrupts)
+const NVIC_REGISTER_COUNT: u32 = 8;
+
+/// Interrupt control and state register
+const SCB_ICSR: u32 = 0xE000_ED04;
+
+/// Vector table offset register
+const SCB_VTOR: u32 = 0xE000_ED08;
+
+/// SysTick control and status register
+const SYST_CSR: u32 = 0xE000_E010;
+
+// SCB_ICSR bits
+const ICSR_PENDSTCLR: u32 = 1 << 25;
+const ICSR_PENDSVCLR: u32 = 1 << 27;
+
+/// Alignment of a relocated vector table
+///
+/// VTOR requires the table to be aligned to its size rounded up to a power
+/// of two: 16 system + 140 S32K148 interrupt vectors need 1024 bytes.
+pub const VECTOR_TABLE_ALIGNMENT: u32 = 0x400;
+
+/// First two entries of a Cortex-M vector table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorTable {
+    /// Initial main stack pointer
+    pub initial_sp: u32,
+    /// Reset handler address (Thumb bit set)
+    pub reset_handler: u32,
+}
+
+impl VectorTable {
+    /// Read the vector table at `address`
+    ///
+    /// # Safety
+    ///
+    /// `address` must be readable memory (e.g. a flash partition).
+    pub unsafe fn read(address: u32) -> Self {
+        Self {
+            initial_sp: read_volatile(address as *const u32),
+            reset_handler: read_volatile((address + 4) as *const u32),
+        }
+    }
+}
+
+/// Check that a vector table can be started
+///
+/// The table must be aligned for VTOR, the stack pointer must point into
+/// RAM (the stack may start at the very end of it) and the reset handler
+/// must be a Thumb address inside the application partition, after the
+/// first two vectors.
+pub fn validate_vector_table(
+    table: &VectorTable,
+    address: u32,
+    application: &Partition,
+    ram_start: u32,
+    ram_end: u32,
+) -> Result<(), HandoffError> {
+    if !address.is_multiple_of(VECTOR_TABLE_ALIGNMENT) || !application.contains(address, 8) {
+        return Err(HandoffError::MisalignedVectorTable);
+    }
+    
+    let sp = table.initial_sp;
+    if !sp.is_multiple_of(4) || sp <= ram_start || sp > ram_end {
+        return Err(HandoffError::InvalidStackPointer);
+    }
+    
+    let reset = table.reset_handler;
+    let entry = reset & !1;
+    if reset & 1 == 0 || entry < address + 8 || !application.contains(entry, 2) {
+        return Err(HandoffError::InvalidResetVector);
+    }
+    
+    Ok(())
+}
+
+/// Hand the core over to the application at `address`
+///
+/// Masks and clears every NVIC interrupt, stops SysTick, relocates the
+/// vector table and branches to the reset handler with the application's
+/// initial stack pointer. Interrupts are unmasked again (PRIMASK as after
+/// reset) since nothing is left enabled or pending.
+///
+/// # Safety
+///
+/// The vector table must have passed `validate_vector_table` and the
+/// bootloader peripherals must already be de-initialized.
+pub unsafe fn jump_to_application(address: u32) -> ! {
+    cortex_m::interrupt::disable();
+    
+    for i in 0..NVIC_REGISTER_COUNT {
+        write_volatile((NVIC_ICER + i * 4) as *mut u32, 0xFFFF_FFFF);
+        write_volatile((NVIC_ICPR + i * 4) as *mut u32, 0xFFFF_FFFF);
+    }
+    
+    // Stop SysTick and drop its pending exception together with PendSV
+    write_volatile(SYST_CSR as *mut u32, 0);
+    write_volatile(SCB_ICSR as *mut u32, ICSR_PENDSTCLR | ICSR_PENDSVCLR);
+    
+    write_volatile(SCB_VTOR as *mut u32, address);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+    
+    cortex_m::interrupt::enable();
+    
+    // Load MSP from the first vector and branch to the reset handler
+    cortex_m::asm::bootload(address as *const u32)
+}
+
+/// Application handoff error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandoffError {
+    MisalignedVectorTable,
+    InvalidStackPointer,
+    InvalidResetVector,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootloader::partition::{PartitionKind, PARTITION_FLAG_EXECUTABLE, PARTITION_FLAG_WRITABLE};
+    
+    const APPLICATION: Partition = Partition {
+        name: "application",
+        kind: PartitionKind::Application,
+        start: 0x0001_0000,
+        end: 0x0008_0000,
+        flags: PARTITION_FLAG_WRITABLE | PARTITION_FLAG_EXECUTABLE,
+        erase_size: 4096,
+    };
+    
+    const RAM_START: u32 = 0x1FFE_0000;
+    const RAM_END: u32 = 0x2002_0000;
+    
+    fn validate(initial_sp: u32, reset_handler: u32) -> Result<(), HandoffError> {
+        validate_at(APPLICATION.start, initial_sp, reset_handler)
+    }
+    
+    fn validate_at(address: u32, initial_sp: u32, reset_handler: u32) -> Result<(), HandoffError> {
+        let table = VectorTable { initial_sp, reset_handler };
+        validate_vector_table(&table, address, &APPLICATION, RAM_START, RAM_END)
+    }
+    
+    #[test]
+    fn valid_table() {
+        assert_eq!(validate(RAM_END, APPLICATION.start + 0x401), Ok(()));
+        
+        // The stack may start anywhere in RAM, the code right after the first two vectors
+        assert_eq!(validate(RAM_START + 4, APPLICATION.start + 9), Ok(()));
+        assert_eq!(validate(0x2000_0000, APPLICATION.end - 1), Ok(()));
+        
+        // Any aligned table inside the partition can be started
+        let address = APPLICATION.start + VECTOR_TABLE_ALIGNMENT;
+        assert_eq!(validate_at(address, RAM_END, address + 0x101), Ok(()));
+    }
+    
+    #[test]
+    fn misplaced_table() {
+        for address in [
+            APPLICATION.start + 0x200,
+            APPLICATION.start + 4,
+            APPLICATION.start - VECTOR_TABLE_ALIGNMENT,
+            APPLICATION.end,
+            0xFFFF_FC00,
+        ] {
+            assert_eq!(
+                validate_at(address, RAM_END, APPLICATION.start + 0x401),
+                Err(HandoffError::MisalignedVectorTable),
+                "table at 0x{:08X}", address
+            );
+        }
+    }
+    
+    #[test]
+    fn bad_stack_pointer() {
+        for sp in [
+            0,
+            0xFFFF_FFFF,
+            RAM_START,
+            RAM_START - 4,
+            RAM_END + 4,
+            RAM_END - 2,
+            RAM_START + 1,
+            APPLICATION.start + 0x1000,
+        ] {
+            assert_eq!(
+                validate(sp, APPLICATION.start + 0x401),
+                Err(HandoffError::InvalidStackPointer),
+                "stack pointer 0x{:08X}", sp
+            );
+        }
+    }
+    
+    #[test]
+    fn bad_reset_vector() {
+        for reset in [
+            // Not a Thumb address
+            APPLICATION.start + 0x400,
+            APPLICATION.start + 0x402,
+            // Erased flash
+            0xFFFF_FFFF,
+            // Inside the first two vectors
+            APPLICATION.start + 1,
+            APPLICATION.start + 5,
+            // Outside the application partition
+            APPLICATION.start - 0x3FF,
+            APPLICATION.end + 1,
+            APPLICATION.end + 0x1001,
+            RAM_START + 0x101,
+            1,
+        ] {
+            assert_eq!(
+                validate(RAM_END, reset),
+                Err(HandoffError::InvalidResetVector),
+                "reset vector 0x{:08X}", reset
+            );
+        }
+        
+        // A table further up the partition may not branch below itself
+        let address = APPLICATION.start + VECTOR_TABLE_ALIGNMENT;
+        assert_eq!(
+            validate_at(address, RAM_END, APPLICATION.start + 0x101),
+            Err(HandoffError::InvalidResetVector)
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,249 @@
+use sha2::{Digest, Sha256};
+
+/// Signed image magic ("GBLU")
+pub const IMAGE_MAGIC: [u8; 4] = *b"GBLU";
+
+/// Signed image header format version
+pub const IMAGE_VERSION: u8 = 1;
+
+/// Length of the signed image header
+pub const IMAGE_HEADER_LENGTH: usize = 256;
+
+/// Bytes at the start of the header covered by the signature
+pub const IMAGE_SIGNED_LENGTH: usize = 52;
+
+/// Length of the raw (r || s) ECDSA P-256 signature in the header
+pub const IMAGE_SIGNATURE_LENGTH: usize = 64;
+
+/// Bytes hashed per flash read
+const HASH_CHUNK_SIZE: usize = 256;
+
+/// Signature algorithm of an image
+///
+/// Images from before the field existed carry 0 there, which is the
+/// algorithm they were signed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// ECDSA P-256 with SHA-256, raw (r || s) signature
+    EcdsaP256Sha256,
+}
+
+impl SignatureAlgorithm {
+    /// Decode the algorithm byte of a header
+    ///
+    /// ECDSA is only supported with the `ecdsa` feature.
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 if cfg!(feature = "ecdsa") => Some(Self::EcdsaP256Sha256),
+            _ => None,
+        }
+    }
+    
+    /// Algorithm byte of a header
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::EcdsaP256Sha256 => 0x00,
+        }
+    }
+}
+
+/// Signature check by key ID: key ID, message, signature
+pub type SignatureCheck<'a> = dyn Fn(u8, &[u8], &[u8]) -> bool + 'a;
+
+/// Signed image header
+///
+/// ```text
+/// 0   magic "GBLU"
+/// 4   version (1)
+/// 5   key ID of the signing key (`keystore`)
+/// 6   signature algorithm (`SignatureAlgorithm`), 1 reserved byte
+/// 8   image size (u32 LE)
+/// 12  security version (u32 LE)
+/// 16  load address (u32 LE)
+/// 20  SHA-256 of the image
+/// 52  signature of bytes 0-51 with the key of the key ID (64 bytes)
+/// 116 reserved up to 256 (0xFF)
+/// ```
+///
+/// The same header is used in two places: in front of the image in the
+/// staging partition (updater image), and in the last
+/// `IMAGE_HEADER_LENGTH` bytes of the bootloader partition, where stage 0
+/// checks it before starting the bootloader.
+///
+/// This module only depends on `sha2`, it is shared with stage 0 and the
+/// host tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// ID of the key the image is signed with
+    pub key_id: u8,
+    /// Algorithm of the signature
+    pub algorithm: SignatureAlgorithm,
+    /// Size of the image
+    pub image_size: u32,
+    /// Security version, never lower than the one installed (downgrade protection)
+    pub security_version: u32,
+    /// Address the image is built for
+    pub load_address: u32,
+    /// SHA-256 of the image
+    pub image_hash: [u8; 32],
+    /// Signature of the first `IMAGE_SIGNED_LENGTH` header bytes
+    pub signature: [u8; IMAGE_SIGNATURE_LENGTH],
+}
+
+impl ImageHeader {
+    /// Parse a signed image header
+    pub fn parse(data: &[u8; IMAGE_HEADER_LENGTH]) -> Result<Self, ImageError> {
+        if data[0..4] != IMAGE_MAGIC {
+            return Err(ImageError::InvalidHeader);
+        }
+        
+        if data[4] != IMAGE_VERSION {
+            return Err(ImageError::UnsupportedVersion);
+        }
+        
+        let algorithm = SignatureAlgorithm::from_byte(data[6]).ok_or(ImageError::UnsupportedAlgorithm)?;
+        
+        let word = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        
+        let mut image_hash = [0u8; 32];
+        let mut signature = [0u8; IMAGE_SIGNATURE_LENGTH];
+        image_hash.copy_from_slice(&data[20..52]);
+        signature.copy_from_slice(&data[52..116]);
+        
+        Ok(Self {
+            key_id: data[5],
+            algorithm,
+            image_size: word(8),
+            security_version: word(12),
+            load_address: word(16),
+            image_hash,
+            signature,
+        })
+    }
+    
+    /// Header bytes covered by the signature
+    pub fn signed_data(&self) -> [u8; IMAGE_SIGNED_LENGTH] {
+        let mut data = [0u8; IMAGE_SIGNED_LENGTH];
+        data[0..4].copy_from_slice(&IMAGE_MAGIC);
+        data[4] = IMAGE_VERSION;
+        data[5] = self.key_id;
+        data[6] = self.algorithm.to_byte();
+        data[8..12].copy_from_slice(&self.image_size.to_le_bytes());
+        data[12..16].copy_from_slice(&self.security_version.to_le_bytes());
+        data[16..20].copy_from_slice(&self.load_address.to_le_bytes());
+        data[20..52].copy_from_slice(&self.image_hash);
+        data
+    }
+    
+    /// Serialize the header
+    pub fn to_bytes(self) -> [u8; IMAGE_HEADER_LENGTH] {
+        let mut data = [0xFF; IMAGE_HEADER_LENGTH];
+        data[..IMAGE_SIGNED_LENGTH].copy_from_slice(&self.signed_data());
+        data[52..116].copy_from_slice(&self.signature);
+        data
+    }
+}
+
+/// Requirements an image must meet where it is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImagePolicy {
+    /// Address the image must be built for
+    pub load_address: u32,
+    /// Largest image that fits the partition
+    pub max_size: u32,
+    /// Lowest accepted security version
+    pub min_security_version: u32,
+}
+
+/// SHA-256 of `length` bytes at `address`, read through `read`
+pub fn hash(read: &dyn Fn(u32, &mut [u8]), address: u32, length: u32) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; HASH_CHUNK_SIZE];
+    let mut offset = 0;
+    
+    while offset < length {
+        let chunk = (length - offset).min(HASH_CHUNK_SIZE as u32) as usize;
+        read(address + offset, &mut buffer[..chunk]);
+        hasher.update(&buffer[..chunk]);
+        offset += chunk as u32;
+    }
+    
+    hasher.finalize().into()
+}
+
+/// Check a signed image against its header
+///
+/// The image is `header.image_size` bytes at `image_address`, read through
+/// `read`. `verify_signature` checks a signature over the given message
+/// with the key of the given key ID, and rejects unknown and revoked
+/// keys. The checks run from the cheapest to the most expensive one, the
+/// image is hashed last.
+pub fn verify(
+    header: &ImageHeader,
+    image_address: u32,
+    policy: &ImagePolicy,
+    read: &dyn Fn(u32, &mut [u8]),
+    verify_signature: &SignatureCheck<'_>,
+) -> Result<(), ImageError> {
+    if header.load_address != policy.load_address {
+        return Err(ImageError::WrongLoadAddress);
+    }
+    
+    if header.image_size == 0 || header.image_size > policy.max_size {
+        return Err(ImageError::InvalidSize);
+    }
+    
+    if header.security_version < policy.min_security_version {
+        return Err(ImageError::Downgrade);
+    }
+    
+    if !verify_signature(header.key_id, &header.signed_data(), &header.signature) {
+        return Err(ImageError::InvalidSignature);
+    }
+    
+    if hash(read, image_address, header.image_size) != header.image_hash {
+        return Err(ImageError::HashMismatch);
+    }
+    
+    Ok(())
+}
+
+/// Signed image error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageError {
+    InvalidHeader,
+    UnsupportedVersion,
+    UnsupportedAlgorithm,
+    WrongLoadAddress,
+    InvalidSize,
+    Downgrade,
+    InvalidSignature,
+    HashMismatch,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn ecdsa_headers_need_the_ecdsa_feature() {
+        let header = ImageHeader {
+            key_id: 2,
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            image_size: 0x1000,
+            security_version: 1,
+            load_address: 0x0001_0000,
+            image_hash: [0x5A; 32],
+            signature: [0xA5; IMAGE_SIGNATURE_LENGTH],
+        };
+        let mut data = header.to_bytes();
+        
+        let expected = if cfg!(feature = "ecdsa") { Ok(header) } else { Err(ImageError::UnsupportedAlgorithm) };
+        assert_eq!(ImageHeader::parse(&data), expected);
+        
+        // No other algorithm is known
+        data[6] = 0x01;
+        assert_eq!(ImageHeader::parse(&data), Err(ImageError::UnsupportedAlgorithm));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,411 @@
+use sha2::{Digest, Sha256};
+use super::nvm::{NvStorage, NVM_KEY_STORE_OFFSET};
+
+/// Key store record marker
+const STORE_MAGIC: [u8; 4] = *b"GKST";
+
+/// Key update marker
+const UPDATE_MAGIC: [u8; 4] = *b"GKUP";
+
+/// Layout version of the key store record and of key updates
+pub const KEY_STORE_VERSION: u8 = 1;
+
+/// Number of key slots
+pub const KEY_SLOT_COUNT: usize = 3;
+
+/// Highest key ID, one revocation bit per ID
+pub const MAX_KEY_ID: u8 = 31;
+
+/// Key ID of an empty slot in a key update
+pub const KEY_ID_NONE: u8 = 0xFF;
+
+/// Length of an uncompressed SEC1 P-256 public key
+pub const PUBLIC_KEY_LENGTH: usize = 65;
+
+/// Signature check with a public key: key, message, signature
+pub type KeyCheck<'a> = dyn Fn(&[u8; PUBLIC_KEY_LENGTH], &[u8], &[u8]) -> bool + 'a;
+
+/// Length of a key store record in bytes
+const STORE_RECORD_LENGTH: usize = 224;
+
+/// Bytes covered by the check value of a record (everything before it)
+const STORE_CHECK_OFFSET: usize = STORE_RECORD_LENGTH - 4;
+
+/// Length of a slot in the key store record
+const SLOT_RECORD_LENGTH: usize = 68;
+
+/// Distance of the two key store records in non-volatile storage
+pub const KEY_STORE_SLOT_SIZE: u32 = 256;
+
+/// Length of a key update
+pub const KEY_UPDATE_LENGTH: usize = 148;
+
+/// Bytes of a key update covered by its signature
+pub const KEY_UPDATE_SIGNED_LENGTH: usize = 84;
+
+/// Role of a key slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySlot {
+    /// Signs key updates, never images
+    Root,
+    /// Signs released images
+    Production,
+    /// Signs development images
+    Development,
+}
+
+impl KeySlot {
+    /// Decode a slot number
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Root),
+            0x01 => Some(Self::Production),
+            0x02 => Some(Self::Development),
+            _ => None,
+        }
+    }
+    
+    /// Index of the slot in the key store
+    pub fn index(self) -> usize {
+        match self {
+            Self::Root => 0,
+            Self::Production => 1,
+            Self::Development => 2,
+        }
+    }
+}
+
+/// Public key in a key slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEntry {
+    /// ID the image header and key updates refer to the key by
+    pub id: u8,
+    /// Uncompressed SEC1 P-256 public key
+    pub public_key: [u8; PUBLIC_KEY_LENGTH],
+}
+
+/// Verification keys and their revocation state
+///
+/// The key store built into stage 0 and the bootloader
+/// (`config::BUILTIN_KEYS`) holds until the first key update. From then
+/// on it is kept in the emulated EEPROM, in two records at
+/// `NVM_KEY_STORE_OFFSET` written alternately so that a power cut while
+/// storing leaves the previous one. A record is:
+///
+/// ```text
+/// 0   magic "GKST"
+/// 4   version (1), 3 reserved bytes
+/// 8   sequence of the last key update installed (u32 LE)
+/// 12  revoked key IDs (u32 LE, bit n for key ID n)
+/// 16  3 slots of 68 bytes (root, production, development):
+///     key ID, 1 if the slot holds a key, public key (65 bytes),
+///     1 reserved byte
+/// 220 first 4 bytes of the SHA-256 of bytes 0-219
+/// ```
+///
+/// Revocation bits are only ever set: a key update adds to them, and an
+/// update has to carry a higher sequence than the one installed, so an
+/// older update cannot bring a revoked key back.
+///
+/// The module only depends on `sha2` and the storage trait, stage 0 and
+/// the host tools share it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyStore {
+    /// Sequence of the last key update installed, 0 for the built-in keys
+    pub sequence: u32,
+    /// Revoked key IDs, bit n for key ID n
+    pub revoked: u32,
+    /// Keys by slot (`KeySlot::index`)
+    pub slots: [Option<KeyEntry>; KEY_SLOT_COUNT],
+}
+
+impl KeyStore {
+    /// Key store with the keys built into the image
+    pub const fn builtin(slots: [Option<KeyEntry>; KEY_SLOT_COUNT]) -> Self {
+        Self {
+            sequence: 0,
+            revoked: 0,
+            slots,
+        }
+    }
+    
+    /// Key in a slot
+    pub fn key(&self, slot: KeySlot) -> Option<&KeyEntry> {
+        self.slots[slot.index()].as_ref()
+    }
+    
+    /// Whether a key ID has been revoked
+    pub fn is_revoked(&self, key_id: u8) -> bool {
+        key_id > MAX_KEY_ID || self.revoked & (1 << key_id) != 0
+    }
+    
+    /// Public key an image signed with `key_id` is verified with
+    ///
+    /// Only production and development keys sign images.
+    pub fn image_key(&self, key_id: u8) -> Result<&[u8; PUBLIC_KEY_LENGTH], KeyError> {
+        if self.is_revoked(key_id) {
+            return Err(KeyError::Revoked);
+        }
+        
+        [KeySlot::Production, KeySlot::Development]
+            .iter()
+            .filter_map(|slot| self.key(*slot))
+            .find(|entry| entry.id == key_id)
+            .map(|entry| &entry.public_key)
+            .ok_or(KeyError::UnknownKey)
+    }
+    
+    /// Key store after installing `update`
+    ///
+    /// `verify_signature` checks a signature over a message with the given
+    /// public key. The update must be signed by the current, unrevoked
+    /// root key and leave a root key that is not revoked.
+    pub fn apply(
+        &self,
+        update: &KeyUpdate,
+        verify_signature: &KeyCheck<'_>,
+    ) -> Result<Self, KeyError> {
+        if update.sequence <= self.sequence {
+            return Err(KeyError::Replayed);
+        }
+        
+        let root = self.key(KeySlot::Root).ok_or(KeyError::UnknownKey)?;
+        if root.id != update.signer_id {
+            return Err(KeyError::UnknownKey);
+        }
+        if self.is_revoked(root.id) {
+            return Err(KeyError::Revoked);
+        }
+        if !verify_signature(&root.public_key, &update.signed_data(), &update.signature) {
+            return Err(KeyError::InvalidSignature);
+        }
+        
+        let mut store = *self;
+        store.sequence = update.sequence;
+        store.revoked |= update.revoke;
+        
+        if let Some(key) = update.key {
+            if key.id > MAX_KEY_ID || store.is_revoked(key.id) {
+                return Err(KeyError::Revoked);
+            }
+            
+            let in_use = store
+                .slots
+                .iter()
+                .enumerate()
+                .any(|(index, entry)| index != update.slot.index() && entry.is_some_and(|entry| entry.id == key.id));
+            if in_use {
+                return Err(KeyError::DuplicateKeyId);
+            }
+        } else if update.slot == KeySlot::Root {
+            return Err(KeyError::RootRequired);
+        }
+        store.slots[update.slot.index()] = update.key;
+        
+        // Without a usable root key no further update could be installed
+        match store.key(KeySlot::Root) {
+            Some(root) if !store.is_revoked(root.id) => Ok(store),
+            _ => Err(KeyError::RootRequired),
+        }
+    }
+    
+    /// Parse a key store record, `None` if it is empty, corrupted or of another version
+    fn parse(record: &[u8; STORE_RECORD_LENGTH]) -> Option<Self> {
+        if record[0..4] != STORE_MAGIC || record[STORE_CHECK_OFFSET..] != Sha256::digest(&record[..STORE_CHECK_OFFSET])[..4] {
+            return None;
+        }
+        
+        if record[4] != KEY_STORE_VERSION {
+            return None;
+        }
+        
+        let word = |offset: usize| {
+            u32::from_le_bytes([record[offset], record[offset + 1], record[offset + 2], record[offset + 3]])
+        };
+        
+        let mut slots = [None; KEY_SLOT_COUNT];
+        for (index, slot) in slots.iter_mut().enumerate() {
+            let data = &record[16 + index * SLOT_RECORD_LENGTH..16 + (index + 1) * SLOT_RECORD_LENGTH];
+            if data[1] == 1 {
+                let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+                public_key.copy_from_slice(&data[2..2 + PUBLIC_KEY_LENGTH]);
+                *slot = Some(KeyEntry { id: data[0], public_key });
+            }
+        }
+        
+        Some(Self {
+            sequence: word(8),
+            revoked: word(12),
+            slots,
+        })
+    }
+    
+    /// Serialize the key store into a record
+    fn to_record(self) -> [u8; STORE_RECORD_LENGTH] {
+        let mut record = [0u8; STORE_RECORD_LENGTH];
+        
+        record[0..4].copy_from_slice(&STORE_MAGIC);
+        record[4] = KEY_STORE_VERSION;
+        record[8..12].copy_from_slice(&self.sequence.to_le_bytes());
+        record[12..16].copy_from_slice(&self.revoked.to_le_bytes());
+        
+        for (index, entry) in self.slots.iter().enumerate() {
+            if let Some(entry) = entry {
+                let data = &mut record[16 + index * SLOT_RECORD_LENGTH..16 + (index + 1) * SLOT_RECORD_LENGTH];
+                data[0] = entry.id;
+                data[1] = 1;
+                data[2..2 + PUBLIC_KEY_LENGTH].copy_from_slice(&entry.public_key);
+            }
+        }
+        
+        let check = Sha256::digest(&record[..STORE_CHECK_OFFSET]);
+        record[STORE_CHECK_OFFSET..].copy_from_slice(&check[..4]);
+        
+        record
+    }
+}
+
+/// Signed change of one key slot
+///
+/// ```text
+/// 0   magic "GKUP"
+/// 4   version (1)
+/// 5   slot (0 root, 1 production, 2 development)
+/// 6   key ID of the new key, 0xFF to empty the slot
+/// 7   key ID of the root key signing the update
+/// 8   sequence (u32 LE), above the one of the installed key store
+/// 12  key IDs to revoke (u32 LE, bit n for key ID n)
+/// 16  new public key (65 bytes, uncompressed SEC1; 0xFF when emptying)
+/// 81  3 reserved bytes (0xFF)
+/// 84  ECDSA P-256 signature (r || s) of bytes 0-83 with the root key
+/// ```
+///
+/// Rotating the root key is an update of the root slot signed with the
+/// old root key, which may revoke itself in the same update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUpdate {
+    /// Slot to change
+    pub slot: KeySlot,
+    /// New key of the slot, `None` to empty it
+    pub key: Option<KeyEntry>,
+    /// Key ID of the root key signing the update
+    pub signer_id: u8,
+    /// Sequence, above the one of the installed key store
+    pub sequence: u32,
+    /// Key IDs to revoke, bit n for key ID n
+    pub revoke: u32,
+    /// Signature of the first `KEY_UPDATE_SIGNED_LENGTH` bytes
+    pub signature: [u8; 64],
+}
+
+impl KeyUpdate {
+    /// Parse a key update
+    pub fn parse(data: &[u8; KEY_UPDATE_LENGTH]) -> Result<Self, KeyError> {
+        if data[0..4] != UPDATE_MAGIC || data[4] != KEY_STORE_VERSION {
+            return Err(KeyError::InvalidFormat);
+        }
+        
+        let slot = KeySlot::from_byte(data[5]).ok_or(KeyError::InvalidFormat)?;
+        let key = match data[6] {
+            KEY_ID_NONE => None,
+            id => {
+                let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+                public_key.copy_from_slice(&data[16..16 + PUBLIC_KEY_LENGTH]);
+                Some(KeyEntry { id, public_key })
+            }
+        };
+        
+        let word = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&data[KEY_UPDATE_SIGNED_LENGTH..]);
+        
+        Ok(Self {
+            slot,
+            key,
+            signer_id: data[7],
+            sequence: word(8),
+            revoke: word(12),
+            signature,
+        })
+    }
+    
+    /// Bytes covered by the signature
+    pub fn signed_data(&self) -> [u8; KEY_UPDATE_SIGNED_LENGTH] {
+        let mut data = [0xFF; KEY_UPDATE_SIGNED_LENGTH];
+        data[0..4].copy_from_slice(&UPDATE_MAGIC);
+        data[4] = KEY_STORE_VERSION;
+        data[5] = self.slot.index() as u8;
+        data[6] = self.key.map_or(KEY_ID_NONE, |key| key.id);
+        data[7] = self.signer_id;
+        data[8..12].copy_from_slice(&self.sequence.to_le_bytes());
+        data[12..16].copy_from_slice(&self.revoke.to_le_bytes());
+        if let Some(key) = self.key {
+            data[16..16 + PUBLIC_KEY_LENGTH].copy_from_slice(&key.public_key);
+        }
+        data
+    }
+    
+    /// Serialize the key update
+    pub fn to_bytes(self) -> [u8; KEY_UPDATE_LENGTH] {
+        let mut data = [0u8; KEY_UPDATE_LENGTH];
+        data[..KEY_UPDATE_SIGNED_LENGTH].copy_from_slice(&self.signed_data());
+        data[KEY_UPDATE_SIGNED_LENGTH..].copy_from_slice(&self.signature);
+        data
+    }
+}
+
+/// Read the key store record in one of the two slots
+fn load_slot(storage: &dyn NvStorage, slot: u32) -> Option<KeyStore> {
+    let mut record = [0u8; STORE_RECORD_LENGTH];
+    storage.read(NVM_KEY_STORE_OFFSET + slot * KEY_STORE_SLOT_SIZE, &mut record).ok()?;
+    KeyStore::parse(&record)
+}
+
+/// Load the installed key store, the built-in one if no key update was stored
+pub fn load(storage: &dyn NvStorage, builtin: &KeyStore) -> KeyStore {
+    match (load_slot(storage, 0), load_slot(storage, 1)) {
+        (Some(first), Some(second)) => if second.sequence > first.sequence { second } else { first },
+        (Some(store), None) | (None, Some(store)) => store,
+        (None, None) => *builtin,
+    }
+}
+
+/// Verify `update` and store the resulting key store
+///
+/// The record is written over the older of the two records.
+pub fn install(
+    storage: &mut dyn NvStorage,
+    builtin: &KeyStore,
+    update: &KeyUpdate,
+    verify_signature: &KeyCheck<'_>,
+) -> Result<KeyStore, KeyError> {
+    let store = load(storage, builtin).apply(update, verify_signature)?;
+    
+    let slot = match (load_slot(storage, 0), load_slot(storage, 1)) {
+        (Some(first), Some(second)) => if first.sequence > second.sequence { 1 } else { 0 },
+        (Some(_), None) => 1,
+        _ => 0,
+    };
+    
+    storage
+        .write(NVM_KEY_STORE_OFFSET + slot * KEY_STORE_SLOT_SIZE, &store.to_record())
+        .map_err(|_| KeyError::StorageError)?;
+    
+    Ok(store)
+}
+
+/// Key management error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyError {
+    InvalidFormat,
+    UnknownKey,
+    Revoked,
+    InvalidSignature,
+    Replayed,
+    DuplicateKeyId,
+    RootRequired,
+    StorageError,
+}
\ No newline at end of file
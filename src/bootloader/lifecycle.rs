@@ -0,0 +1,275 @@
+use sha2::{Digest, Sha256};
+use super::nvm::{NvStorage, NVM_IDENTITY_OFFSET, NVM_LIFECYCLE_OFFSET};
+use super::secure_boot::{self, SecureBootEngine, SecureBootError};
+use crate::hal::s32k148::csec::{BootFlavor, CsecError, KeyUpdateMessages, KeyUpdateProof, CSEC_BOOT_MAC, CSEC_BOOT_MAC_KEY};
+
+/// Marker of a valid lifecycle record
+const LIFECYCLE_MAGIC: u16 = 0x4C43;
+
+/// Length of the lifecycle record in bytes
+const LIFECYCLE_RECORD_LENGTH: usize = 4;
+
+/// Device identity record marker
+const IDENTITY_MAGIC: [u8; 4] = *b"GDID";
+
+/// Length of the ECU serial number
+pub const SERIAL_NUMBER_LENGTH: usize = 16;
+
+/// Length of the device configuration
+pub const CONFIGURATION_LENGTH: usize = 16;
+
+/// Length of the device identity written by the provisioning routine
+pub const IDENTITY_LENGTH: usize = SERIAL_NUMBER_LENGTH + CONFIGURATION_LENGTH;
+
+/// Length of the device identity record in bytes
+const IDENTITY_RECORD_LENGTH: usize = 4 + IDENTITY_LENGTH + 4;
+
+/// Bytes covered by the check value of the identity record
+const IDENTITY_CHECK_OFFSET: usize = IDENTITY_RECORD_LENGTH - 4;
+
+/// Lifecycle state of the device
+///
+/// The state only moves forward. Keys, identity and configuration can be
+/// provisioned in development and production; `Locked` closes the
+/// lifecycle for good and the bootloader refuses any further provisioning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleState {
+    /// Fresh device, as delivered by the semiconductor vendor
+    Development,
+    /// End of line: the device is being personalized
+    Production,
+    /// Lifecycle closed, provisioning refused
+    Locked,
+}
+
+impl LifecycleState {
+    /// Decode a state byte
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Development),
+            0x01 => Some(Self::Production),
+            0x02 => Some(Self::Locked),
+            _ => None,
+        }
+    }
+    
+    /// Encode the state byte
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Development => 0x00,
+            Self::Production => 0x01,
+            Self::Locked => 0x02,
+        }
+    }
+    
+    /// State the device may move to from this one
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Development => Some(Self::Production),
+            Self::Production => Some(Self::Locked),
+            Self::Locked => None,
+        }
+    }
+    
+    /// Check if keys and identity may still be provisioned
+    pub fn is_open(self) -> bool {
+        self != Self::Locked
+    }
+}
+
+/// Identity written at the end of line
+///
+/// Stored at `NVM_IDENTITY_OFFSET` as:
+///
+/// ```text
+/// 0   magic "GDID"
+/// 4   ECU serial number (16 bytes)
+/// 20  device configuration (16 bytes)
+/// 36  first 4 bytes of the SHA-256 of bytes 0-35
+/// ```
+///
+/// Both fields are opaque to the bootloader, the serial number is
+/// usually ASCII padded with spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    /// ECU serial number
+    pub serial_number: [u8; SERIAL_NUMBER_LENGTH],
+    /// Device configuration (variant coding)
+    pub configuration: [u8; CONFIGURATION_LENGTH],
+}
+
+impl DeviceIdentity {
+    /// Parse the identity as sent to the provisioning routine
+    pub fn parse(data: &[u8; IDENTITY_LENGTH]) -> Self {
+        let mut serial_number = [0u8; SERIAL_NUMBER_LENGTH];
+        let mut configuration = [0u8; CONFIGURATION_LENGTH];
+        serial_number.copy_from_slice(&data[..SERIAL_NUMBER_LENGTH]);
+        configuration.copy_from_slice(&data[SERIAL_NUMBER_LENGTH..]);
+        
+        Self { serial_number, configuration }
+    }
+    
+    /// Serialize the identity
+    pub fn to_bytes(self) -> [u8; IDENTITY_LENGTH] {
+        let mut data = [0u8; IDENTITY_LENGTH];
+        data[..SERIAL_NUMBER_LENGTH].copy_from_slice(&self.serial_number);
+        data[SERIAL_NUMBER_LENGTH..].copy_from_slice(&self.configuration);
+        data
+    }
+    
+    /// Parse an identity record, `None` if it is empty or corrupted
+    fn from_record(record: &[u8; IDENTITY_RECORD_LENGTH]) -> Option<Self> {
+        if record[0..4] != IDENTITY_MAGIC || record[IDENTITY_CHECK_OFFSET..] != Sha256::digest(&record[..IDENTITY_CHECK_OFFSET])[..4] {
+            return None;
+        }
+        
+        let mut data = [0u8; IDENTITY_LENGTH];
+        data.copy_from_slice(&record[4..IDENTITY_CHECK_OFFSET]);
+        Some(Self::parse(&data))
+    }
+    
+    /// Serialize the identity into a record
+    fn to_record(self) -> [u8; IDENTITY_RECORD_LENGTH] {
+        let mut record = [0u8; IDENTITY_RECORD_LENGTH];
+        
+        record[0..4].copy_from_slice(&IDENTITY_MAGIC);
+        record[4..IDENTITY_CHECK_OFFSET].copy_from_slice(&self.to_bytes());
+        
+        let check = Sha256::digest(&record[..IDENTITY_CHECK_OFFSET]);
+        record[IDENTITY_CHECK_OFFSET..].copy_from_slice(&check[..4]);
+        
+        record
+    }
+}
+
+/// Read the lifecycle state
+///
+/// The record is magic (u16 BE), state, !state: one word, which the
+/// EEPROM emulation writes atomically. A device that never stored one is
+/// in development; a corrupted record counts as locked, so that damaging
+/// it cannot reopen provisioning.
+pub fn load_state(storage: &dyn NvStorage) -> LifecycleState {
+    let mut record = [0u8; LIFECYCLE_RECORD_LENGTH];
+    if storage.read(NVM_LIFECYCLE_OFFSET, &mut record).is_err() {
+        return LifecycleState::Locked;
+    }
+    
+    if record == [0xFF; LIFECYCLE_RECORD_LENGTH] {
+        // Never written
+        return LifecycleState::Development;
+    }
+    
+    let magic = u16::from_be_bytes([record[0], record[1]]);
+    if magic != LIFECYCLE_MAGIC || record[2] != !record[3] {
+        return LifecycleState::Locked;
+    }
+    
+    LifecycleState::from_byte(record[2]).unwrap_or(LifecycleState::Locked)
+}
+
+/// Move the device to the next lifecycle state
+///
+/// Only the next state is accepted, and locking needs the identity to be
+/// written. The record is read back so that a failed write is reported.
+pub fn advance(storage: &mut dyn NvStorage, target: LifecycleState) -> Result<(), LifecycleError> {
+    let current = load_state(storage);
+    if !current.is_open() {
+        return Err(LifecycleError::Closed);
+    }
+    if current.next() != Some(target) {
+        return Err(LifecycleError::InvalidTransition);
+    }
+    if target == LifecycleState::Locked && load_identity(storage).is_none() {
+        return Err(LifecycleError::NotPersonalized);
+    }
+    
+    let magic = LIFECYCLE_MAGIC.to_be_bytes();
+    let state = target.to_byte();
+    storage
+        .write(NVM_LIFECYCLE_OFFSET, &[magic[0], magic[1], state, !state])
+        .map_err(|_| LifecycleError::StorageError)?;
+    
+    if load_state(storage) != target {
+        return Err(LifecycleError::StorageError);
+    }
+    
+    Ok(())
+}
+
+/// Read the device identity, `None` if it was not written
+pub fn load_identity(storage: &dyn NvStorage) -> Option<DeviceIdentity> {
+    let mut record = [0u8; IDENTITY_RECORD_LENGTH];
+    storage.read(NVM_IDENTITY_OFFSET, &mut record).ok()?;
+    DeviceIdentity::from_record(&record)
+}
+
+/// Store the device identity, replacing a previous one until the lifecycle is closed
+pub fn write_identity(storage: &mut dyn NvStorage, identity: &DeviceIdentity) -> Result<(), LifecycleError> {
+    if !load_state(storage).is_open() {
+        return Err(LifecycleError::Closed);
+    }
+    
+    storage
+        .write(NVM_IDENTITY_OFFSET, &identity.to_record())
+        .map_err(|_| LifecycleError::StorageError)?;
+    
+    if load_identity(storage) != Some(*identity) {
+        return Err(LifecycleError::StorageError);
+    }
+    
+    Ok(())
+}
+
+/// Load a SHE key with the key update protocol until the lifecycle is closed
+///
+/// Any key slot may be loaded, the engine checks the authorization.
+/// Storing the secure boot keys also configures secure boot as the key
+/// routine of `secure_boot` does.
+pub fn provision_key(
+    storage: &dyn NvStorage,
+    engine: &mut dyn SecureBootEngine,
+    messages: &KeyUpdateMessages,
+    size: u32,
+    flavor: BootFlavor,
+) -> Result<KeyUpdateProof, LifecycleError> {
+    if !load_state(storage).is_open() {
+        return Err(LifecycleError::Closed);
+    }
+    
+    let key_id = messages.key_id();
+    if flavor != BootFlavor::NoBoot && (key_id == CSEC_BOOT_MAC_KEY || key_id == CSEC_BOOT_MAC) {
+        return Ok(secure_boot::store_key(engine, messages, size, flavor)?);
+    }
+    
+    Ok(engine.load_key(messages)?)
+}
+
+/// Lifecycle and provisioning error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleError {
+    Closed,
+    InvalidTransition,
+    NotPersonalized,
+    KeyRejected,
+    KeyWriteProtected,
+    EngineFault,
+    StorageError,
+}
+
+impl From<SecureBootError> for LifecycleError {
+    fn from(error: SecureBootError) -> Self {
+        match error {
+            SecureBootError::KeyRejected => LifecycleError::KeyRejected,
+            SecureBootError::KeyWriteProtected => LifecycleError::KeyWriteProtected,
+            SecureBootError::Disabled | SecureBootError::WrongKeySlot | SecureBootError::EngineFault => {
+                LifecycleError::EngineFault
+            },
+        }
+    }
+}
+
+impl From<CsecError> for LifecycleError {
+    fn from(error: CsecError) -> Self {
+        SecureBootError::from(error).into()
+    }
+}
\ No newline at end of file
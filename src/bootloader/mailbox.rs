@@ -0,0 +1,314 @@
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use defmt::{info, warn};
+use crate::bootloader::verification::crc32;
+use crate::config::MAILBOX_LENGTH;
+use crate::drivers::power::ResetCause;
+use crate::protocol::uds::permissions::AddressingMode;
+
+/// Marker of a filled mailbox ("BLMB")
+const MAILBOX_MAGIC: u32 = 0x424C_4D42;
+
+/// Layout version of the mailbox frame
+pub const MAILBOX_VERSION: u8 = 1;
+
+/// Size of the mailbox frame in bytes
+pub const MAILBOX_FRAME_LENGTH: usize = 32;
+
+// The frame must fit the MAILBOX region reserved in memory.x
+const _: () = assert!(MAILBOX_FRAME_LENGTH as u32 <= MAILBOX_LENGTH);
+
+/// Bytes covered by the CRC (everything before it)
+const MAILBOX_CRC_OFFSET: usize = MAILBOX_FRAME_LENGTH - 4;
+
+/// The tester set suppressPosRspMsgIndicationBit, no response is sent
+pub const MAILBOX_FLAG_SUPPRESS_RESPONSE: u8 = 1 << 0;
+
+/// Mailbox frame in the `.bootloader_data` no-init section
+///
+/// Placed in the `MAILBOX` region of memory.x at `config::MAILBOX_START`
+/// so that the application can fill it before resetting. The frame is:
+///
+/// | Offset | Size | Content                                   |
+/// |--------|------|-------------------------------------------|
+/// | 0      | 4    | magic `MAILBOX_MAGIC` (LE)                |
+/// | 4      | 1    | version `MAILBOX_VERSION`                 |
+/// | 5      | 1    | request type (`HandoffRequestType`)       |
+/// | 6      | 1    | diagnostic session requested              |
+/// | 7      | 1    | security level unlocked (0 if locked)     |
+/// | 8      | 4    | tester request CAN identifier (LE)        |
+/// | 12     | 4    | tester response CAN identifier (LE)       |
+/// | 16     | 1    | addressing mode (0 physical, 1 functional)|
+/// | 17     | 1    | flags (`MAILBOX_FLAG_*`)                  |
+/// | 18     | 10   | reserved (0)                              |
+/// | 28     | 4    | CRC-32 of bytes 0-27 (LE)                 |
+#[link_section = ".bootloader_data"]
+#[used]
+static mut MAILBOX: MaybeUninit<[u8; MAILBOX_FRAME_LENGTH]> = MaybeUninit::uninit();
+
+/// Request passed from the application to the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandoffRequestType {
+    /// DiagnosticSessionControl(programming) received by the application
+    EnterProgramming,
+}
+
+impl HandoffRequestType {
+    /// Decode the request type byte
+    fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::EnterProgramming),
+            _ => None,
+        }
+    }
+    
+    /// Encode the request type byte
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::EnterProgramming => 0x01,
+        }
+    }
+}
+
+/// Addressing of the tester that sent the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TesterAddress {
+    /// CAN identifier the tester sent the request on (bit 31 marks an extended identifier)
+    pub request_id: u32,
+    /// CAN identifier the response is expected on (bit 31 marks an extended identifier)
+    pub response_id: u32,
+    /// Addressing mode of the request
+    pub addressing: AddressingMode,
+}
+
+/// Context handed over from the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandoffRequest {
+    /// What the application requests
+    pub request: HandoffRequestType,
+    /// Diagnostic session requested by the tester
+    pub session: u8,
+    /// Security level unlocked in the application (0 if locked)
+    pub security_level: u8,
+    /// Tester addressing for the delayed response
+    pub tester: TesterAddress,
+    /// Flags (`MAILBOX_FLAG_*`)
+    pub flags: u8,
+}
+
+impl HandoffRequest {
+    /// Check if a response must be sent for the request
+    pub fn response_required(&self) -> bool {
+        self.flags & MAILBOX_FLAG_SUPPRESS_RESPONSE == 0
+    }
+    
+    /// Parse a mailbox frame, `None` if it is empty, corrupted or of another version
+    pub fn parse(frame: &[u8; MAILBOX_FRAME_LENGTH]) -> Option<Self> {
+        let word = |offset: usize| {
+            u32::from_le_bytes([frame[offset], frame[offset + 1], frame[offset + 2], frame[offset + 3]])
+        };
+        
+        if word(0) != MAILBOX_MAGIC || word(MAILBOX_CRC_OFFSET) != crc32(&frame[..MAILBOX_CRC_OFFSET]) {
+            return None;
+        }
+        
+        if frame[4] != MAILBOX_VERSION {
+            warn!("Mailbox version {} not supported", frame[4]);
+            return None;
+        }
+        
+        let addressing = match frame[16] {
+            0 => AddressingMode::Physical,
+            1 => AddressingMode::Functional,
+            _ => return None,
+        };
+        
+        Some(Self {
+            request: HandoffRequestType::from_byte(frame[5])?,
+            session: frame[6],
+            security_level: frame[7],
+            tester: TesterAddress {
+                request_id: word(8),
+                response_id: word(12),
+                addressing,
+            },
+            flags: frame[17],
+        })
+    }
+    
+    /// Serialize the request into a mailbox frame
+    pub fn to_frame(self) -> [u8; MAILBOX_FRAME_LENGTH] {
+        let mut frame = [0u8; MAILBOX_FRAME_LENGTH];
+        
+        frame[0..4].copy_from_slice(&MAILBOX_MAGIC.to_le_bytes());
+        frame[4] = MAILBOX_VERSION;
+        frame[5] = self.request.to_byte();
+        frame[6] = self.session;
+        frame[7] = self.security_level;
+        frame[8..12].copy_from_slice(&self.tester.request_id.to_le_bytes());
+        frame[12..16].copy_from_slice(&self.tester.response_id.to_le_bytes());
+        frame[16] = match self.tester.addressing {
+            AddressingMode::Physical => 0,
+            AddressingMode::Functional => 1,
+        };
+        frame[17] = self.flags;
+        
+        let crc = crc32(&frame[..MAILBOX_CRC_OFFSET]);
+        frame[MAILBOX_CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        
+        frame
+    }
+}
+
+/// Take the request left by the application, if any
+///
+/// The mailbox is cleared in any case so that a request is acted on once.
+/// Only a software reset can carry a request: after power-on or brown-out
+/// the RAM content (and its ECC) is undefined and is not read at all.
+pub fn take(reset_cause: ResetCause) -> Option<HandoffRequest> {
+    let request = match reset_cause {
+        ResetCause::Software => HandoffRequest::parse(&read_frame()),
+        _ => None,
+    };
+    
+    clear();
+    
+    if let Some(request) = request {
+        info!(
+            "Mailbox request {} for session 0x{:02X} from tester 0x{:X}",
+            defmt::Debug2Format(&request.request),
+            request.session,
+            request.tester.request_id
+        );
+    }
+    
+    request
+}
+
+/// Leave a request for the next start of the bootloader
+///
+/// Used by the application before it triggers a software reset.
+pub fn write(request: &HandoffRequest) {
+    write_frame(&request.to_frame());
+}
+
+/// Invalidate the mailbox
+pub fn clear() {
+    write_frame(&[0u8; MAILBOX_FRAME_LENGTH]);
+}
+
+/// Read the raw mailbox frame
+fn read_frame() -> [u8; MAILBOX_FRAME_LENGTH] {
+    // Safety: the mailbox is only accessed from thread mode and any bit
+    // pattern is a valid byte array
+    unsafe { read_volatile(addr_of!(MAILBOX) as *const [u8; MAILBOX_FRAME_LENGTH]) }
+}
+
+/// Write the raw mailbox frame
+fn write_frame(frame: &[u8; MAILBOX_FRAME_LENGTH]) {
+    // Safety: the mailbox is only accessed from thread mode
+    unsafe { write_volatile(addr_of_mut!(MAILBOX) as *mut [u8; MAILBOX_FRAME_LENGTH], *frame) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn request() -> HandoffRequest {
+        HandoffRequest {
+            request: HandoffRequestType::EnterProgramming,
+            session: 0x02,
+            security_level: 0x03,
+            tester: TesterAddress {
+                request_id: 0x8000_18DA_u32,
+                response_id: 0x0000_07E8,
+                addressing: AddressingMode::Functional,
+            },
+            flags: MAILBOX_FLAG_SUPPRESS_RESPONSE,
+        }
+    }
+    
+    /// Frame of `request()` with `edit` applied and the CRC recomputed
+    fn frame_with(edit: impl FnOnce(&mut [u8; MAILBOX_FRAME_LENGTH])) -> [u8; MAILBOX_FRAME_LENGTH] {
+        let mut frame = request().to_frame();
+        edit(&mut frame);
+        let crc = crc32(&frame[..MAILBOX_CRC_OFFSET]);
+        frame[MAILBOX_CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        frame
+    }
+    
+    #[test]
+    fn round_trip() {
+        assert_eq!(HandoffRequest::parse(&request().to_frame()), Some(request()));
+        
+        let physical = HandoffRequest {
+            tester: TesterAddress {
+                addressing: AddressingMode::Physical,
+                ..request().tester
+            },
+            flags: 0,
+            ..request()
+        };
+        assert_eq!(HandoffRequest::parse(&physical.to_frame()), Some(physical));
+        assert!(physical.response_required());
+        assert!(!request().response_required());
+    }
+    
+    #[test]
+    fn corrupted_crc() {
+        let frame = request().to_frame();
+        
+        // Any flipped bit, CRC included, invalidates the frame
+        for bit in 0..MAILBOX_FRAME_LENGTH * 8 {
+            let mut corrupted = frame;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert_eq!(HandoffRequest::parse(&corrupted), None, "bit {bit}");
+        }
+        
+        assert_eq!(HandoffRequest::parse(&[0; MAILBOX_FRAME_LENGTH]), None);
+        assert_eq!(HandoffRequest::parse(&[0xFF; MAILBOX_FRAME_LENGTH]), None);
+    }
+    
+    #[test]
+    fn wrong_magic_or_version() {
+        assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[0] ^= 0x01)), None);
+        assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[4] = MAILBOX_VERSION + 1)), None);
+        assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[4] = 0)), None);
+    }
+    
+    #[test]
+    fn invalid_fields() {
+        for addressing in 2..=0xFF {
+            assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[16] = addressing)), None);
+        }
+        
+        assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[5] = 0x00)), None);
+        assert_eq!(HandoffRequest::parse(&frame_with(|frame| frame[5] = 0x02)), None);
+    }
+    
+    #[test]
+    fn take_only_after_software_reset() {
+        // The single test using the mailbox RAM, tests run in parallel
+        for cause in [
+            ResetCause::Unknown,
+            ResetCause::PowerOn,
+            ResetCause::External,
+            ResetCause::Watchdog,
+            ResetCause::Lockup,
+            ResetCause::Jtag,
+            ResetCause::LowVoltage,
+        ] {
+            write(&request());
+            assert_eq!(take(cause), None, "{cause:?}");
+            
+            // The request is discarded, not kept for a later reset
+            assert_eq!(take(ResetCause::Software), None, "{cause:?}");
+        }
+        
+        write(&request());
+        assert_eq!(take(ResetCause::Software), Some(request()));
+        
+        // A request is acted on once
+        assert_eq!(take(ResetCause::Software), None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,377 @@
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use sha2::{Digest, Sha256};
+
+/// Marker of a measurement log record ("MLOG")
+const LOG_MAGIC: u32 = 0x474F_4C4D;
+
+/// Layout version of the measurement log record
+pub const LOG_VERSION: u8 = 1;
+
+/// Most measurements the log holds
+pub const MAX_MEASUREMENTS: usize = 4;
+
+/// Size of a serialized measurement
+pub const MEASUREMENT_LENGTH: usize = 40;
+
+/// Size of the measurement log record in bytes
+pub const LOG_RECORD_LENGTH: usize = 256;
+
+/// Offset of the first measurement in the record
+const LOG_ENTRIES_OFFSET: usize = 40;
+
+/// Bytes covered by the check value (everything before it)
+const LOG_CHECK_OFFSET: usize = LOG_RECORD_LENGTH - 4;
+
+/// Marker of the message authenticated by an attestation report ("GATR")
+const REPORT_MAGIC: [u8; 4] = *b"GATR";
+
+/// Format version of the attestation report
+pub const REPORT_VERSION: u8 = 1;
+
+/// Length of the nonce chosen by the verifier
+pub const ATTESTATION_NONCE_LENGTH: usize = 16;
+
+/// Length of the report MAC (AES-128 CMAC)
+pub const ATTESTATION_MAC_LENGTH: usize = 16;
+
+/// Length of the message covered by the report MAC
+pub const REPORT_MESSAGE_LENGTH: usize = 56;
+
+/// Length of a serialized report (count, chain and MAC)
+pub const REPORT_LENGTH: usize = 1 + 32 + ATTESTATION_MAC_LENGTH;
+
+/// Measurement log in the `.boot_measurements` no-init section
+///
+/// Placed in the `MEASUREMENTS` region of memory.x at
+/// `config::MEASUREMENT_START`, outside the RAM of the bootloader and of
+/// the application, which finds the log of its own start there. The
+/// bootloader writes it from scratch early at every start, so whatever a
+/// previous application left there never ends up in a report. The record
+/// is:
+///
+/// | Offset | Size | Content                                        |
+/// |--------|------|------------------------------------------------|
+/// | 0      | 4    | magic `LOG_MAGIC` (LE)                         |
+/// | 4      | 1    | version `LOG_VERSION`                          |
+/// | 5      | 1    | number of measurements                         |
+/// | 6      | 2    | reserved (0)                                   |
+/// | 8      | 32   | hash chain over the measurements               |
+/// | 40     | 160  | `MAX_MEASUREMENTS` measurements of 40 bytes    |
+/// | 200    | 52   | reserved (0)                                   |
+/// | 252    | 4    | first 4 bytes of the SHA-256 of bytes 0-251    |
+///
+/// A measurement is the stage byte (`MeasuredStage`), 3 reserved bytes
+/// (0), the security version of the image (u32 LE, 0 for images without
+/// one) and the SHA-256 of the image.
+///
+/// The module only depends on `sha2`, the host tools share it.
+#[link_section = ".boot_measurements"]
+#[used]
+static mut LOG: MaybeUninit<[u8; LOG_RECORD_LENGTH]> = MaybeUninit::uninit();
+
+/// Boot stage a measurement was taken of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeasuredStage {
+    /// Bootloader, measured by stage 0 (or by itself without stage 0)
+    Bootloader,
+    /// Application, measured by the bootloader before it is started
+    Application,
+}
+
+impl MeasuredStage {
+    /// Decode the stage byte
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::Bootloader),
+            0x02 => Some(Self::Application),
+            _ => None,
+        }
+    }
+    
+    /// Encode the stage byte
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Bootloader => 0x01,
+            Self::Application => 0x02,
+        }
+    }
+}
+
+/// SHA-256 of one boot stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    /// Stage the image belongs to
+    pub stage: MeasuredStage,
+    /// Security version of the image, 0 if it has none
+    pub security_version: u32,
+    /// SHA-256 of the image
+    pub digest: [u8; 32],
+}
+
+impl Measurement {
+    /// Parse a serialized measurement
+    pub fn parse(data: &[u8; MEASUREMENT_LENGTH]) -> Option<Self> {
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&data[8..]);
+        
+        Some(Self {
+            stage: MeasuredStage::from_byte(data[0])?,
+            security_version: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            digest,
+        })
+    }
+    
+    /// Serialize the measurement, as it is hashed into the chain
+    pub fn to_bytes(self) -> [u8; MEASUREMENT_LENGTH] {
+        let mut data = [0u8; MEASUREMENT_LENGTH];
+        data[0] = self.stage.to_byte();
+        data[4..8].copy_from_slice(&self.security_version.to_le_bytes());
+        data[8..].copy_from_slice(&self.digest);
+        data
+    }
+}
+
+/// Extend a hash chain by one measurement
+///
+/// `chain' = SHA-256(chain || measurement)` with the serialized
+/// measurement, the chain starts at 32 zero bytes. Like a TPM PCR, the result commits to
+/// every measurement and to their order.
+pub fn extend_chain(chain: &[u8; 32], measurement: &Measurement) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(chain);
+    hasher.update(measurement.to_bytes());
+    hasher.finalize().into()
+}
+
+/// Hash chain over a list of measurements
+///
+/// What a verifier computes from the measurements it read to check them
+/// against the chain of a report.
+pub fn replay(measurements: &[Measurement]) -> [u8; 32] {
+    measurements.iter().fold([0u8; 32], |chain, measurement| extend_chain(&chain, measurement))
+}
+
+/// Measurements of the current start and their hash chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasurementLog {
+    chain: [u8; 32],
+    count: usize,
+    measurements: [Measurement; MAX_MEASUREMENTS],
+}
+
+impl MeasurementLog {
+    /// Create an empty log
+    pub const fn new() -> Self {
+        Self {
+            chain: [0u8; 32],
+            count: 0,
+            measurements: [Measurement {
+                stage: MeasuredStage::Bootloader,
+                security_version: 0,
+                digest: [0u8; 32],
+            }; MAX_MEASUREMENTS],
+        }
+    }
+    
+    /// Append a measurement and extend the chain with it
+    pub fn extend(&mut self, measurement: Measurement) -> Result<(), MeasurementError> {
+        if self.count == MAX_MEASUREMENTS {
+            return Err(MeasurementError::LogFull);
+        }
+        
+        self.chain = extend_chain(&self.chain, &measurement);
+        self.measurements[self.count] = measurement;
+        self.count += 1;
+        
+        Ok(())
+    }
+    
+    /// Measurements in the order they were taken
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements[..self.count]
+    }
+    
+    /// Hash chain over all measurements
+    pub fn chain(&self) -> &[u8; 32] {
+        &self.chain
+    }
+    
+    /// Parse a log record, `None` if it is empty, corrupted or of another version
+    ///
+    /// The chain must match the measurements it was built from.
+    pub fn parse(record: &[u8; LOG_RECORD_LENGTH]) -> Option<Self> {
+        let magic = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+        if magic != LOG_MAGIC || record[LOG_CHECK_OFFSET..] != Sha256::digest(&record[..LOG_CHECK_OFFSET])[..4] {
+            return None;
+        }
+        
+        if record[4] != LOG_VERSION || record[5] as usize > MAX_MEASUREMENTS {
+            return None;
+        }
+        
+        let mut log = Self::new();
+        for i in 0..record[5] as usize {
+            let offset = LOG_ENTRIES_OFFSET + i * MEASUREMENT_LENGTH;
+            let mut data = [0u8; MEASUREMENT_LENGTH];
+            data.copy_from_slice(&record[offset..offset + MEASUREMENT_LENGTH]);
+            log.extend(Measurement::parse(&data)?).ok()?;
+        }
+        
+        if record[8..40] != log.chain {
+            return None;
+        }
+        
+        Some(log)
+    }
+    
+    /// Serialize the log into a record
+    pub fn to_record(&self) -> [u8; LOG_RECORD_LENGTH] {
+        let mut record = [0u8; LOG_RECORD_LENGTH];
+        
+        record[0..4].copy_from_slice(&LOG_MAGIC.to_le_bytes());
+        record[4] = LOG_VERSION;
+        record[5] = self.count as u8;
+        record[8..40].copy_from_slice(&self.chain);
+        
+        for (i, measurement) in self.measurements().iter().enumerate() {
+            let offset = LOG_ENTRIES_OFFSET + i * MEASUREMENT_LENGTH;
+            record[offset..offset + MEASUREMENT_LENGTH].copy_from_slice(&measurement.to_bytes());
+        }
+        
+        let check = Sha256::digest(&record[..LOG_CHECK_OFFSET]);
+        record[LOG_CHECK_OFFSET..].copy_from_slice(&check[..4]);
+        
+        record
+    }
+}
+
+impl Default for MeasurementLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Attestation report over the measurements of the current start
+///
+/// The MAC is an AES-128 CMAC with the device attestation key over:
+///
+/// ```text
+/// 0   magic "GATR"
+/// 4   version (1)
+/// 5   number of measurements
+/// 6   2 reserved bytes (0)
+/// 8   nonce chosen by the verifier (16 bytes)
+/// 24  hash chain (32 bytes)
+/// ```
+///
+/// The verifier reads the measurements, replays the chain (`replay`),
+/// and checks the MAC over the message built from its nonce and that
+/// chain. A fresh nonce keeps a recorded report from being replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttestationReport {
+    /// Number of measurements covered by the chain
+    pub count: u8,
+    /// Hash chain over the measurements
+    pub chain: [u8; 32],
+    /// CMAC of `message`
+    pub mac: [u8; ATTESTATION_MAC_LENGTH],
+}
+
+impl AttestationReport {
+    /// Message covered by the MAC of a report
+    pub fn message(nonce: &[u8; ATTESTATION_NONCE_LENGTH], count: u8, chain: &[u8; 32]) -> [u8; REPORT_MESSAGE_LENGTH] {
+        let mut message = [0u8; REPORT_MESSAGE_LENGTH];
+        message[0..4].copy_from_slice(&REPORT_MAGIC);
+        message[4] = REPORT_VERSION;
+        message[5] = count;
+        message[8..24].copy_from_slice(nonce);
+        message[24..56].copy_from_slice(chain);
+        message
+    }
+    
+    /// Create the report for `log` and the verifier's nonce
+    ///
+    /// `generate_mac` computes the CMAC of a message with the device key.
+    pub fn create(
+        log: &MeasurementLog,
+        nonce: &[u8; ATTESTATION_NONCE_LENGTH],
+        generate_mac: &mut dyn FnMut(&[u8]) -> Option<[u8; ATTESTATION_MAC_LENGTH]>,
+    ) -> Result<Self, MeasurementError> {
+        let count = log.measurements().len() as u8;
+        let mac = generate_mac(&Self::message(nonce, count, log.chain())).ok_or(MeasurementError::MacFailed)?;
+        
+        Ok(Self {
+            count,
+            chain: *log.chain(),
+            mac,
+        })
+    }
+    
+    /// Parse a serialized report
+    pub fn parse(data: &[u8; REPORT_LENGTH]) -> Self {
+        let mut chain = [0u8; 32];
+        let mut mac = [0u8; ATTESTATION_MAC_LENGTH];
+        chain.copy_from_slice(&data[1..33]);
+        mac.copy_from_slice(&data[33..]);
+        
+        Self {
+            count: data[0],
+            chain,
+            mac,
+        }
+    }
+    
+    /// Serialize the report (count, chain, MAC), as returned by the UDS routine
+    pub fn to_bytes(self) -> [u8; REPORT_LENGTH] {
+        let mut data = [0u8; REPORT_LENGTH];
+        data[0] = self.count;
+        data[1..33].copy_from_slice(&self.chain);
+        data[33..].copy_from_slice(&self.mac);
+        data
+    }
+    
+    /// Check the report against the measurements read from the device
+    ///
+    /// Verifier side. `verify_mac` checks a CMAC over a message with the
+    /// device key.
+    pub fn verify(
+        &self,
+        measurements: &[Measurement],
+        nonce: &[u8; ATTESTATION_NONCE_LENGTH],
+        verify_mac: &dyn Fn(&[u8], &[u8; ATTESTATION_MAC_LENGTH]) -> bool,
+    ) -> Result<(), MeasurementError> {
+        if !verify_mac(&Self::message(nonce, self.count, &self.chain), &self.mac) {
+            return Err(MeasurementError::InvalidMac);
+        }
+        
+        if self.count as usize != measurements.len() || replay(measurements) != self.chain {
+            return Err(MeasurementError::ChainMismatch);
+        }
+        
+        Ok(())
+    }
+}
+
+/// Hand the log of this start to the application
+pub fn publish(log: &MeasurementLog) {
+    // Safety: the log is only accessed from thread mode
+    unsafe { write_volatile(addr_of_mut!(LOG) as *mut [u8; LOG_RECORD_LENGTH], log.to_record()) }
+}
+
+/// Log published for the current start, if any
+pub fn read() -> Option<MeasurementLog> {
+    // Safety: the log is only accessed from thread mode and any bit
+    // pattern is a valid byte array
+    let record = unsafe { read_volatile(addr_of!(LOG) as *const [u8; LOG_RECORD_LENGTH]) };
+    MeasurementLog::parse(&record)
+}
+
+/// Measured boot error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasurementError {
+    LogFull,
+    MacFailed,
+    InvalidMac,
+    ChainMismatch,
+}
\ No newline at end of file
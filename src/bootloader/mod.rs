@@ -1,4 +1,16 @@
 pub mod core;
 pub mod flash;
+pub mod partition;
+pub mod handoff;
+pub mod boot_manager;
+pub mod mailbox;
+pub mod boot_handoff;
+pub mod image;
+pub mod keystore;
+pub mod lifecycle;
+pub mod measurement;
+pub mod self_update;
+pub mod secure_boot;
 pub mod verification;
-pub mod timeout;
\ No newline at end of file
+pub mod timeout;
+pub mod nvm;
\ No newline at end of file
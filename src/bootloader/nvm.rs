@@ -0,0 +1,74 @@
+/// Size of the emulated EEPROM (FlexRAM) in bytes
+pub const NVM_SIZE: usize = 4096;
+
+// Non-volatile data layout (offsets into the emulated EEPROM)
+pub const NVM_SECURITY_ATTEMPTS_OFFSET: u32 = 0x0000;
+pub const NVM_BOOT_RECORD_OFFSET: u32 = 0x0010;
+pub const NVM_UPDATE_JOURNAL_OFFSET: u32 = 0x0020;  // Two 64 byte slots
+pub const NVM_KEY_STORE_OFFSET: u32 = 0x00A0;  // Two 256 byte slots
+pub const NVM_LIFECYCLE_OFFSET: u32 = 0x02A0;
+pub const NVM_IDENTITY_OFFSET: u32 = 0x02B0;
+
+/// Non-volatile storage for small, frequently updated records
+pub trait NvStorage {
+    /// Read `buffer.len()` bytes starting at `offset`
+    fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError>;
+    
+    /// Write `data` starting at `offset`
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), NvmError>;
+}
+
+/// RAM backed storage for host tests
+///
+/// The content survives as long as the instance lives, which allows
+/// simulating resets by re-creating the users of the storage.
+pub struct RamStorage {
+    data: [u8; NVM_SIZE],
+}
+
+impl RamStorage {
+    /// Create a new storage in erased state
+    pub fn new() -> Self {
+        Self {
+            data: [0xFF; NVM_SIZE],
+        }
+    }
+}
+
+impl Default for RamStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NvStorage for RamStorage {
+    fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError> {
+        let start = offset as usize;
+        let end = start.checked_add(buffer.len()).ok_or(NvmError::InvalidAddress)?;
+        if end > NVM_SIZE {
+            return Err(NvmError::InvalidAddress);
+        }
+        
+        buffer.copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+    
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), NvmError> {
+        let start = offset as usize;
+        let end = start.checked_add(data.len()).ok_or(NvmError::InvalidAddress)?;
+        if end > NVM_SIZE {
+            return Err(NvmError::InvalidAddress);
+        }
+        
+        self.data[start..end].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Non-volatile storage error types
+#[derive(Debug)]
+pub enum NvmError {
+    InvalidAddress,
+    NotReady,
+    WriteError,
+}
\ No newline at end of file
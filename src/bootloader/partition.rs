@@ -0,0 +1,114 @@
+use super::self_update::UpdateLayout;
+
+/// Program flash (P-Flash) erase sector size
+pub const PFLASH_SECTOR_SIZE: u32 = 4096;
+
+/// Data flash (FlexNVM) erase sector size
+pub const DFLASH_SECTOR_SIZE: u32 = 2048;
+
+// Partition flags
+pub const PARTITION_FLAG_WRITABLE: u8 = 1 << 0;   // Programmable via UDS RequestDownload
+pub const PARTITION_FLAG_EXECUTABLE: u8 = 1 << 1; // Holds code that may be started
+pub const PARTITION_FLAG_READABLE: u8 = 1 << 2;   // Readable via UDS RequestUpload/ReadMemoryByAddress
+
+/// Role of a partition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// The bootloader itself
+    Bootloader,
+    /// Application image, starting with its vector table
+    Application,
+    /// Application calibration data
+    Calibration,
+    /// Non-volatile data (FlexNVM backing the emulated EEPROM)
+    NvData,
+    /// Staging area for a bootloader update (see `self_update`)
+    Staging,
+    /// Immutable first stage verifying and starting the bootloader (never written)
+    Stage0,
+    /// Area a delta update is reconstructed in before it replaces the application
+    Scratch,
+}
+
+/// Flash partition given as a half-open address range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    /// Partition name for logging
+    pub name: &'static str,
+    /// Role of the partition
+    pub kind: PartitionKind,
+    /// First address of the partition
+    pub start: u32,
+    /// First address after the partition
+    pub end: u32,
+    /// Partition flags (`PARTITION_FLAG_*`)
+    pub flags: u8,
+    /// Erase granularity in bytes
+    pub erase_size: u32,
+}
+
+impl Partition {
+    /// Size of the partition in bytes
+    pub const fn size(&self) -> u32 {
+        self.end - self.start
+    }
+    
+    /// Check if the partition has all of the given flags
+    pub const fn has_flags(&self, flags: u8) -> bool {
+        self.flags & flags == flags
+    }
+    
+    /// Check if the partition fully contains `address..address + size`
+    pub fn contains(&self, address: u32, size: u32) -> bool {
+        match address.checked_add(size) {
+            Some(end) => address >= self.start && end <= self.end,
+            None => false,
+        }
+    }
+}
+
+/// Partition table of the S32K148
+///
+/// The single description of the memory map: flash validation, UDS range
+/// checks and the application start all use it. Generated together with
+/// `memory.x` from the `[[partition]]` entries of the ECU configuration.
+pub use crate::config::PARTITION_TABLE;
+
+/// Look up the partition with the given role
+pub fn find(kind: PartitionKind) -> Option<&'static Partition> {
+    PARTITION_TABLE.iter().find(|partition| partition.kind == kind)
+}
+
+/// Look up the partition fully containing `address..address + size`
+pub fn find_containing(address: u32, size: u32) -> Option<&'static Partition> {
+    PARTITION_TABLE.iter().find(|partition| partition.contains(address, size))
+}
+
+/// Check if `address..address + size` lies in a single partition with all given flags
+pub fn is_range_allowed(address: u32, size: u32, flags: u8) -> bool {
+    size != 0 && find_containing(address, size).is_some_and(|partition| partition.has_flags(flags))
+}
+
+/// Start address of the application partition (its vector table)
+pub fn application_start() -> u32 {
+    find(PartitionKind::Application).map_or(0, |partition| partition.start)
+}
+
+/// Check if the bootloader is started by stage 0
+pub fn has_stage0() -> bool {
+    find(PartitionKind::Stage0).is_some()
+}
+
+/// Flash ranges of a bootloader update, `None` without a staging partition
+pub fn update_layout() -> Option<UpdateLayout> {
+    let bootloader = find(PartitionKind::Bootloader)?;
+    let staging = find(PartitionKind::Staging)?;
+    
+    Some(UpdateLayout {
+        target_start: bootloader.start,
+        target_end: bootloader.end,
+        staging_start: staging.start,
+        staging_end: staging.end,
+        sector_size: bootloader.erase_size,
+    })
+}
\ No newline at end of file
@@ -0,0 +1,159 @@
+use crate::hal::s32k148::csec::{
+    BootFlavor, CsecError, KeyUpdateMessages, KeyUpdateProof, CSEC_BOOT_MAC, CSEC_BOOT_MAC_KEY,
+    CSEC_SREG_BOOT_FINISHED, CSEC_SREG_BOOT_INIT, CSEC_SREG_BOOT_OK, CSEC_SREG_SECURE_BOOT,
+};
+
+/// Length of the secure boot status record (DID 0xFD00)
+pub const SECURE_BOOT_STATUS_LENGTH: usize = 3;
+
+/// SHE engine functions for secure boot and key provisioning
+///
+/// Implemented by the CSEc driver. The engine checks the code at the
+/// reset vector against BOOT_MAC before the bootloader runs and keeps
+/// keys with the `BOOT_PROTECTION` flag locked if the check fails.
+pub trait SecureBootEngine {
+    /// Status register (`CSEC_SREG_*` bits)
+    fn status(&self) -> u8;
+    
+    /// Store a key with the SHE key update protocol, returns M4 and M5
+    fn load_key(&mut self, messages: &KeyUpdateMessages) -> Result<KeyUpdateProof, CsecError>;
+    
+    /// Configure secure boot over the first `size` bytes of the program flash
+    fn define(&mut self, size: u32, flavor: BootFlavor) -> Result<(), CsecError>;
+    
+    /// Finish the boot phase, successfully or not
+    fn finish(&mut self, ok: bool) -> Result<(), CsecError>;
+}
+
+/// Outcome of secure boot at the last reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecureBootState {
+    /// Not configured, BOOT_MAC_KEY missing or flavor `none`
+    Disabled,
+    /// BOOT_MAC matched, boot protected keys are available
+    Verified,
+    /// BOOT_MAC did not match, or was learned at this reset
+    Failed,
+}
+
+impl SecureBootState {
+    /// Derive the state from the status register
+    pub fn from_status(status: u8) -> Self {
+        if status & CSEC_SREG_SECURE_BOOT == 0 {
+            Self::Disabled
+        } else if status & CSEC_SREG_BOOT_OK != 0 {
+            Self::Verified
+        } else {
+            Self::Failed
+        }
+    }
+    
+    /// Encode the state byte of the status record
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Disabled => 0x00,
+            Self::Verified => 0x01,
+            Self::Failed => 0x02,
+        }
+    }
+}
+
+/// Secure boot status reported over UDS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecureBootStatus {
+    /// Outcome of secure boot at the last reset
+    pub state: SecureBootState,
+    /// Status register of the engine
+    pub status_register: u8,
+    /// Flavor the bootloader configures
+    pub flavor: BootFlavor,
+}
+
+impl SecureBootStatus {
+    /// Read the status from the engine
+    pub fn read(engine: &dyn SecureBootEngine, flavor: BootFlavor) -> Self {
+        let status_register = engine.status();
+        
+        Self {
+            state: SecureBootState::from_status(status_register),
+            status_register,
+            flavor,
+        }
+    }
+    
+    /// Serialize the status record: state, status register, configured flavor
+    pub fn to_bytes(self) -> [u8; SECURE_BOOT_STATUS_LENGTH] {
+        [self.state.to_byte(), self.status_register, self.flavor.to_byte()]
+    }
+}
+
+/// Store a secure boot key
+///
+/// BOOT_MAC_KEY (authorized by MASTER_ECU_KEY) is followed by
+/// BOOT_DEFINE with the configured size and flavor; the engine learns
+/// BOOT_MAC at the next reset if it is empty. BOOT_MAC (authorized by
+/// BOOT_MAC_KEY) replaces the expected MAC, e.g. for a new bootloader
+/// when secure boot covers it. A device already defined keeps its
+/// flavor, the key is updated anyway.
+pub fn store_key(
+    engine: &mut dyn SecureBootEngine,
+    messages: &KeyUpdateMessages,
+    size: u32,
+    flavor: BootFlavor,
+) -> Result<KeyUpdateProof, SecureBootError> {
+    if flavor == BootFlavor::NoBoot {
+        return Err(SecureBootError::Disabled);
+    }
+    
+    let key_id = messages.key_id();
+    if key_id != CSEC_BOOT_MAC_KEY && key_id != CSEC_BOOT_MAC {
+        return Err(SecureBootError::WrongKeySlot);
+    }
+    
+    let proof = engine.load_key(messages)?;
+    
+    if key_id == CSEC_BOOT_MAC_KEY {
+        match engine.define(size, flavor) {
+            Ok(()) | Err(CsecError::SequenceError) => {},
+            Err(error) => return Err(error.into()),
+        }
+    }
+    
+    Ok(proof)
+}
+
+/// Close the boot phase before starting verified code
+///
+/// Only needed while the engine leaves the boot phase open after a
+/// successful check; boot protected keys stay available afterwards.
+pub fn finish(engine: &mut dyn SecureBootEngine) -> Result<(), SecureBootError> {
+    let status = engine.status();
+    if status & CSEC_SREG_BOOT_INIT == 0 || status & CSEC_SREG_BOOT_FINISHED != 0 {
+        return Ok(());
+    }
+    
+    engine.finish(status & CSEC_SREG_BOOT_OK != 0)?;
+    Ok(())
+}
+
+/// Secure boot error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SecureBootError {
+    Disabled,
+    WrongKeySlot,
+    KeyRejected,
+    KeyWriteProtected,
+    EngineFault,
+}
+
+impl From<CsecError> for SecureBootError {
+    fn from(error: CsecError) -> Self {
+        match error {
+            CsecError::KeyUpdateError | CsecError::KeyInvalid | CsecError::KeyEmpty | CsecError::KeyNotAvailable => {
+                SecureBootError::KeyRejected
+            },
+            CsecError::KeyWriteProtected => SecureBootError::KeyWriteProtected,
+            _ => SecureBootError::EngineFault,
+        }
+    }
+}
\ No newline at end of file
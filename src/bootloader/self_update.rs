@@ -0,0 +1,409 @@
+use sha2::{Digest, Sha256};
+use super::image::{self, ImageError, ImageHeader, ImagePolicy, SignatureCheck, IMAGE_HEADER_LENGTH};
+use super::nvm::{NvStorage, NVM_UPDATE_JOURNAL_OFFSET};
+
+/// Journal entry magic ("SJ")
+const JOURNAL_MAGIC: [u8; 2] = *b"SJ";
+
+/// Length of a journal entry in bytes
+const JOURNAL_ENTRY_LENGTH: usize = 56;
+
+/// Distance of the two journal slots in non-volatile storage
+pub const JOURNAL_SLOT_SIZE: u32 = 64;
+
+/// Bytes copied and compared per flash operation
+const COPY_CHUNK_SIZE: usize = 256;
+
+/// Flash access needed to install a bootloader update
+pub trait UpdateFlash {
+    /// Read `buffer.len()` bytes starting at `address`
+    fn read(&self, address: u32, buffer: &mut [u8]);
+    
+    /// Erase the sector starting at `address`
+    fn erase(&mut self, address: u32) -> Result<(), UpdateError>;
+    
+    /// Program `data` to erased flash at `address`
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), UpdateError>;
+}
+
+/// Flash ranges involved in a bootloader update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateLayout {
+    /// First address of the bootloader partition
+    pub target_start: u32,
+    /// First address after the bootloader partition
+    pub target_end: u32,
+    /// First address of the staging partition (updater image header)
+    pub staging_start: u32,
+    /// First address after the staging partition
+    pub staging_end: u32,
+    /// Erase sector size of both partitions
+    pub sector_size: u32,
+}
+
+impl UpdateLayout {
+    /// Number of sectors of the bootloader partition
+    fn sector_count(&self) -> u32 {
+        (self.target_end - self.target_start) / self.sector_size
+    }
+    
+    /// Address of the staged bootloader image
+    fn image_start(&self) -> u32 {
+        self.staging_start + IMAGE_HEADER_LENGTH as u32
+    }
+    
+    /// Address of the image header kept at the end of the bootloader partition
+    ///
+    /// Stage 0 checks the installed bootloader against this copy of the
+    /// header, the bootloader image itself must end before it.
+    pub fn descriptor_start(&self) -> u32 {
+        self.target_end - IMAGE_HEADER_LENGTH as u32
+    }
+    
+    /// Read access of `flash` as needed by `image`
+    fn reader(flash: &dyn UpdateFlash) -> impl Fn(u32, &mut [u8]) + '_ {
+        move |address, buffer| flash.read(address, buffer)
+    }
+}
+
+/// Progress of a bootloader update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateState {
+    /// No update pending
+    Idle,
+    /// A verified update is being copied over the bootloader
+    Copying,
+    /// The copy is complete, the staged image is not yet consumed
+    Complete,
+}
+
+/// Self-update journal entry
+///
+/// Kept twice in non-volatile storage and written alternately, so a power
+/// cut during a write leaves the previous entry intact:
+///
+/// ```text
+/// 0   magic "SJ"
+/// 2   sequence number (u16 LE, the newer entry wins)
+/// 4   state (0 idle, 1 copying, 2 complete), 1 reserved byte
+/// 6   next sector to copy (u16 LE)
+/// 8   lowest accepted security version (u32 LE)
+/// 12  security version of the update (u32 LE)
+/// 16  image size of the update (u32 LE)
+/// 20  SHA-256 of the update image
+/// 52  first 4 bytes of the SHA-256 of bytes 0-51
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Journal {
+    /// Sequence number of the entry
+    pub sequence: u16,
+    /// Progress of the update
+    pub state: UpdateState,
+    /// Next sector of the bootloader partition to copy
+    pub next_sector: u16,
+    /// Lowest security version still accepted
+    pub min_security_version: u32,
+    /// Security version of the update
+    pub security_version: u32,
+    /// Size of the update image
+    pub image_size: u32,
+    /// SHA-256 of the update image
+    pub image_hash: [u8; 32],
+}
+
+impl Journal {
+    /// Journal of a device that never installed an update
+    pub const fn empty() -> Self {
+        Self {
+            sequence: 0,
+            state: UpdateState::Idle,
+            next_sector: 0,
+            min_security_version: 0,
+            security_version: 0,
+            image_size: 0,
+            image_hash: [0; 32],
+        }
+    }
+    
+    /// Parse a stored entry, `None` if it is erased or corrupted
+    fn parse(data: &[u8; JOURNAL_ENTRY_LENGTH]) -> Option<Self> {
+        if data[0..2] != JOURNAL_MAGIC || data[52..56] != Sha256::digest(&data[..52])[..4] {
+            return None;
+        }
+        
+        let word = |offset: usize| {
+            u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        
+        let state = match data[4] {
+            0 => UpdateState::Idle,
+            1 => UpdateState::Copying,
+            2 => UpdateState::Complete,
+            _ => return None,
+        };
+        
+        let mut image_hash = [0u8; 32];
+        image_hash.copy_from_slice(&data[20..52]);
+        
+        Some(Self {
+            sequence: u16::from_le_bytes([data[2], data[3]]),
+            state,
+            next_sector: u16::from_le_bytes([data[6], data[7]]),
+            min_security_version: word(8),
+            security_version: word(12),
+            image_size: word(16),
+            image_hash,
+        })
+    }
+    
+    /// Serialize the entry
+    fn to_bytes(self) -> [u8; JOURNAL_ENTRY_LENGTH] {
+        let mut data = [0u8; JOURNAL_ENTRY_LENGTH];
+        data[0..2].copy_from_slice(&JOURNAL_MAGIC);
+        data[2..4].copy_from_slice(&self.sequence.to_le_bytes());
+        data[4] = match self.state {
+            UpdateState::Idle => 0,
+            UpdateState::Copying => 1,
+            UpdateState::Complete => 2,
+        };
+        data[6..8].copy_from_slice(&self.next_sector.to_le_bytes());
+        data[8..12].copy_from_slice(&self.min_security_version.to_le_bytes());
+        data[12..16].copy_from_slice(&self.security_version.to_le_bytes());
+        data[16..20].copy_from_slice(&self.image_size.to_le_bytes());
+        data[20..52].copy_from_slice(&self.image_hash);
+        
+        let check = Sha256::digest(&data[..52]);
+        data[52..56].copy_from_slice(&check[..4]);
+        data
+    }
+}
+
+/// Load the newest valid journal entry
+pub fn load_journal(storage: &dyn NvStorage) -> Journal {
+    let mut newest: Option<Journal> = None;
+    
+    for slot in 0..2 {
+        let mut data = [0u8; JOURNAL_ENTRY_LENGTH];
+        if storage.read(NVM_UPDATE_JOURNAL_OFFSET + slot * JOURNAL_SLOT_SIZE, &mut data).is_err() {
+            continue;
+        }
+        
+        if let Some(entry) = Journal::parse(&data) {
+            newest = match newest {
+                // Sequence numbers wrap, the entry ahead by less than half the range is newer
+                Some(other) if (entry.sequence.wrapping_sub(other.sequence) as i16) < 0 => Some(other),
+                _ => Some(entry),
+            };
+        }
+    }
+    
+    newest.unwrap_or(Journal::empty())
+}
+
+/// Store a journal entry in the slot not holding the current one
+fn store_journal(storage: &mut dyn NvStorage, journal: &mut Journal) -> Result<(), UpdateError> {
+    journal.sequence = journal.sequence.wrapping_add(1);
+    let slot = (journal.sequence % 2) as u32;
+    
+    storage
+        .write(NVM_UPDATE_JOURNAL_OFFSET + slot * JOURNAL_SLOT_SIZE, &journal.to_bytes())
+        .map_err(|_| UpdateError::JournalWriteFailed)
+}
+
+/// Lowest security version an update may have
+///
+/// `floor` is the security version of the running bootloader.
+pub fn min_security_version(storage: &dyn NvStorage, floor: u32) -> u32 {
+    load_journal(storage).min_security_version.max(floor)
+}
+
+/// Check the updater image in the staging partition
+///
+/// `verify_signature` checks a signature over the given message with the
+/// key of the given key ID. Returns the header of an image that may be
+/// installed.
+pub fn verify_staged(
+    flash: &dyn UpdateFlash,
+    layout: &UpdateLayout,
+    min_security_version: u32,
+    verify_signature: &SignatureCheck<'_>,
+) -> Result<ImageHeader, UpdateError> {
+    let mut data = [0u8; IMAGE_HEADER_LENGTH];
+    flash.read(layout.staging_start, &mut data);
+    let header = ImageHeader::parse(&data)?;
+    
+    let policy = ImagePolicy {
+        load_address: layout.target_start,
+        max_size: (layout.descriptor_start() - layout.target_start).min(layout.staging_end - layout.image_start()),
+        min_security_version,
+    };
+    image::verify(&header, layout.image_start(), &policy, &UpdateLayout::reader(flash), verify_signature)?;
+    
+    Ok(header)
+}
+
+/// Arm the installation of a verified update at the next start
+pub fn arm(storage: &mut dyn NvStorage, header: &ImageHeader) -> Result<(), UpdateError> {
+    let mut journal = load_journal(storage);
+    if journal.state != UpdateState::Idle {
+        return Err(UpdateError::Busy);
+    }
+    
+    journal.state = UpdateState::Copying;
+    journal.next_sector = 0;
+    journal.security_version = header.security_version;
+    journal.image_size = header.image_size;
+    journal.image_hash = header.image_hash;
+    
+    store_journal(storage, &mut journal)
+}
+
+/// Copy one sector of the staged image over the bootloader partition
+///
+/// Bytes after the end of the image are left erased, except for the image
+/// header that goes to the end of the last sector for stage 0.
+fn copy_sector(flash: &mut dyn UpdateFlash, layout: &UpdateLayout, sector: u32, image_size: u32) -> Result<(), UpdateError> {
+    let offset = sector * layout.sector_size;
+    let length = image_size.saturating_sub(offset).min(layout.sector_size);
+    
+    flash.erase(layout.target_start + offset)?;
+    copy_range(flash, layout.image_start() + offset, layout.target_start + offset, length)?;
+    
+    if sector + 1 == layout.sector_count() {
+        copy_range(flash, layout.staging_start, layout.descriptor_start(), IMAGE_HEADER_LENGTH as u32)?;
+    }
+    
+    Ok(())
+}
+
+/// Program `length` bytes from `source` to erased flash at `destination`
+///
+/// Every chunk is read back and compared with the source.
+fn copy_range(flash: &mut dyn UpdateFlash, source: u32, destination: u32, length: u32) -> Result<(), UpdateError> {
+    let mut staged = [0u8; COPY_CHUNK_SIZE];
+    let mut written = [0u8; COPY_CHUNK_SIZE];
+    let mut position = 0;
+    
+    while position < length {
+        let chunk = (length - position).min(COPY_CHUNK_SIZE as u32) as usize;
+        let address = destination + position;
+        
+        flash.read(source + position, &mut staged[..chunk]);
+        flash.program(address, &staged[..chunk])?;
+        
+        flash.read(address, &mut written[..chunk]);
+        if written[..chunk] != staged[..chunk] {
+            return Err(UpdateError::VerificationFailed);
+        }
+        
+        position += chunk as u32;
+    }
+    
+    Ok(())
+}
+
+/// Continue an armed or interrupted bootloader update
+///
+/// Meant to run at every start before anything else touches the flash.
+/// Each step is recorded in the journal, so after a power cut the next
+/// call resumes with the sector that was being copied. Returns the
+/// security version of the new bootloader once it is installed (the
+/// device must then reset), `None` if no update is pending.
+///
+/// The staged image is verified again before copying and must still be
+/// the armed one. If it is not, an update that has not started copying
+/// is abandoned and the current bootloader stays.
+///
+/// Every step survives a power cut as far as the journal and flash content
+/// go. The bootloader being rewritten only starts again once the copy is
+/// complete: until then stage 0 rejects it, see the `stage0` crate.
+pub fn resume(
+    flash: &mut dyn UpdateFlash,
+    storage: &mut dyn NvStorage,
+    layout: &UpdateLayout,
+    floor: u32,
+    verify_signature: &SignatureCheck<'_>,
+) -> Result<Option<u32>, UpdateError> {
+    let mut journal = load_journal(storage);
+    
+    match journal.state {
+        UpdateState::Idle => return Ok(None),
+        UpdateState::Copying => {
+            let min_security_version = journal.min_security_version.max(floor);
+            let staged = verify_staged(flash, layout, min_security_version, verify_signature).and_then(|header| {
+                let armed = header.image_hash == journal.image_hash
+                    && header.image_size == journal.image_size
+                    && header.security_version == journal.security_version;
+                if armed { Ok(header) } else { Err(UpdateError::StagingChanged) }
+            });
+            
+            if let Err(error) = staged {
+                if journal.next_sector == 0 {
+                    journal.state = UpdateState::Idle;
+                    store_journal(storage, &mut journal)?;
+                }
+                return Err(error);
+            }
+            
+            for sector in journal.next_sector as u32..layout.sector_count() {
+                copy_sector(flash, layout, sector, journal.image_size)?;
+                journal.next_sector = sector as u16 + 1;
+                store_journal(storage, &mut journal)?;
+            }
+            
+            // Start over if the installed image does not match as a whole
+            let installed = image::hash(&UpdateLayout::reader(flash), layout.target_start, journal.image_size);
+            if installed != journal.image_hash {
+                journal.next_sector = 0;
+                store_journal(storage, &mut journal)?;
+                return Err(UpdateError::VerificationFailed);
+            }
+            
+            journal.state = UpdateState::Complete;
+            store_journal(storage, &mut journal)?;
+        },
+        UpdateState::Complete => {},
+    }
+    
+    // Consume the staged image and raise the downgrade protection
+    flash.erase(layout.staging_start)?;
+    journal.state = UpdateState::Idle;
+    journal.min_security_version = journal.min_security_version.max(journal.security_version);
+    store_journal(storage, &mut journal)?;
+    
+    Ok(Some(journal.security_version))
+}
+
+/// Bootloader update error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateError {
+    InvalidHeader,
+    UnsupportedVersion,
+    UnsupportedAlgorithm,
+    WrongLoadAddress,
+    InvalidSize,
+    Downgrade,
+    InvalidSignature,
+    HashMismatch,
+    Busy,
+    StagingChanged,
+    EraseFailed,
+    ProgramFailed,
+    VerificationFailed,
+    JournalWriteFailed,
+}
+
+impl From<ImageError> for UpdateError {
+    fn from(error: ImageError) -> Self {
+        match error {
+            ImageError::InvalidHeader => UpdateError::InvalidHeader,
+            ImageError::UnsupportedVersion => UpdateError::UnsupportedVersion,
+            ImageError::UnsupportedAlgorithm => UpdateError::UnsupportedAlgorithm,
+            ImageError::WrongLoadAddress => UpdateError::WrongLoadAddress,
+            ImageError::InvalidSize => UpdateError::InvalidSize,
+            ImageError::Downgrade => UpdateError::Downgrade,
+            ImageError::InvalidSignature => UpdateError::InvalidSignature,
+            ImageError::HashMismatch => UpdateError::HashMismatch,
+        }
+    }
+}
\ No newline at end of file
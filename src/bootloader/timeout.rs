@@ -1,8 +1,9 @@
 use defmt::{info, debug};
 use crate::hal::s32k148::peripherals::SystemReset;
+use crate::drivers::systick;
 
-/// Reset timeout values in milliseconds
-const CAN_RESET_TIMEOUT_MS: u32 = 500;
+// Reset timeout value in milliseconds from the ECU configuration
+use crate::config::RESET_TIMEOUT_MS;
 
 /// Timeout reset handling for firmware updates
 pub struct TimeoutReset {
@@ -51,14 +52,11 @@ impl TimeoutReset {
             let current_time = Self::get_current_time();
             
             // Check if timeout has elapsed
-            if current_time >= self.timeout_timestamp + CAN_RESET_TIMEOUT_MS {
-                info!("Reset timeout reached: {}ms", CAN_RESET_TIMEOUT_MS);
+            if current_time.wrapping_sub(self.timeout_timestamp) >= RESET_TIMEOUT_MS {
+                info!("Reset timeout reached: {}ms", RESET_TIMEOUT_MS);
                 
                 // Perform system reset
                 SystemReset::reset();
-                
-                // This should never be reached (reset happens above)
-                return true;
             }
         }
         
@@ -67,9 +65,12 @@ impl TimeoutReset {
     
     /// Get current system time in milliseconds
     fn get_current_time() -> u32 {
-        // Access system timer or other time source
-        // In real implementation, this would use a hardware timer
-        // For now, return dummy value
-        0
+        systick::millis()
+    }
+}
+
+impl Default for TimeoutReset {
+    fn default() -> Self {
+        Self::new()
     }
 }
\ No newline at end of file
@@ -1,4 +1,12 @@
-use defmt::{debug, info};
+use defmt::{debug, info, warn};
+use crate::bootloader::keystore::{self, KeyError, KeyStore, KeyUpdate};
+use crate::bootloader::nvm::NvStorage;
+use crate::config::BUILTIN_KEYS;
+#[cfg(feature = "ecdsa")]
+use crate::crypto::ecdsa;
+
+/// Key store built into this bootloader, used until a key update is installed
+pub const BUILTIN_KEY_STORE: KeyStore = KeyStore::builtin(BUILTIN_KEYS);
 
 /// Verification methods for firmware integrity
 pub struct FirmwareVerification {
@@ -102,6 +110,66 @@ impl FirmwareVerification {
     }
 }
 
+impl Default for FirmwareVerification {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Load the installed key store
+pub fn load_keys(storage: &dyn NvStorage) -> KeyStore {
+    keystore::load(storage, &BUILTIN_KEY_STORE)
+}
+
+/// Verify the signature of an image header with the key of its key ID
+///
+/// Unknown and revoked keys, and the root key, are rejected.
+pub fn verify_image_signature(keys: &KeyStore, key_id: u8, message: &[u8], signature: &[u8]) -> bool {
+    match keys.image_key(key_id) {
+        Ok(public_key) => verify_raw_signature(public_key, message, signature),
+        Err(error) => {
+            warn!("Signing key {} rejected: {}", key_id, defmt::Debug2Format(&error));
+            false
+        }
+    }
+}
+
+/// Verify a key update with the root key and store the new key store
+pub fn install_key_update(storage: &mut dyn NvStorage, update: &KeyUpdate) -> Result<KeyStore, KeyError> {
+    keystore::install(storage, &BUILTIN_KEY_STORE, update, &|public_key, message, signature| {
+        verify_raw_signature(public_key, message, signature)
+    })
+}
+
+/// Verify a raw (r || s) ECDSA P-256 signature, as in image headers and key updates
+#[cfg(feature = "ecdsa")]
+fn verify_raw_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    ecdsa::verify_raw(public_key, message, signature).is_ok()
+}
+
+/// Without the `ecdsa` feature no signature is accepted
+#[cfg(not(feature = "ecdsa"))]
+fn verify_raw_signature(_public_key: &[u8], _message: &[u8], _signature: &[u8]) -> bool {
+    false
+}
+
+/// CRC-32 (IEEE 802.3, reflected, as used by zlib) of `data`
+///
+/// Bitwise implementation without a table, meant for short records.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    
+    !crc
+}
+
 /// Verification error types
 #[derive(Debug)]
 pub enum VerificationError {
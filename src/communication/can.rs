@@ -1,20 +1,11 @@
 use defmt::{debug, info, error};
 use heapless::Vec;
-use core::cell::RefCell;
 
 /// Maximum CAN message data length
 const CAN_MAX_DATA_LENGTH: usize = 8;
 
-/// CAN message ID configuration (29-bit extended IDs)
-const CAN_TX_MSG_ID: u32 = 0x7E1 | 0x80000000; // With extended ID bit set
-const CAN_RX_MSG_ID: u32 = 0x148 | 0x80000000; // With extended ID bit set
-
-/// CAN baudrate in bits per second
-const CAN_BAUDRATE: u32 = 250_000;
-
-/// Timeout for CAN operations in milliseconds
-const CAN_MSG_TX_TIMEOUT_MS: u32 = 50;
-const CAN_INIT_TIMEOUT_MS: u32 = 250;
+// CAN message IDs, baudrate and timeouts come from the ECU configuration (`config`)
+use crate::config::{CAN_BAUDRATE, CAN_TX_MSG_ID};
 
 /// CAN controller for S32K148
 pub struct Can {
@@ -48,6 +39,9 @@ impl Can {
         
         // 1. Set up clock source
         // 2. Configure baudrate
+        if let Err(e) = self.configure_bus_timing(CAN_BAUDRATE) {
+            error!("CAN bus timing not configured: {}", defmt::Debug2Format(&e));
+        }
         // 3. Set up mailboxes/message objects
         // 4. Configure message filtering
         // 5. Enable CAN controller
@@ -56,8 +50,24 @@ impl Can {
         info!("CAN controller initialized at {} bps", CAN_BAUDRATE);
     }
     
+    /// Return the CAN controller to its reset state (e.g. before starting the application)
+    pub fn deinit(&mut self) {
+        info!("De-initializing CAN controller");
+        
+        // 1. Request freeze mode and wait for acknowledge
+        // 2. Disable the module (MCR[MDIS])
+        // 3. Gate the FlexCAN clock in PCC
+        
+        self.initialized = false;
+    }
+    
     /// Transmit CAN message with data
     pub fn transmit(&self, data: &[u8]) -> Result<(), CanError> {
+        self.transmit_to(CAN_TX_MSG_ID, data)
+    }
+    
+    /// Transmit CAN message with data on the given identifier (bit 31 marks an extended identifier)
+    pub fn transmit_to(&self, id: u32, data: &[u8]) -> Result<(), CanError> {
         if !self.initialized {
             return Err(CanError::NotInitialized);
         }
@@ -66,7 +76,7 @@ impl Can {
             return Err(CanError::DataTooLong);
         }
         
-        debug!("Transmitting {} bytes via CAN", data.len());
+        debug!("Transmitting {} bytes via CAN (ID 0x{:X})", data.len(), id);
         
         // Create a CAN message
         // Hardware-specific implementation would go here
@@ -88,7 +98,7 @@ impl Can {
     }
     
     /// Configure CAN controller bus timing
-    fn configure_bus_timing(&self, baudrate: u32) -> Result<(), CanError> {
+    fn configure_bus_timing(&self, _baudrate: u32) -> Result<(), CanError> {
         // Calculate bus timing parameters based on baudrate
         // This depends on CAN clock source and hardware capabilities
         
@@ -101,6 +111,12 @@ impl Can {
     }
 }
 
+impl Default for Can {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// CAN operation error types
 #[derive(Debug)]
 pub enum CanError {
@@ -1,4 +1,4 @@
-use defmt::{debug, info, error};
+use defmt::info;
 use core::fmt;
 use heapless::Vec;
 
@@ -34,7 +34,7 @@ impl Serial {
     }
     
     /// Send a byte over the serial interface
-    pub fn send_byte(&self, byte: u8) -> Result<(), SerialError> {
+    pub fn send_byte(&self, _byte: u8) -> Result<(), SerialError> {
         if !self.initialized {
             return Err(SerialError::NotInitialized);
         }
@@ -76,7 +76,7 @@ impl Serial {
     }
     
     /// Receive data from the serial interface with timeout
-    pub fn receive_timeout(&self, timeout_ms: u32) -> Option<Vec<u8, 64>> {
+    pub fn receive_timeout(&self, _timeout_ms: u32) -> Option<Vec<u8, 64>> {
         if !self.initialized {
             return None;
         }
@@ -88,6 +88,12 @@ impl Serial {
     }
 }
 
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // Implement write! and writeln! support
 impl fmt::Write for Serial {
     fn write_str(&mut self, s: &str) -> fmt::Result {
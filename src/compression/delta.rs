@@ -0,0 +1,380 @@
+use sha2::{Digest, Sha256};
+
+/// Patch magic ("GDLT")
+pub const DELTA_MAGIC: [u8; 4] = *b"GDLT";
+
+/// Patch format version
+pub const DELTA_VERSION: u8 = 1;
+
+/// Length of the patch header in bytes
+pub const DELTA_HEADER_LENGTH: usize = 80;
+
+/// Decoded bytes buffered before they are passed to the output
+const OUTPUT_BUFFER_SIZE: usize = 64;
+
+/// Patch header
+///
+/// ```text
+/// 0   magic "GDLT"
+/// 4   version (1), 3 reserved bytes
+/// 8   old image size (u32 LE)
+/// 12  new image size (u32 LE)
+/// 16  SHA-256 of the old image
+/// 48  SHA-256 of the new image
+/// ```
+///
+/// The header is followed by records of a control triple (diff length,
+/// extra length, old position adjustment; u32/u32/i32 LE), `diff length`
+/// bytes added to the old image and `extra length` literal bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaHeader {
+    /// Size of the image the patch applies to
+    pub old_size: u32,
+    /// Size of the reconstructed image
+    pub new_size: u32,
+    /// SHA-256 of the image the patch applies to
+    pub old_hash: [u8; 32],
+    /// SHA-256 of the reconstructed image
+    pub new_hash: [u8; 32],
+}
+
+impl DeltaHeader {
+    /// Parse a patch header
+    pub fn parse(data: &[u8; DELTA_HEADER_LENGTH]) -> Result<Self, DeltaError> {
+        if data[0..4] != DELTA_MAGIC {
+            return Err(DeltaError::InvalidHeader);
+        }
+        
+        if data[4] != DELTA_VERSION {
+            return Err(DeltaError::UnsupportedVersion);
+        }
+        
+        let mut old_hash = [0u8; 32];
+        let mut new_hash = [0u8; 32];
+        old_hash.copy_from_slice(&data[16..48]);
+        new_hash.copy_from_slice(&data[48..80]);
+        
+        Ok(Self {
+            old_size: u32::from_le_bytes([data[8], data[9], data[10], data[11]]),
+            new_size: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            old_hash,
+            new_hash,
+        })
+    }
+    
+    /// Serialize the header
+    pub fn to_bytes(self) -> [u8; DELTA_HEADER_LENGTH] {
+        let mut data = [0u8; DELTA_HEADER_LENGTH];
+        data[0..4].copy_from_slice(&DELTA_MAGIC);
+        data[4] = DELTA_VERSION;
+        data[8..12].copy_from_slice(&self.old_size.to_le_bytes());
+        data[12..16].copy_from_slice(&self.new_size.to_le_bytes());
+        data[16..48].copy_from_slice(&self.old_hash);
+        data[48..80].copy_from_slice(&self.new_hash);
+        data
+    }
+}
+
+/// Patcher state between input bytes
+#[derive(Clone, Copy)]
+enum PatchState {
+    /// Collecting the header
+    Header,
+    /// Collecting the diff length of a control triple
+    DiffLength,
+    /// Collecting the extra length of a control triple
+    ExtraLength,
+    /// Collecting the old position adjustment of a control triple
+    Seek,
+    /// Adding diff bytes to the old image
+    Diff,
+    /// Copying extra bytes
+    Extra,
+}
+
+/// Streaming delta patcher
+///
+/// Reconstructs the new image from the installed (old) image and a patch
+/// received in chunks of any size. The old image is checked against the
+/// header hash before the first byte is produced and the new image hash is
+/// checked by `finish`.
+pub struct DeltaPatcher {
+    /// Patcher state
+    state: PatchState,
+    /// Header bytes received so far
+    header_buffer: [u8; DELTA_HEADER_LENGTH],
+    /// Number of bytes collected for the current header or control field
+    field_len: usize,
+    /// Parsed header
+    header: Option<DeltaHeader>,
+    /// Control field being collected
+    field: [u8; 4],
+    /// Diff length of the current record
+    diff_length: u32,
+    /// Extra length of the current record
+    extra_length: u32,
+    /// Old position adjustment of the current record
+    seek: i32,
+    /// Bytes left in the current diff or extra block
+    remaining: u32,
+    /// Read position in the old image
+    old_position: u32,
+    /// Number of bytes produced
+    written: u32,
+    /// Hash over the produced bytes
+    hasher: Sha256,
+    /// Produced bytes not yet passed to the output
+    output: [u8; OUTPUT_BUFFER_SIZE],
+    /// Number of valid bytes in `output`
+    output_len: usize,
+}
+
+impl DeltaPatcher {
+    /// Create a new patcher
+    pub fn new() -> Self {
+        Self {
+            state: PatchState::Header,
+            header_buffer: [0; DELTA_HEADER_LENGTH],
+            field_len: 0,
+            header: None,
+            field: [0; 4],
+            diff_length: 0,
+            extra_length: 0,
+            seek: 0,
+            remaining: 0,
+            old_position: 0,
+            written: 0,
+            hasher: Sha256::new(),
+            output: [0; OUTPUT_BUFFER_SIZE],
+            output_len: 0,
+        }
+    }
+    
+    /// Reset the patcher for a new patch
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+    
+    /// Header of the patch, once received
+    pub fn header(&self) -> Option<&DeltaHeader> {
+        self.header.as_ref()
+    }
+    
+    /// Apply a chunk of the patch
+    ///
+    /// `old` is the installed image. Reconstructed bytes are passed to `sink`
+    /// in pieces of at most 64 bytes.
+    pub fn apply<F, E>(&mut self, old: &[u8], input: &[u8], mut sink: F) -> Result<(), PatchError<E>>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        for &byte in input {
+            match self.state {
+                PatchState::Header => {
+                    self.header_buffer[self.field_len] = byte;
+                    self.field_len += 1;
+                    if self.field_len == DELTA_HEADER_LENGTH {
+                        self.start(old)?;
+                    }
+                },
+                PatchState::DiffLength | PatchState::ExtraLength | PatchState::Seek => {
+                    self.field[self.field_len] = byte;
+                    self.field_len += 1;
+                    if self.field_len == 4 {
+                        self.field_len = 0;
+                        self.control_field_complete()?;
+                    }
+                },
+                PatchState::Diff => {
+                    let position = self.old_position as usize;
+                    if position >= self.old_size() {
+                        return Err(PatchError::Delta(DeltaError::Corrupt));
+                    }
+                    self.old_position += 1;
+                    self.emit(old[position].wrapping_add(byte), &mut sink)?;
+                    self.remaining -= 1;
+                    if self.remaining == 0 {
+                        self.begin_extra()?;
+                    }
+                },
+                PatchState::Extra => {
+                    self.emit(byte, &mut sink)?;
+                    self.remaining -= 1;
+                    if self.remaining == 0 {
+                        self.end_record()?;
+                    }
+                },
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Flush the remaining bytes and verify the reconstructed image
+    pub fn finish<F, E>(&mut self, mut sink: F) -> Result<(), PatchError<E>>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        self.flush(&mut sink)?;
+        
+        let header = self.header.ok_or(PatchError::Delta(DeltaError::Truncated))?;
+        
+        // The patch must end on a record boundary
+        let complete = matches!(self.state, PatchState::DiffLength) && self.field_len == 0;
+        if !complete || self.written != header.new_size {
+            return Err(PatchError::Delta(DeltaError::Truncated));
+        }
+        
+        let hash: [u8; 32] = self.hasher.clone().finalize().into();
+        if hash != header.new_hash {
+            return Err(PatchError::Delta(DeltaError::HashMismatch));
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate the header against the installed image
+    fn start<E>(&mut self, old: &[u8]) -> Result<(), PatchError<E>> {
+        let header = DeltaHeader::parse(&self.header_buffer).map_err(PatchError::Delta)?;
+        
+        let old_size = header.old_size as usize;
+        if old_size > old.len() {
+            return Err(PatchError::Delta(DeltaError::OldImageMismatch));
+        }
+        
+        let old_hash: [u8; 32] = Sha256::digest(&old[..old_size]).into();
+        if old_hash != header.old_hash {
+            return Err(PatchError::Delta(DeltaError::OldImageMismatch));
+        }
+        
+        self.header = Some(header);
+        self.field_len = 0;
+        self.state = PatchState::DiffLength;
+        
+        Ok(())
+    }
+    
+    /// Handle a completed control field
+    fn control_field_complete<E>(&mut self) -> Result<(), PatchError<E>> {
+        let value = u32::from_le_bytes(self.field);
+        
+        match self.state {
+            PatchState::DiffLength => {
+                self.diff_length = value;
+                self.state = PatchState::ExtraLength;
+            },
+            PatchState::ExtraLength => {
+                self.extra_length = value;
+                self.state = PatchState::Seek;
+            },
+            _ => {
+                self.seek = value as i32;
+                self.begin_diff()?;
+            },
+        }
+        
+        Ok(())
+    }
+    
+    /// Size of the old image, known once the header is validated
+    fn old_size(&self) -> usize {
+        self.header.map_or(0, |header| header.old_size as usize)
+    }
+    
+    /// Enter the diff block of the current record
+    fn begin_diff<E>(&mut self) -> Result<(), PatchError<E>> {
+        self.remaining = self.diff_length;
+        if self.remaining == 0 {
+            return self.begin_extra();
+        }
+        
+        self.state = PatchState::Diff;
+        Ok(())
+    }
+    
+    /// Enter the extra block of the current record
+    fn begin_extra<E>(&mut self) -> Result<(), PatchError<E>> {
+        self.remaining = self.extra_length;
+        if self.remaining == 0 {
+            return self.end_record();
+        }
+        
+        self.state = PatchState::Extra;
+        Ok(())
+    }
+    
+    /// Apply the old position adjustment and wait for the next record
+    fn end_record<E>(&mut self) -> Result<(), PatchError<E>> {
+        // Seek relative to the end of the diff block
+        let position = self.old_position as i64 + self.seek as i64;
+        if position < 0 || position > self.old_size() as i64 {
+            return Err(PatchError::Delta(DeltaError::Corrupt));
+        }
+        
+        self.old_position = position as u32;
+        self.state = PatchState::DiffLength;
+        Ok(())
+    }
+    
+    /// Append a produced byte to the output buffer
+    fn emit<F, E>(&mut self, byte: u8, sink: &mut F) -> Result<(), PatchError<E>>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        let new_size = self.header.map_or(0, |header| header.new_size);
+        if self.written >= new_size {
+            return Err(PatchError::Delta(DeltaError::Corrupt));
+        }
+        self.written += 1;
+        
+        self.output[self.output_len] = byte;
+        self.output_len += 1;
+        
+        if self.output_len == OUTPUT_BUFFER_SIZE {
+            self.flush(sink)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Hash the output buffer and pass it to `sink`
+    fn flush<F, E>(&mut self, sink: &mut F) -> Result<(), PatchError<E>>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        if self.output_len == 0 {
+            return Ok(());
+        }
+        
+        let len = self.output_len;
+        self.output_len = 0;
+        self.hasher.update(&self.output[..len]);
+        sink(&self.output[..len]).map_err(PatchError::Output)
+    }
+}
+
+impl Default for DeltaPatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Patch format error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeltaError {
+    InvalidHeader,
+    UnsupportedVersion,
+    OldImageMismatch,
+    Corrupt,
+    Truncated,
+    HashMismatch,
+}
+
+/// Error while applying a patch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchError<E> {
+    /// The patch is invalid or does not match the installed image
+    Delta(DeltaError),
+    /// The output sink failed
+    Output(E),
+}
\ No newline at end of file
@@ -0,0 +1,398 @@
+/// Window size exponent (`heatshrink -w`)
+pub const HEATSHRINK_WINDOW_BITS: u8 = 8;
+
+/// Lookahead size exponent (`heatshrink -l`)
+pub const HEATSHRINK_LOOKAHEAD_BITS: u8 = 4;
+
+/// Size of the back-reference window in bytes
+const WINDOW_SIZE: usize = 1 << HEATSHRINK_WINDOW_BITS;
+
+/// Decoded bytes buffered before they are passed to the output
+const OUTPUT_BUFFER_SIZE: usize = 64;
+
+/// Decoder state between input bits
+#[derive(Clone, Copy)]
+enum DecoderState {
+    /// Waiting for the literal/back-reference tag bit
+    Tag,
+    /// Waiting for the 8 bits of a literal
+    Literal,
+    /// Waiting for the back-reference index
+    BackrefIndex,
+    /// Waiting for the back-reference count
+    BackrefCount { index: u16 },
+}
+
+/// Streaming heatshrink (LZSS) decoder
+///
+/// Decodes data produced by `heatshrink -e -w 8 -l 4` in fixed RAM (the
+/// back-reference window plus a small output buffer), independent of how
+/// the compressed stream is split into chunks.
+pub struct HeatshrinkDecoder {
+    /// Decoder state
+    state: DecoderState,
+    /// Input bits not consumed yet (right aligned)
+    bits: u32,
+    /// Number of valid bits in `bits`
+    bit_count: u8,
+    /// Back-reference window
+    window: [u8; WINDOW_SIZE],
+    /// Total number of bytes decoded, also the window write position
+    head: usize,
+    /// Decoded bytes not yet passed to the output
+    output: [u8; OUTPUT_BUFFER_SIZE],
+    /// Number of valid bytes in `output`
+    output_len: usize,
+}
+
+impl HeatshrinkDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Self {
+            state: DecoderState::Tag,
+            bits: 0,
+            bit_count: 0,
+            window: [0; WINDOW_SIZE],
+            head: 0,
+            output: [0; OUTPUT_BUFFER_SIZE],
+            output_len: 0,
+        }
+    }
+    
+    /// Reset the decoder for a new stream
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+    
+    /// Total number of bytes decoded so far
+    pub fn decoded_len(&self) -> usize {
+        self.head
+    }
+    
+    /// Decode a chunk of compressed data
+    ///
+    /// Decoded bytes are passed to `sink` in pieces of at most 64 bytes;
+    /// bytes that do not fill a piece stay buffered until the next call
+    /// or `finish`. An error returned by `sink` aborts decoding.
+    pub fn decode<F, E>(&mut self, input: &[u8], mut sink: F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        for &byte in input {
+            self.bits = (self.bits << 8) | byte as u32;
+            self.bit_count += 8;
+            
+            while self.step(&mut sink)? {}
+        }
+        
+        Ok(())
+    }
+    
+    /// Pass the remaining buffered bytes to `sink`
+    ///
+    /// Unused bits of the last input byte are padding and are discarded.
+    /// A stream ending inside a literal or back-reference is truncated.
+    pub fn finish<F, E>(&mut self, mut sink: F) -> Result<(), DecodeError<E>>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        self.flush(&mut sink).map_err(DecodeError::Output)?;
+        
+        // Padding is at most 7 zero bits, read as a back-reference tag
+        let complete = match self.state {
+            DecoderState::Tag => true,
+            DecoderState::BackrefIndex => self.bit_count < 7 && self.bits == 0,
+            _ => false,
+        };
+        if !complete {
+            return Err(DecodeError::Truncated);
+        }
+        
+        Ok(())
+    }
+    
+    /// Decode one element, returns `false` when more input is needed
+    fn step<F, E>(&mut self, sink: &mut F) -> Result<bool, E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        match self.state {
+            DecoderState::Tag => match self.take_bits(1) {
+                Some(1) => self.state = DecoderState::Literal,
+                Some(_) => self.state = DecoderState::BackrefIndex,
+                None => return Ok(false),
+            },
+            DecoderState::Literal => match self.take_bits(8) {
+                Some(value) => {
+                    self.emit(value as u8, sink)?;
+                    self.state = DecoderState::Tag;
+                },
+                None => return Ok(false),
+            },
+            DecoderState::BackrefIndex => match self.take_bits(HEATSHRINK_WINDOW_BITS) {
+                Some(index) => self.state = DecoderState::BackrefCount { index },
+                None => return Ok(false),
+            },
+            DecoderState::BackrefCount { index } => match self.take_bits(HEATSHRINK_LOOKAHEAD_BITS) {
+                Some(count) => {
+                    let offset = index as usize + 1;
+                    for _ in 0..=count {
+                        // Positions before the start of the stream read as zero
+                        let byte = self.window[self.head.wrapping_sub(offset) & (WINDOW_SIZE - 1)];
+                        self.emit(byte, sink)?;
+                    }
+                    self.state = DecoderState::Tag;
+                },
+                None => return Ok(false),
+            },
+        }
+        
+        Ok(true)
+    }
+    
+    /// Take `count` bits (MSB first) from the bit buffer
+    fn take_bits(&mut self, count: u8) -> Option<u16> {
+        if self.bit_count < count {
+            return None;
+        }
+        
+        self.bit_count -= count;
+        let value = (self.bits >> self.bit_count) & ((1 << count) - 1);
+        self.bits &= (1 << self.bit_count) - 1;
+        
+        Some(value as u16)
+    }
+    
+    /// Append a decoded byte to the window and the output buffer
+    fn emit<F, E>(&mut self, byte: u8, sink: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        self.window[self.head & (WINDOW_SIZE - 1)] = byte;
+        self.head += 1;
+        
+        self.output[self.output_len] = byte;
+        self.output_len += 1;
+        
+        if self.output_len == OUTPUT_BUFFER_SIZE {
+            self.flush(sink)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Pass the output buffer to `sink`
+    fn flush<F, E>(&mut self, sink: &mut F) -> Result<(), E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        if self.output_len == 0 {
+            return Ok(());
+        }
+        
+        let len = self.output_len;
+        self.output_len = 0;
+        sink(&self.output[..len])
+    }
+}
+
+impl Default for HeatshrinkDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error while decoding a stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError<E> {
+    /// The stream ended inside a literal or back-reference
+    Truncated,
+    /// The output sink failed
+    Output(E),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+    
+    /// Stream element written by the test encoder
+    #[derive(Clone, Copy)]
+    enum Element {
+        Literal(u8),
+        Backref { index: u16, count: u16 },
+    }
+    
+    /// Encode elements MSB first with zero padding
+    fn encode(elements: &[Element]) -> Vec<u8> {
+        let mut bits = Vec::new();
+        let mut push = |value: u32, count: u8| {
+            for bit in (0..count).rev() {
+                bits.push((value >> bit) & 1 != 0);
+            }
+        };
+        
+        for element in elements {
+            match *element {
+                Element::Literal(byte) => {
+                    push(1, 1);
+                    push(byte as u32, 8);
+                },
+                Element::Backref { index, count } => {
+                    push(0, 1);
+                    push(index as u32, HEATSHRINK_WINDOW_BITS);
+                    push(count as u32, HEATSHRINK_LOOKAHEAD_BITS);
+                },
+            }
+        }
+        
+        bits.chunks(8)
+            .map(|byte| byte.iter().enumerate().fold(0, |acc, (i, &bit)| acc | (bit as u8) << (7 - i)))
+            .collect()
+    }
+    
+    /// Decode `input` split into the given chunks
+    fn decode_chunks(chunks: &[&[u8]]) -> Result<Vec<u8>, DecodeError<()>> {
+        let mut decoder = HeatshrinkDecoder::new();
+        let mut output = Vec::new();
+        let mut sink = |chunk: &[u8]| -> Result<(), ()> {
+            assert!(chunk.len() <= OUTPUT_BUFFER_SIZE);
+            output.extend_from_slice(chunk);
+            Ok(())
+        };
+        
+        for chunk in chunks {
+            decoder.decode(chunk, &mut sink).map_err(DecodeError::Output)?;
+        }
+        decoder.finish(&mut sink)?;
+        assert_eq!(decoder.decoded_len(), output.len());
+        
+        Ok(output)
+    }
+    
+    fn decode(input: &[u8]) -> Result<Vec<u8>, DecodeError<()>> {
+        decode_chunks(&[input])
+    }
+    
+    #[test]
+    fn literals() {
+        let data = b"Gridania";
+        let elements: Vec<_> = data.iter().map(|&byte| Element::Literal(byte)).collect();
+        
+        assert_eq!(decode(&encode(&elements)).unwrap(), data);
+        assert_eq!(decode(&[]).unwrap(), b"");
+    }
+    
+    #[test]
+    fn overlapping_backref() {
+        // A count beyond the offset repeats the referenced bytes
+        let stream = encode(&[
+            Element::Literal(b'a'),
+            Element::Literal(b'b'),
+            Element::Backref { index: 1, count: 15 },
+            Element::Literal(b'c'),
+        ]);
+        
+        assert_eq!(decode(&stream).unwrap(), b"abababababababababc");
+    }
+    
+    #[test]
+    fn backrefs_across_chunks() {
+        let mut elements: Vec<_> = (0..40u8).map(Element::Literal).collect();
+        for i in 0..20u16 {
+            elements.push(Element::Backref { index: 3 * i, count: i % 16 });
+            elements.push(Element::Literal(0xA0 | i as u8));
+        }
+        let stream = encode(&elements);
+        let expected = decode(&stream).unwrap();
+        assert!(expected.len() > 2 * OUTPUT_BUFFER_SIZE);
+        
+        // Every split point, and one byte at a time
+        for split in 0..=stream.len() {
+            let (first, second) = stream.split_at(split);
+            assert_eq!(decode_chunks(&[first, second]).unwrap(), expected);
+        }
+        let bytes: Vec<&[u8]> = stream.chunks(1).collect();
+        assert_eq!(decode_chunks(&bytes).unwrap(), expected);
+    }
+    
+    #[test]
+    fn backref_before_stream_start() {
+        // The window starts out zeroed like the reference decoder
+        let stream = encode(&[
+            Element::Literal(0x11),
+            Element::Backref { index: 200, count: 3 },
+            Element::Backref { index: 255, count: 0 },
+            Element::Literal(0x22),
+        ]);
+        
+        assert_eq!(decode(&stream).unwrap(), [0x11, 0, 0, 0, 0, 0, 0x22]);
+    }
+    
+    #[test]
+    fn backref_wraps_around_window() {
+        let mut elements: Vec<_> = (0..=255u8).map(Element::Literal).collect();
+        elements.extend((0..=255u8).map(Element::Literal));
+        elements.push(Element::Backref { index: 255, count: 2 });
+        let output = decode(&encode(&elements)).unwrap();
+        
+        // The oldest window byte is 256 bytes back
+        assert_eq!(output.len(), 515);
+        assert_eq!(output[512..], [0, 1, 2]);
+    }
+    
+    #[test]
+    fn padding() {
+        // 9 bits, 7 bits padding
+        assert_eq!(decode(&encode(&[Element::Literal(0x5A)])).unwrap(), [0x5A]);
+        // 72 bits, no padding
+        let data = [0xFF; 8];
+        let elements: Vec<_> = data.iter().map(|&byte| Element::Literal(byte)).collect();
+        assert_eq!(decode(&encode(&elements)).unwrap(), data);
+        
+        // Padding bits must be zero
+        let mut stream = encode(&[Element::Literal(0x5A)]);
+        *stream.last_mut().unwrap() |= 0x01;
+        assert_eq!(decode(&stream), Err(DecodeError::Truncated));
+    }
+    
+    #[test]
+    fn truncated_streams() {
+        // Each prefix ends inside a literal
+        let data = b"bootload";
+        let elements: Vec<_> = data.iter().map(|&byte| Element::Literal(byte)).collect();
+        let stream = encode(&elements);
+        assert_eq!(stream.len(), 9);
+        for length in 1..stream.len() {
+            assert_eq!(decode(&stream[..length]), Err(DecodeError::Truncated));
+        }
+        
+        // Ends inside the back-reference index and count
+        let mut elements: Vec<_> = b"crate".iter().map(|&byte| Element::Literal(byte)).collect();
+        elements.push(Element::Backref { index: 0xC0, count: 0xF });
+        let stream = encode(&elements);
+        assert_eq!(stream.len(), 8);
+        for length in 1..stream.len() {
+            assert_eq!(decode(&stream[..length]), Err(DecodeError::Truncated));
+        }
+        assert_eq!(decode(&stream).unwrap(), [&b"crate"[..], &[0; 16]].concat());
+    }
+    
+    #[test]
+    fn sink_error_aborts() {
+        let stream = encode(&[Element::Backref { index: 0, count: 15 }; 8]);
+        let mut decoder = HeatshrinkDecoder::new();
+        let mut calls = 0;
+        let result = decoder.decode(&stream, |_| {
+            calls += 1;
+            Err(0x72)
+        });
+        
+        assert_eq!(result, Err(0x72));
+        assert_eq!(calls, 1);
+        
+        let mut decoder = HeatshrinkDecoder::new();
+        decoder.decode(&encode(&[Element::Literal(0x11)]), |_| Err(0x72)).unwrap();
+        assert_eq!(decoder.finish(|_| Err(0x72)), Err(DecodeError::Output(0x72)));
+    }
+}
\ No newline at end of file
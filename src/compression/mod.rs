@@ -0,0 +1,2 @@
+pub mod heatshrink;
+pub mod delta;
\ No newline at end of file
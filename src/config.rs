@@ -0,0 +1,6 @@
+//! Build-time configuration
+//!
+//! Generated by `build.rs` from `config/<variant>.toml`, the variant is
+//! selected with the `ECU_VARIANT` environment variable.
+
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
@@ -0,0 +1,67 @@
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+/// AES block size in bytes
+pub const AES_BLOCK_SIZE: usize = 16;
+
+/// AES-128 key length in bytes
+pub const AES128_KEY_LENGTH: usize = 16;
+
+/// AES-128 block encryption with a key the caller never sees
+///
+/// Only the forward direction is needed: counter based modes decrypt by
+/// encrypting the counter blocks.
+pub trait BlockCipher {
+    /// Encrypt a single block in place
+    fn encrypt_block(&mut self, block: &mut [u8; AES_BLOCK_SIZE]) -> Result<(), CipherError>;
+}
+
+/// Software AES-128 for host tests and targets without a key store
+pub struct SoftwareAes128 {
+    cipher: Aes128,
+}
+
+impl SoftwareAes128 {
+    /// Create a software cipher from a raw key
+    pub fn new(key: &[u8; AES128_KEY_LENGTH]) -> Self {
+        Self {
+            cipher: Aes128::new(key.into()),
+        }
+    }
+}
+
+impl BlockCipher for SoftwareAes128 {
+    fn encrypt_block(&mut self, block: &mut [u8; AES_BLOCK_SIZE]) -> Result<(), CipherError> {
+        self.cipher.encrypt_block(block.into());
+        Ok(())
+    }
+}
+
+/// Cipher error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CipherError {
+    KeyNotAvailable,
+    HardwareFault,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn fips_197_vector() {
+        // FIPS-197 appendix C.1
+        let key: [u8; AES128_KEY_LENGTH] = core::array::from_fn(|i| i as u8);
+        let mut block: [u8; AES_BLOCK_SIZE] = core::array::from_fn(|i| (i as u8) * 0x11);
+        
+        SoftwareAes128::new(&key).encrypt_block(&mut block).unwrap();
+        
+        assert_eq!(
+            block,
+            [
+                0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+                0xc5, 0x5a
+            ]
+        );
+    }
+}
\ No newline at end of file
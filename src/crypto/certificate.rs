@@ -0,0 +1,464 @@
+use super::der::{DerReader, DerError, DER_TAG_BOOLEAN, DER_TAG_INTEGER, DER_TAG_OCTET_STRING, DER_TAG_OID, DER_TAG_SEQUENCE};
+use super::ecdsa::{verify_der, EcdsaError, P256_PUBLIC_KEY_LENGTH};
+
+// Object identifiers (DER content octets)
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1D, 0x13];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1D, 0x0F];
+
+/// OEM extension carrying the diagnostic role bitmask (1.3.6.1.4.1.47000.1.1)
+pub const OID_DIAGNOSTIC_ROLE: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xEF, 0x18, 0x01, 0x01];
+
+// Context specific tags of the TBSCertificate
+const TAG_VERSION: u8 = 0xA0;
+const TAG_ISSUER_UNIQUE_ID: u8 = 0x81;
+const TAG_SUBJECT_UNIQUE_ID: u8 = 0x82;
+const TAG_EXTENSIONS: u8 = 0xA3;
+
+/// X.509 v3 version number
+const X509_VERSION_3: u8 = 0x02;
+
+/// Intermediate CA certificates accepted between the root key and the client certificate
+pub const MAX_INTERMEDIATE_CERTIFICATES: usize = 1;
+
+/// Parsed X.509 certificate with an ECDSA P-256 subject key
+///
+/// Only the subset needed for diagnostic authentication is supported:
+/// ecdsa-with-SHA256 signatures, P-256 subject keys, basic constraints and
+/// the OEM role extension. The validity period is not evaluated because
+/// the ECU has no trusted time source.
+pub struct Certificate<'a> {
+    /// DER encoding of the signed TBSCertificate
+    pub tbs: &'a [u8],
+    /// DER encoded signature over `tbs`
+    pub signature: &'a [u8],
+    /// Serial number octets
+    pub serial: &'a [u8],
+    /// DER content of the issuer name
+    pub issuer: &'a [u8],
+    /// DER content of the subject name
+    pub subject: &'a [u8],
+    /// Uncompressed SEC1 subject public key
+    pub public_key: &'a [u8],
+    /// Diagnostic roles granted by the certificate
+    pub roles: u16,
+    /// Whether basic constraints mark the certificate as a CA
+    pub is_ca: bool,
+}
+
+impl<'a> Certificate<'a> {
+    /// Parse a DER encoded certificate
+    pub fn parse(der: &'a [u8]) -> Result<Self, CertificateError> {
+        match Self::parse_first(der)? {
+            (certificate, []) => Ok(certificate),
+            _ => Err(CertificateError::Format(DerError::InvalidContent)),
+        }
+    }
+    
+    /// Parse the first of several concatenated DER certificates, returning the remaining data
+    pub fn parse_first(der: &'a [u8]) -> Result<(Self, &'a [u8]), CertificateError> {
+        let mut outer = DerReader::new(der);
+        let (tag, content, encoding) = outer.read_any()?;
+        if tag != DER_TAG_SEQUENCE {
+            return Err(CertificateError::Format(DerError::UnexpectedTag));
+        }
+        let mut certificate = DerReader::new(content);
+        
+        // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signatureValue }
+        let (tag, tbs_content, tbs) = certificate.read_any()?;
+        if tag != DER_TAG_SEQUENCE {
+            return Err(CertificateError::Format(DerError::UnexpectedTag));
+        }
+        check_signature_algorithm(certificate.read(DER_TAG_SEQUENCE)?)?;
+        let signature = certificate.read_bit_string()?;
+        
+        let mut fields = DerReader::new(tbs_content);
+        
+        // Only v3 certificates carry extensions
+        let version = fields.read_optional(TAG_VERSION)?
+            .ok_or(CertificateError::UnsupportedVersion)?;
+        if DerReader::new(version).read(DER_TAG_INTEGER)? != [X509_VERSION_3] {
+            return Err(CertificateError::UnsupportedVersion);
+        }
+        
+        let serial = fields.read(DER_TAG_INTEGER)?;
+        check_signature_algorithm(fields.read(DER_TAG_SEQUENCE)?)?;
+        let issuer = fields.read(DER_TAG_SEQUENCE)?;
+        let _validity = fields.read(DER_TAG_SEQUENCE)?;
+        let subject = fields.read(DER_TAG_SEQUENCE)?;
+        let public_key = parse_public_key(fields.read(DER_TAG_SEQUENCE)?)?;
+        
+        fields.read_optional(TAG_ISSUER_UNIQUE_ID)?;
+        fields.read_optional(TAG_SUBJECT_UNIQUE_ID)?;
+        
+        let (roles, is_ca) = match fields.read_optional(TAG_EXTENSIONS)? {
+            Some(extensions) => parse_extensions(extensions)?,
+            None => (0, false),
+        };
+        
+        if !fields.is_empty() || !certificate.is_empty() {
+            return Err(CertificateError::Format(DerError::InvalidContent));
+        }
+        
+        let certificate = Self {
+            tbs,
+            signature,
+            serial,
+            issuer,
+            subject,
+            public_key,
+            roles,
+            is_ca,
+        };
+        
+        Ok((certificate, &der[encoding.len()..]))
+    }
+    
+    /// Verify the certificate signature with the issuer's public key
+    pub fn verify_signature(&self, issuer_public_key: &[u8]) -> Result<(), CertificateError> {
+        verify_der(issuer_public_key, self.tbs, self.signature).map_err(|error| match error {
+            EcdsaError::InvalidPublicKey => CertificateError::InvalidIssuerKey,
+            _ => CertificateError::InvalidSignature,
+        })
+    }
+}
+
+/// Verify a client certificate chain against the root key
+///
+/// `chain` is the client certificate, optionally followed by the
+/// certificate of the intermediate CA that issued it, both DER encoded.
+/// The intermediate must be a CA signed by the root key and name the
+/// client certificate's issuer as its subject; the client certificate
+/// must not be a CA. Longer chains are rejected.
+///
+/// Returns the client certificate and the roles granted to it, which an
+/// intermediate limits to its own roles.
+pub fn verify_chain<'a>(chain: &'a [u8], root_public_key: &[u8]) -> Result<(Certificate<'a>, u16), CertificateError> {
+    let (client, rest) = Certificate::parse_first(chain)?;
+    if client.is_ca {
+        return Err(CertificateError::UnexpectedCertificateAuthority);
+    }
+    
+    if rest.is_empty() {
+        client.verify_signature(root_public_key)?;
+        let roles = client.roles;
+        return Ok((client, roles));
+    }
+    
+    let (intermediate, rest) = Certificate::parse_first(rest)?;
+    if !rest.is_empty() {
+        return Err(CertificateError::ChainTooLong);
+    }
+    
+    if !intermediate.is_ca {
+        return Err(CertificateError::NotCertificateAuthority);
+    }
+    
+    if client.issuer != intermediate.subject {
+        return Err(CertificateError::IssuerMismatch);
+    }
+    
+    intermediate.verify_signature(root_public_key)?;
+    client.verify_signature(intermediate.public_key)?;
+    
+    let roles = client.roles & intermediate.roles;
+    Ok((client, roles))
+}
+
+/// Check that an AlgorithmIdentifier is ecdsa-with-SHA256
+fn check_signature_algorithm(algorithm: &[u8]) -> Result<(), CertificateError> {
+    let mut reader = DerReader::new(algorithm);
+    if reader.read(DER_TAG_OID)? != OID_ECDSA_WITH_SHA256 || !reader.is_empty() {
+        return Err(CertificateError::UnsupportedAlgorithm);
+    }
+    Ok(())
+}
+
+/// Extract the P-256 key from a SubjectPublicKeyInfo
+fn parse_public_key(info: &[u8]) -> Result<&[u8], CertificateError> {
+    let mut reader = DerReader::new(info);
+    let mut algorithm = DerReader::new(reader.read(DER_TAG_SEQUENCE)?);
+    
+    if algorithm.read(DER_TAG_OID)? != OID_EC_PUBLIC_KEY || algorithm.read(DER_TAG_OID)? != OID_PRIME256V1 {
+        return Err(CertificateError::UnsupportedAlgorithm);
+    }
+    
+    let key = reader.read_bit_string()?;
+    if key.len() != P256_PUBLIC_KEY_LENGTH || key[0] != 0x04 {
+        return Err(CertificateError::UnsupportedAlgorithm);
+    }
+    
+    Ok(key)
+}
+
+/// Walk the extensions, returning the diagnostic role bitmask and the CA flag
+fn parse_extensions(extensions: &[u8]) -> Result<(u16, bool), CertificateError> {
+    let mut outer = DerReader::new(extensions);
+    let mut list = DerReader::new(outer.read(DER_TAG_SEQUENCE)?);
+    let mut roles = 0;
+    let mut is_ca = false;
+    
+    while !list.is_empty() {
+        // Extension ::= SEQUENCE { extnID, critical BOOLEAN DEFAULT FALSE, extnValue }
+        let mut extension = DerReader::new(list.read(DER_TAG_SEQUENCE)?);
+        let oid = extension.read(DER_TAG_OID)?;
+        let critical = extension.read_optional(DER_TAG_BOOLEAN)?.is_some_and(|value| value != [0x00]);
+        let value = extension.read(DER_TAG_OCTET_STRING)?;
+        
+        if oid == OID_DIAGNOSTIC_ROLE {
+            let mut role = [0u8; 2];
+            DerReader::new(value).read_unsigned_integer(&mut role)?;
+            roles = u16::from_be_bytes(role);
+        } else if oid == OID_BASIC_CONSTRAINTS {
+            // BasicConstraints ::= SEQUENCE { cA BOOLEAN DEFAULT FALSE, pathLenConstraint INTEGER OPTIONAL }
+            let mut constraints = DerReader::new(DerReader::new(value).read(DER_TAG_SEQUENCE)?);
+            is_ca = constraints.read_optional(DER_TAG_BOOLEAN)?.is_some_and(|value| value != [0x00]);
+        } else if critical && oid != OID_KEY_USAGE {
+            // Unknown critical extensions must not be ignored
+            return Err(CertificateError::UnsupportedCriticalExtension);
+        }
+    }
+    
+    Ok((roles, is_ca))
+}
+
+/// Certificate error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CertificateError {
+    Format(DerError),
+    UnsupportedVersion,
+    UnsupportedAlgorithm,
+    UnsupportedCriticalExtension,
+    InvalidIssuerKey,
+    InvalidSignature,
+    IssuerMismatch,
+    NotCertificateAuthority,
+    UnexpectedCertificateAuthority,
+    ChainTooLong,
+}
+
+impl From<DerError> for CertificateError {
+    fn from(error: DerError) -> Self {
+        CertificateError::Format(error)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::{Signature, SigningKey};
+    use super::*;
+    
+    const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+    
+    /// Signing key derived from a single byte
+    pub(crate) fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 32]).unwrap()
+    }
+    
+    /// Uncompressed SEC1 public key of a signing key
+    pub(crate) fn public_key(key: &SigningKey) -> [u8; P256_PUBLIC_KEY_LENGTH] {
+        key.verifying_key().to_encoded_point(false).as_bytes().try_into().unwrap()
+    }
+    
+    /// DER encoding of an element
+    pub(crate) fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut encoding = vec![tag];
+        match content.len() {
+            0..=0x7F => encoding.push(content.len() as u8),
+            0x80..=0xFF => encoding.extend_from_slice(&[0x81, content.len() as u8]),
+            _ => encoding.extend_from_slice(&[0x82, (content.len() >> 8) as u8, content.len() as u8]),
+        }
+        encoding.extend_from_slice(content);
+        encoding
+    }
+    
+    fn integer(value: &[u8]) -> Vec<u8> {
+        let mut value = value;
+        while value.len() > 1 && value[0] == 0 && value[1] & 0x80 == 0 {
+            value = &value[1..];
+        }
+        
+        let mut content = Vec::new();
+        if value[0] & 0x80 != 0 {
+            content.push(0);
+        }
+        content.extend_from_slice(value);
+        tlv(DER_TAG_INTEGER, &content)
+    }
+    
+    fn name(common_name: &str) -> Vec<u8> {
+        let attribute = [tlv(DER_TAG_OID, OID_COMMON_NAME), tlv(0x0C, common_name.as_bytes())].concat();
+        tlv(DER_TAG_SEQUENCE, &tlv(0x31, &tlv(DER_TAG_SEQUENCE, &attribute)))
+    }
+    
+    /// DER encoded ECDSA signature
+    pub(crate) fn sign_der(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+        let signature: Signature = key.sign(message);
+        let raw = signature.to_bytes();
+        tlv(DER_TAG_SEQUENCE, &[integer(&raw[..32]), integer(&raw[32..])].concat())
+    }
+    
+    /// Certificate content used by `build`
+    pub(crate) struct Template<'a> {
+        pub(crate) subject: &'a str,
+        pub(crate) issuer: &'a str,
+        pub(crate) subject_key: &'a SigningKey,
+        pub(crate) issuer_key: &'a SigningKey,
+        pub(crate) roles: Option<u16>,
+        pub(crate) is_ca: bool,
+    }
+    
+    /// Build a DER certificate
+    pub(crate) fn build(template: &Template) -> Vec<u8> {
+        let algorithm = tlv(DER_TAG_SEQUENCE, &tlv(DER_TAG_OID, OID_ECDSA_WITH_SHA256));
+        let key_algorithm = tlv(DER_TAG_SEQUENCE, &[tlv(DER_TAG_OID, OID_EC_PUBLIC_KEY), tlv(DER_TAG_OID, OID_PRIME256V1)].concat());
+        let key = [&[0x00][..], &public_key(template.subject_key)].concat();
+        let key_info = tlv(DER_TAG_SEQUENCE, &[key_algorithm, tlv(0x03, &key)].concat());
+        
+        let mut extensions = Vec::new();
+        if template.is_ca {
+            let constraints = tlv(DER_TAG_SEQUENCE, &tlv(DER_TAG_BOOLEAN, &[0xFF]));
+            extensions.extend(tlv(DER_TAG_SEQUENCE, &[
+                tlv(DER_TAG_OID, OID_BASIC_CONSTRAINTS),
+                tlv(DER_TAG_BOOLEAN, &[0xFF]),
+                tlv(DER_TAG_OCTET_STRING, &constraints),
+            ].concat()));
+        }
+        if let Some(roles) = template.roles {
+            extensions.extend(tlv(DER_TAG_SEQUENCE, &[
+                tlv(DER_TAG_OID, OID_DIAGNOSTIC_ROLE),
+                tlv(DER_TAG_OCTET_STRING, &integer(&roles.to_be_bytes())),
+            ].concat()));
+        }
+        
+        let tbs = tlv(DER_TAG_SEQUENCE, &[
+            tlv(TAG_VERSION, &integer(&[X509_VERSION_3])),
+            integer(&[0x01, 0x23]),
+            algorithm.clone(),
+            name(template.issuer),
+            tlv(DER_TAG_SEQUENCE, &[]),
+            name(template.subject),
+            key_info,
+            tlv(TAG_EXTENSIONS, &tlv(DER_TAG_SEQUENCE, &extensions)),
+        ].concat());
+        
+        let signature = [&[0x00][..], &sign_der(template.issuer_key, &tbs)].concat();
+        tlv(DER_TAG_SEQUENCE, &[tbs, algorithm, tlv(0x03, &signature)].concat())
+    }
+    
+    /// Client certificate issued directly by the root
+    pub(crate) fn client_certificate(root: &SigningKey, client: &SigningKey, roles: u16) -> Vec<u8> {
+        build(&Template {
+            subject: "Tester",
+            issuer: "Root",
+            subject_key: client,
+            issuer_key: root,
+            roles: Some(roles),
+            is_ca: false,
+        })
+    }
+    
+    fn intermediate_certificate(root: &SigningKey, intermediate: &SigningKey, roles: u16, is_ca: bool) -> Vec<u8> {
+        build(&Template {
+            subject: "Workshop CA",
+            issuer: "Root",
+            subject_key: intermediate,
+            issuer_key: root,
+            roles: Some(roles),
+            is_ca,
+        })
+    }
+    
+    fn issued_certificate(intermediate: &SigningKey, client: &SigningKey, issuer: &str, roles: u16) -> Vec<u8> {
+        build(&Template {
+            subject: "Tester",
+            issuer,
+            subject_key: client,
+            issuer_key: intermediate,
+            roles: Some(roles),
+            is_ca: false,
+        })
+    }
+    
+    #[test]
+    fn parse_certificate() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let der = client_certificate(&root, &client, 0x0003);
+        
+        let certificate = Certificate::parse(&der).unwrap();
+        assert_eq!(certificate.public_key, public_key(&client));
+        assert_eq!(certificate.roles, 0x0003);
+        assert_eq!(certificate.serial, [0x01, 0x23]);
+        assert!(!certificate.is_ca);
+        assert!(certificate.verify_signature(&public_key(&root)).is_ok());
+        
+        // Trailing data after a single certificate
+        let trailing = [der.as_slice(), &[0x00]].concat();
+        assert_eq!(Certificate::parse(&trailing).err(), Some(CertificateError::Format(DerError::InvalidContent)));
+        assert!(Certificate::parse(&der[..der.len() - 1]).is_err());
+    }
+    
+    #[test]
+    fn chain_signed_by_root() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let der = client_certificate(&root, &client, 0x0002);
+        
+        let (certificate, roles) = verify_chain(&der, &public_key(&root)).unwrap();
+        assert_eq!(roles, 0x0002);
+        assert_eq!(certificate.public_key, public_key(&client));
+        
+        assert_eq!(verify_chain(&der, &public_key(&signing_key(3))).err(), Some(CertificateError::InvalidSignature));
+    }
+    
+    #[test]
+    fn chain_with_intermediate() {
+        let (root, intermediate, client) = (signing_key(1), signing_key(2), signing_key(3));
+        let chain = [
+            issued_certificate(&intermediate, &client, "Workshop CA", 0x0007),
+            intermediate_certificate(&root, &intermediate, 0x0003, true),
+        ].concat();
+        
+        // The intermediate limits the roles of the client certificate
+        let (certificate, roles) = verify_chain(&chain, &public_key(&root)).unwrap();
+        assert_eq!(roles, 0x0003);
+        assert_eq!(certificate.public_key, public_key(&client));
+    }
+    
+    #[test]
+    fn chain_errors() {
+        let (root, intermediate, client) = (signing_key(1), signing_key(2), signing_key(3));
+        let root_key = public_key(&root);
+        let client_der = issued_certificate(&intermediate, &client, "Workshop CA", 0x0001);
+        let intermediate_der = intermediate_certificate(&root, &intermediate, 0x0001, true);
+        
+        // Intermediate without the CA flag
+        let chain = [client_der.clone(), intermediate_certificate(&root, &intermediate, 0x0001, false)].concat();
+        assert_eq!(verify_chain(&chain, &root_key).err(), Some(CertificateError::NotCertificateAuthority));
+        
+        // Client certificate naming another issuer
+        let chain = [issued_certificate(&intermediate, &client, "Other CA", 0x0001), intermediate_der.clone()].concat();
+        assert_eq!(verify_chain(&chain, &root_key).err(), Some(CertificateError::IssuerMismatch));
+        
+        // Intermediate not signed by the root
+        let chain = [client_der.clone(), intermediate_certificate(&signing_key(4), &intermediate, 0x0001, true)].concat();
+        assert_eq!(verify_chain(&chain, &root_key).err(), Some(CertificateError::InvalidSignature));
+        
+        // Client certificate not signed by the intermediate
+        let chain = [issued_certificate(&signing_key(4), &client, "Workshop CA", 0x0001), intermediate_der.clone()].concat();
+        assert_eq!(verify_chain(&chain, &root_key).err(), Some(CertificateError::InvalidSignature));
+        
+        // A second intermediate
+        let chain = [client_der.clone(), intermediate_der.clone(), intermediate_der.clone()].concat();
+        assert_eq!(verify_chain(&chain, &root_key).err(), Some(CertificateError::ChainTooLong));
+        
+        // A CA certificate used as client certificate
+        assert_eq!(verify_chain(&intermediate_der, &root_key).err(), Some(CertificateError::UnexpectedCertificateAuthority));
+        
+        // Garbage after the chain
+        let chain = [client_der, vec![0x30, 0x00]].concat();
+        assert!(matches!(verify_chain(&chain, &root_key), Err(CertificateError::Format(_))));
+    }
+}
\ No newline at end of file
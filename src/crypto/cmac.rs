@@ -0,0 +1,65 @@
+use super::aes::{BlockCipher, CipherError, SoftwareAes128, AES_BLOCK_SIZE};
+
+/// Length of an AES-CMAC tag in bytes
+pub const CMAC_LENGTH: usize = 16;
+
+/// Message authentication with a key the caller never sees
+pub trait MacGenerator {
+    /// Compute the AES-128 CMAC of `message`
+    fn generate_mac(&mut self, message: &[u8]) -> Result<[u8; CMAC_LENGTH], CipherError>;
+}
+
+impl MacGenerator for SoftwareAes128 {
+    fn generate_mac(&mut self, message: &[u8]) -> Result<[u8; CMAC_LENGTH], CipherError> {
+        cmac(self, message)
+    }
+}
+
+/// AES-CMAC (RFC 4493) on top of a block cipher
+pub fn cmac(cipher: &mut dyn BlockCipher, message: &[u8]) -> Result<[u8; CMAC_LENGTH], CipherError> {
+    // Subkeys from the encrypted zero block
+    let mut k1 = [0u8; AES_BLOCK_SIZE];
+    cipher.encrypt_block(&mut k1)?;
+    k1 = double(&k1);
+    let k2 = double(&k1);
+    
+    // All blocks but the last one are chained as in CBC-MAC
+    let blocks = message.len().div_ceil(AES_BLOCK_SIZE).max(1);
+    let mut state = [0u8; AES_BLOCK_SIZE];
+    
+    for block in message.chunks(AES_BLOCK_SIZE).take(blocks - 1) {
+        xor(&mut state, block);
+        cipher.encrypt_block(&mut state)?;
+    }
+    
+    // The last block is masked with K1 if complete, padded and masked with K2 otherwise
+    let last = &message[(blocks - 1) * AES_BLOCK_SIZE..];
+    if last.len() == AES_BLOCK_SIZE {
+        xor(&mut state, last);
+        xor(&mut state, &k1);
+    } else {
+        let mut padded = [0u8; AES_BLOCK_SIZE];
+        padded[..last.len()].copy_from_slice(last);
+        padded[last.len()] = 0x80;
+        xor(&mut state, &padded);
+        xor(&mut state, &k2);
+    }
+    cipher.encrypt_block(&mut state)?;
+    
+    k1.fill(0);
+    Ok(state)
+}
+
+/// Multiply by x in GF(2^128)
+fn double(block: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+    let value = u128::from_be_bytes(*block);
+    let reduction = if value >> 127 == 1 { 0x87 } else { 0 };
+    ((value << 1) ^ reduction).to_be_bytes()
+}
+
+/// XOR `data` into `state`
+fn xor(state: &mut [u8; AES_BLOCK_SIZE], data: &[u8]) {
+    for (state, byte) in state.iter_mut().zip(data) {
+        *state ^= byte;
+    }
+}
\ No newline at end of file
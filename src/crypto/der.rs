@@ -0,0 +1,138 @@
+// ASN.1 DER tags
+pub const DER_TAG_BOOLEAN: u8 = 0x01;
+pub const DER_TAG_INTEGER: u8 = 0x02;
+pub const DER_TAG_BIT_STRING: u8 = 0x03;
+pub const DER_TAG_OCTET_STRING: u8 = 0x04;
+pub const DER_TAG_OID: u8 = 0x06;
+pub const DER_TAG_SEQUENCE: u8 = 0x30;
+
+/// Minimal zero-copy reader for ASN.1 DER structures
+pub struct DerReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> DerReader<'a> {
+    /// Create a reader over DER encoded data
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+    
+    /// Check if all data has been consumed
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+    
+    /// Tag of the next element without consuming it
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+    
+    /// Read the next element, returning its tag, content and complete encoding
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), DerError> {
+        let start = self.position;
+        let tag = *self.data.get(self.position).ok_or(DerError::Truncated)?;
+        
+        // Multi-byte tags are not used by the supported structures
+        if tag & 0x1F == 0x1F {
+            return Err(DerError::UnsupportedTag);
+        }
+        
+        let first = *self.data.get(self.position + 1).ok_or(DerError::Truncated)?;
+        let mut header = 2;
+        let length = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            // Long form, at most 3 length bytes and no non-minimal encodings
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 3 {
+                return Err(DerError::InvalidLength);
+            }
+            
+            let mut length = 0usize;
+            for i in 0..count {
+                let byte = *self.data.get(self.position + 2 + i).ok_or(DerError::Truncated)?;
+                length = (length << 8) | byte as usize;
+            }
+            header += count;
+            
+            if length < 0x80 {
+                return Err(DerError::InvalidLength);
+            }
+            length
+        };
+        
+        let content_start = self.position + header;
+        let end = content_start.checked_add(length).ok_or(DerError::InvalidLength)?;
+        if end > self.data.len() {
+            return Err(DerError::Truncated);
+        }
+        
+        self.position = end;
+        Ok((tag, &self.data[content_start..end], &self.data[start..end]))
+    }
+    
+    /// Read the next element and check its tag, returning its content
+    pub fn read(&mut self, expected_tag: u8) -> Result<&'a [u8], DerError> {
+        let (tag, content, _) = self.read_any()?;
+        if tag != expected_tag {
+            return Err(DerError::UnexpectedTag);
+        }
+        Ok(content)
+    }
+    
+    /// Read the next element only if it has the given tag
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, DerError> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+    
+    /// Read a BIT STRING without unused bits
+    pub fn read_bit_string(&mut self) -> Result<&'a [u8], DerError> {
+        let content = self.read(DER_TAG_BIT_STRING)?;
+        match content.split_first() {
+            Some((0, bits)) => Ok(bits),
+            _ => Err(DerError::InvalidContent),
+        }
+    }
+    
+    /// Read a non-negative INTEGER into a fixed size big-endian buffer
+    pub fn read_unsigned_integer(&mut self, output: &mut [u8]) -> Result<(), DerError> {
+        let mut content = self.read(DER_TAG_INTEGER)?;
+        
+        // Negative numbers are not valid here
+        if content.first().is_none_or(|b| b & 0x80 != 0) {
+            return Err(DerError::InvalidContent);
+        }
+        
+        // Strip the sign padding byte, which DER only allows in front of a set high bit
+        if content.len() > 1 && content[0] == 0 {
+            if content[1] & 0x80 == 0 {
+                return Err(DerError::InvalidContent);
+            }
+            content = &content[1..];
+        }
+        
+        if content.len() > output.len() {
+            return Err(DerError::InvalidContent);
+        }
+        
+        let offset = output.len() - content.len();
+        output[..offset].fill(0);
+        output[offset..].copy_from_slice(content);
+        Ok(())
+    }
+}
+
+/// DER decoding error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DerError {
+    Truncated,
+    InvalidLength,
+    UnexpectedTag,
+    UnsupportedTag,
+    InvalidContent,
+}
\ No newline at end of file
@@ -0,0 +1,318 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use super::entropy::{self, EntropySource, EntropyError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Output length of the underlying hash function
+const OUTLEN: usize = 32;
+
+/// Entropy input length used for instantiation and reseeding
+const ENTROPY_LENGTH: usize = 32;
+
+/// Nonce length used for instantiation
+const NONCE_LENGTH: usize = 16;
+
+/// Number of generate requests before a reseed is required
+const RESEED_INTERVAL: u32 = 1024;
+
+/// Maximum number of bytes per generate request
+const MAX_REQUEST_LENGTH: usize = 256;
+
+/// HMAC-DRBG with SHA-256 (NIST SP 800-90A)
+pub struct HmacDrbg {
+    /// Key of the internal state
+    key: [u8; OUTLEN],
+    /// Value of the internal state
+    value: [u8; OUTLEN],
+    /// Number of generate requests since the last (re)seed
+    reseed_counter: u32,
+}
+
+impl HmacDrbg {
+    /// Instantiate the DRBG from an entropy source
+    ///
+    /// Entropy input failing the repetition count test is refused, a stuck
+    /// source would otherwise give the same output after every reset.
+    pub fn new(entropy: &mut dyn EntropySource, personalization: &[u8]) -> Result<Self, DrbgError> {
+        let mut entropy_input = [0u8; ENTROPY_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        entropy.fill_entropy(&mut entropy_input).map_err(DrbgError::Entropy)?;
+        entropy.fill_entropy(&mut nonce).map_err(DrbgError::Entropy)?;
+        entropy::repetition_count_test(&entropy_input).map_err(DrbgError::Entropy)?;
+        entropy::repetition_count_test(&nonce).map_err(DrbgError::Entropy)?;
+        if entropy_input[..NONCE_LENGTH] == nonce {
+            return Err(DrbgError::Entropy(EntropyError::HealthTestFailed));
+        }
+        
+        let mut drbg = Self {
+            key: [0x00; OUTLEN],
+            value: [0x01; OUTLEN],
+            reseed_counter: 1,
+        };
+        drbg.update(&[&entropy_input, &nonce, personalization]);
+        
+        // Do not keep the seed material around
+        entropy_input.fill(0);
+        nonce.fill(0);
+        
+        Ok(drbg)
+    }
+    
+    /// Reseed the DRBG with fresh entropy
+    pub fn reseed(&mut self, entropy: &mut dyn EntropySource, additional: &[u8]) -> Result<(), DrbgError> {
+        let mut entropy_input = [0u8; ENTROPY_LENGTH];
+        entropy.fill_entropy(&mut entropy_input).map_err(DrbgError::Entropy)?;
+        entropy::repetition_count_test(&entropy_input).map_err(DrbgError::Entropy)?;
+        
+        self.update(&[&entropy_input, additional]);
+        self.reseed_counter = 1;
+        
+        entropy_input.fill(0);
+        Ok(())
+    }
+    
+    /// Check if the DRBG must be reseeded before the next generate request
+    pub fn reseed_required(&self) -> bool {
+        self.reseed_counter > RESEED_INTERVAL
+    }
+    
+    /// Generate pseudo-random bytes
+    pub fn generate(&mut self, output: &mut [u8], additional: &[u8]) -> Result<(), DrbgError> {
+        if output.len() > MAX_REQUEST_LENGTH {
+            return Err(DrbgError::RequestTooLong);
+        }
+        
+        if self.reseed_required() {
+            return Err(DrbgError::ReseedRequired);
+        }
+        
+        if !additional.is_empty() {
+            self.update(&[additional]);
+        }
+        
+        for chunk in output.chunks_mut(OUTLEN) {
+            self.value = self.hmac(&[&self.value]);
+            chunk.copy_from_slice(&self.value[..chunk.len()]);
+        }
+        
+        self.update(&[additional]);
+        self.reseed_counter += 1;
+        
+        Ok(())
+    }
+    
+    /// HMAC-DRBG update function
+    fn update(&mut self, provided: &[&[u8]]) {
+        let has_data = provided.iter().any(|part| !part.is_empty());
+        
+        self.key = self.hmac_with_separator(0x00, provided);
+        self.value = self.hmac(&[&self.value]);
+        
+        if has_data {
+            self.key = self.hmac_with_separator(0x01, provided);
+            self.value = self.hmac(&[&self.value]);
+        }
+    }
+    
+    /// Compute HMAC(K, V || separator || provided)
+    fn hmac_with_separator(&self, separator: u8, provided: &[&[u8]]) -> [u8; OUTLEN] {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(&self.value);
+        mac.update(&[separator]);
+        for part in provided {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    }
+    
+    /// Compute HMAC(K, data)
+    fn hmac(&self, data: &[&[u8]]) -> [u8; OUTLEN] {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        for part in data {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes().into()
+    }
+}
+
+/// HMAC-DRBG instantiated on first use from a registered entropy source
+///
+/// Reseeds automatically from the same source when the reseed interval has
+/// been reached.
+pub struct RandomGenerator {
+    /// Entropy source used for instantiation and reseeding
+    entropy: Option<*mut dyn EntropySource>,
+    /// DRBG state, `None` until the first request
+    drbg: Option<HmacDrbg>,
+    /// Personalization string separating independent generators
+    personalization: &'static [u8],
+}
+
+impl RandomGenerator {
+    /// Create a new generator without entropy source
+    pub const fn new(personalization: &'static [u8]) -> Self {
+        Self {
+            entropy: None,
+            drbg: None,
+            personalization,
+        }
+    }
+    
+    /// Register the entropy source, discarding any previous DRBG state
+    pub fn register_entropy_source(&mut self, entropy: *mut (dyn EntropySource + 'static)) {
+        self.entropy = Some(entropy);
+        self.drbg = None;
+    }
+    
+    /// Fill the output with pseudo-random bytes
+    pub fn fill(&mut self, output: &mut [u8]) -> Result<(), DrbgError> {
+        let entropy = match self.entropy {
+            // Safety: We know this pointer is valid
+            Some(entropy) => unsafe { &mut *entropy },
+            None => return Err(DrbgError::Entropy(EntropyError::NotAvailable)),
+        };
+        
+        let drbg = match &mut self.drbg {
+            Some(drbg) => {
+                if drbg.reseed_required() {
+                    drbg.reseed(entropy, &[])?;
+                }
+                drbg
+            },
+            None => self.drbg.insert(HmacDrbg::new(entropy, self.personalization)?),
+        };
+        
+        drbg.generate(output, &[])
+    }
+}
+
+/// DRBG error types
+#[derive(Debug)]
+pub enum DrbgError {
+    Entropy(EntropyError),
+    ReseedRequired,
+    RequestTooLong,
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+    use crate::crypto::entropy::MockEntropy;
+    
+    /// Entropy source stuck at one byte value
+    struct StuckEntropy(u8);
+    
+    impl EntropySource for StuckEntropy {
+        fn fill_entropy(&mut self, buffer: &mut [u8]) -> Result<(), EntropyError> {
+            buffer.fill(self.0);
+            Ok(())
+        }
+    }
+    
+    /// Entropy source repeating the same counting pattern for every request
+    struct RepeatingEntropy;
+    
+    impl EntropySource for RepeatingEntropy {
+        fn fill_entropy(&mut self, buffer: &mut [u8]) -> Result<(), EntropyError> {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = i as u8;
+            }
+            Ok(())
+        }
+    }
+    
+    /// Entropy source becoming stuck after a number of bytes
+    struct FailingEntropy {
+        inner: MockEntropy,
+        remaining: usize,
+    }
+    
+    impl EntropySource for FailingEntropy {
+        fn fill_entropy(&mut self, buffer: &mut [u8]) -> Result<(), EntropyError> {
+            if self.remaining < buffer.len() {
+                buffer.fill(0xFF);
+                return Ok(());
+            }
+            self.remaining -= buffer.len();
+            self.inner.fill_entropy(buffer)
+        }
+    }
+    
+    fn is_health_test_failure(result: Result<(), DrbgError>) -> bool {
+        matches!(result, Err(DrbgError::Entropy(EntropyError::HealthTestFailed)))
+    }
+    
+    #[test]
+    fn stuck_entropy_is_refused() {
+        for value in [0x00, 0xFF, 0xA5] {
+            assert!(is_health_test_failure(HmacDrbg::new(&mut StuckEntropy(value), b"test").map(|_| ())));
+        }
+        assert!(is_health_test_failure(HmacDrbg::new(&mut RepeatingEntropy, b"test").map(|_| ())));
+    }
+    
+    #[test]
+    fn stuck_entropy_is_refused_on_reseed() {
+        let mut entropy = FailingEntropy { inner: MockEntropy::new(1), remaining: ENTROPY_LENGTH + NONCE_LENGTH };
+        let mut drbg = HmacDrbg::new(&mut entropy, b"test").unwrap();
+        
+        assert!(is_health_test_failure(drbg.reseed(&mut entropy, &[])));
+    }
+    
+    #[test]
+    fn generator_without_entropy_fails() {
+        let mut generator = RandomGenerator::new(b"test");
+        assert!(matches!(generator.fill(&mut [0u8; 4]), Err(DrbgError::Entropy(EntropyError::NotAvailable))));
+        
+        generator.register_entropy_source(Box::leak(Box::new(StuckEntropy(0x00))));
+        assert!(is_health_test_failure(generator.fill(&mut [0u8; 4])));
+    }
+    
+    #[test]
+    fn outputs_never_repeat() {
+        let mut generator = RandomGenerator::new(b"test");
+        generator.register_entropy_source(Box::leak(Box::new(MockEntropy::new(7))));
+        
+        // Crosses the reseed interval twice
+        let mut outputs = HashSet::new();
+        for _ in 0..2 * RESEED_INTERVAL + 16 {
+            let mut output = [0u8; 16];
+            generator.fill(&mut output).unwrap();
+            
+            assert!(output != [0x00; 16] && output != [0xFF; 16]);
+            assert!(outputs.insert(output), "output repeated");
+        }
+    }
+    
+    #[test]
+    fn personalization_separates_generators() {
+        let mut a = HmacDrbg::new(&mut MockEntropy::new(3), b"security access").unwrap();
+        let mut b = HmacDrbg::new(&mut MockEntropy::new(3), b"authentication").unwrap();
+        let mut c = HmacDrbg::new(&mut MockEntropy::new(3), b"security access").unwrap();
+        
+        let (mut x, mut y, mut z) = ([0u8; 32], [0u8; 32], [0u8; 32]);
+        a.generate(&mut x, &[]).unwrap();
+        b.generate(&mut y, &[]).unwrap();
+        c.generate(&mut z, &[]).unwrap();
+        
+        assert_ne!(x, y);
+        assert_eq!(x, z);
+    }
+    
+    #[test]
+    fn reseed_is_required_after_interval() {
+        let mut drbg = HmacDrbg::new(&mut MockEntropy::new(5), b"test").unwrap();
+        let mut output = [0u8; 4];
+        
+        for _ in 0..RESEED_INTERVAL {
+            drbg.generate(&mut output, &[]).unwrap();
+        }
+        assert!(matches!(drbg.generate(&mut output, &[]), Err(DrbgError::ReseedRequired)));
+        
+        drbg.reseed(&mut MockEntropy::new(6), &[]).unwrap();
+        assert!(drbg.generate(&mut output, &[]).is_ok());
+        
+        let mut long = [0u8; MAX_REQUEST_LENGTH + 1];
+        assert!(matches!(drbg.generate(&mut long, &[]), Err(DrbgError::RequestTooLong)));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,65 @@
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use super::der::{DerReader, DerError, DER_TAG_SEQUENCE};
+
+/// Length of an uncompressed SEC1 P-256 public key
+pub const P256_PUBLIC_KEY_LENGTH: usize = 65;
+
+/// Length of a raw (r || s) P-256 signature
+pub const P256_SIGNATURE_LENGTH: usize = 64;
+
+/// Verify an ECDSA P-256/SHA-256 signature in raw (r || s) form
+///
+/// Used for image headers, which carry the fixed size form. The `p256`
+/// arithmetic works on fixed size values without heap or recursion, the
+/// stack use does not depend on the input. `tools/ecdsa-check` runs the
+/// Wycheproof vectors through this function and `verify_der`.
+pub fn verify_raw(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), EcdsaError> {
+    let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| EcdsaError::InvalidPublicKey)?;
+    verify(&key, message, signature)
+}
+
+/// Verify an ASN.1 DER encoded ECDSA P-256/SHA-256 signature
+///
+/// Used for certificates and the proof of ownership, which carry an
+/// ECDSA-Sig-Value. Encodings DER forbids are rejected.
+pub fn verify_der(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), EcdsaError> {
+    let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| EcdsaError::InvalidPublicKey)?;
+    
+    let mut raw = [0u8; P256_SIGNATURE_LENGTH];
+    decode_der_signature(signature, &mut raw).map_err(|_| EcdsaError::InvalidSignature)?;
+    
+    verify(&key, message, &raw)
+}
+
+/// Verify a raw (r || s) signature with a decoded key
+fn verify(key: &VerifyingKey, message: &[u8], raw: &[u8]) -> Result<(), EcdsaError> {
+    let signature = Signature::from_slice(raw).map_err(|_| EcdsaError::InvalidSignature)?;
+    key.verify(message, &signature).map_err(|_| EcdsaError::VerificationFailed)
+}
+
+/// Convert a DER encoded ECDSA-Sig-Value into raw (r || s)
+fn decode_der_signature(der: &[u8], raw: &mut [u8; P256_SIGNATURE_LENGTH]) -> Result<(), DerError> {
+    let mut outer = DerReader::new(der);
+    let mut sequence = DerReader::new(outer.read(DER_TAG_SEQUENCE)?);
+    if !outer.is_empty() {
+        return Err(DerError::InvalidContent);
+    }
+    
+    let (r, s) = raw.split_at_mut(P256_SIGNATURE_LENGTH / 2);
+    sequence.read_unsigned_integer(r)?;
+    sequence.read_unsigned_integer(s)?;
+    
+    if !sequence.is_empty() {
+        return Err(DerError::InvalidContent);
+    }
+    Ok(())
+}
+
+/// ECDSA error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EcdsaError {
+    InvalidPublicKey,
+    InvalidSignature,
+    VerificationFailed,
+}
\ No newline at end of file
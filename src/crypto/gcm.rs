@@ -0,0 +1,250 @@
+use ghash::universal_hash::{KeyInit, UniversalHash};
+use ghash::GHash;
+use super::aes::{BlockCipher, CipherError, AES_BLOCK_SIZE};
+
+/// GCM initialization vector length (96-bit IVs only)
+pub const GCM_IV_LENGTH: usize = 12;
+
+/// GCM authentication tag length
+pub const GCM_TAG_LENGTH: usize = 16;
+
+/// Streaming AES-GCM decryption without additional authenticated data
+///
+/// Ciphertext may be passed in chunks of any size. The cipher is handed in
+/// on every call so the key can stay in a hardware key slot.
+pub struct GcmDecryptor {
+    /// GHASH state over the ciphertext
+    ghash: GHash,
+    /// Current counter block
+    counter: [u8; AES_BLOCK_SIZE],
+    /// Keystream of the current counter block
+    keystream: [u8; AES_BLOCK_SIZE],
+    /// Keystream bytes already used
+    keystream_used: usize,
+    /// Ciphertext not yet hashed (partial block)
+    pending: [u8; AES_BLOCK_SIZE],
+    /// Number of valid bytes in `pending`
+    pending_len: usize,
+    /// Encrypted pre-counter block E(K, J0)
+    tag_mask: [u8; AES_BLOCK_SIZE],
+    /// Total ciphertext length in bytes
+    length: u64,
+}
+
+impl GcmDecryptor {
+    /// Start decrypting a stream with the given IV
+    pub fn new(cipher: &mut dyn BlockCipher, iv: &[u8; GCM_IV_LENGTH]) -> Result<Self, CipherError> {
+        // Hash subkey H = E(K, 0^128)
+        let mut hash_key = [0u8; AES_BLOCK_SIZE];
+        cipher.encrypt_block(&mut hash_key)?;
+        
+        // J0 = IV || 0^31 || 1
+        let mut counter = [0u8; AES_BLOCK_SIZE];
+        counter[..GCM_IV_LENGTH].copy_from_slice(iv);
+        counter[AES_BLOCK_SIZE - 1] = 1;
+        
+        let mut tag_mask = counter;
+        cipher.encrypt_block(&mut tag_mask)?;
+        
+        let decryptor = Self {
+            ghash: GHash::new(&hash_key.into()),
+            counter,
+            keystream: [0; AES_BLOCK_SIZE],
+            keystream_used: AES_BLOCK_SIZE,
+            pending: [0; AES_BLOCK_SIZE],
+            pending_len: 0,
+            tag_mask,
+            length: 0,
+        };
+        
+        hash_key.fill(0);
+        Ok(decryptor)
+    }
+    
+    /// Decrypt a chunk of ciphertext in place
+    pub fn decrypt(&mut self, cipher: &mut dyn BlockCipher, data: &mut [u8]) -> Result<(), CipherError> {
+        for byte in data.iter_mut() {
+            // Authenticate the ciphertext
+            self.pending[self.pending_len] = *byte;
+            self.pending_len += 1;
+            if self.pending_len == AES_BLOCK_SIZE {
+                self.ghash.update(&[self.pending.into()]);
+                self.pending_len = 0;
+            }
+            
+            if self.keystream_used == AES_BLOCK_SIZE {
+                self.next_keystream(cipher)?;
+            }
+            
+            *byte ^= self.keystream[self.keystream_used];
+            self.keystream_used += 1;
+        }
+        
+        self.length += data.len() as u64;
+        Ok(())
+    }
+    
+    /// Check the authentication tag of the complete stream
+    pub fn verify(mut self, tag: &[u8]) -> bool {
+        if self.pending_len > 0 {
+            self.ghash.update_padded(&self.pending[..self.pending_len]);
+        }
+        
+        // len(A) || len(C) in bits
+        let mut lengths = [0u8; AES_BLOCK_SIZE];
+        lengths[8..].copy_from_slice(&(self.length * 8).to_be_bytes());
+        self.ghash.update(&[lengths.into()]);
+        
+        let expected = self.ghash.finalize();
+        
+        if tag.len() != GCM_TAG_LENGTH {
+            return false;
+        }
+        
+        // Compare without an early exit
+        let mut diff = 0u8;
+        for i in 0..GCM_TAG_LENGTH {
+            diff |= expected[i] ^ self.tag_mask[i] ^ tag[i];
+        }
+        
+        diff == 0
+    }
+    
+    /// Increment the 32-bit counter and encrypt it
+    fn next_keystream(&mut self, cipher: &mut dyn BlockCipher) -> Result<(), CipherError> {
+        let mut value = [0u8; 4];
+        value.copy_from_slice(&self.counter[12..]);
+        let value = u32::from_be_bytes(value).wrapping_add(1);
+        self.counter[12..].copy_from_slice(&value.to_be_bytes());
+        
+        self.keystream = self.counter;
+        cipher.encrypt_block(&mut self.keystream)?;
+        self.keystream_used = 0;
+        
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::aes::SoftwareAes128;
+    
+    /// AES-128-GCM test case of the GCM specification, without AAD
+    struct Vector {
+        key: &'static str,
+        iv: &'static str,
+        plaintext: &'static str,
+        ciphertext: &'static str,
+        tag: &'static str,
+    }
+    
+    /// Test cases 1 to 3 of McGrew and Viega, as used by NIST CAVP
+    const VECTORS: [Vector; 3] = [
+        Vector {
+            key: "00000000000000000000000000000000",
+            iv: "000000000000000000000000",
+            plaintext: "",
+            ciphertext: "",
+            tag: "58e2fccefa7e3061367f1d57a4e7455a",
+        },
+        Vector {
+            key: "00000000000000000000000000000000",
+            iv: "000000000000000000000000",
+            plaintext: "00000000000000000000000000000000",
+            ciphertext: "0388dace60b6a392f328c2b971b2fe78",
+            tag: "ab6e47d42cec13bdf53a67b21257bddf",
+        },
+        Vector {
+            key: "feffe9928665731c6d6a8f9467308308",
+            iv: "cafebabefacedbaddecaf888",
+            plaintext: "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                        1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255",
+            ciphertext: "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                         21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985",
+            tag: "4d5c2af327cd64a62cf35abd2ba6fab4",
+        },
+    ];
+    
+    fn hex(text: &str) -> std::vec::Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+    
+    /// Decrypt `ciphertext` split at `split` and check `tag`
+    fn decrypt(vector: &Vector, split: usize, tag: &[u8]) -> (std::vec::Vec<u8>, bool) {
+        let mut cipher = SoftwareAes128::new(&hex(vector.key).try_into().unwrap());
+        let iv = hex(vector.iv).try_into().unwrap();
+        let mut data = hex(vector.ciphertext);
+        
+        let mut gcm = GcmDecryptor::new(&mut cipher, &iv).unwrap();
+        let (first, second) = data.split_at_mut(split);
+        gcm.decrypt(&mut cipher, first).unwrap();
+        gcm.decrypt(&mut cipher, second).unwrap();
+        let authentic = gcm.verify(tag);
+        
+        (data, authentic)
+    }
+    
+    #[test]
+    fn nist_vectors_every_split() {
+        for vector in &VECTORS {
+            let tag = hex(vector.tag);
+            for split in 0..=hex(vector.ciphertext).len() {
+                let (plaintext, authentic) = decrypt(vector, split, &tag);
+                assert_eq!(
+                    plaintext,
+                    hex(vector.plaintext),
+                    "key {}, split {split}",
+                    vector.key
+                );
+                assert!(authentic, "key {}, split {split}", vector.key);
+            }
+        }
+    }
+    
+    #[test]
+    fn byte_by_byte() {
+        let vector = &VECTORS[2];
+        let mut cipher = SoftwareAes128::new(&hex(vector.key).try_into().unwrap());
+        let mut gcm = GcmDecryptor::new(&mut cipher, &hex(vector.iv).try_into().unwrap()).unwrap();
+        let mut data = hex(vector.ciphertext);
+        
+        for byte in data.chunks_mut(1) {
+            gcm.decrypt(&mut cipher, byte).unwrap();
+        }
+        
+        assert_eq!(data, hex(vector.plaintext));
+        assert!(gcm.verify(&hex(vector.tag)));
+    }
+    
+    #[test]
+    fn wrong_tag_rejected() {
+        for vector in &VECTORS {
+            let tag = hex(vector.tag);
+            for bit in 0..tag.len() * 8 {
+                let mut wrong = tag.clone();
+                wrong[bit / 8] ^= 1 << (bit % 8);
+                assert!(!decrypt(vector, 0, &wrong).1);
+            }
+            
+            // Truncated and extended tags are not accepted
+            assert!(!decrypt(vector, 0, &tag[..GCM_TAG_LENGTH - 1]).1);
+            assert!(!decrypt(vector, 0, &[tag.as_slice(), &[0]].concat()).1);
+            assert!(!decrypt(vector, 0, &[]).1);
+        }
+    }
+    
+    #[test]
+    fn modified_ciphertext_rejected() {
+        let vector = &VECTORS[2];
+        let mut cipher = SoftwareAes128::new(&hex(vector.key).try_into().unwrap());
+        let mut gcm = GcmDecryptor::new(&mut cipher, &hex(vector.iv).try_into().unwrap()).unwrap();
+        let mut data = hex(vector.ciphertext);
+        data[17] ^= 0x01;
+        
+        gcm.decrypt(&mut cipher, &mut data).unwrap();
+        assert!(!gcm.verify(&hex(vector.tag)));
+    }
+}
\ No newline at end of file
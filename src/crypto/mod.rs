@@ -0,0 +1,10 @@
+pub mod entropy;
+pub mod drbg;
+pub mod der;
+#[cfg(feature = "ecdsa")]
+pub mod ecdsa;
+#[cfg(feature = "ecdsa")]
+pub mod certificate;
+pub mod aes;
+pub mod gcm;
+pub mod cmac;
\ No newline at end of file
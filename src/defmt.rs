@@ -1,6 +1,23 @@
+#[cfg(not(test))]
 use defmt_rtt as _;
 
 #[no_mangle]
 fn _defmt_timestamp() -> u64 {
     0
-} 
+}
+
+/// Logger discarding the output of host tests
+#[cfg(test)]
+#[defmt::global_logger]
+struct TestLogger;
+
+#[cfg(test)]
+unsafe impl defmt::Logger for TestLogger {
+    fn acquire() {}
+    
+    unsafe fn flush() {}
+    
+    unsafe fn release() {}
+    
+    unsafe fn write(_bytes: &[u8]) {}
+}
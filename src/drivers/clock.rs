@@ -1,5 +1,4 @@
 // use defmt::{debug, info};
-use crate::hal::s32k148::peripherals::SCG;
 
 /// Clock configuration for S32K148
 pub struct Clock {
@@ -27,10 +26,23 @@ impl Clock {
         // 2. Configure PLL
         // 3. Set up system clock dividers
         // 4. Switch to the desired clock source
+        self.configure_sosc();
+        self.configure_pll();
+        self.configure_system_clock();
         
         // println!("Clock system initialized at {} Hz", self.system_clock_hz);
     }
     
+    /// Restore the reset clock configuration (e.g. before starting the application)
+    pub fn deinit(&mut self) {
+        // Return to the reset clock configuration:
+        // 1. Switch the system clock back to FIRC
+        // 2. Disable SPLL and SOSC
+        // 3. Restore the reset dividers
+        
+        self.system_clock_hz = 48_000_000; // FIRC after reset
+    }
+    
     /// Get the system clock frequency in Hz
     pub fn get_system_clock_hz(&self) -> u32 {
         self.system_clock_hz
@@ -66,4 +78,10 @@ impl Clock {
         // - Switch to desired source
         // - Wait for clock switch to complete
     }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
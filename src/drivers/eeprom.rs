@@ -0,0 +1,110 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::bootloader::nvm::{NvStorage, NvmError, NVM_SIZE};
+
+/// FlexRAM base address in EEPROM emulation mode
+const FLEXRAM_BASE: u32 = 0x1400_0000;
+
+/// FTFC registers
+const FTFC_FSTAT: u32 = 0x4002_0000;
+const FTFC_FCNFG: u32 = 0x4002_0001;
+
+// Register bits
+const FSTAT_CCIF: u8 = 0x80;
+const FSTAT_ACCERR: u8 = 0x20;
+const FSTAT_FPVIOL: u8 = 0x10;
+const FCNFG_EEERDY: u8 = 0x01;
+
+/// Emulated EEPROM (FlexRAM/FlexNVM) of the S32K148
+///
+/// The FlexNVM must have been partitioned for EEPROM emulation (PGMPART)
+/// during production. Aligned 32-bit writes are atomic with respect to
+/// power loss.
+pub struct Eeprom {
+    initialized: bool,
+}
+
+impl Eeprom {
+    /// Create a new emulated EEPROM driver
+    pub fn new() -> Self {
+        Self {
+            initialized: false,
+        }
+    }
+    
+    /// Initialize the emulated EEPROM
+    pub fn init(&mut self) {
+        // Safety: FTFC_FCNFG is a fixed memory-mapped register
+        self.initialized = unsafe { read_volatile(FTFC_FCNFG as *const u8) } & FCNFG_EEERDY != 0;
+    }
+    
+    /// Wait until the previous EEPROM write has completed
+    fn wait_ready(&self) -> Result<(), NvmError> {
+        // Safety: FTFC_FSTAT is a fixed memory-mapped register
+        unsafe {
+            while read_volatile(FTFC_FSTAT as *const u8) & FSTAT_CCIF == 0 {}
+            
+            if read_volatile(FTFC_FSTAT as *const u8) & (FSTAT_ACCERR | FSTAT_FPVIOL) != 0 {
+                write_volatile(FTFC_FSTAT as *mut u8, FSTAT_ACCERR | FSTAT_FPVIOL);
+                return Err(NvmError::WriteError);
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NvStorage for Eeprom {
+    fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError> {
+        if !self.initialized {
+            return Err(NvmError::NotReady);
+        }
+        
+        if offset as usize + buffer.len() > NVM_SIZE {
+            return Err(NvmError::InvalidAddress);
+        }
+        
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            // Safety: the range was checked against the FlexRAM size
+            *byte = unsafe { read_volatile((FLEXRAM_BASE + offset + i as u32) as *const u8) };
+        }
+        
+        Ok(())
+    }
+    
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), NvmError> {
+        if !self.initialized {
+            return Err(NvmError::NotReady);
+        }
+        
+        if offset as usize + data.len() > NVM_SIZE {
+            return Err(NvmError::InvalidAddress);
+        }
+        
+        let mut index = 0;
+        while index < data.len() {
+            let address = FLEXRAM_BASE + offset + index as u32;
+            
+            self.wait_ready()?;
+            
+            // Safety: the range was checked against the FlexRAM size
+            unsafe {
+                if address.is_multiple_of(4) && data.len() - index >= 4 {
+                    let word = u32::from_le_bytes([data[index], data[index + 1], data[index + 2], data[index + 3]]);
+                    write_volatile(address as *mut u32, word);
+                    index += 4;
+                } else {
+                    write_volatile(address as *mut u8, data[index]);
+                    index += 1;
+                }
+            }
+        }
+        
+        self.wait_ready()
+    }
+}
\ No newline at end of file
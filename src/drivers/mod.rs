@@ -1,4 +1,6 @@
 pub mod clock;
 pub mod gpio;
 pub mod watchdog;
-pub mod power;
\ No newline at end of file
+pub mod power;
+pub mod eeprom;
+pub mod systick;
\ No newline at end of file
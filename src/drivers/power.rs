@@ -33,6 +33,13 @@ impl Power {
         self.reset_cause
     }
     
+    /// Take the reset cause from an `RCM_SRS` value read earlier
+    ///
+    /// Used with the reset status stage 0 passes in its handoff record.
+    pub fn set_reset_status(&mut self, status: u32) {
+        self.reset_cause = ResetCause::from_status(status);
+    }
+    
     /// Set power mode
     pub fn set_power_mode(&mut self, mode: PowerMode) -> Result<(), PowerError> {
         // println!("Setting power mode to {:?}", mode);
@@ -59,6 +66,12 @@ impl Power {
     }
 }
 
+impl Default for Power {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Power modes for S32K148
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PowerMode {
@@ -82,6 +95,42 @@ pub enum ResetCause {
     LowVoltage,
 }
 
+impl ResetCause {
+    /// Decode the RCM system reset status register (`RCM_SRS`)
+    ///
+    /// Several bits may be set, a power-on reset also sets the low-voltage
+    /// detect bit, so the bits are checked from the most to the least
+    /// significant cause.
+    pub fn from_status(status: u32) -> Self {
+        const SRS_LVD: u32 = 1 << 1;
+        const SRS_WDOG: u32 = 1 << 5;
+        const SRS_PIN: u32 = 1 << 6;
+        const SRS_POR: u32 = 1 << 7;
+        const SRS_JTAG: u32 = 1 << 8;
+        const SRS_LOCKUP: u32 = 1 << 9;
+        const SRS_SW: u32 = 1 << 10;
+        const SRS_MDM_AP: u32 = 1 << 11;
+        
+        if status & SRS_POR != 0 {
+            ResetCause::PowerOn
+        } else if status & SRS_LVD != 0 {
+            ResetCause::LowVoltage
+        } else if status & SRS_WDOG != 0 {
+            ResetCause::Watchdog
+        } else if status & SRS_LOCKUP != 0 {
+            ResetCause::Lockup
+        } else if status & (SRS_JTAG | SRS_MDM_AP) != 0 {
+            ResetCause::Jtag
+        } else if status & SRS_SW != 0 {
+            ResetCause::Software
+        } else if status & SRS_PIN != 0 {
+            ResetCause::External
+        } else {
+            ResetCause::Unknown
+        }
+    }
+}
+
 impl core::fmt::Display for ResetCause {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
@@ -0,0 +1,73 @@
+use core::ptr::write_volatile;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// SysTick registers
+const SYST_CSR: u32 = 0xE000_E010;
+const SYST_RVR: u32 = 0xE000_E014;
+const SYST_CVR: u32 = 0xE000_E018;
+
+// SYST_CSR bits
+const CSR_ENABLE: u32 = 1 << 0;
+const CSR_TICKINT: u32 = 1 << 1;
+const CSR_CLKSOURCE: u32 = 1 << 2;
+
+/// Milliseconds elapsed since the timer was started
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+
+/// Millisecond time base using the Cortex-M SysTick timer
+///
+/// The SysTick exception handler must call `tick`.
+pub struct SysTickTimer {
+    running: bool,
+}
+
+impl SysTickTimer {
+    /// Create a new SysTick timer
+    pub fn new() -> Self {
+        Self {
+            running: false,
+        }
+    }
+    
+    /// Start a 1 ms tick from the core clock
+    pub fn init(&mut self, core_clock_hz: u32) {
+        // Safety: SysTick registers are fixed core peripherals
+        unsafe {
+            write_volatile(SYST_RVR as *mut u32, core_clock_hz / 1000 - 1);
+            write_volatile(SYST_CVR as *mut u32, 0);
+            write_volatile(SYST_CSR as *mut u32, CSR_CLKSOURCE | CSR_TICKINT | CSR_ENABLE);
+        }
+        
+        self.running = true;
+    }
+    
+    /// Stop the tick (e.g. before handing over to the application)
+    pub fn deinit(&mut self) {
+        // Safety: SysTick registers are fixed core peripherals
+        unsafe {
+            write_volatile(SYST_CSR as *mut u32, 0);
+            write_volatile(SYST_CVR as *mut u32, 0);
+        }
+        
+        self.running = false;
+    }
+}
+
+impl Default for SysTickTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the milliseconds elapsed since the timer was started
+pub fn millis() -> u32 {
+    MILLIS.load(Ordering::Relaxed)
+}
+
+/// Advance the time base by one tick
+///
+/// Called from the SysTick exception handler, which the binary defines so
+/// that the library does not claim the exception.
+pub fn tick() {
+    MILLIS.fetch_add(1, Ordering::Relaxed);
+}
\ No newline at end of file
@@ -1,5 +1,4 @@
 // use defmt::{debug, info};
-use crate::hal::s32k148::peripherals::WDOG;
 
 /// Watchdog timer controller for S32K148
 pub struct Watchdog {
@@ -63,7 +62,7 @@ impl Watchdog {
         }
         
         // Validate timeout range
-        if timeout_ms < 10 || timeout_ms > 10000 {
+        if !(10..=10000).contains(&timeout_ms) {
             return Err(WatchdogError::InvalidTimeout);
         }
         
@@ -79,6 +78,12 @@ impl Watchdog {
     }
 }
 
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Watchdog error types
 #[derive(Debug)]
 pub enum WatchdogError {
@@ -1,4 +1,4 @@
-/// Cortex-M4 specific functionality
+//! Cortex-M4 specific functionality
 
 /// Enable interrupts
 #[inline(always)]
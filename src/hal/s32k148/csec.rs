@@ -0,0 +1,692 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::crypto::entropy::{EntropySource, EntropyError};
+use crate::crypto::aes::{BlockCipher, CipherError, AES_BLOCK_SIZE};
+use crate::crypto::cmac::{MacGenerator, CMAC_LENGTH};
+use crate::bootloader::secure_boot::SecureBootEngine;
+
+/// FTFC flash status register
+const FTFC_FSTAT: u32 = 0x4002_0000;
+
+/// FTFC CSEc status register (SREG)
+const FTFC_FCSESTAT: u32 = 0x4002_002C;
+
+/// CSEc parameter RAM (command interface)
+const CSE_PRAM_BASE: u32 = 0x1400_1000;
+
+// FTFC_FSTAT bits
+const FSTAT_CCIF: u8 = 0x80;
+const FSTAT_ACCERR: u8 = 0x20;
+const FSTAT_FPVIOL: u8 = 0x10;
+
+// CSEc command identifiers
+pub const CSEC_CMD_ENC_ECB: u8 = 0x01;
+pub const CSEC_CMD_ENC_CBC: u8 = 0x02;
+pub const CSEC_CMD_DEC_ECB: u8 = 0x03;
+pub const CSEC_CMD_DEC_CBC: u8 = 0x04;
+pub const CSEC_CMD_GENERATE_MAC: u8 = 0x05;
+pub const CSEC_CMD_VERIFY_MAC: u8 = 0x06;
+pub const CSEC_CMD_LOAD_KEY: u8 = 0x07;
+pub const CSEC_CMD_LOAD_PLAIN_KEY: u8 = 0x08;
+pub const CSEC_CMD_INIT_RNG: u8 = 0x0A;
+pub const CSEC_CMD_RND: u8 = 0x0C;
+pub const CSEC_CMD_BOOT_FAILURE: u8 = 0x0E;
+pub const CSEC_CMD_BOOT_OK: u8 = 0x0F;
+pub const CSEC_CMD_GET_ID: u8 = 0x10;
+pub const CSEC_CMD_BOOT_DEFINE: u8 = 0x11;
+
+// CSEc command format and call sequence
+pub const CSEC_FORMAT_COPY: u8 = 0x00;
+pub const CSEC_CALL_SEQ_FIRST: u8 = 0x00;
+pub const CSEC_CALL_SEQ_SUBSEQUENT: u8 = 0x01;
+
+// CSEc error codes (ERC field of the command header)
+pub const CSEC_ERC_NO_ERROR: u16 = 0x0001;
+pub const CSEC_ERC_SEQUENCE_ERROR: u16 = 0x0002;
+pub const CSEC_ERC_KEY_NOT_AVAILABLE: u16 = 0x0004;
+pub const CSEC_ERC_KEY_INVALID: u16 = 0x0008;
+pub const CSEC_ERC_KEY_EMPTY: u16 = 0x0010;
+pub const CSEC_ERC_NO_SECURE_BOOT: u16 = 0x0020;
+pub const CSEC_ERC_KEY_WRITE_PROTECTED: u16 = 0x0040;
+pub const CSEC_ERC_KEY_UPDATE_ERROR: u16 = 0x0080;
+pub const CSEC_ERC_RNG_SEED: u16 = 0x0100;
+pub const CSEC_ERC_NO_DEBUGGING: u16 = 0x0200;
+pub const CSEC_ERC_MEMORY_FAILURE: u16 = 0x0400;
+pub const CSEC_ERC_GENERAL_ERROR: u16 = 0x0800;
+
+// Status register (SREG) bits
+pub const CSEC_SREG_BUSY: u8 = 0x01;
+pub const CSEC_SREG_SECURE_BOOT: u8 = 0x02;
+pub const CSEC_SREG_BOOT_INIT: u8 = 0x04;
+pub const CSEC_SREG_BOOT_FINISHED: u8 = 0x08;
+pub const CSEC_SREG_BOOT_OK: u8 = 0x10;
+pub const CSEC_SREG_RND_INIT: u8 = 0x20;
+pub const CSEC_SREG_EXT_DEBUGGER: u8 = 0x40;
+pub const CSEC_SREG_INT_DEBUGGER: u8 = 0x80;
+
+// Key flags of the key update protocol (6 bit field of M2)
+pub const CSEC_FLAG_WRITE_PROTECTION: u8 = 0x20;
+pub const CSEC_FLAG_BOOT_PROTECTION: u8 = 0x10;
+pub const CSEC_FLAG_DEBUGGER_PROTECTION: u8 = 0x08;
+pub const CSEC_FLAG_KEY_USAGE: u8 = 0x04;
+pub const CSEC_FLAG_WILDCARD: u8 = 0x02;
+pub const CSEC_FLAG_VERIFY_ONLY: u8 = 0x01;
+
+/// Size of a CSEc parameter RAM page in bytes
+pub const CSEC_PAGE_SIZE: u32 = 16;
+
+/// Offset of the PAGE_LENGTH field (blocks of the whole operation) of the cipher commands
+pub const CSEC_PAGE_LENGTH_OFFSET: u32 = 0x0C;
+
+/// Offset of the VERIFICATION_STATUS field (upper half word, 0 if the MAC matches)
+pub const CSEC_VERIFICATION_STATUS_OFFSET: u32 = 0x14;
+
+/// Offset of the MAC_LENGTH field (in bits) of VERIFY_MAC
+pub const CSEC_MAC_LENGTH_OFFSET: u32 = 0x18;
+
+/// Offset of the word holding BOOT_FLAVOR in its lowest byte
+pub const CSEC_BOOT_FLAVOR_OFFSET: u32 = 0x18;
+
+/// Offset of the MESSAGE_LENGTH field (in bits) of the MAC commands
+pub const CSEC_MESSAGE_LENGTH_OFFSET: u32 = 0x1C;
+
+/// Offset of the BOOT_SIZE field (in bytes) of BOOT_DEFINE
+pub const CSEC_BOOT_SIZE_OFFSET: u32 = 0x1C;
+
+/// Parameter RAM pages holding message data
+pub const CSEC_LAST_DATA_PAGE: u32 = 7;
+
+/// Length of the unique device identifier
+pub const CSEC_UID_LENGTH: usize = 15;
+
+/// Length of M1-M3 of a key update
+pub const CSEC_KEY_UPDATE_LENGTH: usize = 64;
+
+/// Length of M4-M5 of a key update
+pub const CSEC_KEY_PROOF_LENGTH: usize = 48;
+
+/// Master key authorizing the update of all other keys
+pub const CSEC_MASTER_ECU_KEY: u8 = 0x01;
+
+/// Key of the secure boot MAC
+pub const CSEC_BOOT_MAC_KEY: u8 = 0x02;
+
+/// Expected secure boot MAC
+pub const CSEC_BOOT_MAC: u8 = 0x03;
+
+/// User key slot holding the firmware decryption key
+///
+/// Provision it for encryption (`CSEC_FLAG_KEY_USAGE` clear).
+pub const CSEC_KEY_1: u8 = 0x04;
+
+/// User key slot holding the device attestation key
+///
+/// Provision it for MAC generation (`CSEC_FLAG_KEY_USAGE`) with the
+/// `CSEC_FLAG_BOOT_PROTECTION` flag so it can only be used after a
+/// successful secure boot.
+pub const CSEC_KEY_2: u8 = 0x05;
+
+/// Volatile key, loaded in plain text
+pub const CSEC_RAM_KEY: u8 = 0x0F;
+
+/// Access to the CSEc command interface
+///
+/// `FtfcPort` is the engine of the S32K148. Host tools run the driver on
+/// a software model of it through the same interface.
+pub trait CsecPort {
+    /// Write a word of the parameter RAM
+    fn write_word(&mut self, offset: u32, value: u32);
+    
+    /// Read a word of the parameter RAM
+    fn read_word(&self, offset: u32) -> u32;
+    
+    /// Launch the command with the given header word and wait for its completion
+    fn execute(&mut self, header: u32);
+    
+    /// Status register (`CSEC_SREG_*` bits)
+    fn status(&self) -> u8;
+}
+
+/// CSEc command interface of the FTFC flash controller
+pub struct FtfcPort;
+
+impl CsecPort for FtfcPort {
+    fn write_word(&mut self, offset: u32, value: u32) {
+        // Safety: the driver only passes offsets within the CSE_PRAM window
+        unsafe { write_volatile((CSE_PRAM_BASE + offset) as *mut u32, value) };
+    }
+    
+    fn read_word(&self, offset: u32) -> u32 {
+        // Safety: the driver only passes offsets within the CSE_PRAM window
+        unsafe { read_volatile((CSE_PRAM_BASE + offset) as *const u32) }
+    }
+    
+    fn execute(&mut self, header: u32) {
+        // Safety: FTFC and CSE_PRAM are fixed memory-mapped peripherals
+        unsafe {
+            // Wait for any previous flash or CSEc command to complete
+            while read_volatile(FTFC_FSTAT as *const u8) & FSTAT_CCIF == 0 {}
+            
+            // Clear stale error flags (write 1 to clear)
+            write_volatile(FTFC_FSTAT as *mut u8, FSTAT_ACCERR | FSTAT_FPVIOL);
+            
+            // Writing the command header launches the command
+            write_volatile(CSE_PRAM_BASE as *mut u32, header);
+            
+            while read_volatile(FTFC_FSTAT as *const u8) & FSTAT_CCIF == 0 {}
+        }
+    }
+    
+    fn status(&self) -> u8 {
+        // Safety: fixed memory-mapped FTFC register
+        unsafe { read_volatile(FTFC_FCSESTAT as *const u8) }
+    }
+}
+
+/// Secure boot flavor of BOOT_DEFINE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootFlavor {
+    /// Bootloader checked before the CPU starts, the flavor cannot be changed afterwards
+    Strict,
+    /// Bootloader checked before the CPU starts
+    Serial,
+    /// Bootloader checked while the CPU runs
+    Parallel,
+    /// No secure boot
+    NoBoot,
+}
+
+impl BootFlavor {
+    /// Decode the BOOT_FLAVOR byte
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Strict),
+            0x01 => Some(Self::Serial),
+            0x02 => Some(Self::Parallel),
+            0x03 => Some(Self::NoBoot),
+            _ => None,
+        }
+    }
+    
+    /// Encode the BOOT_FLAVOR byte
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Self::Strict => 0x00,
+            Self::Serial => 0x01,
+            Self::Parallel => 0x02,
+            Self::NoBoot => 0x03,
+        }
+    }
+}
+
+/// Messages M1-M3 of the SHE key update protocol
+///
+/// M1 names the device (UID), the key and the authorizing key, M2 holds
+/// the encrypted counter, flags and key, M3 is the CMAC over M1 and M2
+/// with a key derived from the authorizing key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUpdateMessages {
+    pub m1: [u8; 16],
+    pub m2: [u8; 32],
+    pub m3: [u8; 16],
+}
+
+impl KeyUpdateMessages {
+    /// Split M1 | M2 | M3 as sent by the tester
+    pub fn parse(data: &[u8; CSEC_KEY_UPDATE_LENGTH]) -> Self {
+        let mut messages = Self { m1: [0; 16], m2: [0; 32], m3: [0; 16] };
+        messages.m1.copy_from_slice(&data[..16]);
+        messages.m2.copy_from_slice(&data[16..48]);
+        messages.m3.copy_from_slice(&data[48..]);
+        messages
+    }
+    
+    /// Serialize M1 | M2 | M3
+    pub fn to_bytes(self) -> [u8; CSEC_KEY_UPDATE_LENGTH] {
+        let mut data = [0u8; CSEC_KEY_UPDATE_LENGTH];
+        data[..16].copy_from_slice(&self.m1);
+        data[16..48].copy_from_slice(&self.m2);
+        data[48..].copy_from_slice(&self.m3);
+        data
+    }
+    
+    /// Key slot the messages update
+    pub fn key_id(&self) -> u8 {
+        self.m1[15] >> 4
+    }
+}
+
+/// Messages M4-M5 the engine returns after storing a key
+///
+/// M4 holds the encrypted counter and M5 its CMAC, both with keys
+/// derived from the new key: they prove the key was stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUpdateProof {
+    pub m4: [u8; 32],
+    pub m5: [u8; 16],
+}
+
+impl KeyUpdateProof {
+    /// Serialize M4 | M5
+    pub fn to_bytes(self) -> [u8; CSEC_KEY_PROOF_LENGTH] {
+        let mut data = [0u8; CSEC_KEY_PROOF_LENGTH];
+        data[..32].copy_from_slice(&self.m4);
+        data[32..].copy_from_slice(&self.m5);
+        data
+    }
+}
+
+/// Device identification of GET_ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceId {
+    /// Unique device identifier
+    pub uid: [u8; CSEC_UID_LENGTH],
+    /// Status register at the time of the command
+    pub status: u8,
+    /// CMAC over challenge, UID and status with the MASTER_ECU_KEY (0 if it is empty)
+    pub mac: [u8; CMAC_LENGTH],
+}
+
+/// CSEc (SHE-compatible) security engine of the S32K148
+///
+/// Parameter RAM layout of the commands, in 16 byte pages after the
+/// header page (command header, error code, PAGE_LENGTH):
+///
+/// ```text
+/// ENC/DEC_ECB    pages 1-7 data, in place; PAGE_LENGTH in the first call
+/// ENC/DEC_CBC    first call: page 1 IV, pages 2-7 data; then pages 1-7
+/// GENERATE_MAC   MESSAGE_LENGTH; first call pages 2-7, then pages 1-7;
+///                MAC in page 2
+/// VERIFY_MAC     as GENERATE_MAC plus MAC_LENGTH, the MAC in the page
+///                after the message; VERIFICATION_STATUS
+/// LOAD_KEY       page 1 M1, pages 2-3 M2, page 4 M3 -> pages 5-6 M4, page 7 M5
+/// LOAD_PLAIN_KEY page 1 key (RAM key only)
+/// RND            page 1 random bits
+/// GET_ID         page 1 challenge -> page 2 UID and SREG, page 3 MAC
+/// BOOT_DEFINE    BOOT_SIZE, BOOT_FLAVOR
+/// ```
+pub struct Csec<P: CsecPort = FtfcPort> {
+    /// Command interface
+    port: P,
+    /// Whether the random number generator has been initialized
+    rng_initialized: bool,
+    /// Key slot used for block encryption
+    cipher_key_id: u8,
+    /// Key slot used for MAC generation
+    mac_key_id: u8,
+}
+
+impl Csec {
+    /// Create a new CSEc driver instance
+    pub fn new() -> Self {
+        Self::with_port(FtfcPort)
+    }
+}
+
+impl Default for Csec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: CsecPort> Csec<P> {
+    /// Create a driver on another command interface (a software model on the host)
+    pub fn with_port(port: P) -> Self {
+        Self {
+            port,
+            rng_initialized: false,
+            cipher_key_id: CSEC_KEY_1,
+            mac_key_id: CSEC_KEY_2,
+        }
+    }
+    
+    /// Command interface of the driver
+    pub fn port(&mut self) -> &mut P {
+        &mut self.port
+    }
+    
+    /// Status register (`CSEC_SREG_*` bits)
+    pub fn status(&self) -> u8 {
+        self.port.status()
+    }
+    
+    /// Initialize the CSEc random number generator
+    pub fn init_rng(&mut self) -> Result<(), CsecError> {
+        self.run_command(CSEC_CMD_INIT_RNG, CSEC_CALL_SEQ_FIRST, 0)?;
+        self.rng_initialized = true;
+        Ok(())
+    }
+    
+    /// Generate 128 random bits with the CSEc PRNG
+    pub fn generate_random(&mut self, output: &mut [u8; 16]) -> Result<(), CsecError> {
+        if !self.rng_initialized {
+            self.init_rng()?;
+        }
+        
+        self.run_command(CSEC_CMD_RND, CSEC_CALL_SEQ_FIRST, 0)?;
+        self.read_page(1, output);
+        
+        Ok(())
+    }
+    
+    /// Select the key slot used for block encryption
+    pub fn set_cipher_key(&mut self, key_id: u8) {
+        self.cipher_key_id = key_id;
+    }
+    
+    /// Encrypt whole blocks in place with AES-128-ECB using a key slot
+    pub fn encrypt_ecb(&mut self, key_id: u8, data: &mut [u8]) -> Result<(), CsecError> {
+        self.run_cipher(CSEC_CMD_ENC_ECB, key_id, None, data)
+    }
+    
+    /// Decrypt whole blocks in place with AES-128-ECB using a key slot
+    pub fn decrypt_ecb(&mut self, key_id: u8, data: &mut [u8]) -> Result<(), CsecError> {
+        self.run_cipher(CSEC_CMD_DEC_ECB, key_id, None, data)
+    }
+    
+    /// Encrypt whole blocks in place with AES-128-CBC using a key slot
+    pub fn encrypt_cbc(&mut self, key_id: u8, iv: &[u8; 16], data: &mut [u8]) -> Result<(), CsecError> {
+        self.run_cipher(CSEC_CMD_ENC_CBC, key_id, Some(iv), data)
+    }
+    
+    /// Decrypt whole blocks in place with AES-128-CBC using a key slot
+    pub fn decrypt_cbc(&mut self, key_id: u8, iv: &[u8; 16], data: &mut [u8]) -> Result<(), CsecError> {
+        self.run_cipher(CSEC_CMD_DEC_CBC, key_id, Some(iv), data)
+    }
+    
+    /// Select the key slot used for MAC generation
+    pub fn set_mac_key(&mut self, key_id: u8) {
+        self.mac_key_id = key_id;
+    }
+    
+    /// Compute the AES-128 CMAC of a message using a key slot
+    pub fn generate_mac(&mut self, key_id: u8, message: &[u8], mac: &mut [u8; 16]) -> Result<(), CsecError> {
+        self.port.write_word(CSEC_MESSAGE_LENGTH_OFFSET, message.len() as u32 * 8);
+        self.run_message(CSEC_CMD_GENERATE_MAC, key_id, message, None)?;
+        self.read_page(2, mac);
+        
+        Ok(())
+    }
+    
+    /// Check the AES-128 CMAC of a message using a key slot
+    ///
+    /// Returns whether all 128 bits of `mac` match.
+    pub fn verify_mac(&mut self, key_id: u8, message: &[u8], mac: &[u8; 16]) -> Result<bool, CsecError> {
+        self.port.write_word(CSEC_MESSAGE_LENGTH_OFFSET, message.len() as u32 * 8);
+        self.port.write_word(CSEC_MAC_LENGTH_OFFSET, CMAC_LENGTH as u32 * 8);
+        self.run_message(CSEC_CMD_VERIFY_MAC, key_id, message, Some(mac))?;
+        
+        Ok(self.port.read_word(CSEC_VERIFICATION_STATUS_OFFSET) >> 16 == 0)
+    }
+    
+    /// Store a key with the SHE key update protocol
+    ///
+    /// The engine checks M1-M3 against the authorizing key, the device UID
+    /// and the counter of the key slot.
+    pub fn load_key(&mut self, messages: &KeyUpdateMessages) -> Result<KeyUpdateProof, CsecError> {
+        self.write_page(1, &messages.m1);
+        self.write_page(2, messages.m2[..16].try_into().unwrap());
+        self.write_page(3, messages.m2[16..].try_into().unwrap());
+        self.write_page(4, &messages.m3);
+        
+        self.run_command(CSEC_CMD_LOAD_KEY, CSEC_CALL_SEQ_FIRST, messages.key_id())?;
+        
+        let mut proof = KeyUpdateProof { m4: [0; 32], m5: [0; 16] };
+        let (m4_first, m4_second) = proof.m4.split_at_mut(16);
+        self.read_page(5, m4_first.try_into().unwrap());
+        self.read_page(6, m4_second.try_into().unwrap());
+        self.read_page(7, &mut proof.m5);
+        
+        Ok(proof)
+    }
+    
+    /// Load the volatile RAM key in plain text
+    pub fn load_plain_key(&mut self, key: &[u8; 16]) -> Result<(), CsecError> {
+        self.write_page(1, key);
+        self.run_command(CSEC_CMD_LOAD_PLAIN_KEY, CSEC_CALL_SEQ_FIRST, CSEC_RAM_KEY)
+    }
+    
+    /// Read the device identification, authenticated over `challenge`
+    pub fn get_id(&mut self, challenge: &[u8; 16]) -> Result<DeviceId, CsecError> {
+        self.write_page(1, challenge);
+        self.run_command(CSEC_CMD_GET_ID, CSEC_CALL_SEQ_FIRST, 0)?;
+        
+        let mut page = [0u8; 16];
+        self.read_page(2, &mut page);
+        let mut id = DeviceId {
+            uid: [0; CSEC_UID_LENGTH],
+            status: page[CSEC_UID_LENGTH],
+            mac: [0; CMAC_LENGTH],
+        };
+        id.uid.copy_from_slice(&page[..CSEC_UID_LENGTH]);
+        self.read_page(3, &mut id.mac);
+        
+        Ok(id)
+    }
+    
+    /// Configure secure boot over the first `size` bytes of the program flash
+    ///
+    /// Takes effect at the next reset; the engine learns BOOT_MAC at the
+    /// first boot if it is empty.
+    pub fn boot_define(&mut self, size: u32, flavor: BootFlavor) -> Result<(), CsecError> {
+        self.port.write_word(CSEC_BOOT_SIZE_OFFSET, size);
+        self.port.write_word(CSEC_BOOT_FLAVOR_OFFSET, flavor.to_byte() as u32);
+        self.run_command(CSEC_CMD_BOOT_DEFINE, CSEC_CALL_SEQ_FIRST, 0)
+    }
+    
+    /// Finish the boot phase successfully
+    pub fn boot_ok(&mut self) -> Result<(), CsecError> {
+        self.run_command(CSEC_CMD_BOOT_OK, CSEC_CALL_SEQ_FIRST, 0)
+    }
+    
+    /// Finish the boot phase as failed, locking boot protected keys until the next reset
+    pub fn boot_failure(&mut self) -> Result<(), CsecError> {
+        self.run_command(CSEC_CMD_BOOT_FAILURE, CSEC_CALL_SEQ_FIRST, 0)
+    }
+    
+    /// Run an ECB or CBC command over whole blocks in place
+    ///
+    /// The first call carries the number of blocks of the whole
+    /// operation, and the IV in page 1 for CBC.
+    fn run_cipher(&mut self, command: u8, key_id: u8, iv: Option<&[u8; 16]>, data: &mut [u8]) -> Result<(), CsecError> {
+        if data.is_empty() || !data.len().is_multiple_of(AES_BLOCK_SIZE) {
+            return Err(CsecError::InvalidLength);
+        }
+        
+        self.port.write_word(CSEC_PAGE_LENGTH_OFFSET, (data.len() / AES_BLOCK_SIZE) as u32);
+        
+        let mut first_page = 1;
+        if let Some(iv) = iv {
+            self.write_page(1, iv);
+            first_page = 2;
+        }
+        
+        let mut remaining = data;
+        let mut call_seq = CSEC_CALL_SEQ_FIRST;
+        
+        loop {
+            let capacity = ((CSEC_LAST_DATA_PAGE + 1 - first_page) * CSEC_PAGE_SIZE) as usize;
+            let (chunk, rest) = remaining.split_at_mut(remaining.len().min(capacity));
+            
+            for (i, block) in chunk.as_chunks::<16>().0.iter().enumerate() {
+                self.write_page(first_page + i as u32, block);
+            }
+            
+            self.run_command(command, call_seq, key_id)?;
+            
+            for (i, block) in chunk.as_chunks_mut::<16>().0.iter_mut().enumerate() {
+                self.read_page(first_page + i as u32, block);
+            }
+            
+            if rest.is_empty() {
+                return Ok(());
+            }
+            
+            remaining = rest;
+            first_page = 1;
+            call_seq = CSEC_CALL_SEQ_SUBSEQUENT;
+        }
+    }
+    
+    /// Pass a message through the parameter RAM for a MAC command
+    ///
+    /// Pages 2-7 with the first call, pages 1-7 with every further call;
+    /// the last page of the message is padded with zeros and `mac`
+    /// follows in the next page.
+    fn run_message(&mut self, command: u8, key_id: u8, message: &[u8], mac: Option<&[u8; 16]>) -> Result<(), CsecError> {
+        let message_pages = message.len().div_ceil(16);
+        let pages = message_pages + mac.is_some() as usize;
+        
+        let mut page = 0;
+        let mut first_page = 2;
+        let mut call_seq = CSEC_CALL_SEQ_FIRST;
+        
+        loop {
+            for target in first_page..=CSEC_LAST_DATA_PAGE {
+                if page == pages {
+                    break;
+                }
+                
+                let mut data = [0u8; 16];
+                match mac {
+                    Some(mac) if page == message_pages => data.copy_from_slice(mac),
+                    _ => {
+                        let chunk = &message[page * 16..message.len().min(page * 16 + 16)];
+                        data[..chunk.len()].copy_from_slice(chunk);
+                    }
+                }
+                self.write_page(target, &data);
+                page += 1;
+            }
+            
+            self.run_command(command, call_seq, key_id)?;
+            
+            if page == pages {
+                return Ok(());
+            }
+            
+            first_page = 1;
+            call_seq = CSEC_CALL_SEQ_SUBSEQUENT;
+        }
+    }
+    
+    /// Launch a CSEc command and wait for its completion
+    fn run_command(&mut self, command: u8, call_seq: u8, key_id: u8) -> Result<(), CsecError> {
+        let header = ((command as u32) << 24)
+            | ((CSEC_FORMAT_COPY as u32) << 16)
+            | ((call_seq as u32) << 8)
+            | key_id as u32;
+        self.port.execute(header);
+        
+        // The error code is returned in the upper half of the second header word
+        CsecError::check((self.port.read_word(4) >> 16) as u16)
+    }
+    
+    /// Write a 16 byte page to the CSEc parameter RAM
+    fn write_page(&mut self, page: u32, input: &[u8; 16]) {
+        for (i, chunk) in input.chunks(4).enumerate() {
+            let word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.port.write_word(page * CSEC_PAGE_SIZE + (i as u32) * 4, word);
+        }
+    }
+    
+    /// Read a 16 byte page from the CSEc parameter RAM
+    fn read_page(&self, page: u32, output: &mut [u8; 16]) {
+        for (i, chunk) in output.chunks_mut(4).enumerate() {
+            let word = self.port.read_word(page * CSEC_PAGE_SIZE + (i as u32) * 4);
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+    }
+}
+
+impl<P: CsecPort> EntropySource for Csec<P> {
+    fn fill_entropy(&mut self, buffer: &mut [u8]) -> Result<(), EntropyError> {
+        let mut block = [0u8; 16];
+        
+        for chunk in buffer.chunks_mut(16) {
+            self.generate_random(&mut block).map_err(|_| EntropyError::HardwareFault)?;
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        
+        block.fill(0);
+        Ok(())
+    }
+}
+
+impl<P: CsecPort> BlockCipher for Csec<P> {
+    fn encrypt_block(&mut self, block: &mut [u8; AES_BLOCK_SIZE]) -> Result<(), CipherError> {
+        self.encrypt_ecb(self.cipher_key_id, block).map_err(CsecError::to_cipher_error)
+    }
+}
+
+impl<P: CsecPort> MacGenerator for Csec<P> {
+    fn generate_mac(&mut self, message: &[u8]) -> Result<[u8; CMAC_LENGTH], CipherError> {
+        let mut mac = [0u8; CMAC_LENGTH];
+        Csec::generate_mac(self, self.mac_key_id, message, &mut mac).map_err(CsecError::to_cipher_error)?;
+        Ok(mac)
+    }
+}
+
+impl<P: CsecPort> SecureBootEngine for Csec<P> {
+    fn status(&self) -> u8 {
+        Csec::status(self)
+    }
+    
+    fn load_key(&mut self, messages: &KeyUpdateMessages) -> Result<KeyUpdateProof, CsecError> {
+        Csec::load_key(self, messages)
+    }
+    
+    fn define(&mut self, size: u32, flavor: BootFlavor) -> Result<(), CsecError> {
+        self.boot_define(size, flavor)
+    }
+    
+    fn finish(&mut self, ok: bool) -> Result<(), CsecError> {
+        if ok { self.boot_ok() } else { self.boot_failure() }
+    }
+}
+
+/// CSEc error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsecError {
+    SequenceError,
+    KeyNotAvailable,
+    KeyInvalid,
+    KeyEmpty,
+    NoSecureBoot,
+    KeyWriteProtected,
+    KeyUpdateError,
+    RngSeed,
+    NoDebugging,
+    MemoryFailure,
+    GeneralError,
+    InvalidLength,
+    Unknown(u16),
+}
+
+impl CsecError {
+    /// Convert a CSEc error code into a result
+    fn check(erc: u16) -> Result<(), CsecError> {
+        match erc {
+            CSEC_ERC_NO_ERROR => Ok(()),
+            CSEC_ERC_SEQUENCE_ERROR => Err(CsecError::SequenceError),
+            CSEC_ERC_KEY_NOT_AVAILABLE => Err(CsecError::KeyNotAvailable),
+            CSEC_ERC_KEY_INVALID => Err(CsecError::KeyInvalid),
+            CSEC_ERC_KEY_EMPTY => Err(CsecError::KeyEmpty),
+            CSEC_ERC_NO_SECURE_BOOT => Err(CsecError::NoSecureBoot),
+            CSEC_ERC_KEY_WRITE_PROTECTED => Err(CsecError::KeyWriteProtected),
+            CSEC_ERC_KEY_UPDATE_ERROR => Err(CsecError::KeyUpdateError),
+            CSEC_ERC_RNG_SEED => Err(CsecError::RngSeed),
+            CSEC_ERC_NO_DEBUGGING => Err(CsecError::NoDebugging),
+            CSEC_ERC_MEMORY_FAILURE => Err(CsecError::MemoryFailure),
+            CSEC_ERC_GENERAL_ERROR => Err(CsecError::GeneralError),
+            other => Err(CsecError::Unknown(other)),
+        }
+    }
+    
+    /// Error of the generic cipher traits
+    fn to_cipher_error(self) -> CipherError {
+        match self {
+            CsecError::KeyNotAvailable | CsecError::KeyInvalid | CsecError::KeyEmpty => CipherError::KeyNotAvailable,
+            _ => CipherError::HardwareFault,
+        }
+    }
+}
\ No newline at end of file
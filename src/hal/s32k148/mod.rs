@@ -1,2 +1,3 @@
 pub mod registers;
 pub mod peripherals;
+pub mod csec;
@@ -1,5 +1,3 @@
-use core::marker::PhantomData;
-
 /// System Control Group (SCG) peripheral
 pub struct SCG;
 
@@ -27,12 +25,15 @@ pub struct SystemReset;
 impl SystemReset {
     /// Perform a system reset
     pub fn reset() -> ! {
-        // In a real implementation, this would write to the ARM Core SYSRESETREQ bit
-        // to trigger a system reset
+        // Request the reset with the SYSRESETREQ bit of the ARM core
+        // Safety: AIRCR is written with its key and SYSRESETREQ only
+        unsafe {
+            (*cortex_m::peripheral::SCB::PTR).aircr.write(0x05FA_0004);
+        }
         
-        // For now, just loop forever
+        // Wait for the reset to take effect
         loop {
-            // This will never be reached in real implementation
+            core::hint::spin_loop();
         }
     }
 }
\ No newline at end of file
@@ -1,7 +1,10 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 pub mod bootloader;
+pub mod config;
 pub mod communication;
+pub mod crypto;
+pub mod compression;
 pub mod protocol;
 pub mod drivers;
 pub mod hal;
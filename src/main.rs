@@ -3,15 +3,18 @@
 
 // Import dependencies
 use panic_halt as _;
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
 use defmt::info;
 use defmt_rtt as _;
 
 // Import our modules
 use gridania_telematic_bootloader::bootloader::core::BootLoader;
+use gridania_telematic_bootloader::bootloader::boot_manager::BootDecision;
+use gridania_telematic_bootloader::bootloader::{boot_handoff, partition};
 use gridania_telematic_bootloader::drivers::clock::Clock;
 use gridania_telematic_bootloader::drivers::gpio::Gpio;
 use gridania_telematic_bootloader::drivers::power::Power;
+use gridania_telematic_bootloader::drivers::systick::{self, SysTickTimer};
 use gridania_telematic_bootloader::drivers::watchdog::Watchdog;
 
 #[entry]
@@ -19,12 +22,25 @@ fn main() -> ! {
     info!("Gridania Telematic ECU Bootloader");
     info!("Build Date: {}", env!("CARGO_PKG_VERSION"));
     
+    // Take the record of stage 0 before anything else runs, it is only
+    // written when stage 0 is installed
+    let handoff = if partition::has_stage0() { boot_handoff::take() } else { None };
+    if partition::has_stage0() && handoff.is_none() {
+        info!("No stage 0 handoff record, started by a debugger?");
+    }
+    
     // Initialize core hardware components
     let mut clock = Clock::new();
     clock.init();
     
+    let mut systick = SysTickTimer::new();
+    systick.init(clock.get_system_clock_hz());
+    
     let mut power = Power::new();
     power.init();
+    if let Some(handoff) = &handoff {
+        power.set_reset_status(handoff.reset_status);
+    }
     
     let mut gpio = Gpio::new();
     gpio.init();
@@ -37,11 +53,18 @@ fn main() -> ! {
     
     // Initialize and run the bootloader
     let mut bootloader = BootLoader::new();
+    if let Some(handoff) = handoff {
+        bootloader.set_boot_handoff(handoff);
+    }
     bootloader.init();
     
     // Enable CPU interrupts
     unsafe { cortex_m::interrupt::enable() };
     
+    // Decide between application and bootloader from the reset cause,
+    // programming request and application validity
+    let mut decision = bootloader.boot_decision(power.get_reset_cause());
+    
     // Main loop - continue processing bootloader tasks
     loop {
         // Run the bootloader task (handle communication, flashing, etc.)
@@ -49,6 +72,18 @@ fn main() -> ! {
         
         // Service the watchdog
         watchdog.service();
+        
+        // Give a tester the chance to connect before starting the application
+        if decision == BootDecision::WaitForBackdoor {
+            decision = bootloader.poll_boot_decision();
+        }
+        
+        if decision == BootDecision::RunApplication
+            && bootloader.start_application(&mut clock, &mut watchdog, &mut systick).is_err()
+        {
+            info!("Application cannot be started, staying in bootloader");
+            decision = BootDecision::Recovery;
+        }
     }
 }
 
@@ -63,3 +98,9 @@ fn check_hmi_power(gpio: &Gpio) {
         gpio.set_port_c(5);
     }
 }
+
+/// Millisecond time base of the bootloader
+#[exception]
+fn SysTick() {
+    systick::tick();
+}
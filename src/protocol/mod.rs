@@ -1,2 +1,14 @@
 pub mod uds;
-pub mod xcp;
\ No newline at end of file
+pub mod xcp;
+
+use heapless::Vec;
+
+/// Create a response from a fixed number of bytes
+///
+/// `N` is checked against the 64 byte response buffer at compile time, so
+/// building the response cannot fail.
+pub fn fixed_response<const N: usize>(bytes: [u8; N]) -> Vec<u8, 64> {
+    const { assert!(N <= 64, "response exceeds the response buffer") };
+    
+    bytes.into_iter().collect()
+}
\ No newline at end of file
@@ -0,0 +1,473 @@
+use defmt::{debug, info, warn};
+use heapless::Vec;
+use super::*;
+use crate::crypto::certificate::{self, CertificateError};
+use crate::crypto::drbg::RandomGenerator;
+use crate::crypto::ecdsa::{verify_der, P256_PUBLIC_KEY_LENGTH};
+use crate::crypto::entropy::EntropySource;
+use crate::protocol::fixed_response;
+
+/// Root public key of the diagnostic PKI (uncompressed SEC1)
+///
+/// Placeholder that rejects every certificate until the OEM root key is
+/// configured with `set_root_public_key`.
+pub static AUTHENTICATION_ROOT_PUBLIC_KEY: [u8; P256_PUBLIC_KEY_LENGTH] = [0; P256_PUBLIC_KEY_LENGTH];
+
+// Diagnostic roles granted by the certificate role extension
+pub const AUTH_ROLE_EXTENDED_DIAGNOSTICS: u16 = 1 << 0;
+pub const AUTH_ROLE_PROGRAMMING: u16 = 1 << 1;
+pub const AUTH_ROLE_END_OF_LINE: u16 = 1 << 2;
+
+/// Security levels unlocked by each role
+const ROLE_SECURITY_LEVELS: &[(u16, u8)] = &[
+    (AUTH_ROLE_EXTENDED_DIAGNOSTICS, UDS_SECURITY_LEVEL_EXTENDED),
+    (AUTH_ROLE_PROGRAMMING, UDS_SECURITY_LEVEL_PROGRAMMING),
+    (AUTH_ROLE_END_OF_LINE, UDS_SECURITY_LEVEL_EOL),
+];
+
+// Authentication return parameters
+const AUTH_RETURN_AUTHENTICATION_CONFIGURATION_APCE: u8 = 0x02;
+const AUTH_RETURN_DEAUTHENTICATION_SUCCESSFUL: u8 = 0x10;
+const AUTH_RETURN_CERTIFICATE_VERIFIED_OWNERSHIP_NECESSARY: u8 = 0x11;
+const AUTH_RETURN_OWNERSHIP_VERIFIED: u8 = 0x12;
+
+/// Communication configuration without secure communication
+const AUTH_COMMUNICATION_CONFIGURATION_NONE: u8 = 0x00;
+
+/// Length of the server challenge
+const CHALLENGE_LENGTH: usize = 32;
+
+/// Personalization string for the challenge DRBG
+const CHALLENGE_PERSONALIZATION: &[u8] = b"Gridania UDS Authentication challenge";
+
+/// Authentication state
+enum AuthenticationState {
+    /// No authenticated tester
+    Deauthenticated,
+    /// Certificate verified, waiting for the proof of ownership
+    OwnershipPending {
+        public_key: [u8; P256_PUBLIC_KEY_LENGTH],
+        roles: u16,
+        challenge: [u8; CHALLENGE_LENGTH],
+    },
+    /// Tester authenticated with the given roles
+    Authenticated {
+        roles: u16,
+    },
+}
+
+/// UDS Authentication (0x29) with PKI certificate exchange (APCE)
+pub struct Authentication {
+    /// Current authentication state
+    state: AuthenticationState,
+    /// Root public key the client certificate must chain to
+    root_public_key: &'static [u8; P256_PUBLIC_KEY_LENGTH],
+    /// Random bit generator for server challenges
+    rng: RandomGenerator,
+}
+
+impl Authentication {
+    /// Create a new authentication handler
+    pub fn new() -> Self {
+        Self {
+            state: AuthenticationState::Deauthenticated,
+            root_public_key: &AUTHENTICATION_ROOT_PUBLIC_KEY,
+            rng: RandomGenerator::new(CHALLENGE_PERSONALIZATION),
+        }
+    }
+    
+    /// Initialize authentication
+    pub fn init(&mut self) {
+        debug!("Initializing authentication");
+        self.state = AuthenticationState::Deauthenticated;
+    }
+    
+    /// Configure the root public key of the diagnostic PKI
+    pub fn set_root_public_key(&mut self, root_public_key: &'static [u8; P256_PUBLIC_KEY_LENGTH]) {
+        self.root_public_key = root_public_key;
+        self.state = AuthenticationState::Deauthenticated;
+    }
+    
+    /// Register the entropy source for server challenges
+    pub fn register_entropy_source(&mut self, entropy: *mut (dyn EntropySource + 'static)) {
+        self.rng.register_entropy_source(entropy);
+    }
+    
+    /// Drop the authenticated state
+    pub fn deauthenticate(&mut self) {
+        if !matches!(self.state, AuthenticationState::Deauthenticated) {
+            debug!("Authentication reset");
+        }
+        self.state = AuthenticationState::Deauthenticated;
+    }
+    
+    /// Roles granted to the authenticated tester
+    pub fn granted_roles(&self) -> u16 {
+        match self.state {
+            AuthenticationState::Authenticated { roles } => roles,
+            _ => 0,
+        }
+    }
+    
+    /// Check if the granted roles include the rights of a security level
+    pub fn grants_level(&self, level: u8) -> bool {
+        let roles = self.granted_roles();
+        ROLE_SECURITY_LEVELS
+            .iter()
+            .any(|&(role, role_level)| role_level == level && roles & role != 0)
+    }
+    
+    /// Handle authentication service
+    pub fn handle_authentication(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        if data.is_empty() {
+            return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT);
+        }
+        
+        let subfunction = data[0] & 0x7F;
+        let suppress_response = (data[0] & 0x80) != 0;
+        
+        let response = match subfunction {
+            UDS_AUTH_DEAUTHENTICATE => self.handle_deauthenticate(&data[1..]),
+            UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL => self.handle_verify_certificate(&data[1..]),
+            UDS_AUTH_PROOF_OF_OWNERSHIP => self.handle_proof_of_ownership(&data[1..]),
+            UDS_AUTH_AUTHENTICATION_CONFIGURATION => self.handle_configuration(&data[1..]),
+            _ => self.create_negative_response(UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED),
+        };
+        
+        if suppress_response && response.first() != Some(&UDS_SID_NEGATIVE_RESPONSE) {
+            return Vec::new();
+        }
+        
+        response
+    }
+    
+    /// Handle deAuthenticate
+    fn handle_deauthenticate(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        if !data.is_empty() {
+            return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT);
+        }
+        
+        self.deauthenticate();
+        info!("Tester deauthenticated");
+        
+        self.create_positive_response(UDS_AUTH_DEAUTHENTICATE, AUTH_RETURN_DEAUTHENTICATION_SUCCESSFUL)
+    }
+    
+    /// Handle authenticationConfiguration
+    fn handle_configuration(&self, data: &[u8]) -> Vec<u8, 64> {
+        if !data.is_empty() {
+            return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT);
+        }
+        
+        self.create_positive_response(UDS_AUTH_AUTHENTICATION_CONFIGURATION, AUTH_RETURN_AUTHENTICATION_CONFIGURATION_APCE)
+    }
+    
+    /// Handle verifyCertificateUnidirectional
+    ///
+    /// The certificate parameter holds the client certificate, optionally
+    /// followed by the certificate of the intermediate CA that issued it.
+    fn handle_verify_certificate(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        // communicationConfiguration, certificate and (optional) client challenge
+        let (configuration, rest) = match data.split_first() {
+            Some((configuration, rest)) => (*configuration, rest),
+            None => return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+        };
+        
+        let (certificate, rest) = match split_length_prefixed(rest) {
+            Some(parts) => parts,
+            None => return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+        };
+        
+        // The client challenge is only used for bidirectional authentication
+        match split_length_prefixed(rest) {
+            Some((_, [])) => {},
+            _ => return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+        }
+        
+        if configuration != AUTH_COMMUNICATION_CONFIGURATION_NONE {
+            return self.create_negative_response(UDS_NRC_REQUEST_OUT_OF_RANGE);
+        }
+        
+        // A new certificate always restarts authentication
+        self.state = AuthenticationState::Deauthenticated;
+        
+        let (certificate, roles) = match certificate::verify_chain(certificate, self.root_public_key) {
+            Ok(verified) => verified,
+            Err(error) => {
+                warn!("Client certificate rejected: {}", defmt::Debug2Format(&error));
+                return self.create_negative_response(certificate_nrc(error));
+            }
+        };
+        
+        if roles == 0 {
+            warn!("Client certificate grants no diagnostic role");
+            return self.create_negative_response(UDS_NRC_CERTIFICATE_INVALID_SCOPE);
+        }
+        
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        if self.rng.fill(&mut challenge).is_err() {
+            return self.create_negative_response(UDS_NRC_CHALLENGE_CALCULATION_FAILED);
+        }
+        
+        let mut public_key = [0u8; P256_PUBLIC_KEY_LENGTH];
+        public_key.copy_from_slice(certificate.public_key);
+        
+        self.state = AuthenticationState::OwnershipPending {
+            public_key,
+            roles,
+            challenge,
+        };
+        
+        debug!("Client certificate verified, roles 0x{:04X}", roles);
+        
+        let mut response = self.create_positive_response(
+            UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL,
+            AUTH_RETURN_CERTIFICATE_VERIFIED_OWNERSHIP_NECESSARY
+        );
+        
+        // Server challenge followed by an empty ephemeral public key
+        let appended = response
+            .extend_from_slice(&(CHALLENGE_LENGTH as u16).to_be_bytes())
+            .and_then(|_| response.extend_from_slice(&challenge))
+            .and_then(|_| response.extend_from_slice(&[0x00, 0x00]));
+        if appended.is_err() {
+            return self.create_negative_response(UDS_NRC_RESPONSE_TOO_LONG);
+        }
+        
+        response
+    }
+    
+    /// Handle proofOfOwnership
+    fn handle_proof_of_ownership(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        let (proof, rest) = match split_length_prefixed(data) {
+            Some(parts) => parts,
+            None => return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+        };
+        
+        // Ephemeral keys are only used for session key derivation, which is not supported
+        match split_length_prefixed(rest) {
+            Some((_, [])) => {},
+            _ => return self.create_negative_response(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+        }
+        
+        let (public_key, roles, challenge) = match &self.state {
+            AuthenticationState::OwnershipPending { public_key, roles, challenge } => (*public_key, *roles, *challenge),
+            _ => return self.create_negative_response(UDS_NRC_REQUEST_SEQUENCE_ERROR),
+        };
+        
+        // The challenge is single use
+        self.state = AuthenticationState::Deauthenticated;
+        
+        // The proof is the client's DER encoded signature over the server challenge
+        if verify_der(&public_key, &challenge, proof).is_err() {
+            warn!("Proof of ownership verification failed");
+            return self.create_negative_response(UDS_NRC_OWNERSHIP_VERIFICATION_FAILED);
+        }
+        
+        self.state = AuthenticationState::Authenticated { roles };
+        info!("Tester authenticated, roles 0x{:04X}", roles);
+        
+        // No session key information
+        fixed_response([
+            UDS_SID_AUTHENTICATION + UDS_RSP_POSITIVE,
+            UDS_AUTH_PROOF_OF_OWNERSHIP,
+            AUTH_RETURN_OWNERSHIP_VERIFIED,
+            0x00,
+            0x00,
+        ])
+    }
+    
+    /// Create a positive response with an authentication return parameter
+    fn create_positive_response(&self, subfunction: u8, return_parameter: u8) -> Vec<u8, 64> {
+        fixed_response([UDS_SID_AUTHENTICATION + UDS_RSP_POSITIVE, subfunction, return_parameter])
+    }
+    
+    /// Create a negative response
+    fn create_negative_response(&self, nrc: u8) -> Vec<u8, 64> {
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, UDS_SID_AUTHENTICATION, nrc])
+    }
+}
+
+impl Default for Authentication {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Split a parameter with a 16-bit big-endian length prefix from the rest of the data
+fn split_length_prefixed(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    if data.len() < 2 {
+        return None;
+    }
+    
+    let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+    if data.len() < 2 + length {
+        return None;
+    }
+    
+    Some(data[2..].split_at(length))
+}
+
+/// Map a certificate error to the authentication NRC
+fn certificate_nrc(error: CertificateError) -> u8 {
+    match error {
+        CertificateError::Format(_) => UDS_NRC_CERTIFICATE_INVALID_FORMAT,
+        CertificateError::UnsupportedVersion
+        | CertificateError::UnsupportedAlgorithm
+        | CertificateError::UnexpectedCertificateAuthority => UDS_NRC_CERTIFICATE_INVALID_TYPE,
+        CertificateError::UnsupportedCriticalExtension => UDS_NRC_CERTIFICATE_INVALID_CONTENT,
+        CertificateError::InvalidIssuerKey
+        | CertificateError::IssuerMismatch
+        | CertificateError::NotCertificateAuthority
+        | CertificateError::ChainTooLong => UDS_NRC_CERTIFICATE_INVALID_CHAIN_OF_TRUST,
+        CertificateError::InvalidSignature => UDS_NRC_CERTIFICATE_INVALID_SIGNATURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::SigningKey;
+    use super::*;
+    use crate::crypto::certificate::tests::{client_certificate, public_key, sign_der, signing_key};
+    use crate::crypto::entropy::MockEntropy;
+    
+    fn authentication(root: &SigningKey) -> Authentication {
+        let mut authentication = Authentication::new();
+        authentication.set_root_public_key(Box::leak(Box::new(public_key(root))));
+        authentication.register_entropy_source(Box::leak(Box::new(MockEntropy::new(21))));
+        authentication
+    }
+    
+    fn verify_certificate(authentication: &mut Authentication, certificate: &[u8]) -> Vec<u8, 64> {
+        let mut request = std::vec![UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL, AUTH_COMMUNICATION_CONFIGURATION_NONE];
+        request.extend_from_slice(&(certificate.len() as u16).to_be_bytes());
+        request.extend_from_slice(certificate);
+        request.extend_from_slice(&[0x00, 0x00]);
+        authentication.handle_authentication(&request)
+    }
+    
+    fn proof_of_ownership(authentication: &mut Authentication, proof: &[u8]) -> Vec<u8, 64> {
+        let mut request = std::vec![UDS_AUTH_PROOF_OF_OWNERSHIP];
+        request.extend_from_slice(&(proof.len() as u16).to_be_bytes());
+        request.extend_from_slice(proof);
+        request.extend_from_slice(&[0x00, 0x00]);
+        authentication.handle_authentication(&request)
+    }
+    
+    /// Server challenge of a positive verifyCertificateUnidirectional response
+    fn challenge(response: &[u8]) -> &[u8] {
+        assert_eq!(&response[..5], &[0x69, UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL, 0x11, 0x00, CHALLENGE_LENGTH as u8]);
+        &response[5..5 + CHALLENGE_LENGTH]
+    }
+    
+    #[test]
+    fn authenticate_with_role() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        
+        let response = verify_certificate(&mut authentication, &client_certificate(&root, &client, AUTH_ROLE_PROGRAMMING));
+        let proof = sign_der(&client, challenge(&response));
+        
+        let response = proof_of_ownership(&mut authentication, &proof);
+        assert_eq!(&response[..], &[0x69, UDS_AUTH_PROOF_OF_OWNERSHIP, 0x12, 0x00, 0x00]);
+        assert!(authentication.grants_level(UDS_SECURITY_LEVEL_PROGRAMMING));
+        
+        // The role does not grant other levels
+        assert!(!authentication.grants_level(UDS_SECURITY_LEVEL_EXTENDED));
+        assert!(!authentication.grants_level(UDS_SECURITY_LEVEL_EOL));
+        
+        let response = authentication.handle_authentication(&[UDS_AUTH_DEAUTHENTICATE]);
+        assert_eq!(&response[..], &[0x69, UDS_AUTH_DEAUTHENTICATE, 0x10]);
+        assert!(!authentication.grants_level(UDS_SECURITY_LEVEL_PROGRAMMING));
+    }
+    
+    #[test]
+    fn certificate_without_role() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        
+        let response = verify_certificate(&mut authentication, &client_certificate(&root, &client, 0));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_CERTIFICATE_INVALID_SCOPE]);
+        
+        // No challenge was issued
+        let response = proof_of_ownership(&mut authentication, &sign_der(&client, &[0u8; CHALLENGE_LENGTH]));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_REQUEST_SEQUENCE_ERROR]);
+    }
+    
+    #[test]
+    fn certificate_with_bad_signature() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        
+        // Signed by another key than the root
+        let response = verify_certificate(&mut authentication, &client_certificate(&signing_key(3), &client, AUTH_ROLE_PROGRAMMING));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_CERTIFICATE_INVALID_SIGNATURE]);
+        
+        // Modified after signing
+        let mut certificate = client_certificate(&root, &client, AUTH_ROLE_PROGRAMMING);
+        let subject = certificate.windows(6).position(|window| window == b"Tester").unwrap();
+        certificate[subject] = b'X';
+        let response = verify_certificate(&mut authentication, &certificate);
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_CERTIFICATE_INVALID_SIGNATURE]);
+        
+        let response = verify_certificate(&mut authentication, &certificate[..certificate.len() - 2]);
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_CERTIFICATE_INVALID_FORMAT]);
+        assert_eq!(authentication.granted_roles(), 0);
+    }
+    
+    #[test]
+    fn proof_over_wrong_challenge() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        let certificate = client_certificate(&root, &client, AUTH_ROLE_END_OF_LINE);
+        
+        let response = verify_certificate(&mut authentication, &certificate);
+        let mut wrong = [0u8; CHALLENGE_LENGTH];
+        wrong.copy_from_slice(challenge(&response));
+        wrong[0] ^= 0x01;
+        
+        let response = proof_of_ownership(&mut authentication, &sign_der(&client, &wrong));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_OWNERSHIP_VERIFICATION_FAILED]);
+        assert!(!authentication.grants_level(UDS_SECURITY_LEVEL_EOL));
+        
+        // The challenge is single use, a correct proof afterwards is refused
+        let response = verify_certificate(&mut authentication, &certificate);
+        let expected = challenge(&response).to_vec();
+        let _ = proof_of_ownership(&mut authentication, &sign_der(&signing_key(3), &expected));
+        let response = proof_of_ownership(&mut authentication, &sign_der(&client, &expected));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_REQUEST_SEQUENCE_ERROR]);
+        assert_eq!(authentication.granted_roles(), 0);
+    }
+    
+    #[test]
+    fn challenges_differ() {
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        let certificate = client_certificate(&root, &client, AUTH_ROLE_PROGRAMMING);
+        
+        let first = challenge(&verify_certificate(&mut authentication, &certificate)).to_vec();
+        let second = challenge(&verify_certificate(&mut authentication, &certificate)).to_vec();
+        assert_ne!(first, second);
+        
+        // Only the latest challenge is accepted
+        let response = proof_of_ownership(&mut authentication, &sign_der(&client, &first));
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_OWNERSHIP_VERIFICATION_FAILED]);
+    }
+    
+    #[test]
+    fn raw_proof_rejected() {
+        use p256::ecdsa::signature::Signer;
+        use p256::ecdsa::Signature;
+        
+        let (root, client) = (signing_key(1), signing_key(2));
+        let mut authentication = authentication(&root);
+        let certificate = client_certificate(&root, &client, AUTH_ROLE_PROGRAMMING);
+        
+        // The proof must be DER encoded, the raw (r || s) form of a valid signature is refused
+        let response = verify_certificate(&mut authentication, &certificate);
+        let signature: Signature = client.sign(challenge(&response));
+        let response = proof_of_ownership(&mut authentication, &signature.to_bytes());
+        assert_eq!(&response[..], &[0x7F, UDS_SID_AUTHENTICATION, UDS_NRC_OWNERSHIP_VERIFICATION_FAILED]);
+        assert_eq!(authentication.granted_roles(), 0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,153 @@
+use defmt::{info, warn};
+use heapless::Vec;
+use super::*;
+use crate::bootloader::lifecycle::{self, DeviceIdentity};
+use crate::bootloader::nvm::NvStorage;
+use crate::bootloader::secure_boot::{SecureBootEngine, SecureBootStatus};
+use crate::config;
+use crate::protocol::fixed_response;
+
+/// Longest data record of an identifier
+const MAX_RECORD_LENGTH: usize = 16;
+
+/// UDS ReadDataByIdentifier handler
+pub struct DataIdentifiers {
+    /// Engine reporting the secure boot status
+    secure_boot: Option<*mut dyn SecureBootEngine>,
+    /// Non-volatile storage holding the lifecycle state and identity
+    nv_storage: Option<*mut dyn NvStorage>,
+}
+
+impl DataIdentifiers {
+    /// Create a new data identifier handler
+    pub fn new() -> Self {
+        Self {
+            secure_boot: None,
+            nv_storage: None,
+        }
+    }
+    
+    /// Initialize the data identifier handler
+    pub fn init(&mut self) {
+        info!("Initializing UDS data identifiers");
+    }
+    
+    /// Register the engine reporting the secure boot status
+    pub fn register_secure_boot(&mut self, engine: *mut (dyn SecureBootEngine + 'static)) {
+        self.secure_boot = Some(engine);
+    }
+    
+    /// Register the non-volatile storage holding the lifecycle state and identity
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.nv_storage = Some(storage);
+    }
+    
+    /// Handle ReadDataByIdentifier
+    ///
+    /// Identifiers the bootloader does not know are skipped, the request is
+    /// only rejected if none of them is supported.
+    pub fn handle_read_data_by_identifier(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        // One or more identifiers of two bytes each
+        if data.is_empty() || !data.len().is_multiple_of(2) {
+            return self.create_negative_response(
+                UDS_SID_READ_DATA_BY_IDENTIFIER,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let mut response = fixed_response([UDS_SID_READ_DATA_BY_IDENTIFIER + UDS_RSP_POSITIVE]);
+        
+        for identifier in data.chunks(2) {
+            let did = u16::from_be_bytes([identifier[0], identifier[1]]);
+            
+            let record = match self.read_record(did) {
+                Ok(Some(record)) => record,
+                Ok(None) => {
+                    warn!("Unsupported data identifier 0x{:04X}", did);
+                    continue;
+                },
+                Err(nrc) => {
+                    return self.create_negative_response(UDS_SID_READ_DATA_BY_IDENTIFIER, nrc);
+                }
+            };
+            
+            if response.extend_from_slice(identifier).is_err() || response.extend_from_slice(&record).is_err() {
+                return self.create_negative_response(
+                    UDS_SID_READ_DATA_BY_IDENTIFIER,
+                    UDS_NRC_RESPONSE_TOO_LONG
+                );
+            }
+        }
+        
+        if response.len() == 1 {
+            return self.create_negative_response(
+                UDS_SID_READ_DATA_BY_IDENTIFIER,
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            );
+        }
+        
+        response
+    }
+    
+    /// Data record of an identifier, `None` if the identifier is not supported
+    fn read_record(&self, did: u16) -> Result<Option<Vec<u8, MAX_RECORD_LENGTH>>, u8> {
+        let mut record = Vec::new();
+        
+        match did {
+            UDS_DID_SECURE_BOOT_STATUS => {
+                let engine = match self.secure_boot {
+                    // Safety: We know this pointer is valid
+                    Some(engine) => unsafe { &*engine },
+                    None => return Err(UDS_NRC_CONDITIONS_NOT_CORRECT),
+                };
+                
+                record
+                    .extend_from_slice(&SecureBootStatus::read(engine, config::SECURE_BOOT_FLAVOR).to_bytes())
+                    .map_err(|_| UDS_NRC_RESPONSE_TOO_LONG)?;
+            },
+            UDS_DID_LIFECYCLE_STATE => {
+                record
+                    .push(lifecycle::load_state(self.storage()?).to_byte())
+                    .map_err(|_| UDS_NRC_RESPONSE_TOO_LONG)?;
+            },
+            UDS_DID_ECU_SERIAL_NUMBER => {
+                record
+                    .extend_from_slice(&self.identity()?.serial_number)
+                    .map_err(|_| UDS_NRC_RESPONSE_TOO_LONG)?;
+            },
+            UDS_DID_DEVICE_CONFIGURATION => {
+                record
+                    .extend_from_slice(&self.identity()?.configuration)
+                    .map_err(|_| UDS_NRC_RESPONSE_TOO_LONG)?;
+            },
+            _ => return Ok(None),
+        }
+        
+        Ok(Some(record))
+    }
+    
+    /// Registered non-volatile storage
+    fn storage(&self) -> Result<&dyn NvStorage, u8> {
+        match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) => Ok(unsafe { &*storage }),
+            None => Err(UDS_NRC_CONDITIONS_NOT_CORRECT),
+        }
+    }
+    
+    /// Provisioned device identity, not available before the end of line
+    fn identity(&self) -> Result<DeviceIdentity, u8> {
+        lifecycle::load_identity(self.storage()?).ok_or(UDS_NRC_CONDITIONS_NOT_CORRECT)
+    }
+    
+    /// Create a negative response
+    fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
+    }
+}
+
+impl Default for DataIdentifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,140 @@
+use heapless::Vec;
+use super::memory::MemoryRegion;
+use crate::bootloader::partition::PFLASH_SECTOR_SIZE;
+
+/// Size of a flash erase sector
+pub const SECTOR_SIZE: u32 = PFLASH_SECTOR_SIZE;
+
+/// Number of erase sectors tracked (512 KB program flash)
+const SECTOR_COUNT: usize = 128;
+
+/// Maximum number of segments in one download session
+pub const MAX_SEGMENTS: usize = 8;
+
+/// Memory segment written by one RequestDownload/RequestTransferExit sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// Address range of the segment
+    pub region: MemoryRegion,
+    /// Set once the segment was exited successfully
+    pub complete: bool,
+}
+
+/// Download session spanning all segments of one programming sequence
+///
+/// Segments may share an erase sector, so each sector is erased only the
+/// first time a segment touches it. The downloaded image is finalized only
+/// once the tester requests the dependency check, after the last segment.
+pub struct DownloadSession {
+    /// Segments requested so far, in request order
+    segments: Vec<Segment, MAX_SEGMENTS>,
+    /// Bitmap of the sectors erased in this session
+    touched_sectors: [u32; SECTOR_COUNT / 32],
+}
+
+impl DownloadSession {
+    /// Create an empty download session
+    pub fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+            touched_sectors: [0; SECTOR_COUNT / 32],
+        }
+    }
+    
+    /// Discard all segments and sector state
+    pub fn reset(&mut self) {
+        self.segments.clear();
+        self.touched_sectors = [0; SECTOR_COUNT / 32];
+    }
+    
+    /// Segments of the session
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+    
+    /// Check if the sector containing `address` was erased in this session
+    pub fn is_sector_touched(&self, address: u32) -> bool {
+        let sector = (address / SECTOR_SIZE) as usize;
+        sector < SECTOR_COUNT && self.touched_sectors[sector / 32] & (1 << (sector % 32)) != 0
+    }
+    
+    /// Add a segment and erase the sectors it touches for the first time
+    ///
+    /// `erase` is called with the start address of every sector that needs
+    /// erasing and returns whether the erase succeeded.
+    pub fn begin_segment<F>(&mut self, address: u32, size: u32, mut erase: F) -> Result<(), DownloadError>
+    where
+        F: FnMut(u32) -> bool,
+    {
+        let end = address.checked_add(size).ok_or(DownloadError::OutOfRange)?;
+        if size == 0 || end.div_ceil(SECTOR_SIZE) as usize > SECTOR_COUNT {
+            return Err(DownloadError::OutOfRange);
+        }
+        
+        // Segments are never written twice within a session
+        if self.segments.iter().any(|segment| segment.region.overlaps(address, size)) {
+            return Err(DownloadError::Overlap);
+        }
+        
+        if self.segments.is_full() {
+            return Err(DownloadError::TooManySegments);
+        }
+        
+        for sector in address / SECTOR_SIZE..end.div_ceil(SECTOR_SIZE) {
+            let sector_address = sector * SECTOR_SIZE;
+            if self.is_sector_touched(sector_address) {
+                continue;
+            }
+            
+            if !erase(sector_address) {
+                return Err(DownloadError::EraseFailed);
+            }
+            self.touched_sectors[sector as usize / 32] |= 1 << (sector % 32);
+        }
+        
+        let _ = self.segments.push(Segment {
+            region: MemoryRegion::new(address, end),
+            complete: false,
+        });
+        
+        Ok(())
+    }
+    
+    /// Mark the most recent segment as completely transferred
+    pub fn complete_segment(&mut self) {
+        if let Some(segment) = self.segments.last_mut() {
+            segment.complete = true;
+        }
+    }
+    
+    /// Check that the session holds at least one segment and all are complete
+    pub fn check_dependencies(&self) -> Result<(), DownloadError> {
+        if self.segments.is_empty() {
+            return Err(DownloadError::Empty);
+        }
+        
+        if self.segments.iter().any(|segment| !segment.complete) {
+            return Err(DownloadError::Incomplete);
+        }
+        
+        Ok(())
+    }
+}
+
+impl Default for DownloadSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Download session error types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadError {
+    OutOfRange,
+    Overlap,
+    TooManySegments,
+    EraseFailed,
+    ProgrammingFailed,
+    Empty,
+    Incomplete,
+}
\ No newline at end of file
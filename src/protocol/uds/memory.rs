@@ -0,0 +1,169 @@
+use core::ptr::read_volatile;
+use super::*;
+use crate::bootloader::partition::{self, PARTITION_FLAG_READABLE, PARTITION_TABLE};
+
+/// Memory region given as a half-open address range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// First address of the region
+    pub start: u32,
+    /// First address after the region
+    pub end: u32,
+}
+
+impl MemoryRegion {
+    /// Create a new memory region
+    pub const fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+    
+    /// Check if the region fully contains `address..address + size`
+    pub fn contains(&self, address: u32, size: u32) -> bool {
+        match address.checked_add(size) {
+            Some(end) => address >= self.start && end <= self.end,
+            None => false,
+        }
+    }
+    
+    /// Check if the region overlaps `address..address + size`
+    pub fn overlaps(&self, address: u32, size: u32) -> bool {
+        let end = address.saturating_add(size);
+        address < self.end && end > self.start
+    }
+}
+
+/// Peripheral RAM that is never readable
+///
+/// FlexRAM holds the emulated EEPROM with the attempt counter and the
+/// CSEc parameter RAM exposes key material during commands. Flash is
+/// covered by the partition table.
+pub static PERIPHERAL_RAM_REGIONS: &[MemoryRegion] = &[
+    MemoryRegion::new(0x1400_0000, 0x1400_1000),
+    MemoryRegion::new(0x1400_1000, 0x1400_1080),
+];
+
+/// Parse an addressAndLengthFormatIdentifier followed by memoryAddress and memorySize
+///
+/// `data` must start with the format identifier and end with the last
+/// memorySize byte. Bits 7-4 give the length of memorySize and bits 3-0 the
+/// length of memoryAddress, each limited to 1-4 bytes. Returns the address
+/// and size or the NRC to respond with.
+pub fn parse_address_and_length(data: &[u8]) -> Result<(u32, u32), u8> {
+    let format = match data.first() {
+        Some(format) => *format,
+        None => return Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+    };
+    
+    let size_len = ((format >> 4) & 0x0F) as usize;
+    let addr_len = (format & 0x0F) as usize;
+    
+    // Only 32-bit addresses and sizes exist on this target
+    if !(1..=4).contains(&size_len) || !(1..=4).contains(&addr_len) {
+        return Err(UDS_NRC_REQUEST_OUT_OF_RANGE);
+    }
+    
+    if data.len() != 1 + addr_len + size_len {
+        return Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT);
+    }
+    
+    let address = data[1..1 + addr_len]
+        .iter()
+        .fold(0u32, |value, byte| (value << 8) | *byte as u32);
+    let size = data[1 + addr_len..]
+        .iter()
+        .fold(0u32, |value, byte| (value << 8) | *byte as u32);
+    
+    Ok((address, size))
+}
+
+/// Check if `address..address + size` touches memory that is never readable
+///
+/// Protected are the peripheral RAM and every partition without the
+/// readable flag, e.g. stage 0 and the bootloader holding the
+/// authentication root key and the seed/key constants.
+pub fn is_protected(address: u32, size: u32) -> bool {
+    let unreadable = PARTITION_TABLE
+        .iter()
+        .filter(|partition| !partition.has_flags(PARTITION_FLAG_READABLE))
+        .map(|partition| MemoryRegion::new(partition.start, partition.end));
+    
+    PERIPHERAL_RAM_REGIONS
+        .iter()
+        .copied()
+        .chain(unreadable)
+        .any(|region| region.overlaps(address, size))
+}
+
+/// Check if `address..address + size` may be read by the tester
+pub fn is_readable(address: u32, size: u32) -> bool {
+    if size == 0 || is_protected(address, size) {
+        return false;
+    }
+    
+    partition::is_range_allowed(address, size, PARTITION_FLAG_READABLE)
+}
+
+/// Read memory-mapped memory into `buffer`
+///
+/// Callers must check the range with `is_readable` first.
+pub fn read_memory(address: u32, buffer: &mut [u8]) {
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        // Safety: the range was checked against the readable regions
+        *byte = unsafe { read_volatile((address + i as u32) as *const u8) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootloader::partition::{find, PartitionKind};
+    
+    #[test]
+    fn unreadable_partitions_are_protected() {
+        for partition in PARTITION_TABLE {
+            let readable = partition.has_flags(PARTITION_FLAG_READABLE);
+            
+            assert_eq!(is_protected(partition.start, partition.size()), !readable, "{}", partition.name);
+            assert_eq!(is_protected(partition.end - 1, 1), !readable, "{}", partition.name);
+            assert_eq!(is_readable(partition.start, partition.size()), readable, "{}", partition.name);
+        }
+        
+        for kind in [PartitionKind::Stage0, PartitionKind::Bootloader, PartitionKind::Staging, PartitionKind::Scratch] {
+            if let Some(partition) = find(kind) {
+                assert!(!is_readable(partition.start, 4));
+            }
+        }
+    }
+    
+    #[test]
+    fn peripheral_ram_is_protected() {
+        for region in PERIPHERAL_RAM_REGIONS {
+            assert!(is_protected(region.start, 1));
+            assert!(is_protected(region.end - 1, 1));
+            assert!(!is_readable(region.start, region.end - region.start));
+        }
+        assert!(!is_protected(0x1400_1080, 0x10));
+    }
+    
+    #[test]
+    fn ranges_crossing_into_protected_memory() {
+        let application = find(PartitionKind::Application).unwrap();
+        assert!(is_readable(application.start, 0x100));
+        assert!(!is_readable(application.start, 0));
+        
+        // Starting in the readable application, ending in the partition after it
+        assert!(!is_readable(application.end - 0x10, 0x20));
+        assert!(!is_readable(application.start - 0x10, 0x20));
+        assert!(!is_readable(u32::MAX - 0x10, 0x20));
+    }
+    
+    #[test]
+    fn address_and_length() {
+        assert_eq!(parse_address_and_length(&[0x44, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]), Ok((0x0001_0000, 0x100)));
+        assert_eq!(parse_address_and_length(&[0x12, 0x12, 0x34, 0x56]), Ok((0x1234, 0x56)));
+        assert_eq!(parse_address_and_length(&[0x44, 0x00]), Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT));
+        assert_eq!(parse_address_and_length(&[0x45, 0, 0, 0, 0, 0, 0, 0, 0, 0]), Err(UDS_NRC_REQUEST_OUT_OF_RANGE));
+        assert_eq!(parse_address_and_length(&[0x04, 0, 0, 0, 0]), Err(UDS_NRC_REQUEST_OUT_OF_RANGE));
+        assert_eq!(parse_address_and_length(&[]), Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT));
+    }
+}
\ No newline at end of file
@@ -1,15 +1,29 @@
 pub mod services;
 pub mod session;
 pub mod security;
+#[cfg(feature = "ecdsa")]
+pub mod authentication;
+pub mod seed_key;
 pub mod transfer;
+pub mod download;
+pub mod routine;
+pub mod data_identifier;
+pub mod provisioning;
+pub mod memory;
+pub mod permissions;
 
 // UDS Service IDs
 pub const UDS_SID_DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
 pub const UDS_SID_ECU_RESET: u8 = 0x11;
+pub const UDS_SID_READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+pub const UDS_SID_READ_MEMORY_BY_ADDRESS: u8 = 0x23;
 pub const UDS_SID_SECURITY_ACCESS: u8 = 0x27;
 pub const UDS_SID_COMMUNICATION_CONTROL: u8 = 0x28;
+pub const UDS_SID_AUTHENTICATION: u8 = 0x29;
+pub const UDS_SID_ROUTINE_CONTROL: u8 = 0x31;
 pub const UDS_SID_TESTER_PRESENT: u8 = 0x3E;
 pub const UDS_SID_REQUEST_DOWNLOAD: u8 = 0x34;
+pub const UDS_SID_REQUEST_UPLOAD: u8 = 0x35;
 pub const UDS_SID_TRANSFER_DATA: u8 = 0x36;
 pub const UDS_SID_REQUEST_TRANSFER_EXIT: u8 = 0x37;
 pub const UDS_SID_NEGATIVE_RESPONSE: u8 = 0x7F;
@@ -21,21 +35,81 @@ pub const UDS_RSP_POSITIVE: u8 = 0x40;  // Added to SID for positive response
 pub const UDS_NRC_GENERAL_REJECT: u8 = 0x10;
 pub const UDS_NRC_SERVICE_NOT_SUPPORTED: u8 = 0x11;
 pub const UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED: u8 = 0x12;
+pub const UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT: u8 = 0x13;
+pub const UDS_NRC_RESPONSE_TOO_LONG: u8 = 0x14;
 pub const UDS_NRC_CONDITIONS_NOT_CORRECT: u8 = 0x22;
 pub const UDS_NRC_REQUEST_SEQUENCE_ERROR: u8 = 0x24;
 pub const UDS_NRC_REQUEST_OUT_OF_RANGE: u8 = 0x31;
 pub const UDS_NRC_SECURITY_ACCESS_DENIED: u8 = 0x33;
 pub const UDS_NRC_INVALID_KEY: u8 = 0x35;
 pub const UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS: u8 = 0x36;
+pub const UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED: u8 = 0x37;
+pub const UDS_NRC_CERTIFICATE_INVALID_TIME_PERIOD: u8 = 0x50;
+pub const UDS_NRC_CERTIFICATE_INVALID_SIGNATURE: u8 = 0x51;
+pub const UDS_NRC_CERTIFICATE_INVALID_CHAIN_OF_TRUST: u8 = 0x52;
+pub const UDS_NRC_CERTIFICATE_INVALID_TYPE: u8 = 0x53;
+pub const UDS_NRC_CERTIFICATE_INVALID_FORMAT: u8 = 0x54;
+pub const UDS_NRC_CERTIFICATE_INVALID_CONTENT: u8 = 0x55;
+pub const UDS_NRC_CERTIFICATE_INVALID_SCOPE: u8 = 0x56;
+pub const UDS_NRC_CERTIFICATE_INVALID_CERTIFICATE: u8 = 0x57;
+pub const UDS_NRC_OWNERSHIP_VERIFICATION_FAILED: u8 = 0x58;
+pub const UDS_NRC_CHALLENGE_CALCULATION_FAILED: u8 = 0x59;
+pub const UDS_NRC_SETTING_ACCESS_RIGHTS_FAILED: u8 = 0x5A;
+pub const UDS_NRC_SESSION_KEY_CREATION_FAILED: u8 = 0x5B;
+pub const UDS_NRC_CONFIGURATION_DATA_USAGE_FAILED: u8 = 0x5C;
+pub const UDS_NRC_DEAUTHENTICATION_FAILED: u8 = 0x5D;
+pub const UDS_NRC_UPLOAD_DOWNLOAD_NOT_ACCEPTED: u8 = 0x70;
 pub const UDS_NRC_TRANSFER_DATA_SUSPENDED: u8 = 0x71;
 pub const UDS_NRC_GENERAL_PROGRAMMING_FAILURE: u8 = 0x72;
 pub const UDS_NRC_WRONG_BLOCK_SEQUENCE_COUNTER: u8 = 0x73;
 pub const UDS_NRC_RESPONSE_PENDING: u8 = 0x78;
+pub const UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED_IN_ACTIVE_SESSION: u8 = 0x7E;
+pub const UDS_NRC_SERVICE_NOT_SUPPORTED_IN_ACTIVE_SESSION: u8 = 0x7F;
 
 // Session Types
 pub const UDS_SESSION_DEFAULT: u8 = 0x01;
 pub const UDS_SESSION_PROGRAMMING: u8 = 0x02;
 pub const UDS_SESSION_EXTENDED: u8 = 0x03;
+pub const UDS_SESSION_PROVISIONING: u8 = 0x60;  // System supplier specific
+
+// Security Levels (seed request subfunctions)
+pub const UDS_SECURITY_LEVEL_EXTENDED: u8 = 0x01;
+pub const UDS_SECURITY_LEVEL_PROGRAMMING: u8 = 0x11;
+pub const UDS_SECURITY_LEVEL_EOL: u8 = 0x61;
+
+// Authentication Subfunctions
+pub const UDS_AUTH_DEAUTHENTICATE: u8 = 0x00;
+pub const UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL: u8 = 0x01;
+pub const UDS_AUTH_PROOF_OF_OWNERSHIP: u8 = 0x03;
+pub const UDS_AUTH_AUTHENTICATION_CONFIGURATION: u8 = 0x08;
+
+// Routine Control Subfunctions
+pub const UDS_ROUTINE_START: u8 = 0x01;
+
+// Routine Identifiers
+pub const UDS_RID_CHECK_PROGRAMMING_DEPENDENCIES: u16 = 0xFF01;
+pub const UDS_RID_INSTALL_BOOTLOADER_UPDATE: u16 = 0xF010;  // System supplier specific
+pub const UDS_RID_ATTESTATION_REPORT: u16 = 0xF020;  // System supplier specific
+pub const UDS_RID_READ_MEASUREMENT: u16 = 0xF021;  // System supplier specific
+pub const UDS_RID_UPDATE_KEYS: u16 = 0xF030;  // System supplier specific
+pub const UDS_RID_SECURE_BOOT_KEY: u16 = 0xF040;  // System supplier specific
+pub const UDS_RID_PROVISION_KEY: u16 = 0xF050;  // System supplier specific
+pub const UDS_RID_WRITE_IDENTITY: u16 = 0xF051;  // System supplier specific
+pub const UDS_RID_ADVANCE_LIFECYCLE: u16 = 0xF052;  // System supplier specific
+
+// Data Identifiers
+pub const UDS_DID_ECU_SERIAL_NUMBER: u16 = 0xF18C;
+pub const UDS_DID_SECURE_BOOT_STATUS: u16 = 0xFD00;  // System supplier specific
+pub const UDS_DID_DEVICE_CONFIGURATION: u16 = 0xFD01;  // System supplier specific
+pub const UDS_DID_LIFECYCLE_STATE: u16 = 0xFD02;  // System supplier specific
+
+// Data Format Identifier (compressionMethod in bits 7-4, encryptingMethod in bits 3-0)
+pub const UDS_DFI_COMPRESSION_NONE: u8 = 0x0;
+pub const UDS_DFI_COMPRESSION_HEATSHRINK: u8 = 0x1;
+pub const UDS_DFI_COMPRESSION_DELTA: u8 = 0x2;
+pub const UDS_DFI_COMPRESSION_HEATSHRINK_DELTA: u8 = 0x3;
+pub const UDS_DFI_ENCRYPTION_NONE: u8 = 0x0;
+pub const UDS_DFI_ENCRYPTION_AES128_GCM: u8 = 0x1;
 
 // Reset Types
 pub const UDS_RESET_HARD: u8 = 0x01;
@@ -0,0 +1,511 @@
+use super::*;
+
+/// Addressing mode a request was received with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// Physically addressed request (point-to-point)
+    Physical,
+    /// Functionally addressed request (broadcast)
+    Functional,
+}
+
+// Session masks
+pub const SESSION_MASK_DEFAULT: u8 = 1 << 0;
+pub const SESSION_MASK_PROGRAMMING: u8 = 1 << 1;
+pub const SESSION_MASK_EXTENDED: u8 = 1 << 2;
+pub const SESSION_MASK_PROVISIONING: u8 = 1 << 3;
+pub const SESSION_MASK_NON_DEFAULT: u8 = SESSION_MASK_PROGRAMMING | SESSION_MASK_EXTENDED | SESSION_MASK_PROVISIONING;
+pub const SESSION_MASK_ALL: u8 = SESSION_MASK_DEFAULT | SESSION_MASK_NON_DEFAULT;
+
+// Addressing masks
+pub const ADDRESSING_PHYSICAL: u8 = 1 << 0;
+pub const ADDRESSING_FUNCTIONAL: u8 = 1 << 1;
+pub const ADDRESSING_ALL: u8 = ADDRESSING_PHYSICAL | ADDRESSING_FUNCTIONAL;
+
+/// Security levels accepted for programming services
+const SECURITY_PROGRAMMING: &[u8] = &[UDS_SECURITY_LEVEL_PROGRAMMING];
+
+/// Security levels accepted for provisioning
+const SECURITY_PROVISIONING: &[u8] = &[UDS_SECURITY_LEVEL_EOL];
+
+/// Security levels accepted for memory readback
+const SECURITY_READBACK: &[u8] = &[UDS_SECURITY_LEVEL_PROGRAMMING, UDS_SECURITY_LEVEL_EOL];
+
+/// Security access subfunctions only available in the extended session
+const SECURITY_ACCESS_EXTENDED: AccessRule = AccessRule {
+    sessions: SESSION_MASK_EXTENDED,
+    security_levels: &[],
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Security access subfunctions only available in the programming session
+const SECURITY_ACCESS_PROGRAMMING: AccessRule = AccessRule {
+    sessions: SESSION_MASK_PROGRAMMING,
+    security_levels: &[],
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// End of line security access, in the extended and the provisioning session
+const SECURITY_ACCESS_END_OF_LINE: AccessRule = AccessRule {
+    sessions: SESSION_MASK_EXTENDED | SESSION_MASK_PROVISIONING,
+    security_levels: &[],
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Provisioning session, entered by the end of line tester only
+const SESSION_PROVISIONING: AccessRule = AccessRule {
+    sessions: SESSION_MASK_ALL,
+    security_levels: &[],
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Authentication subfunctions, available in every session
+const AUTHENTICATION: AccessRule = AccessRule {
+    sessions: SESSION_MASK_ALL,
+    security_levels: &[],
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Routines run while programming
+const ROUTINE_CONTROL_PROGRAMMING: AccessRule = AccessRule {
+    sessions: SESSION_MASK_PROGRAMMING,
+    security_levels: SECURITY_PROGRAMMING,
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Provisioning routines
+const ROUTINE_CONTROL_PROVISIONING: AccessRule = AccessRule {
+    sessions: SESSION_MASK_PROVISIONING,
+    security_levels: SECURITY_PROVISIONING,
+    addressing: ADDRESSING_PHYSICAL,
+};
+
+/// Access conditions shared by services and subfunctions
+#[derive(Clone, Copy)]
+pub struct AccessRule {
+    /// Sessions in which the request is permitted
+    pub sessions: u8,
+    /// Security levels of which at least one must be unlocked or granted (empty = no security)
+    pub security_levels: &'static [u8],
+    /// Addressing modes the request may be received with
+    pub addressing: u8,
+}
+
+impl AccessRule {
+    /// Rule without any session, security or addressing restriction
+    pub const fn open() -> Self {
+        Self {
+            sessions: SESSION_MASK_ALL,
+            security_levels: &[],
+            addressing: ADDRESSING_ALL,
+        }
+    }
+}
+
+/// Permission entry for a single subfunction
+pub struct SubfunctionPermission {
+    /// Subfunction value without the suppress positive response bit
+    pub subfunction: u8,
+    /// Access conditions for this subfunction
+    pub rule: AccessRule,
+}
+
+/// Permission entry for a service
+pub struct ServicePermission {
+    /// Service identifier
+    pub sid: u8,
+    /// Access conditions for the service itself
+    pub rule: AccessRule,
+    /// Supported subfunctions, `None` if the service has no subfunction parameter
+    pub subfunctions: Option<&'static [SubfunctionPermission]>,
+}
+
+/// Service permission table of the bootloader
+///
+/// A service with different conditions per session has one entry per
+/// group of sessions.
+pub static SERVICE_PERMISSIONS: &[ServicePermission] = &[
+    ServicePermission {
+        sid: UDS_SID_DIAGNOSTIC_SESSION_CONTROL,
+        rule: AccessRule::open(),
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_SESSION_DEFAULT, rule: AccessRule::open() },
+            SubfunctionPermission { subfunction: UDS_SESSION_PROGRAMMING, rule: AccessRule::open() },
+            SubfunctionPermission { subfunction: UDS_SESSION_EXTENDED, rule: AccessRule::open() },
+            SubfunctionPermission { subfunction: UDS_SESSION_PROVISIONING, rule: SESSION_PROVISIONING },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_ECU_RESET,
+        rule: AccessRule::open(),
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_RESET_HARD, rule: AccessRule::open() },
+            SubfunctionPermission { subfunction: UDS_RESET_SOFT, rule: AccessRule::open() },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_READ_DATA_BY_IDENTIFIER,
+        rule: AccessRule::open(),
+        subfunctions: None,
+    },
+    ServicePermission {
+        sid: UDS_SID_READ_MEMORY_BY_ADDRESS,
+        rule: AccessRule {
+            sessions: SESSION_MASK_NON_DEFAULT,
+            security_levels: SECURITY_READBACK,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: None,
+    },
+    ServicePermission {
+        sid: UDS_SID_SECURITY_ACCESS,
+        rule: AccessRule {
+            sessions: SESSION_MASK_NON_DEFAULT,
+            security_levels: &[],
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_EXTENDED, rule: SECURITY_ACCESS_EXTENDED },
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_EXTENDED + 1, rule: SECURITY_ACCESS_EXTENDED },
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_PROGRAMMING, rule: SECURITY_ACCESS_PROGRAMMING },
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_PROGRAMMING + 1, rule: SECURITY_ACCESS_PROGRAMMING },
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_EOL, rule: SECURITY_ACCESS_END_OF_LINE },
+            SubfunctionPermission { subfunction: UDS_SECURITY_LEVEL_EOL + 1, rule: SECURITY_ACCESS_END_OF_LINE },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_AUTHENTICATION,
+        rule: AccessRule {
+            sessions: SESSION_MASK_ALL,
+            security_levels: &[],
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_AUTH_DEAUTHENTICATE, rule: AUTHENTICATION },
+            SubfunctionPermission { subfunction: UDS_AUTH_VERIFY_CERTIFICATE_UNIDIRECTIONAL, rule: AUTHENTICATION },
+            SubfunctionPermission { subfunction: UDS_AUTH_PROOF_OF_OWNERSHIP, rule: AUTHENTICATION },
+            SubfunctionPermission { subfunction: UDS_AUTH_AUTHENTICATION_CONFIGURATION, rule: AUTHENTICATION },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_ROUTINE_CONTROL,
+        rule: AccessRule {
+            sessions: SESSION_MASK_PROGRAMMING,
+            security_levels: SECURITY_PROGRAMMING,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_ROUTINE_START, rule: ROUTINE_CONTROL_PROGRAMMING },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_ROUTINE_CONTROL,
+        rule: ROUTINE_CONTROL_PROVISIONING,
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: UDS_ROUTINE_START, rule: ROUTINE_CONTROL_PROVISIONING },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_TESTER_PRESENT,
+        rule: AccessRule::open(),
+        subfunctions: Some(&[
+            SubfunctionPermission { subfunction: 0x00, rule: AccessRule::open() },
+        ]),
+    },
+    ServicePermission {
+        sid: UDS_SID_REQUEST_DOWNLOAD,
+        rule: AccessRule {
+            sessions: SESSION_MASK_PROGRAMMING,
+            security_levels: SECURITY_PROGRAMMING,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: None,
+    },
+    ServicePermission {
+        sid: UDS_SID_REQUEST_UPLOAD,
+        rule: AccessRule {
+            sessions: SESSION_MASK_PROGRAMMING,
+            security_levels: SECURITY_READBACK,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: None,
+    },
+    ServicePermission {
+        sid: UDS_SID_TRANSFER_DATA,
+        rule: AccessRule {
+            sessions: SESSION_MASK_PROGRAMMING,
+            security_levels: SECURITY_READBACK,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: None,
+    },
+    ServicePermission {
+        sid: UDS_SID_REQUEST_TRANSFER_EXIT,
+        rule: AccessRule {
+            sessions: SESSION_MASK_PROGRAMMING,
+            security_levels: SECURITY_READBACK,
+            addressing: ADDRESSING_PHYSICAL,
+        },
+        subfunctions: None,
+    },
+];
+
+/// Convert a session type into its session mask bit
+pub fn session_mask(session: u8) -> u8 {
+    match session {
+        UDS_SESSION_DEFAULT => SESSION_MASK_DEFAULT,
+        UDS_SESSION_PROGRAMMING => SESSION_MASK_PROGRAMMING,
+        UDS_SESSION_EXTENDED => SESSION_MASK_EXTENDED,
+        UDS_SESSION_PROVISIONING => SESSION_MASK_PROVISIONING,
+        _ => 0,
+    }
+}
+
+/// Convert an addressing mode into its addressing mask bit
+pub fn addressing_mask(addressing: AddressingMode) -> u8 {
+    match addressing {
+        AddressingMode::Physical => ADDRESSING_PHYSICAL,
+        AddressingMode::Functional => ADDRESSING_FUNCTIONAL,
+    }
+}
+
+/// Look up the permission entry of a service
+///
+/// With several entries for the service the one of the active session
+/// applies, otherwise the first one.
+pub fn find_service(table: &'static [ServicePermission], sid: u8, session: u8) -> Option<&'static ServicePermission> {
+    let mut entries = table.iter().filter(|entry| entry.sid == sid);
+    let first = entries.clone().next();
+    
+    entries.find(|entry| entry.rule.sessions & session_mask(session) != 0).or(first)
+}
+
+/// Check a request against the permission table
+///
+/// `data` is the complete request including the SID and `is_level_unlocked`
+/// reports whether a given security level is currently unlocked. The checks
+/// are performed in the NRC priority order of ISO 14229-1 (general server
+/// response behaviour followed by the subfunction checks), returning the
+/// first NRC that applies.
+pub fn check_request<F>(
+    table: &'static [ServicePermission],
+    data: &[u8],
+    session: u8,
+    addressing: AddressingMode,
+    is_level_unlocked: F,
+) -> Result<(), u8>
+where
+    F: Fn(u8) -> bool,
+{
+    let sid = match data.first() {
+        Some(sid) => *sid,
+        None => return Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT),
+    };
+    
+    let security_ok = |levels: &[u8]| levels.is_empty() || levels.iter().any(|&l| is_level_unlocked(l));
+    
+    // Service level checks
+    let service = match find_service(table, sid, session) {
+        Some(service) if service.rule.addressing & addressing_mask(addressing) != 0 => service,
+        _ => return Err(UDS_NRC_SERVICE_NOT_SUPPORTED),
+    };
+    
+    if service.rule.sessions & session_mask(session) == 0 {
+        return Err(UDS_NRC_SERVICE_NOT_SUPPORTED_IN_ACTIVE_SESSION);
+    }
+    
+    if !security_ok(service.rule.security_levels) {
+        return Err(UDS_NRC_SECURITY_ACCESS_DENIED);
+    }
+    
+    // Subfunction level checks
+    let subfunctions = match service.subfunctions {
+        Some(subfunctions) => subfunctions,
+        None => return Ok(()),
+    };
+    
+    if data.len() < 2 {
+        return Err(UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT);
+    }
+    
+    let subfunction = data[1] & 0x7F;
+    let entry = match subfunctions.iter().find(|entry| entry.subfunction == subfunction) {
+        Some(entry) if entry.rule.addressing & addressing_mask(addressing) != 0 => entry,
+        _ => return Err(UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED),
+    };
+    
+    if entry.rule.sessions & session_mask(session) == 0 {
+        return Err(UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED_IN_ACTIVE_SESSION);
+    }
+    
+    if !security_ok(entry.rule.security_levels) {
+        return Err(UDS_NRC_SECURITY_ACCESS_DENIED);
+    }
+    
+    Ok(())
+}
+
+/// Whether a negative response must be suppressed for a functional request
+pub fn is_suppressed_for_functional(nrc: u8) -> bool {
+    matches!(
+        nrc,
+        UDS_NRC_SERVICE_NOT_SUPPORTED
+            | UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
+            | UDS_NRC_REQUEST_OUT_OF_RANGE
+            | UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED_IN_ACTIVE_SESSION
+            | UDS_NRC_SERVICE_NOT_SUPPORTED_IN_ACTIVE_SESSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Expected result of a request that passes all checks
+    const OK: u8 = 0x00;
+    
+    /// Sessions of the columns of the tables below
+    const SESSIONS: [u8; 4] = [UDS_SESSION_DEFAULT, UDS_SESSION_PROGRAMMING, UDS_SESSION_EXTENDED, UDS_SESSION_PROVISIONING];
+    
+    /// Request, unlocked security level (0 = none) and the expected result per session
+    type Row = (&'static [u8], u8, [u8; 4]);
+    
+    const PHYSICAL: &[Row] = &[
+        (&[0x10, 0x01], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x82], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x03], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x60], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x05], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x10], 0, [0x13, 0x13, 0x13, 0x13]),
+        (&[0x11, 0x01], 0, [OK, OK, OK, OK]),
+        (&[0x11, 0x83], 0, [OK, OK, OK, OK]),
+        (&[0x11, 0x02], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x22, 0xF1, 0x8C], 0, [OK, OK, OK, OK]),
+        (&[0x23, 0x14, 0x00, 0x10], 0, [0x7F, 0x33, 0x33, 0x33]),
+        (&[0x23, 0x14, 0x00, 0x10], 0x01, [0x7F, 0x33, 0x33, 0x33]),
+        (&[0x23, 0x14, 0x00, 0x10], 0x11, [0x7F, OK, OK, OK]),
+        (&[0x23, 0x14, 0x00, 0x10], 0x61, [0x7F, OK, OK, OK]),
+        (&[0x27, 0x01], 0, [0x7F, 0x7E, OK, 0x7E]),
+        (&[0x27, 0x02], 0, [0x7F, 0x7E, OK, 0x7E]),
+        (&[0x27, 0x11], 0, [0x7F, OK, 0x7E, 0x7E]),
+        (&[0x27, 0x12], 0, [0x7F, OK, 0x7E, 0x7E]),
+        (&[0x27, 0x61], 0, [0x7F, 0x7E, OK, OK]),
+        (&[0x27, 0x62], 0, [0x7F, 0x7E, OK, OK]),
+        (&[0x27, 0x05], 0, [0x7F, 0x12, 0x12, 0x12]),
+        (&[0x27], 0, [0x7F, 0x13, 0x13, 0x13]),
+        (&[0x29, 0x00], 0, [OK, OK, OK, OK]),
+        (&[0x29, 0x01], 0, [OK, OK, OK, OK]),
+        (&[0x29, 0x03], 0, [OK, OK, OK, OK]),
+        (&[0x29, 0x08], 0, [OK, OK, OK, OK]),
+        (&[0x29, 0x02], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x31, 0x01, 0xFF, 0x01], 0, [0x7F, 0x33, 0x7F, 0x33]),
+        (&[0x31, 0x01, 0xFF, 0x01], 0x01, [0x7F, 0x33, 0x7F, 0x33]),
+        (&[0x31, 0x01, 0xFF, 0x01], 0x11, [0x7F, OK, 0x7F, 0x33]),
+        (&[0x31, 0x01, 0xF0, 0x50], 0x61, [0x7F, 0x33, 0x7F, OK]),
+        (&[0x31, 0x02, 0xFF, 0x01], 0, [0x7F, 0x33, 0x7F, 0x33]),
+        (&[0x31, 0x02, 0xFF, 0x01], 0x11, [0x7F, 0x12, 0x7F, 0x33]),
+        (&[0x31, 0x02, 0xF0, 0x50], 0x61, [0x7F, 0x33, 0x7F, 0x12]),
+        (&[0x31], 0x11, [0x7F, 0x13, 0x7F, 0x33]),
+        (&[0x3E, 0x00], 0, [OK, OK, OK, OK]),
+        (&[0x3E, 0x80], 0, [OK, OK, OK, OK]),
+        (&[0x3E, 0x01], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x3E], 0, [0x13, 0x13, 0x13, 0x13]),
+        (&[0x34, 0x00, 0x44], 0, [0x7F, 0x33, 0x7F, 0x7F]),
+        (&[0x34, 0x00, 0x44], 0x11, [0x7F, OK, 0x7F, 0x7F]),
+        (&[0x34, 0x00, 0x44], 0x61, [0x7F, 0x33, 0x7F, 0x7F]),
+        (&[0x35, 0x00, 0x44], 0, [0x7F, 0x33, 0x7F, 0x7F]),
+        (&[0x35, 0x00, 0x44], 0x11, [0x7F, OK, 0x7F, 0x7F]),
+        (&[0x35, 0x00, 0x44], 0x61, [0x7F, OK, 0x7F, 0x7F]),
+        (&[0x36, 0x01], 0, [0x7F, 0x33, 0x7F, 0x7F]),
+        (&[0x36, 0x01], 0x61, [0x7F, OK, 0x7F, 0x7F]),
+        (&[0x37], 0, [0x7F, 0x33, 0x7F, 0x7F]),
+        (&[0x37], 0x11, [0x7F, OK, 0x7F, 0x7F]),
+        (&[0x28, 0x00], 0, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x85, 0x01], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[], 0, [0x13, 0x13, 0x13, 0x13]),
+    ];
+    
+    const FUNCTIONAL: &[Row] = &[
+        (&[0x10, 0x01], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x83], 0, [OK, OK, OK, OK]),
+        (&[0x10, 0x60], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x10, 0xE0], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x11, 0x01], 0, [OK, OK, OK, OK]),
+        (&[0x22, 0xF1, 0x8C], 0, [OK, OK, OK, OK]),
+        (&[0x23, 0x14, 0x00, 0x10], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x27, 0x01], 0, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x29, 0x00], 0, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x31, 0x01, 0xFF, 0x01], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x3E, 0x80], 0, [OK, OK, OK, OK]),
+        (&[0x3E, 0x01], 0, [0x12, 0x12, 0x12, 0x12]),
+        (&[0x34, 0x00, 0x44], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x35, 0x00, 0x44], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x36, 0x01], 0x11, [0x11, 0x11, 0x11, 0x11]),
+        (&[0x37], 0x11, [0x11, 0x11, 0x11, 0x11]),
+    ];
+    
+    fn check(data: &[u8], session: u8, addressing: AddressingMode, unlocked: u8) -> u8 {
+        match check_request(SERVICE_PERMISSIONS, data, session, addressing, |level| unlocked != 0 && level == unlocked) {
+            Ok(()) => OK,
+            Err(nrc) => nrc,
+        }
+    }
+    
+    fn check_table(table: &[Row], addressing: AddressingMode) {
+        for (data, unlocked, expected) in table {
+            for (session, expected) in SESSIONS.iter().zip(expected) {
+                assert_eq!(
+                    check(data, *session, addressing, *unlocked),
+                    *expected,
+                    "request {:02X?} in session 0x{:02X}, level 0x{:02X} unlocked, {:?}",
+                    data,
+                    session,
+                    unlocked,
+                    addressing
+                );
+            }
+        }
+    }
+    
+    #[test]
+    fn physical_requests() {
+        check_table(PHYSICAL, AddressingMode::Physical);
+    }
+    
+    #[test]
+    fn functional_requests() {
+        check_table(FUNCTIONAL, AddressingMode::Functional);
+    }
+    
+    #[test]
+    fn every_service_is_covered() {
+        for entry in SERVICE_PERMISSIONS {
+            assert!(PHYSICAL.iter().any(|(data, _, _)| data.first() == Some(&entry.sid)));
+            assert!(FUNCTIONAL.iter().any(|(data, _, _)| data.first() == Some(&entry.sid)));
+            
+            for subfunction in entry.subfunctions.unwrap_or(&[]) {
+                assert!(
+                    PHYSICAL.iter().any(|(data, _, _)| data.len() > 1 && data[0] == entry.sid && data[1] & 0x7F == subfunction.subfunction),
+                    "subfunction 0x{:02X} of service 0x{:02X} not covered",
+                    subfunction.subfunction,
+                    entry.sid
+                );
+            }
+        }
+    }
+    
+    #[test]
+    fn unknown_session_is_rejected() {
+        assert_eq!(check(&[0x23, 0x14, 0x00, 0x10], 0x7F, AddressingMode::Physical, 0x11), 0x7F);
+    }
+    
+    #[test]
+    fn functional_suppression() {
+        for nrc in [0x11, 0x12, 0x31, 0x7E, 0x7F] {
+            assert!(is_suppressed_for_functional(nrc));
+        }
+        for nrc in [0x13, 0x22, 0x24, 0x33, 0x35, 0x36, 0x37, 0x72] {
+            assert!(!is_suppressed_for_functional(nrc));
+        }
+    }
+}
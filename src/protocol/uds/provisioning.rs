@@ -0,0 +1,210 @@
+use defmt::{info, warn};
+use heapless::Vec;
+use super::*;
+use super::routine::{ROUTINE_STATUS_CORRECT, ROUTINE_STATUS_INCORRECT};
+use crate::bootloader::lifecycle::{self, DeviceIdentity, LifecycleError, LifecycleState, IDENTITY_LENGTH};
+use crate::bootloader::nvm::NvStorage;
+use crate::bootloader::secure_boot::SecureBootEngine;
+use crate::config;
+use crate::hal::s32k148::csec::{KeyUpdateMessages, CSEC_KEY_PROOF_LENGTH, CSEC_KEY_UPDATE_LENGTH};
+use crate::protocol::fixed_response;
+
+/// End of line provisioning routines
+///
+/// Run in the provisioning session, which can only be entered until the
+/// device lifecycle is closed. Every routine checks the lifecycle again,
+/// so locking the device ends provisioning within the same session.
+pub struct Provisioning {
+    /// Non-volatile storage holding the lifecycle state and identity
+    nv_storage: Option<*mut dyn NvStorage>,
+    /// SHE engine the keys are loaded into
+    engine: Option<*mut dyn SecureBootEngine>,
+}
+
+impl Provisioning {
+    /// Create a new provisioning handler
+    pub fn new() -> Self {
+        Self {
+            nv_storage: None,
+            engine: None,
+        }
+    }
+    
+    /// Initialize the provisioning handler
+    pub fn init(&mut self) {
+        info!("Initializing UDS provisioning");
+    }
+    
+    /// Register the non-volatile storage holding the lifecycle state and identity
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.nv_storage = Some(storage);
+    }
+    
+    /// Register the SHE engine the keys are loaded into
+    pub fn register_engine(&mut self, engine: *mut (dyn SecureBootEngine + 'static)) {
+        self.engine = Some(engine);
+    }
+    
+    /// Check if the provisioning session may be entered
+    pub fn is_available(&self) -> bool {
+        match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) => lifecycle::load_state(unsafe { &*storage }).is_open(),
+            None => false,
+        }
+    }
+    
+    /// Handle routine control in the provisioning session
+    pub fn handle_routine_control(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        // Subfunction followed by the routine identifier
+        if data.len() < 3 {
+            return self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let storage = match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) => unsafe { &mut *storage },
+            None => {
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL,
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                );
+            }
+        };
+        
+        let subfunction = data[0] & 0x7F;
+        let routine_id = u16::from_be_bytes([data[1], data[2]]);
+        
+        let result = match (subfunction, routine_id) {
+            (UDS_ROUTINE_START, UDS_RID_PROVISION_KEY) => {
+                if data.len() != 3 + CSEC_KEY_UPDATE_LENGTH {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL,
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                let engine = match self.engine {
+                    // Safety: We know this pointer is valid
+                    Some(engine) => unsafe { &mut *engine },
+                    None => {
+                        return self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL,
+                            UDS_NRC_CONDITIONS_NOT_CORRECT
+                        );
+                    }
+                };
+                
+                let mut messages = [0u8; CSEC_KEY_UPDATE_LENGTH];
+                messages.copy_from_slice(&data[3..]);
+                let messages = KeyUpdateMessages::parse(&messages);
+                
+                match lifecycle::provision_key(
+                    storage,
+                    engine,
+                    &messages,
+                    config::SECURE_BOOT_SIZE,
+                    config::SECURE_BOOT_FLAVOR,
+                ) {
+                    Ok(proof) => {
+                        info!("SHE key 0x{:02X} provisioned", messages.key_id());
+                        
+                        // The tester checks M4 and M5 to confirm the key was stored
+                        let mut status_record = [0u8; 1 + CSEC_KEY_PROOF_LENGTH];
+                        status_record[0] = ROUTINE_STATUS_CORRECT;
+                        status_record[1..].copy_from_slice(&proof.to_bytes());
+                        return self.create_positive_response(data, &status_record);
+                    },
+                    Err(error) => Err(error),
+                }
+            },
+            (UDS_ROUTINE_START, UDS_RID_WRITE_IDENTITY) => {
+                if data.len() != 3 + IDENTITY_LENGTH {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL,
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                let mut identity = [0u8; IDENTITY_LENGTH];
+                identity.copy_from_slice(&data[3..]);
+                
+                lifecycle::write_identity(storage, &DeviceIdentity::parse(&identity))
+            },
+            (UDS_ROUTINE_START, UDS_RID_ADVANCE_LIFECYCLE) => {
+                if data.len() != 4 {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL,
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                match LifecycleState::from_byte(data[3]) {
+                    Some(target) => lifecycle::advance(storage, target).map(|()| {
+                        info!("Device lifecycle advanced to {}", defmt::Debug2Format(&target));
+                    }),
+                    None => Err(LifecycleError::InvalidTransition),
+                }
+            },
+            _ => {
+                warn!("Unsupported provisioning routine 0x{:04X}", routine_id);
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL,
+                    UDS_NRC_REQUEST_OUT_OF_RANGE
+                );
+            }
+        };
+        
+        match result {
+            Ok(()) => self.create_positive_response(data, &[ROUTINE_STATUS_CORRECT]),
+            Err(error) => {
+                warn!("Provisioning routine 0x{:04X} refused: {}", routine_id, defmt::Debug2Format(&error));
+                
+                match error {
+                    LifecycleError::Closed | LifecycleError::NotPersonalized => {
+                        self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL,
+                            UDS_NRC_CONDITIONS_NOT_CORRECT
+                        )
+                    },
+                    LifecycleError::InvalidTransition => {
+                        self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL,
+                            UDS_NRC_REQUEST_OUT_OF_RANGE
+                        )
+                    },
+                    _ => self.create_positive_response(data, &[ROUTINE_STATUS_INCORRECT]),
+                }
+            }
+        }
+    }
+    
+    /// Create a positive response with the routine status record
+    fn create_positive_response(&self, data: &[u8], status_record: &[u8]) -> Vec<u8, 64> {
+        // Check if response is suppressed
+        if (data[0] & 0x80) != 0 {
+            return Vec::new();
+        }
+        
+        let mut response = fixed_response([UDS_SID_ROUTINE_CONTROL + UDS_RSP_POSITIVE, data[0] & 0x7F, data[1], data[2]]);
+        if response.extend_from_slice(status_record).is_err() {
+            return self.create_negative_response(UDS_SID_ROUTINE_CONTROL, UDS_NRC_RESPONSE_TOO_LONG);
+        }
+        
+        response
+    }
+    
+    /// Create a negative response
+    fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
+    }
+}
+
+impl Default for Provisioning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
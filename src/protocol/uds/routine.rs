@@ -0,0 +1,368 @@
+use defmt::{info, warn};
+use heapless::Vec;
+use super::*;
+use super::download::DownloadError;
+use super::transfer::TransferManager;
+use crate::bootloader::keystore::{KeyUpdate, KEY_UPDATE_LENGTH};
+use crate::bootloader::measurement::{self, AttestationReport, ATTESTATION_NONCE_LENGTH};
+use crate::bootloader::nvm::NvStorage;
+use crate::bootloader::partition;
+use crate::bootloader::secure_boot::{self, SecureBootEngine, SecureBootError};
+use crate::bootloader::self_update::{self, UpdateError, UpdateFlash, UpdateLayout};
+use crate::bootloader::verification;
+use crate::config;
+use crate::crypto::cmac::MacGenerator;
+use crate::hal::s32k148::csec::{KeyUpdateMessages, CSEC_KEY_PROOF_LENGTH, CSEC_KEY_UPDATE_LENGTH};
+use crate::protocol::fixed_response;
+
+/// routineStatusRecord: dependencies correct / update verified and armed
+pub const ROUTINE_STATUS_CORRECT: u8 = 0x00;
+
+/// routineStatusRecord: dependencies incorrect / update rejected
+pub const ROUTINE_STATUS_INCORRECT: u8 = 0x01;
+
+/// UDS RoutineControl handler
+pub struct RoutineControl {
+    /// Flash holding the staged bootloader update
+    flash: Option<*mut dyn UpdateFlash>,
+    /// Non-volatile storage of the self-update journal and the key store
+    nv_storage: Option<*mut dyn NvStorage>,
+    /// MAC generator holding the device attestation key
+    mac: Option<*mut dyn MacGenerator>,
+    /// SHE engine holding the secure boot keys
+    secure_boot: Option<*mut dyn SecureBootEngine>,
+}
+
+impl RoutineControl {
+    /// Create a new routine control handler
+    pub fn new() -> Self {
+        Self {
+            flash: None,
+            nv_storage: None,
+            mac: None,
+            secure_boot: None,
+        }
+    }
+    
+    /// Initialize routine control
+    pub fn init(&mut self) {
+        info!("Initializing UDS routine control");
+    }
+    
+    /// Register the flash holding the staged bootloader update
+    pub fn register_flash(&mut self, flash: &mut (dyn UpdateFlash + 'static)) {
+        self.flash = Some(flash);
+    }
+    
+    /// Register the non-volatile storage of the self-update journal and the key store
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.nv_storage = Some(storage);
+    }
+    
+    /// Register the MAC generator holding the device attestation key
+    pub fn register_mac_generator(&mut self, mac: *mut (dyn MacGenerator + 'static)) {
+        self.mac = Some(mac);
+    }
+    
+    /// Register the SHE engine holding the secure boot keys
+    pub fn register_secure_boot(&mut self, engine: *mut (dyn SecureBootEngine + 'static)) {
+        self.secure_boot = Some(engine);
+    }
+    
+    /// Handle routine control
+    pub fn handle_routine_control(&mut self, data: &[u8], transfer: &mut TransferManager) -> Vec<u8, 64> {
+        // Subfunction followed by the routine identifier
+        if data.len() < 3 {
+            return self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let subfunction = data[0] & 0x7F;
+        let routine_id = u16::from_be_bytes([data[1], data[2]]);
+        
+        let status = match (subfunction, routine_id) {
+            (UDS_ROUTINE_START, UDS_RID_CHECK_PROGRAMMING_DEPENDENCIES) => {
+                if data.len() != 3 {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL, 
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                match transfer.check_programming_dependencies() {
+                    Ok(()) => ROUTINE_STATUS_CORRECT,
+                    Err(DownloadError::ProgrammingFailed) => {
+                        // The image could not be marked valid
+                        return self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL, 
+                            UDS_NRC_GENERAL_PROGRAMMING_FAILURE
+                        );
+                    },
+                    Err(error) => {
+                        warn!("Programming dependencies incorrect: {}", defmt::Debug2Format(&error));
+                        ROUTINE_STATUS_INCORRECT
+                    }
+                }
+            },
+            (UDS_ROUTINE_START, UDS_RID_INSTALL_BOOTLOADER_UPDATE) => {
+                if data.len() != 3 {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL, 
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                // Only supported with a staging partition
+                let layout = match partition::update_layout() {
+                    Some(layout) => layout,
+                    None => {
+                        return self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL, 
+                            UDS_NRC_REQUEST_OUT_OF_RANGE
+                        );
+                    }
+                };
+                
+                let (flash, storage) = match (self.flash, self.nv_storage) {
+                    // Safety: We know these pointers are valid
+                    (Some(flash), Some(storage)) => unsafe { (&*flash, &mut *storage) },
+                    _ => {
+                        return self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL, 
+                            UDS_NRC_CONDITIONS_NOT_CORRECT
+                        );
+                    }
+                };
+                
+                match Self::arm_bootloader_update(flash, storage, &layout) {
+                    Ok(security_version) => {
+                        info!("Bootloader update (security version {}) installed at the next reset", security_version);
+                        ROUTINE_STATUS_CORRECT
+                    },
+                    Err(error) => {
+                        warn!("Bootloader update rejected: {}", defmt::Debug2Format(&error));
+                        ROUTINE_STATUS_INCORRECT
+                    }
+                }
+            },
+            (UDS_ROUTINE_START, UDS_RID_UPDATE_KEYS) => {
+                if data.len() != 3 + KEY_UPDATE_LENGTH {
+                    return self.create_negative_response(
+                        UDS_SID_ROUTINE_CONTROL, 
+                        UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                    );
+                }
+                
+                let storage = match self.nv_storage {
+                    // Safety: We know this pointer is valid
+                    Some(storage) => unsafe { &mut *storage },
+                    None => {
+                        return self.create_negative_response(
+                            UDS_SID_ROUTINE_CONTROL, 
+                            UDS_NRC_CONDITIONS_NOT_CORRECT
+                        );
+                    }
+                };
+                
+                let mut update = [0u8; KEY_UPDATE_LENGTH];
+                update.copy_from_slice(&data[3..]);
+                
+                match KeyUpdate::parse(&update).and_then(|update| verification::install_key_update(storage, &update)) {
+                    Ok(keys) => {
+                        info!("Key update {} installed (revoked keys 0x{:08X})", keys.sequence, keys.revoked);
+                        ROUTINE_STATUS_CORRECT
+                    },
+                    Err(error) => {
+                        warn!("Key update rejected: {}", defmt::Debug2Format(&error));
+                        ROUTINE_STATUS_INCORRECT
+                    }
+                }
+            },
+            (UDS_ROUTINE_START, UDS_RID_ATTESTATION_REPORT) => {
+                return self.attestation_report(data);
+            },
+            (UDS_ROUTINE_START, UDS_RID_READ_MEASUREMENT) => {
+                return self.read_measurement(data);
+            },
+            (UDS_ROUTINE_START, UDS_RID_SECURE_BOOT_KEY) => {
+                return self.secure_boot_key(data);
+            },
+            _ => {
+                warn!("Unsupported routine 0x{:04X}", routine_id);
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_REQUEST_OUT_OF_RANGE
+                );
+            }
+        };
+        
+        self.create_positive_response(data, &[status])
+    }
+    
+    /// Report the hash chain of this start, authenticated with the device key
+    ///
+    /// The request carries the verifier's nonce, the status record is the
+    /// serialized `AttestationReport` (count, chain, CMAC).
+    fn attestation_report(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        if data.len() != 3 + ATTESTATION_NONCE_LENGTH {
+            return self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let (log, mac) = match (measurement::read(), self.mac) {
+            // Safety: We know this pointer is valid
+            (Some(log), Some(mac)) => (log, unsafe { &mut *mac }),
+            _ => {
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                );
+            }
+        };
+        
+        let mut nonce = [0u8; ATTESTATION_NONCE_LENGTH];
+        nonce.copy_from_slice(&data[3..]);
+        
+        match AttestationReport::create(&log, &nonce, &mut |message| mac.generate_mac(message).ok()) {
+            Ok(report) => {
+                info!("Attestation report over {} measurements", report.count);
+                self.create_positive_response(data, &report.to_bytes())
+            },
+            Err(error) => {
+                warn!("Attestation report failed: {}", defmt::Debug2Format(&error));
+                self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                )
+            }
+        }
+    }
+    
+    /// Return one measurement of this start, for the verifier to replay the chain
+    fn read_measurement(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        if data.len() != 4 {
+            return self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let log = match measurement::read() {
+            Some(log) => log,
+            None => {
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                );
+            }
+        };
+        
+        match log.measurements().get(data[3] as usize) {
+            Some(measurement) => self.create_positive_response(data, &measurement.to_bytes()),
+            None => self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL, 
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            ),
+        }
+    }
+    
+    /// Store BOOT_MAC_KEY or BOOT_MAC from SHE key update messages
+    ///
+    /// The request carries M1 | M2 | M3, the status record of an accepted
+    /// key M4 | M5 for the tester to check that the engine stored it.
+    /// Storing the BOOT_MAC_KEY also configures secure boot.
+    fn secure_boot_key(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        if data.len() != 3 + CSEC_KEY_UPDATE_LENGTH {
+            return self.create_negative_response(
+                UDS_SID_ROUTINE_CONTROL, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let engine = match self.secure_boot {
+            // Safety: We know this pointer is valid
+            Some(engine) => unsafe { &mut *engine },
+            None => {
+                return self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                );
+            }
+        };
+        
+        let mut messages = [0u8; CSEC_KEY_UPDATE_LENGTH];
+        messages.copy_from_slice(&data[3..]);
+        let messages = KeyUpdateMessages::parse(&messages);
+        
+        match secure_boot::store_key(engine, &messages, config::SECURE_BOOT_SIZE, config::SECURE_BOOT_FLAVOR) {
+            Ok(proof) => {
+                info!("Secure boot key 0x{:02X} stored", messages.key_id());
+                
+                let mut status_record = [0u8; 1 + CSEC_KEY_PROOF_LENGTH];
+                status_record[0] = ROUTINE_STATUS_CORRECT;
+                status_record[1..].copy_from_slice(&proof.to_bytes());
+                self.create_positive_response(data, &status_record)
+            },
+            Err(SecureBootError::Disabled) | Err(SecureBootError::WrongKeySlot) => {
+                self.create_negative_response(
+                    UDS_SID_ROUTINE_CONTROL, 
+                    UDS_NRC_REQUEST_OUT_OF_RANGE
+                )
+            },
+            Err(error) => {
+                warn!("Secure boot key rejected: {}", defmt::Debug2Format(&error));
+                self.create_positive_response(data, &[ROUTINE_STATUS_INCORRECT])
+            }
+        }
+    }
+    
+    /// Verify the updater image in the staging partition and arm its installation
+    fn arm_bootloader_update(
+        flash: &dyn UpdateFlash,
+        storage: &mut dyn NvStorage,
+        layout: &UpdateLayout,
+    ) -> Result<u32, UpdateError> {
+        let min_security_version = self_update::min_security_version(storage, config::BOOTLOADER_SECURITY_VERSION);
+        let keys = verification::load_keys(storage);
+        
+        let header = self_update::verify_staged(
+            flash,
+            layout,
+            min_security_version,
+            &|key_id, message, signature| verification::verify_image_signature(&keys, key_id, message, signature),
+        )?;
+        self_update::arm(storage, &header)?;
+        
+        Ok(header.security_version)
+    }
+    
+    /// Create a positive response with the routine status record
+    fn create_positive_response(&self, data: &[u8], status_record: &[u8]) -> Vec<u8, 64> {
+        // Check if response is suppressed
+        if (data[0] & 0x80) != 0 {
+            return Vec::new();
+        }
+        
+        let mut response = fixed_response([UDS_SID_ROUTINE_CONTROL + UDS_RSP_POSITIVE, data[0] & 0x7F, data[1], data[2]]);
+        if response.extend_from_slice(status_record).is_err() {
+            return self.create_negative_response(UDS_SID_ROUTINE_CONTROL, UDS_NRC_RESPONSE_TOO_LONG);
+        }
+        
+        response
+    }
+    
+    /// Create a negative response
+    fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
+    }
+}
+
+impl Default for RoutineControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
\ No newline at end of file
@@ -1,9 +1,37 @@
 use defmt::{debug, info, warn};
-use heapless::Vec;
+use heapless::{HistoryBuffer, Vec};
 use super::*;
+use super::seed_key::{SeedKeyAlgorithm, XorRotateAlgorithm, MAX_KEY_LENGTH, MAX_SEED_LENGTH};
+use crate::crypto::drbg::{DrbgError, RandomGenerator};
+use crate::crypto::entropy::EntropySource;
+use crate::bootloader::nvm::{NvStorage, NvmError, NVM_SECURITY_ATTEMPTS_OFFSET};
+use crate::config::{self, SECURITY_LOCKOUT_DELAY_MS, SEED_PERSONALIZATION};
+use crate::protocol::fixed_response;
 
-// Security level definitions
-const SECURITY_LEVEL_UNLOCKED: u8 = 0x01;
+/// Maximum number of configurable security levels
+const MAX_SECURITY_LEVELS: usize = 8;
+
+// Default seed/key algorithms per security level
+static EXTENDED_ALGORITHM: XorRotateAlgorithm = XorRotateAlgorithm::new(config::SECURITY_EXTENDED_MASK, config::SECURITY_EXTENDED_ROTATION);
+static PROGRAMMING_ALGORITHM: XorRotateAlgorithm = XorRotateAlgorithm::new(config::SECURITY_PROGRAMMING_MASK, config::SECURITY_PROGRAMMING_ROTATION);
+static EOL_ALGORITHM: XorRotateAlgorithm = XorRotateAlgorithm::new(config::SECURITY_EOL_MASK, config::SECURITY_EOL_ROTATION);
+
+/// Marker of a valid failed-attempt record in non-volatile storage
+const ATTEMPT_RECORD_MAGIC: u16 = 0x5AC3;
+
+/// Maximum number of attempts to draw an acceptable seed
+const MAX_SEED_DRAWS: usize = 4;
+
+/// Number of recently issued seeds a new seed must differ from
+const SEED_HISTORY: usize = 8;
+
+/// Security level bound to a seed/key algorithm
+pub struct SecurityLevel {
+    /// Seed request subfunction (odd), the key is sent with `level + 1`
+    pub level: u8,
+    /// Algorithm used to verify keys for this level
+    pub algorithm: &'static dyn SeedKeyAlgorithm,
+}
 
 /// UDS Security Access implementation
 pub struct SecurityAccess {
@@ -15,20 +43,50 @@ pub struct SecurityAccess {
     failed_attempts: u8,
     /// Maximum allowed failed unlock attempts
     max_failed_attempts: u8,
+    /// Configured security levels
+    levels: Vec<SecurityLevel, MAX_SECURITY_LEVELS>,
+    /// Security level the last seed was issued for
+    pending_level: Option<u8>,
     /// Seed value for last challenge
-    last_seed: u32,
+    last_seed: Vec<u8, MAX_SEED_LENGTH>,
+    /// Seeds issued recently, kept across session transitions
+    issued_seeds: HistoryBuffer<Vec<u8, MAX_SEED_LENGTH>, SEED_HISTORY>,
+    /// Random bit generator for seeds
+    rng: RandomGenerator,
+    /// Non-volatile storage for the failed-attempt counter
+    nv_storage: Option<*mut dyn NvStorage>,
+    /// Whether the failed-attempt counter survives a reset
+    attempts_persistent: bool,
+    /// Delay after exceeding the number of attempts
+    lockout_delay_ms: u32,
+    /// Start of the running lockout delay
+    delay_start: Option<u32>,
 }
 
 impl SecurityAccess {
-    /// Create a new security access handler
+    /// Create a new security access handler with the default security levels
     pub fn new() -> Self {
-        Self {
+        let mut security = Self {
             security_level: 0,
             unlocked: false,
             failed_attempts: 0,
             max_failed_attempts: 3,
-            last_seed: 0,
-        }
+            levels: Vec::new(),
+            pending_level: None,
+            last_seed: Vec::new(),
+            issued_seeds: HistoryBuffer::new(),
+            rng: RandomGenerator::new(SEED_PERSONALIZATION),
+            nv_storage: None,
+            attempts_persistent: false,
+            lockout_delay_ms: SECURITY_LOCKOUT_DELAY_MS,
+            delay_start: None,
+        };
+        
+        let _ = security.register_level(UDS_SECURITY_LEVEL_EXTENDED, &EXTENDED_ALGORITHM);
+        let _ = security.register_level(UDS_SECURITY_LEVEL_PROGRAMMING, &PROGRAMMING_ALGORITHM);
+        let _ = security.register_level(UDS_SECURITY_LEVEL_EOL, &EOL_ALGORITHM);
+        
+        security
     }
     
     /// Initialize security access
@@ -37,174 +95,375 @@ impl SecurityAccess {
         self.security_level = 0;
         self.unlocked = false;
         self.failed_attempts = 0;
-        self.last_seed = 0;
+        self.pending_level = None;
+        self.last_seed.clear();
+        self.delay_start = None;
+    }
+    
+    /// Register the non-volatile storage holding the failed-attempt counter
+    ///
+    /// The persisted counter is loaded immediately, so a reset does not grant
+    /// fresh attempts. If the counter is already exhausted, the lockout delay
+    /// starts again with the first request after power-up. A blank record is
+    /// initialized with zero attempts.
+    ///
+    /// Without an initialized EEPROM (FlexNVM not partitioned) the counter
+    /// cannot persist: every power-up then starts with exhausted attempts,
+    /// so a reset grants at most one attempt per lockout delay instead of
+    /// locking security access for good.
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.nv_storage = Some(storage);
+        self.attempts_persistent = true;
+        self.delay_start = None;
+        
+        self.failed_attempts = match self.load_failed_attempts() {
+            Ok(Some(attempts)) => attempts,
+            Ok(None) => {
+                info!("Security access: initializing the attempt counter");
+                if self.store_failed_attempts(0) { 0 } else { self.max_failed_attempts }
+            },
+            Err(NvmError::NotReady) => {
+                warn!("Security access: EEPROM not initialized, attempt counter is volatile");
+                self.attempts_persistent = false;
+                self.max_failed_attempts
+            },
+            Err(_) => self.max_failed_attempts,
+        };
+        
+        if self.failed_attempts > 0 {
+            warn!("Security access: {} failed attempts persisted", self.failed_attempts);
+        }
+    }
+    
+    /// Configure the maximum number of failed attempts and the lockout delay
+    pub fn set_attempt_limits(&mut self, max_failed_attempts: u8, lockout_delay_ms: u32) {
+        self.max_failed_attempts = max_failed_attempts.max(1);
+        self.lockout_delay_ms = lockout_delay_ms;
+    }
+    
+    /// Register the entropy source used to seed the seed generator
+    pub fn register_entropy_source(&mut self, entropy: *mut (dyn EntropySource + 'static)) {
+        self.rng.register_entropy_source(entropy);
+    }
+    
+    /// Bind a seed/key algorithm to a security level
+    ///
+    /// An existing binding for the same level is replaced. New levels must
+    /// also be permitted in the service permission table.
+    pub fn register_level(
+        &mut self,
+        level: u8,
+        algorithm: &'static dyn SeedKeyAlgorithm,
+    ) -> Result<(), SecurityError> {
+        // Seed requests use odd subfunctions in the range 0x01..=0x7D
+        if (level & 0x01) == 0 || level > 0x7D {
+            return Err(SecurityError::InvalidLevel);
+        }
+        
+        if algorithm.seed_length() == 0
+            || algorithm.seed_length() > MAX_SEED_LENGTH
+            || algorithm.key_length() == 0
+            || algorithm.key_length() > MAX_KEY_LENGTH
+        {
+            return Err(SecurityError::InvalidLength);
+        }
+        
+        if let Some(existing) = self.levels.iter_mut().find(|l| l.level == level) {
+            existing.algorithm = algorithm;
+            return Ok(());
+        }
+        
+        self.levels
+            .push(SecurityLevel { level, algorithm })
+            .map_err(|_| SecurityError::TooManyLevels)
     }
     
     /// Handle security access service
-    pub fn handle_security_access(&mut self, data: &[u8]) -> Vec<u8, 64> {
+    ///
+    /// `now_ms` is the current millisecond time used for the lockout delay.
+    pub fn handle_security_access(&mut self, data: &[u8], now_ms: u32) -> Vec<u8, 64> {
         if data.is_empty() {
             return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
             );
         }
         
-        let subfunction = data[0];
+        let subfunction = data[0] & 0x7F;
+        let suppress_response = (data[0] & 0x80) != 0;
         
-        // Check if requesting seed (odd subfunction)
-        if (subfunction & 0x01) == 0x01 {
-            return self.handle_seed_request(subfunction);
-        } 
-        // Check if sending key (even subfunction)
-        else if (subfunction & 0x01) == 0x00 {
-            if data.len() < 5 {
-                return self.create_negative_response(
-                    UDS_SID_SECURITY_ACCESS, 
-                    UDS_NRC_CONDITIONS_NOT_CORRECT
-                );
-            }
-            
-            // Extract key from data
-            let key = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
-            return self.handle_key_verification(subfunction, key);
-        } 
-        else {
-            // Invalid subfunction
+        // Odd subfunctions request a seed, even subfunctions send a key
+        let level = if (subfunction & 0x01) == 0x01 { subfunction } else { subfunction.wrapping_sub(1) };
+        if self.find_level(level).is_none() {
             return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
+                UDS_SID_SECURITY_ACCESS,
                 UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
             );
         }
+        
+        let response = if (subfunction & 0x01) == 0x01 {
+            self.handle_seed_request(level, &data[1..], now_ms)
+        } else {
+            self.handle_key_verification(level, &data[1..], now_ms)
+        };
+        
+        // Positive responses to key verification may be suppressed
+        if suppress_response && response.first() != Some(&UDS_SID_NEGATIVE_RESPONSE) {
+            return Vec::new();
+        }
+        
+        response
     }
     
     /// Handle seed request
-    fn handle_seed_request(&mut self, subfunction: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
+    fn handle_seed_request(&mut self, level: u8, _record: &[u8], now_ms: u32) -> Vec<u8, 64> {
+        let seed_length = match self.find_level(level) {
+            Some(config) => config.algorithm.seed_length(),
+            None => {
+                return self.create_negative_response(
+                    UDS_SID_SECURITY_ACCESS,
+                    UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
+                );
+            }
+        };
         
-        // Verify the security level matches what we support
-        if subfunction != SECURITY_LEVEL_UNLOCKED {
-            return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
-            );
-        }
+        let mut response = fixed_response([UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE, level]);
         
-        // Check if already unlocked for this level
-        if self.unlocked {
-            // Already unlocked, return zero seed
-            response.push(UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE);
-            response.push(subfunction);
-            response.push(0);
-            response.push(0);
-            response.push(0);
-            response.push(0);
+        // Already unlocked for this level, return zero seed
+        if self.is_level_unlocked(level) {
+            if response.extend_from_slice(&[0; MAX_SEED_LENGTH][..seed_length]).is_err() {
+                return self.create_negative_response(UDS_SID_SECURITY_ACCESS, UDS_NRC_RESPONSE_TOO_LONG);
+            }
             return response;
         }
         
-        // Check if we have exceeded maximum attempts
-        if self.failed_attempts >= self.max_failed_attempts {
+        // Check if the lockout delay is still running
+        if self.is_delay_active(now_ms) {
             return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED
             );
         }
         
-        // Generate seed value (would be more complex in real implementation)
-        self.last_seed = self.generate_seed();
-        
-        // Build positive response with seed
-        response.push(UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE);
-        response.push(subfunction);
-        
-        // Add seed bytes (big-endian)
-        let seed_bytes = self.last_seed.to_be_bytes();
-        response.push(seed_bytes[0]);
-        response.push(seed_bytes[1]);
-        response.push(seed_bytes[2]);
-        response.push(seed_bytes[3]);
+        // Generate a new seed for the requested level
+        let mut seed = [0u8; MAX_SEED_LENGTH];
+        if self.generate_seed(&mut seed[..seed_length]).is_err() {
+            warn!("Seed generation failed");
+            return self.create_negative_response(
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_CONDITIONS_NOT_CORRECT
+            );
+        }
+        self.last_seed.clear();
+        if self.last_seed.extend_from_slice(&seed[..seed_length]).is_err()
+            || response.extend_from_slice(&seed[..seed_length]).is_err()
+        {
+            return self.create_negative_response(UDS_SID_SECURITY_ACCESS, UDS_NRC_RESPONSE_TOO_LONG);
+        }
+        self.issued_seeds.write(self.last_seed.clone());
+        self.pending_level = Some(level);
         
         response
     }
     
     /// Handle key verification
-    fn handle_key_verification(&mut self, subfunction: u8, key: u32) -> Vec<u8, 64> {
-        let mut response = Vec::new();
+    fn handle_key_verification(&mut self, level: u8, key: &[u8], now_ms: u32) -> Vec<u8, 64> {
+        let algorithm = match self.find_level(level) {
+            Some(config) => config.algorithm,
+            None => {
+                return self.create_negative_response(
+                    UDS_SID_SECURITY_ACCESS,
+                    UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
+                );
+            }
+        };
         
-        // Verify the security level matches what we support
-        if subfunction != SECURITY_LEVEL_UNLOCKED + 1 {
+        // Validate key length for this level
+        if key.len() != algorithm.key_length() {
             return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
             );
         }
         
-        // Check if already unlocked
-        if self.unlocked {
-            response.push(UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE);
-            response.push(subfunction);
-            return response;
+        // A key is only accepted directly after a seed for the same level
+        if self.pending_level != Some(level) {
+            return self.create_negative_response(
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_REQUEST_SEQUENCE_ERROR
+            );
         }
+        self.pending_level = None;
         
-        // Check if we have exceeded maximum attempts
-        if self.failed_attempts >= self.max_failed_attempts {
+        // Check if the lockout delay is still running
+        if self.is_delay_active(now_ms) {
             return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED
             );
         }
         
-        // Calculate expected key from seed
-        let expected_key = self.calculate_key(self.last_seed);
+        // Count the attempt persistently before comparing, so a reset
+        // during verification cannot be used to avoid the counter
+        let attempts = self.failed_attempts.saturating_add(1);
+        if !self.store_failed_attempts(attempts) {
+            return self.create_negative_response(
+                UDS_SID_SECURITY_ACCESS,
+                UDS_NRC_CONDITIONS_NOT_CORRECT
+            );
+        }
+        self.failed_attempts = attempts;
         
         // Verify key
-        if key == expected_key {
+        if algorithm.verify_key(&self.last_seed, key) {
             // Successful unlock
             self.unlocked = true;
-            self.security_level = SECURITY_LEVEL_UNLOCKED;
-            self.failed_attempts = 0;
+            self.security_level = level;
+            self.delay_start = None;
+            if self.store_failed_attempts(0) {
+                self.failed_attempts = 0;
+            }
             
-            info!("Security access unlocked");
+            info!("Security access unlocked (level 0x{:02X})", level);
             
-            response.push(UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE);
-            response.push(subfunction);
+            fixed_response([UDS_SID_SECURITY_ACCESS + UDS_RSP_POSITIVE, level + 1])
         } else {
             // Failed unlock attempt
-            self.failed_attempts += 1;
-            
-            warn!("Invalid security key, attempt {}/{}", 
+            warn!("Invalid security key, attempt {}/{}",
                  self.failed_attempts, self.max_failed_attempts);
             
-            return self.create_negative_response(
-                UDS_SID_SECURITY_ACCESS, 
-                UDS_NRC_INVALID_KEY
-            );
+            if self.failed_attempts >= self.max_failed_attempts {
+                // Start (or restart) the lockout delay
+                self.delay_start = Some(now_ms);
+                return self.create_negative_response(
+                    UDS_SID_SECURITY_ACCESS,
+                    UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS
+                );
+            }
+            
+            self.create_negative_response(UDS_SID_SECURITY_ACCESS, UDS_NRC_INVALID_KEY)
+        }
+    }
+    
+    /// Generate a fresh random seed
+    ///
+    /// Seeds are drawn from the HMAC-DRBG and redrawn if they are not
+    /// acceptable.
+    fn generate_seed(&mut self, seed: &mut [u8]) -> Result<(), SecurityError> {
+        for _ in 0..MAX_SEED_DRAWS {
+            self.rng.fill(seed).map_err(SecurityError::Drbg)?;
+            
+            if self.is_acceptable_seed(seed) {
+                return Ok(());
+            }
         }
         
-        response
+        Err(SecurityError::SeedGeneration)
     }
     
-    /// Generate seed value (simplified version)
-    fn generate_seed(&self) -> u32 {
-        // In a real implementation, this would be more complex
-        // and use some device-specific values
+    /// Check a seed candidate
+    ///
+    /// All-zero seeds are reserved for "already unlocked", all-FF seeds look
+    /// like erased memory and a seed must not repeat one of the last
+    /// `SEED_HISTORY` seeds.
+    fn is_acceptable_seed(&self, seed: &[u8]) -> bool {
+        let all_zero = seed.iter().all(|&b| b == 0x00);
+        let all_ff = seed.iter().all(|&b| b == 0xFF);
+        let repeated = self.issued_seeds.iter().any(|issued| issued.as_slice() == seed);
         
-        // Get some pseudo-random value
-        let timer_value = 0x12345678; // Replace with actual timer value
+        !all_zero && !all_ff && !repeated
+    }
+    
+    /// Check if the lockout delay is running
+    ///
+    /// Once the attempts are exhausted, requests are rejected until the delay
+    /// has expired. After that one further attempt is granted; failing it
+    /// restarts the delay.
+    fn is_delay_active(&mut self, now_ms: u32) -> bool {
+        if self.failed_attempts < self.max_failed_attempts {
+            return false;
+        }
         
-        // XOR with some constant
-        timer_value ^ 0xA5A5A5A5
+        match self.delay_start {
+            Some(start) => now_ms.wrapping_sub(start) < self.lockout_delay_ms,
+            None => {
+                // Exhausted counter restored after power-up
+                self.delay_start = Some(now_ms);
+                true
+            }
+        }
+    }
+    
+    /// Load the failed-attempt counter from non-volatile storage
+    ///
+    /// Returns `None` for a blank record. A corrupted record counts as
+    /// exhausted attempts (fail secure).
+    fn load_failed_attempts(&self) -> Result<Option<u8>, NvmError> {
+        let storage = match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) => unsafe { &*storage },
+            None => return Ok(Some(self.failed_attempts)),
+        };
+        
+        let mut record = [0u8; 4];
+        storage.read(NVM_SECURITY_ATTEMPTS_OFFSET, &mut record)?;
+        
+        let magic = u16::from_be_bytes([record[0], record[1]]);
+        if record == [0xFF; 4] {
+            // Never written
+            Ok(None)
+        } else if magic == ATTEMPT_RECORD_MAGIC && record[2] == !record[3] {
+            Ok(Some(record[2]))
+        } else {
+            warn!("Security access: attempt counter corrupted");
+            Ok(Some(self.max_failed_attempts))
+        }
+    }
+    
+    /// Store the failed-attempt counter in non-volatile storage
+    fn store_failed_attempts(&mut self, attempts: u8) -> bool {
+        let storage = match self.nv_storage {
+            // Safety: We know this pointer is valid
+            Some(storage) if self.attempts_persistent => unsafe { &mut *storage },
+            _ => return true,
+        };
+        
+        let magic = ATTEMPT_RECORD_MAGIC.to_be_bytes();
+        let record = [magic[0], magic[1], attempts, !attempts];
+        
+        storage.write(NVM_SECURITY_ATTEMPTS_OFFSET, &record).is_ok()
+    }
+    
+    /// Find the configuration of a security level
+    fn find_level(&self, level: u8) -> Option<&SecurityLevel> {
+        self.levels.iter().find(|l| l.level == level)
+    }
+    
+    /// Lock security access again (e.g. on a session transition)
+    pub fn lock(&mut self) {
+        if self.unlocked {
+            debug!("Security access locked");
+        }
+        self.unlocked = false;
+        self.security_level = 0;
+        self.pending_level = None;
+        self.last_seed.clear();
     }
     
-    /// Calculate expected key from seed (simplified version)
-    fn calculate_key(&self, seed: u32) -> u32 {
-        // In a real implementation, this would be a more complex algorithm
-        // XOR with a constant and bit rotation (simple example)
-        let mut key = seed ^ 0x5A5A5A5A;
+    /// Restore a security level unlocked before a reset (e.g. by the application)
+    ///
+    /// Only configured levels are restored, returns whether the level is unlocked.
+    pub fn restore_level(&mut self, level: u8) -> bool {
+        if self.find_level(level).is_none() {
+            return false;
+        }
         
-        // Rotate right by 3 bits
-        key = (key >> 3) | (key << 29);
+        self.unlocked = true;
+        self.security_level = level;
+        self.pending_level = None;
+        info!("Security access restored (level 0x{:02X})", level);
         
-        key
+        true
     }
     
     /// Check if security access is unlocked
@@ -212,14 +471,268 @@ impl SecurityAccess {
         self.unlocked
     }
     
+    /// Check if the given security level is unlocked
+    pub fn is_level_unlocked(&self, level: u8) -> bool {
+        self.unlocked && self.security_level == level
+    }
+    
+    /// Get the currently unlocked security level (0 if locked)
+    pub fn get_security_level(&self) -> u8 {
+        if self.unlocked { self.security_level } else { 0 }
+    }
+    
     /// Create a negative response
     fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
+    }
+}
+
+impl Default for SecurityAccess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Security access configuration error types
+#[derive(Debug)]
+pub enum SecurityError {
+    InvalidLevel,
+    InvalidLength,
+    TooManyLevels,
+    Drbg(DrbgError),
+    SeedGeneration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootloader::nvm::RamStorage;
+    use crate::crypto::entropy::{EntropyError, MockEntropy};
+    
+    const LEVEL: u8 = UDS_SECURITY_LEVEL_PROGRAMMING;
+    const DELAY: u32 = SECURITY_LOCKOUT_DELAY_MS;
+    
+    /// Storage of an EEPROM that was never partitioned
+    struct UninitializedStorage;
+    
+    impl NvStorage for UninitializedStorage {
+        fn read(&self, _offset: u32, _buffer: &mut [u8]) -> Result<(), NvmError> {
+            Err(NvmError::NotReady)
+        }
         
-        response.push(UDS_SID_NEGATIVE_RESPONSE);
-        response.push(sid);
-        response.push(nrc);
+        fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), NvmError> {
+            Err(NvmError::NotReady)
+        }
+    }
+    
+    /// Storage that can be read but no longer written
+    struct ReadOnlyStorage(RamStorage);
+    
+    impl NvStorage for ReadOnlyStorage {
+        fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError> {
+            self.0.read(offset, buffer)
+        }
         
-        response
+        fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), NvmError> {
+            Err(NvmError::WriteError)
+        }
+    }
+    
+    /// Entropy source stuck at all-zero
+    struct StuckEntropy;
+    
+    impl EntropySource for StuckEntropy {
+        fn fill_entropy(&mut self, buffer: &mut [u8]) -> Result<(), EntropyError> {
+            buffer.fill(0x00);
+            Ok(())
+        }
+    }
+    
+    fn security_access(entropy: impl EntropySource + 'static) -> SecurityAccess {
+        let mut security = SecurityAccess::new();
+        security.register_entropy_source(Box::leak(Box::new(entropy)));
+        security
+    }
+    
+    #[test]
+    fn seeds_do_not_repeat() {
+        let mut security = security_access(MockEntropy::new(11));
+        let mut seeds: std::vec::Vec<[u8; 4]> = std::vec::Vec::new();
+        
+        for _ in 0..4096 {
+            let response = security.handle_security_access(&[UDS_SECURITY_LEVEL_PROGRAMMING], 0);
+            assert_eq!(&response[..2], &[0x67, UDS_SECURITY_LEVEL_PROGRAMMING]);
+            
+            let seed: [u8; 4] = response[2..].try_into().unwrap();
+            assert!(seed != [0x00; 4] && seed != [0xFF; 4]);
+            assert!(!seeds.iter().rev().take(SEED_HISTORY).any(|previous| *previous == seed));
+            seeds.push(seed);
+            
+            // Session transitions do not reset the history
+            security.lock();
+        }
+    }
+    
+    #[test]
+    fn seed_candidates_are_checked() {
+        let mut security = security_access(MockEntropy::new(12));
+        assert!(!security.is_acceptable_seed(&[0x00; 4]));
+        assert!(!security.is_acceptable_seed(&[0xFF; 4]));
+        assert!(security.is_acceptable_seed(&[0x12, 0x34, 0x56, 0x78]));
+        
+        for i in 0..SEED_HISTORY as u8 {
+            security.issued_seeds.write(Vec::from_slice(&[0x12, 0x34, 0x56, 0x78 + i]).unwrap());
+        }
+        assert!(!security.is_acceptable_seed(&[0x12, 0x34, 0x56, 0x78]));
+        
+        // Only the last seeds are remembered
+        security.issued_seeds.write(Vec::from_slice(&[0x01, 0x02, 0x03, 0x04]).unwrap());
+        assert!(security.is_acceptable_seed(&[0x12, 0x34, 0x56, 0x78]));
+        assert!(!security.is_acceptable_seed(&[0x12, 0x34, 0x56, 0x79]));
+    }
+    
+    #[test]
+    fn stuck_entropy_gives_no_seed() {
+        let mut security = security_access(StuckEntropy);
+        
+        for _ in 0..4 {
+            let response = security.handle_security_access(&[UDS_SECURITY_LEVEL_PROGRAMMING], 0);
+            assert_eq!(&response[..], &[0x7F, 0x27, 0x22]);
+        }
+    }
+    
+    #[test]
+    fn no_entropy_gives_no_seed() {
+        let mut security = SecurityAccess::new();
+        let response = security.handle_security_access(&[UDS_SECURITY_LEVEL_PROGRAMMING], 0);
+        assert_eq!(&response[..], &[0x7F, 0x27, 0x22]);
+    }
+    
+    /// Storage outliving the security access instances, a reset re-creates them
+    fn storage(storage: impl NvStorage + 'static) -> *mut dyn NvStorage {
+        Box::leak(Box::new(storage))
+    }
+    
+    /// Security access after a (simulated) reset
+    fn power_up(storage: *mut dyn NvStorage) -> SecurityAccess {
+        let mut security = security_access(MockEntropy::new(13));
+        // Safety: the storage is leaked and only used by one instance at a time
+        security.register_nv_storage(unsafe { &mut *storage });
+        security
+    }
+    
+    /// Request a seed and send a key, returns the response to the key
+    fn attempt(security: &mut SecurityAccess, correct: bool, now_ms: u32) -> Vec<u8, 64> {
+        let response = security.handle_security_access(&[LEVEL], now_ms);
+        if response[0] == UDS_SID_NEGATIVE_RESPONSE {
+            return response;
+        }
+        
+        let mut key = [0u8; 4];
+        PROGRAMMING_ALGORITHM.compute_key(&response[2..], &mut key);
+        if !correct {
+            key[3] ^= 0x01;
+        }
+        
+        let mut request = [LEVEL + 1, 0, 0, 0, 0];
+        request[1..].copy_from_slice(&key);
+        security.handle_security_access(&request, now_ms)
+    }
+    
+    fn record(storage: *mut dyn NvStorage) -> [u8; 4] {
+        let mut record = [0u8; 4];
+        // Safety: the storage is leaked and not used concurrently
+        unsafe { &*storage }.read(NVM_SECURITY_ATTEMPTS_OFFSET, &mut record).unwrap();
+        record
+    }
+    
+    #[test]
+    fn blank_record_is_initialized() {
+        let storage = storage(RamStorage::new());
+        let mut security = power_up(storage);
+        
+        assert_eq!(record(storage), [0x5A, 0xC3, 0x00, 0xFF]);
+        assert_eq!(&attempt(&mut security, true, 0)[..], &[0x67, LEVEL + 1]);
+    }
+    
+    #[test]
+    fn reset_does_not_grant_attempts() {
+        let storage = storage(RamStorage::new());
+        
+        // Brute force with a reset after every wrong key
+        for expected in [UDS_NRC_INVALID_KEY, UDS_NRC_INVALID_KEY, UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS] {
+            let mut security = power_up(storage);
+            assert_eq!(&attempt(&mut security, false, 0)[..], &[0x7F, 0x27, expected]);
+        }
+        
+        // Every further power-up starts with the lockout delay
+        for _ in 0..8 {
+            let mut security = power_up(storage);
+            assert_eq!(&attempt(&mut security, true, 0)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+            assert_eq!(&attempt(&mut security, true, DELAY - 1)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+        }
+        
+        // One attempt per delay, failing it restarts the delay
+        let mut security = power_up(storage);
+        assert_eq!(&attempt(&mut security, true, 0)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+        assert_eq!(&attempt(&mut security, false, DELAY)[..], &[0x7F, 0x27, UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS]);
+        assert_eq!(&attempt(&mut security, true, DELAY + 1)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+        assert_eq!(&attempt(&mut security, true, 2 * DELAY)[..], &[0x67, LEVEL + 1]);
+        assert_eq!(record(storage), [0x5A, 0xC3, 0x00, 0xFF]);
+    }
+    
+    #[test]
+    fn reset_during_verification_counts_the_attempt() {
+        let storage = storage(RamStorage::new());
+        
+        // The attempt is stored before the key is compared
+        let mut security = power_up(storage);
+        let seed = security.handle_security_access(&[LEVEL], 0);
+        assert_eq!(seed[0], 0x67);
+        let _ = security.handle_security_access(&[LEVEL + 1, 0, 0, 0, 0], 0);
+        assert_eq!(record(storage), [0x5A, 0xC3, 0x01, 0xFE]);
+        
+        let security = power_up(storage);
+        assert_eq!(security.failed_attempts, 1);
+    }
+    
+    #[test]
+    fn corrupted_record_fails_secure() {
+        let storage = storage(RamStorage::new());
+        // Safety: the storage is leaked and not used concurrently
+        unsafe { &mut *storage }.write(NVM_SECURITY_ATTEMPTS_OFFSET, &[0x00, 0x00, 0x00, 0x00]).unwrap();
+        
+        let mut security = power_up(storage);
+        assert_eq!(&attempt(&mut security, true, 0)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+        
+        // A successful unlock repairs the record
+        assert_eq!(&attempt(&mut security, true, DELAY)[..], &[0x67, LEVEL + 1]);
+        assert_eq!(record(storage), [0x5A, 0xC3, 0x00, 0xFF]);
+    }
+    
+    #[test]
+    fn uninitialized_eeprom_does_not_lock_out() {
+        let storage = storage(UninitializedStorage);
+        
+        for _ in 0..4 {
+            let mut security = power_up(storage);
+            assert_eq!(&attempt(&mut security, true, 0)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+            assert_eq!(&attempt(&mut security, false, DELAY)[..], &[0x7F, 0x27, UDS_NRC_EXCEEDED_NUMBER_OF_ATTEMPTS]);
+            assert_eq!(&attempt(&mut security, true, 2 * DELAY)[..], &[0x67, LEVEL + 1]);
+            
+            // Unlocked again without delay until the next reset
+            security.lock();
+            assert_eq!(&attempt(&mut security, false, 2 * DELAY)[..], &[0x7F, 0x27, UDS_NRC_INVALID_KEY]);
+        }
+    }
+    
+    #[test]
+    fn failing_write_refuses_keys() {
+        let storage = storage(ReadOnlyStorage(RamStorage::new()));
+        let mut security = power_up(storage);
+        
+        // The blank record cannot be initialized either
+        assert_eq!(&attempt(&mut security, true, 0)[..], &[0x7F, 0x27, UDS_NRC_REQUIRED_TIME_DELAY_NOT_EXPIRED]);
+        assert_eq!(&attempt(&mut security, true, DELAY)[..], &[0x7F, 0x27, UDS_NRC_CONDITIONS_NOT_CORRECT]);
     }
 }
\ No newline at end of file
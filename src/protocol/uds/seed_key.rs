@@ -0,0 +1,79 @@
+/// Maximum seed length supported by the security access service
+pub const MAX_SEED_LENGTH: usize = 32;
+
+/// Maximum key length supported by the security access service
+pub const MAX_KEY_LENGTH: usize = 32;
+
+/// Seed/key algorithm bound to a security level
+///
+/// Implementations provide the expected key for a seed generated by the
+/// bootloader. Seed and key lengths may differ per algorithm but must not
+/// exceed `MAX_SEED_LENGTH` and `MAX_KEY_LENGTH`.
+pub trait SeedKeyAlgorithm {
+    /// Length of the seed in bytes
+    fn seed_length(&self) -> usize;
+    
+    /// Length of the key in bytes
+    fn key_length(&self) -> usize;
+    
+    /// Compute the expected key for the given seed into `key`
+    fn compute_key(&self, seed: &[u8], key: &mut [u8]);
+    
+    /// Verify a key received from the tester
+    fn verify_key(&self, seed: &[u8], key: &[u8]) -> bool {
+        let key_length = self.key_length();
+        if key.len() != key_length || key_length > MAX_KEY_LENGTH {
+            return false;
+        }
+        
+        let mut expected = [0u8; MAX_KEY_LENGTH];
+        self.compute_key(seed, &mut expected[..key_length]);
+        
+        constant_time_eq(&expected[..key_length], key)
+    }
+}
+
+/// Compare two byte slices without an early exit on the first mismatch
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    
+    diff == 0
+}
+
+/// Legacy XOR/rotate algorithm with 4 byte seed and key
+pub struct XorRotateAlgorithm {
+    /// Constant XORed into the seed
+    pub mask: u32,
+    /// Number of bits the result is rotated right
+    pub rotation: u32,
+}
+
+impl XorRotateAlgorithm {
+    /// Create a new XOR/rotate algorithm
+    pub const fn new(mask: u32, rotation: u32) -> Self {
+        Self { mask, rotation }
+    }
+}
+
+impl SeedKeyAlgorithm for XorRotateAlgorithm {
+    fn seed_length(&self) -> usize {
+        4
+    }
+    
+    fn key_length(&self) -> usize {
+        4
+    }
+    
+    fn compute_key(&self, seed: &[u8], key: &mut [u8]) {
+        let seed = u32::from_be_bytes([seed[0], seed[1], seed[2], seed[3]]);
+        let value = (seed ^ self.mask).rotate_right(self.rotation);
+        key.copy_from_slice(&value.to_be_bytes());
+    }
+}
\ No newline at end of file
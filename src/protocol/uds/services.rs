@@ -2,6 +2,7 @@ use defmt::{debug, info, warn};
 use heapless::Vec;
 use super::*;
 use crate::hal::s32k148::peripherals::SystemReset;
+use crate::protocol::fixed_response;
 
 /// UDS Services implementation
 pub struct UdsServices {
@@ -29,45 +30,34 @@ impl UdsServices {
         }
         
         let reset_type = data[0];
-        let mut response = Vec::new();
         
         match reset_type & 0x7F {
             UDS_RESET_HARD | UDS_RESET_SOFT => {
-                // Create positive response if response is requested
-                if (reset_type & 0x80) == 0 {
-                    response.push(UDS_SID_ECU_RESET + UDS_RSP_POSITIVE);
-                    response.push(reset_type & 0x7F);
-                }
-                
                 // Schedule reset (in real implementation, might delay this)
                 info!("ECU Reset requested (type 0x{:02X})", reset_type & 0x7F);
                 
-                // Optionally: Add small delay before reset
-                
-                // Perform reset
+                // Perform reset, before a positive response could be sent
                 SystemReset::reset();
             },
             _ => {
                 // Unsupported reset type
                 warn!("Unsupported reset type: 0x{:02X}", reset_type);
-                return self.create_negative_response(
+                self.create_negative_response(
                     UDS_SID_ECU_RESET, 
                     UDS_NRC_SUB_FUNCTION_NOT_SUPPORTED
-                );
+                )
             }
         }
-        
-        response
     }
     
     /// Create a negative response
     fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        response.push(UDS_SID_NEGATIVE_RESPONSE);
-        response.push(sid);
-        response.push(nrc);
-        
-        response
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
+    }
+}
+
+impl Default for UdsServices {
+    fn default() -> Self {
+        Self::new()
     }
 }
\ No newline at end of file
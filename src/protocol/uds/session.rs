@@ -1,10 +1,38 @@
-use defmt::{debug, info, warn};
+use defmt::{info, warn};
 use heapless::Vec;
 use super::*;
 use super::services::UdsServices;
-use super::security::SecurityAccess;
+use super::security::{SecurityAccess, SecurityError};
+use super::seed_key::SeedKeyAlgorithm;
+#[cfg(feature = "ecdsa")]
+use super::authentication::Authentication;
+use crate::crypto::entropy::EntropySource;
+use crate::crypto::aes::BlockCipher;
+use crate::crypto::cmac::MacGenerator;
+#[cfg(feature = "ecdsa")]
+use crate::crypto::ecdsa::P256_PUBLIC_KEY_LENGTH;
+use crate::bootloader::nvm::NvStorage;
+use crate::bootloader::flash::Flash;
+use crate::drivers::systick;
 use super::transfer::TransferManager;
+use super::routine::RoutineControl;
+use super::data_identifier::DataIdentifiers;
+use super::provisioning::Provisioning;
+use super::permissions::{self, AddressingMode, SERVICE_PERMISSIONS};
 use crate::bootloader::timeout::TimeoutReset;
+use crate::bootloader::mailbox::{HandoffRequest, HandoffRequestType};
+use crate::bootloader::secure_boot::{self, SecureBootEngine, SecureBootError, SecureBootStatus};
+use crate::hal::s32k148::csec::BootFlavor;
+use crate::config;
+use crate::protocol::fixed_response;
+
+/// CSEc services the session hands to its services
+///
+/// Implemented by the CSEc driver for every type providing all four, so the
+/// same engine is registered once rather than once per trait.
+pub trait SecurityEngine: EntropySource + BlockCipher + MacGenerator + SecureBootEngine {}
+
+impl<T: EntropySource + BlockCipher + MacGenerator + SecureBootEngine> SecurityEngine for T {}
 
 /// UDS Session management
 pub struct UdsSession {
@@ -14,10 +42,21 @@ pub struct UdsSession {
     services: UdsServices,
     /// Security access handler
     security: SecurityAccess,
+    /// Authentication handler
+    #[cfg(feature = "ecdsa")]
+    authentication: Authentication,
     /// Transfer manager for download operations
     transfer: TransferManager,
+    /// Routine control handler
+    routines: RoutineControl,
+    /// Data identifier handler
+    data_identifiers: DataIdentifiers,
+    /// End of line provisioning handler
+    provisioning: Provisioning,
     /// Timeout reset handler reference
     timeout_reset: Option<*mut TimeoutReset>,
+    /// CSEc shared by all services
+    security_engine: Option<*mut dyn SecurityEngine>,
 }
 
 impl UdsSession {
@@ -27,8 +66,14 @@ impl UdsSession {
             current_session: UDS_SESSION_DEFAULT,
             services: UdsServices::new(),
             security: SecurityAccess::new(),
+            #[cfg(feature = "ecdsa")]
+            authentication: Authentication::new(),
             transfer: TransferManager::new(),
+            routines: RoutineControl::new(),
+            data_identifiers: DataIdentifiers::new(),
+            provisioning: Provisioning::new(),
             timeout_reset: None,
+            security_engine: None,
         }
     }
     
@@ -38,7 +83,12 @@ impl UdsSession {
         self.current_session = UDS_SESSION_DEFAULT;
         self.services.init();
         self.security.init();
+        #[cfg(feature = "ecdsa")]
+        self.authentication.init();
         self.transfer.init();
+        self.routines.init();
+        self.data_identifiers.init();
+        self.provisioning.init();
     }
     
     /// Register timeout reset handler
@@ -46,8 +96,114 @@ impl UdsSession {
         self.timeout_reset = Some(timeout_reset);
     }
     
-    /// Process incoming UDS message
+    /// Bind a seed/key algorithm to a security access level
+    pub fn register_security_level(
+        &mut self,
+        level: u8,
+        algorithm: &'static dyn SeedKeyAlgorithm,
+    ) -> Result<(), SecurityError> {
+        self.security.register_level(level, algorithm)
+    }
+    
+    /// Register the CSEc for security access seeds, authentication challenges,
+    /// encrypted downloads, attestation and the secure boot keys and status
+    ///
+    /// The engine is registered once and every service keeps a copy of the
+    /// same pointer, the bootloader reaches it through this session only.
+    pub fn register_security_engine(&mut self, engine: &mut (dyn SecurityEngine + 'static)) {
+        let engine: *mut dyn SecurityEngine = engine;
+        self.security_engine = Some(engine);
+        self.security.register_entropy_source(engine);
+        #[cfg(feature = "ecdsa")]
+        self.authentication.register_entropy_source(engine);
+        self.transfer.register_cipher(engine);
+        self.routines.register_mac_generator(engine);
+        self.routines.register_secure_boot(engine);
+        self.data_identifiers.register_secure_boot(engine);
+        self.provisioning.register_engine(engine);
+    }
+    
+    /// Configure the root public key of the diagnostic PKI
+    #[cfg(feature = "ecdsa")]
+    pub fn set_authentication_root_key(&mut self, root_public_key: &'static [u8; P256_PUBLIC_KEY_LENGTH]) {
+        self.authentication.set_root_public_key(root_public_key);
+    }
+    
+    /// Secure boot status of this reset, `None` without a registered engine
+    pub fn secure_boot_status(&self, flavor: BootFlavor) -> Option<SecureBootStatus> {
+        // Safety: We know this pointer is valid
+        self.security_engine.map(|engine| SecureBootStatus::read(unsafe { &*engine }, flavor))
+    }
+    
+    /// Close the secure boot phase before the application starts
+    pub fn finish_secure_boot(&mut self) -> Result<(), SecureBootError> {
+        let engine = self.security_engine.ok_or(SecureBootError::Disabled)?;
+        // Safety: We know this pointer is valid
+        secure_boot::finish(unsafe { &mut *engine })
+    }
+    
+    /// Register the non-volatile storage for the security access attempt counter,
+    /// the self-update journal, the key store and the device lifecycle
+    pub fn register_nv_storage(&mut self, storage: &mut (dyn NvStorage + 'static)) {
+        self.security.register_nv_storage(storage);
+        self.routines.register_nv_storage(storage);
+        self.data_identifiers.register_nv_storage(storage);
+        self.provisioning.register_nv_storage(storage);
+    }
+    
+    /// Register the flash controller for downloads and the bootloader update
+    pub fn register_flash(&mut self, flash: &mut Flash) {
+        self.transfer.register_flash(flash);
+        self.routines.register_flash(flash);
+    }
+    
+    /// Continue a request the application handed over through the mailbox
+    ///
+    /// Enters the requested session and returns the delayed positive
+    /// response to DiagnosticSessionControl the tester is waiting for (empty
+    /// if the tester suppressed it or the request cannot be continued).
+    pub fn resume_from_handoff(&mut self, request: &HandoffRequest) -> Vec<u8, 64> {
+        if request.request != HandoffRequestType::EnterProgramming || request.session != UDS_SESSION_PROGRAMMING {
+            warn!("Mailbox request for session 0x{:02X} not supported", request.session);
+            return Vec::new();
+        }
+        
+        self.current_session = request.session;
+        self.security.lock();
+        self.transfer.init();
+        
+        // Security access is only carried over when configured, otherwise
+        // the tester unlocks again in the bootloader
+        if config::HANDOFF_RESTORE_SECURITY && request.security_level != 0
+            && !self.security.restore_level(request.security_level)
+        {
+            warn!("Mailbox security level 0x{:02X} not configured", request.security_level);
+        }
+        
+        if let Some(timeout_reset) = self.timeout_reset {
+            // Safety: We know this pointer is valid
+            unsafe {
+                (*timeout_reset).set_flashing_init();
+            }
+        }
+        
+        info!("UDS Session 0x{:02X} entered from application", request.session);
+        
+        let mut response = Vec::new();
+        if request.response_required() {
+            response = fixed_response([UDS_SID_DIAGNOSTIC_SESSION_CONTROL + UDS_RSP_POSITIVE, request.session]);
+        }
+        
+        response
+    }
+    
+    /// Process incoming physically addressed UDS message
     pub fn process_message(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        self.process_message_with_addressing(data, AddressingMode::Physical)
+    }
+    
+    /// Process incoming UDS message received with the given addressing mode
+    pub fn process_message_with_addressing(&mut self, data: &[u8], addressing: AddressingMode) -> Vec<u8, 64> {
         if data.is_empty() {
             return Vec::new();
         }
@@ -69,6 +225,30 @@ impl UdsSession {
             }
         }
         
+        // Check session, security and addressing against the permission table,
+        // a level is satisfied by SecurityAccess or by an authenticated role
+        let security = &self.security;
+        #[cfg(feature = "ecdsa")]
+        let authenticated = |level| self.authentication.grants_level(level);
+        #[cfg(not(feature = "ecdsa"))]
+        let authenticated = |_: u8| false;
+        if let Err(nrc) = permissions::check_request(
+            SERVICE_PERMISSIONS,
+            data,
+            self.current_session,
+            addressing,
+            |level| security.is_level_unlocked(level) || authenticated(level),
+        ) {
+            warn!("UDS service 0x{:02X} rejected with NRC 0x{:02X}", sid, nrc);
+            
+            // Functional requests do not get a response for these NRCs
+            if addressing == AddressingMode::Functional && permissions::is_suppressed_for_functional(nrc) {
+                return Vec::new();
+            }
+            
+            return self.create_negative_response(sid, nrc);
+        }
+        
         // Process the UDS service
         match sid {
             UDS_SID_DIAGNOSTIC_SESSION_CONTROL => {
@@ -77,41 +257,39 @@ impl UdsSession {
             UDS_SID_ECU_RESET => {
                 self.services.handle_ecu_reset(&data[1..])
             },
+            UDS_SID_READ_DATA_BY_IDENTIFIER => {
+                self.data_identifiers.handle_read_data_by_identifier(&data[1..])
+            },
             UDS_SID_SECURITY_ACCESS => {
-                self.security.handle_security_access(&data[1..])
+                self.security.handle_security_access(&data[1..], systick::millis())
+            },
+            #[cfg(feature = "ecdsa")]
+            UDS_SID_AUTHENTICATION => {
+                self.authentication.handle_authentication(&data[1..])
+            },
+            UDS_SID_ROUTINE_CONTROL if self.current_session == UDS_SESSION_PROVISIONING => {
+                self.provisioning.handle_routine_control(&data[1..])
+            },
+            UDS_SID_ROUTINE_CONTROL => {
+                self.routines.handle_routine_control(&data[1..], &mut self.transfer)
             },
             UDS_SID_TESTER_PRESENT => {
                 self.handle_tester_present(&data[1..])
             },
             UDS_SID_REQUEST_DOWNLOAD => {
-                // Only allowed in programming session with security access
-                if self.current_session != UDS_SESSION_PROGRAMMING {
-                    self.create_negative_response(sid, UDS_NRC_CONDITIONS_NOT_CORRECT)
-                } else if !self.security.is_unlocked() {
-                    self.create_negative_response(sid, UDS_NRC_SECURITY_ACCESS_DENIED)
-                } else {
-                    self.transfer.handle_request_download(&data[1..])
-                }
+                self.transfer.handle_request_download(&data[1..])
+            },
+            UDS_SID_REQUEST_UPLOAD => {
+                self.transfer.handle_request_upload(&data[1..])
+            },
+            UDS_SID_READ_MEMORY_BY_ADDRESS => {
+                self.transfer.handle_read_memory_by_address(&data[1..])
             },
             UDS_SID_TRANSFER_DATA => {
-                // Only allowed in programming session with security access
-                if self.current_session != UDS_SESSION_PROGRAMMING {
-                    self.create_negative_response(sid, UDS_NRC_CONDITIONS_NOT_CORRECT)
-                } else if !self.security.is_unlocked() {
-                    self.create_negative_response(sid, UDS_NRC_SECURITY_ACCESS_DENIED)
-                } else {
-                    self.transfer.handle_transfer_data(&data[1..])
-                }
+                self.transfer.handle_transfer_data(&data[1..])
             },
             UDS_SID_REQUEST_TRANSFER_EXIT => {
-                // Only allowed in programming session with security access
-                if self.current_session != UDS_SESSION_PROGRAMMING {
-                    self.create_negative_response(sid, UDS_NRC_CONDITIONS_NOT_CORRECT)
-                } else if !self.security.is_unlocked() {
-                    self.create_negative_response(sid, UDS_NRC_SECURITY_ACCESS_DENIED)
-                } else {
-                    self.transfer.handle_transfer_exit(&data[1..])
-                }
+                self.transfer.handle_transfer_exit(&data[1..])
             },
             _ => {
                 // Unsupported service
@@ -130,18 +308,38 @@ impl UdsSession {
             );
         }
         
-        let session_type = data[0];
+        // The suppress positive response bit is not part of the session type
+        let session_type = data[0] & 0x7F;
         let mut response = Vec::new();
         
         match session_type {
-            UDS_SESSION_DEFAULT | UDS_SESSION_PROGRAMMING | UDS_SESSION_EXTENDED => {
-                // Set the new session type
+            UDS_SESSION_PROVISIONING if !self.provisioning.is_available() => {
+                // The device lifecycle is closed
+                warn!("Provisioning session refused, device locked");
+                return self.create_negative_response(
+                    UDS_SID_DIAGNOSTIC_SESSION_CONTROL, 
+                    UDS_NRC_CONDITIONS_NOT_CORRECT
+                );
+            },
+            UDS_SESSION_DEFAULT | UDS_SESSION_PROGRAMMING | UDS_SESSION_EXTENDED | UDS_SESSION_PROVISIONING => {
+                // Set the new session type, any session transition locks security access
                 self.current_session = session_type;
+                self.security.lock();
+                
+                // Any session transition aborts the transfer and discards downloaded segments
+                self.transfer.init();
+                
+                // Returning to the default session ends the authenticated state
+                #[cfg(feature = "ecdsa")]
+                if session_type == UDS_SESSION_DEFAULT {
+                    self.authentication.deauthenticate();
+                }
                 info!("UDS Session changed to 0x{:02X}", session_type);
                 
-                // Create positive response
-                response.push(UDS_SID_DIAGNOSTIC_SESSION_CONTROL + UDS_RSP_POSITIVE);
-                response.push(session_type);
+                // Check if response is suppressed
+                if (data[0] & 0x80) == 0 {
+                    response = fixed_response([UDS_SID_DIAGNOSTIC_SESSION_CONTROL + UDS_RSP_POSITIVE, session_type]);
+                }
                 
                 // If entering programming session, notify timeout reset
                 if session_type == UDS_SESSION_PROGRAMMING {
@@ -191,8 +389,10 @@ impl UdsSession {
         // Check if response is suppressed
         if (subfunction & 0x80) == 0 {
             // Create positive response
-            response.push(UDS_SID_TESTER_PRESENT + UDS_RSP_POSITIVE);
-            response.push(0x00); // Subfunction echo
+            response = fixed_response([
+                UDS_SID_TESTER_PRESENT + UDS_RSP_POSITIVE,
+                0x00, // Subfunction echo
+            ]);
         }
         
         response
@@ -200,17 +400,92 @@ impl UdsSession {
     
     /// Create a negative response
     fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        response.push(UDS_SID_NEGATIVE_RESPONSE);
-        response.push(sid);
-        response.push(nrc);
-        
-        response
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
     }
     
     /// Get current session type
     pub fn get_session_type(&self) -> u8 {
         self.current_session
     }
+}
+
+impl Default for UdsSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootloader::mailbox::{TesterAddress, MAILBOX_FLAG_SUPPRESS_RESPONSE};
+    
+    #[test]
+    fn session_control_response() {
+        let mut session = UdsSession::new();
+        
+        assert_eq!(&session.process_message(&[0x10, 0x03])[..], &[0x50, 0x03]);
+        assert_eq!(session.get_session_type(), UDS_SESSION_EXTENDED);
+        
+        assert_eq!(&session.process_message(&[0x10, 0x05])[..], &[0x7F, 0x10, 0x12]);
+        assert_eq!(session.get_session_type(), UDS_SESSION_EXTENDED);
+    }
+    
+    #[test]
+    fn session_control_suppresses_positive_response() {
+        let mut session = UdsSession::new();
+        
+        assert!(session.process_message(&[0x10, 0x82]).is_empty());
+        assert_eq!(session.get_session_type(), UDS_SESSION_PROGRAMMING);
+        
+        assert!(session.process_message(&[0x10, 0x81]).is_empty());
+        assert_eq!(session.get_session_type(), UDS_SESSION_DEFAULT);
+        
+        // Negative responses are never suppressed
+        assert_eq!(&session.process_message(&[0x10, 0x85])[..], &[0x7F, 0x10, 0x12]);
+        assert_eq!(&session.process_message(&[0x10, 0xE0])[..], &[0x7F, 0x10, 0x22]);
+    }
+    
+    /// Programming request the application leaves for the tester's 0x10 0x02
+    fn handoff(session: u8, flags: u8) -> HandoffRequest {
+        HandoffRequest {
+            request: HandoffRequestType::EnterProgramming,
+            session,
+            security_level: 0,
+            tester: TesterAddress {
+                request_id: 0x7E0,
+                response_id: 0x7E8,
+                addressing: AddressingMode::Physical,
+            },
+            flags,
+        }
+    }
+    
+    #[test]
+    fn handoff_sends_delayed_response() {
+        let mut session = UdsSession::new();
+        
+        assert_eq!(&session.resume_from_handoff(&handoff(0x02, 0))[..], &[0x50, 0x02]);
+        assert_eq!(session.get_session_type(), UDS_SESSION_PROGRAMMING);
+    }
+    
+    #[test]
+    fn handoff_suppresses_delayed_response() {
+        // The application records the suppress bit of 0x10 0x82 as a flag
+        let mut session = UdsSession::new();
+        
+        assert!(session.resume_from_handoff(&handoff(0x02, MAILBOX_FLAG_SUPPRESS_RESPONSE)).is_empty());
+        assert_eq!(session.get_session_type(), UDS_SESSION_PROGRAMMING);
+    }
+    
+    #[test]
+    fn handoff_of_other_sessions_is_ignored() {
+        // The session is handed over without the suppress bit
+        for requested in [0x01, 0x03, 0x04, 0x82] {
+            let mut session = UdsSession::new();
+            
+            assert!(session.resume_from_handoff(&handoff(requested, 0)).is_empty());
+            assert_eq!(session.get_session_type(), UDS_SESSION_DEFAULT);
+        }
+    }
 }
\ No newline at end of file
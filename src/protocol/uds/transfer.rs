@@ -1,22 +1,148 @@
 use defmt::{debug, info, warn};
 use heapless::Vec;
+use sha2::{Digest, Sha256};
 use super::*;
-use crate::bootloader::flash::Flash;
+use super::memory::{self, parse_address_and_length, MemoryRegion};
+use super::download::{DownloadError, DownloadSession, SECTOR_SIZE};
+use crate::bootloader::flash::{Flash, FlashPort};
+use crate::bootloader::partition::{self, PartitionKind, PARTITION_FLAG_WRITABLE};
+use crate::compression::heatshrink::{DecodeError, HeatshrinkDecoder};
+use crate::compression::delta::{DeltaError, DeltaPatcher, PatchError};
+use crate::crypto::aes::BlockCipher;
+use crate::crypto::gcm::{GcmDecryptor, GCM_IV_LENGTH, GCM_TAG_LENGTH};
+use crate::protocol::fixed_response;
 
-// Transfer data constants
-const MAX_MEMORY_SIZE: u32 = 0x100000; // 1MB max size
-const MAX_BLOCK_SIZE: usize = 1024;    // 1KB block size
+/// Largest TransferData download request (SID and block counter included)
+const MAX_BLOCK_LENGTH: usize = 1024;
+
+/// Ciphertext decrypted per step of an encrypted download
+const DECRYPT_BUFFER_SIZE: usize = 64;
+
+/// Bytes copied per step when a delta image is activated
+const COPY_BUFFER_SIZE: usize = 64;
+
+/// Largest TransferData upload response (SID and block counter included)
+const UPLOAD_MAX_BLOCK_LENGTH: usize = 64;
+
+/// Data bytes carried by one TransferData upload response
+const UPLOAD_BLOCK_DATA_SIZE: usize = UPLOAD_MAX_BLOCK_LENGTH - 2;
+
+/// Largest ReadMemoryByAddress read (response SID included)
+const READ_MEMORY_MAX_SIZE: u32 = 63;
+
+/// Direction of the active transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransferDirection {
+    /// Tester to ECU (RequestDownload)
+    Download,
+    /// ECU to tester (RequestUpload)
+    Upload,
+}
+
+/// Compression method of a download (dataFormatIdentifier bits 7-4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    /// Data is transferred as is
+    None,
+    /// Data is heatshrink compressed (window 8, lookahead 4)
+    Heatshrink,
+    /// Data is a delta patch against the installed image
+    Delta,
+    /// Data is a heatshrink compressed delta patch
+    HeatshrinkDelta,
+}
+
+/// Encryption method of a download (dataFormatIdentifier bits 3-0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encryption {
+    /// Data is transferred in plain text
+    None,
+    /// Data is AES-128-GCM encrypted, the stream starts with the 12 byte IV
+    /// and the tag is sent with RequestTransferExit
+    Aes128Gcm,
+}
+
+/// Create a positive response with a lengthFormatIdentifier and maxNumberOfBlockLength
+///
+/// The high nibble of the lengthFormatIdentifier gives the number of bytes
+/// used to encode maxNumberOfBlockLength.
+fn max_block_length_response(sid: u8, length: usize) -> Vec<u8, 64> {
+    let bytes = (length as u32).to_be_bytes();
+    let skip = bytes.iter().take(3).take_while(|&&byte| byte == 0).count();
+    let count = bytes.len() - skip;
+    
+    let mut response = fixed_response([sid + UDS_RSP_POSITIVE, (count as u8) << 4, 0, 0, 0, 0]);
+    response[2..2 + count].copy_from_slice(&bytes[skip..]);
+    response.truncate(2 + count);
+    
+    response
+}
+
+/// Classification of a received block sequence counter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockSequence {
+    /// The next block in sequence
+    Next,
+    /// Repetition of the last accepted block (tester retry)
+    Repeated,
+    /// Any other counter value
+    Wrong,
+}
+
+/// Classify a block sequence counter against the last accepted one
+///
+/// The first block of a transfer is 0x01 and the counter wraps from 0xFF
+/// to 0x00.
+fn check_block_sequence(last: Option<u8>, received: u8) -> BlockSequence {
+    let expected = last.map_or(0x01, |last| last.wrapping_add(1));
+    
+    if received == expected {
+        BlockSequence::Next
+    } else if Some(received) == last {
+        BlockSequence::Repeated
+    } else {
+        BlockSequence::Wrong
+    }
+}
 
 /// UDS Transfer data manager
 pub struct TransferManager {
     /// Flash controller reference
-    flash: Option<*mut Flash>,
-    /// Current download address
-    download_address: u32,
-    /// Download size remaining
-    download_size: u32,
-    /// Block sequence counter
-    block_counter: u8,
+    flash: Option<*mut Flash<dyn FlashPort>>,
+    /// Current transfer address
+    transfer_address: u32,
+    /// Transfer size remaining
+    transfer_size: u32,
+    /// Direction of the active transfer
+    direction: TransferDirection,
+    /// Compression method of the active download
+    compression: Compression,
+    /// Decoder for compressed downloads
+    decoder: HeatshrinkDecoder,
+    /// Patcher for delta downloads
+    patcher: DeltaPatcher,
+    /// Length of the installed image region readable by the patcher
+    delta_source_len: u32,
+    /// Application range a delta image in the scratch partition replaces
+    delta_target: Option<MemoryRegion>,
+    /// Encryption method of the active download
+    encryption: Encryption,
+    /// Block cipher holding the firmware decryption key
+    cipher: Option<*mut dyn BlockCipher>,
+    /// IV received at the start of an encrypted download
+    iv: [u8; GCM_IV_LENGTH],
+    /// Number of IV bytes received
+    iv_len: usize,
+    /// Decryptor, created once the IV is complete
+    gcm: Option<GcmDecryptor>,
+    /// Block sequence counter of the last accepted block
+    last_block_counter: Option<u8>,
+    /// Start address of the last uploaded block
+    last_block_address: u32,
+    /// Length of the last uploaded block
+    last_block_length: usize,
+    /// Segments downloaded since the programming sequence started
+    download: DownloadSession,
     /// Transfer in progress flag
     transfer_active: bool,
 }
@@ -26,9 +152,23 @@ impl TransferManager {
     pub fn new() -> Self {
         Self {
             flash: None,
-            download_address: 0,
-            download_size: 0,
-            block_counter: 0,
+            transfer_address: 0,
+            transfer_size: 0,
+            direction: TransferDirection::Download,
+            compression: Compression::None,
+            decoder: HeatshrinkDecoder::new(),
+            patcher: DeltaPatcher::new(),
+            delta_source_len: 0,
+            delta_target: None,
+            encryption: Encryption::None,
+            cipher: None,
+            iv: [0; GCM_IV_LENGTH],
+            iv_len: 0,
+            gcm: None,
+            last_block_counter: None,
+            last_block_address: 0,
+            last_block_length: 0,
+            download: DownloadSession::new(),
             transfer_active: false,
         }
     }
@@ -36,52 +176,90 @@ impl TransferManager {
     /// Initialize the transfer manager
     pub fn init(&mut self) {
         debug!("Initializing UDS transfer manager");
-        self.download_address = 0;
-        self.download_size = 0;
-        self.block_counter = 0;
+        self.transfer_address = 0;
+        self.transfer_size = 0;
+        self.direction = TransferDirection::Download;
+        self.compression = Compression::None;
+        self.decoder.reset();
+        self.patcher.reset();
+        self.delta_source_len = 0;
+        self.delta_target = None;
+        self.encryption = Encryption::None;
+        self.iv_len = 0;
+        self.gcm = None;
+        self.last_block_counter = None;
+        self.download.reset();
         self.transfer_active = false;
     }
     
     /// Register flash controller
-    pub fn register_flash(&mut self, flash: &mut Flash) {
+    pub fn register_flash(&mut self, flash: &mut Flash<dyn FlashPort>) {
         self.flash = Some(flash);
     }
     
+    /// Register the block cipher for encrypted downloads
+    pub fn register_cipher(&mut self, cipher: *mut (dyn BlockCipher + 'static)) {
+        self.cipher = Some(cipher);
+    }
+    
     /// Handle download request
     pub fn handle_request_download(&mut self, data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        // Validate data length (at least 3 bytes for format, addr and size)
-        if data.len() < 3 {
+        // dataFormatIdentifier followed by the address and size
+        if data.is_empty() {
             return self.create_negative_response(
                 UDS_SID_REQUEST_DOWNLOAD, 
                 UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
             );
         }
         
-        // Extract address and size format
-        let format = data[0];
-        let addr_len = (format >> 4) & 0x0F;
-        let size_len = format & 0x0F;
+        // Extract memory address and (uncompressed) size
+        let (address, size) = match parse_address_and_length(&data[1..]) {
+            Ok(range) => range,
+            Err(nrc) => return self.create_negative_response(UDS_SID_REQUEST_DOWNLOAD, nrc),
+        };
+        
+        // Compression in the high nibble, encryption in the low nibble
+        let compression = match data[0] >> 4 {
+            UDS_DFI_COMPRESSION_NONE => Compression::None,
+            UDS_DFI_COMPRESSION_HEATSHRINK => Compression::Heatshrink,
+            UDS_DFI_COMPRESSION_DELTA => Compression::Delta,
+            UDS_DFI_COMPRESSION_HEATSHRINK_DELTA => Compression::HeatshrinkDelta,
+            _ => {
+                warn!("Unsupported compression method: 0x{:02X}", data[0]);
+                return self.create_negative_response(
+                    UDS_SID_REQUEST_DOWNLOAD, 
+                    UDS_NRC_REQUEST_OUT_OF_RANGE
+                );
+            }
+        };
         
-        // Validate length
-        if data.len() < (1 + addr_len as usize + size_len as usize) {
+        let encryption = match data[0] & 0x0F {
+            UDS_DFI_ENCRYPTION_NONE => Encryption::None,
+            UDS_DFI_ENCRYPTION_AES128_GCM => Encryption::Aes128Gcm,
+            _ => {
+                warn!("Unsupported encryption method: 0x{:02X}", data[0]);
+                return self.create_negative_response(
+                    UDS_SID_REQUEST_DOWNLOAD, 
+                    UDS_NRC_REQUEST_OUT_OF_RANGE
+                );
+            }
+        };
+        
+        // Encrypted downloads need the key
+        if encryption == Encryption::Aes128Gcm && self.cipher.is_none() {
+            warn!("No cipher available for encrypted download");
             return self.create_negative_response(
                 UDS_SID_REQUEST_DOWNLOAD, 
-                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+                UDS_NRC_CONDITIONS_NOT_CORRECT
             );
         }
         
-        // Extract memory address
-        let mut address: u32 = 0;
-        for i in 0..addr_len as usize {
-            address = (address << 8) | (data[1 + i] as u32);
-        }
-        
-        // Extract memory size
-        let mut size: u32 = 0;
-        for i in 0..size_len as usize {
-            size = (size << 8) | (data[1 + addr_len as usize + i] as u32);
+        // Only one transfer may be active at a time
+        if self.transfer_active {
+            return self.create_negative_response(
+                UDS_SID_REQUEST_DOWNLOAD, 
+                UDS_NRC_CONDITIONS_NOT_CORRECT
+            );
         }
         
         // Validate address and size
@@ -92,48 +270,139 @@ impl TransferManager {
             );
         }
         
-        // If valid, store download info and prepare for transfer
-        self.download_address = address;
-        self.download_size = size;
-        self.block_counter = 0;
-        self.transfer_active = true;
-        
-        info!("Download request: addr=0x{:08X}, size={}", address, size);
+        // Delta images are reconstructed in the scratch partition and replace
+        // the application only once verified, the installed image stays intact
+        let delta = matches!(compression, Compression::Delta | Compression::HeatshrinkDelta);
+        let scratch_address = if delta { Self::scratch_address(address, size) } else { None };
+        if delta && scratch_address.is_none() {
+            warn!("No scratch area for a delta download to 0x{:08X}", address);
+            return self.create_negative_response(
+                UDS_SID_REQUEST_DOWNLOAD, 
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            );
+        }
         
-        // Erase flash memory if needed
-        if let Some(flash) = self.flash {
-            // Safety: We know this pointer is valid
-            unsafe {
-                match (*flash).erase(address, size) {
-                    Ok(_) => {
-                        debug!("Flash erase successful");
-                    },
-                    Err(_) => {
-                        warn!("Flash erase failed");
-                        return self.create_negative_response(
-                            UDS_SID_REQUEST_DOWNLOAD, 
-                            UDS_NRC_GENERAL_PROGRAMMING_FAILURE
-                        );
-                    }
+        let flash = self.flash;
+        if let Some(scratch_address) = scratch_address {
+            // The application sectors are erased on activation, which must not
+            // lose data of earlier segments
+            let end = address + size;
+            let shared = (address / SECTOR_SIZE..end.div_ceil(SECTOR_SIZE))
+                .any(|sector| self.download.is_sector_touched(sector * SECTOR_SIZE));
+            if shared {
+                return self.create_negative_response(
+                    UDS_SID_REQUEST_DOWNLOAD, 
+                    UDS_NRC_UPLOAD_DOWNLOAD_NOT_ACCEPTED
+                );
+            }
+            
+            if let Some(flash) = flash {
+                // Safety: We know this pointer is valid
+                if unsafe { (*flash).erase(scratch_address, size).is_err() } {
+                    return self.create_negative_response(
+                        UDS_SID_REQUEST_DOWNLOAD, 
+                        UDS_NRC_GENERAL_PROGRAMMING_FAILURE
+                    );
                 }
             }
         }
         
-        // Create positive response
-        response.push(UDS_SID_REQUEST_DOWNLOAD + UDS_RSP_POSITIVE);
+        // Add the segment, erasing only sectors no earlier segment touched
+        let result = self.download.begin_segment(address, size, |sector| match flash {
+            // Safety: We know this pointer is valid
+            Some(flash) if !delta => unsafe { (*flash).erase(sector, SECTOR_SIZE).is_ok() },
+            _ => true,
+        });
         
-        // Add max block size (single byte length format followed by 2 bytes size)
-        response.push(0x10); // Length of max block size parameter (1 byte)
-        response.push((MAX_BLOCK_SIZE >> 8) as u8);
-        response.push(MAX_BLOCK_SIZE as u8);
+        if let Err(error) = result {
+            warn!("Download segment rejected: {}", defmt::Debug2Format(&error));
+            let nrc = match error {
+                DownloadError::EraseFailed => UDS_NRC_GENERAL_PROGRAMMING_FAILURE,
+                DownloadError::Overlap | DownloadError::TooManySegments => UDS_NRC_UPLOAD_DOWNLOAD_NOT_ACCEPTED,
+                _ => UDS_NRC_REQUEST_OUT_OF_RANGE,
+            };
+            return self.create_negative_response(UDS_SID_REQUEST_DOWNLOAD, nrc);
+        }
         
-        response
+        // Store download info and prepare for transfer
+        self.transfer_address = scratch_address.unwrap_or(address);
+        self.transfer_size = size;
+        self.direction = TransferDirection::Download;
+        self.compression = compression;
+        self.decoder.reset();
+        self.patcher.reset();
+        self.encryption = encryption;
+        
+        // Patches apply to the whole installed application
+        self.delta_source_len = match partition::find(PartitionKind::Application) {
+            Some(application) if delta => application.size(),
+            _ => 0,
+        };
+        self.delta_target = scratch_address.map(|_| MemoryRegion::new(address, address + size));
+        self.iv_len = 0;
+        self.gcm = None;
+        self.last_block_counter = None;
+        self.transfer_active = true;
+        
+        info!("Download request: addr=0x{:08X}, size={}, format=0x{:02X}", address, size, data[0]);
+        
+        // Create positive response with the max block length
+        max_block_length_response(UDS_SID_REQUEST_DOWNLOAD, MAX_BLOCK_LENGTH)
+    }
+    
+    /// Handle upload request
+    pub fn handle_request_upload(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        // dataFormatIdentifier followed by the address and size
+        if data.is_empty() {
+            return self.create_negative_response(
+                UDS_SID_REQUEST_UPLOAD, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        let (address, size) = match parse_address_and_length(&data[1..]) {
+            Ok(range) => range,
+            Err(nrc) => return self.create_negative_response(UDS_SID_REQUEST_UPLOAD, nrc),
+        };
+        
+        // Only one transfer may be active at a time
+        if self.transfer_active {
+            return self.create_negative_response(
+                UDS_SID_REQUEST_UPLOAD, 
+                UDS_NRC_CONDITIONS_NOT_CORRECT
+            );
+        }
+        
+        // Uploads are sent uncompressed and unencrypted
+        if data[0] != 0x00 {
+            return self.create_negative_response(
+                UDS_SID_REQUEST_UPLOAD, 
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            );
+        }
+        
+        if !memory::is_readable(address, size) {
+            warn!("Upload of 0x{:08X}+{} rejected", address, size);
+            return self.create_negative_response(
+                UDS_SID_REQUEST_UPLOAD, 
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            );
+        }
+        
+        self.transfer_address = address;
+        self.transfer_size = size;
+        self.direction = TransferDirection::Upload;
+        self.last_block_counter = None;
+        self.transfer_active = true;
+        
+        info!("Upload request: addr=0x{:08X}, size={}", address, size);
+        
+        // Max block length limited by the response buffer
+        max_block_length_response(UDS_SID_REQUEST_UPLOAD, UPLOAD_MAX_BLOCK_LENGTH)
     }
     
     /// Handle transfer data
     pub fn handle_transfer_data(&mut self, data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Check if transfer is active
         if !self.transfer_active {
             return self.create_negative_response(
@@ -142,6 +411,14 @@ impl TransferManager {
             );
         }
         
+        match self.direction {
+            TransferDirection::Download => self.download_block(data),
+            TransferDirection::Upload => self.upload_block(data),
+        }
+    }
+    
+    /// Program one TransferData block of a download
+    fn download_block(&mut self, data: &[u8]) -> Vec<u8, 64> {
         // Validate data length (at least 1 byte for block counter)
         if data.is_empty() {
             return self.create_negative_response(
@@ -150,63 +427,274 @@ impl TransferManager {
             );
         }
         
-        // Extract block counter and verify sequence
-        let block_counter = data[0];
-        if block_counter != (self.block_counter + 1) {
-            warn!("Block sequence error: expected={}, received={}", 
-                 self.block_counter + 1, block_counter);
+        // The block must not exceed the negotiated maxNumberOfBlockLength
+        if data.len() + 1 > MAX_BLOCK_LENGTH {
+            warn!("Block length {} exceeds {}", data.len() + 1, MAX_BLOCK_LENGTH);
             return self.create_negative_response(
                 UDS_SID_TRANSFER_DATA, 
-                UDS_NRC_WRONG_BLOCK_SEQUENCE_COUNTER
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
             );
         }
         
+        // Extract block counter and verify sequence
+        let block_counter = data[0];
+        match check_block_sequence(self.last_block_counter, block_counter) {
+            BlockSequence::Next => {},
+            BlockSequence::Repeated => {
+                // The response to this block was lost, acknowledge it without reprogramming
+                debug!("Repeated block {} acknowledged", block_counter);
+                return fixed_response([UDS_SID_TRANSFER_DATA + UDS_RSP_POSITIVE, block_counter]);
+            },
+            BlockSequence::Wrong => {
+                warn!("Block sequence error: last={}, received={}", 
+                     self.last_block_counter, block_counter);
+                return self.create_negative_response(
+                    UDS_SID_TRANSFER_DATA, 
+                    UDS_NRC_WRONG_BLOCK_SEQUENCE_COUNTER
+                );
+            }
+        }
+        
         // Extract data to program
         let program_data = &data[1..];
         
-        // Check if data size exceeds remaining size
-        if program_data.len() as u32 > self.download_size {
+        // Program data to flash, decrypting and decompressing it on the fly.
+        // The position advances only once the whole block is programmed, so
+        // a retried block is written at the same offset.
+        let mut address = self.transfer_address;
+        let mut remaining = self.transfer_size;
+        let result = match self.encryption {
+            Encryption::None => self.store(program_data, &mut address, &mut remaining),
+            Encryption::Aes128Gcm => self.decrypt_and_store(program_data, &mut address, &mut remaining),
+        };
+        
+        if let Err(nrc) = result {
+            // Decoder and decryptor state cannot be rewound for a retry
+            if self.compression != Compression::None || self.encryption != Encryption::None {
+                warn!("Download aborted at 0x{:08X}", self.transfer_address);
+                self.transfer_active = false;
+            }
+            return self.create_negative_response(UDS_SID_TRANSFER_DATA, nrc);
+        }
+        
+        self.transfer_address = address;
+        self.transfer_size = remaining;
+        self.last_block_counter = Some(block_counter);
+        
+        // Create positive response
+        fixed_response([UDS_SID_TRANSFER_DATA + UDS_RSP_POSITIVE, block_counter])
+    }
+    
+    /// Read back one TransferData block of an upload
+    fn upload_block(&mut self, data: &[u8]) -> Vec<u8, 64> {
+        // Upload requests carry the block counter only
+        if data.len() != 1 {
             return self.create_negative_response(
                 UDS_SID_TRANSFER_DATA, 
-                UDS_NRC_REQUEST_OUT_OF_RANGE
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
             );
         }
         
-        // Program data to flash
-        if let Some(flash) = self.flash {
+        let block_counter = data[0];
+        let (address, length) = match check_block_sequence(self.last_block_counter, block_counter) {
+            BlockSequence::Next => {
+                // All requested data has already been sent
+                if self.transfer_size == 0 {
+                    return self.create_negative_response(
+                        UDS_SID_TRANSFER_DATA, 
+                        UDS_NRC_REQUEST_SEQUENCE_ERROR
+                    );
+                }
+                
+                let address = self.transfer_address;
+                let length = core::cmp::min(self.transfer_size as usize, UPLOAD_BLOCK_DATA_SIZE);
+                
+                self.transfer_address += length as u32;
+                self.transfer_size -= length as u32;
+                self.last_block_counter = Some(block_counter);
+                self.last_block_address = address;
+                self.last_block_length = length;
+                
+                (address, length)
+            },
+            BlockSequence::Repeated => {
+                // The response to this block was lost, send the same data again
+                debug!("Repeated block {} resent", block_counter);
+                (self.last_block_address, self.last_block_length)
+            },
+            BlockSequence::Wrong => {
+                warn!("Block sequence error: last={}, received={}", 
+                     self.last_block_counter, block_counter);
+                return self.create_negative_response(
+                    UDS_SID_TRANSFER_DATA, 
+                    UDS_NRC_WRONG_BLOCK_SEQUENCE_COUNTER
+                );
+            }
+        };
+        
+        let mut block = [0u8; UPLOAD_BLOCK_DATA_SIZE];
+        memory::read_memory(address, &mut block[..length]);
+        
+        let mut response = fixed_response([UDS_SID_TRANSFER_DATA + UDS_RSP_POSITIVE, block_counter]);
+        if response.extend_from_slice(&block[..length]).is_err() {
+            return self.create_negative_response(UDS_SID_TRANSFER_DATA, UDS_NRC_RESPONSE_TOO_LONG);
+        }
+        
+        response
+    }
+    
+    /// Decrypt encrypted download data and store the plain text
+    fn decrypt_and_store(&mut self, mut data: &[u8], address: &mut u32, remaining: &mut u32) -> Result<(), u8> {
+        // The stream starts with the IV
+        if self.gcm.is_none() {
+            let length = core::cmp::min(GCM_IV_LENGTH - self.iv_len, data.len());
+            self.iv[self.iv_len..self.iv_len + length].copy_from_slice(&data[..length]);
+            self.iv_len += length;
+            data = &data[length..];
+            
+            if self.iv_len < GCM_IV_LENGTH {
+                return Ok(());
+            }
+            
+            let cipher = self.cipher.ok_or(UDS_NRC_CONDITIONS_NOT_CORRECT)?;
             // Safety: We know this pointer is valid
-            unsafe {
-                match (*flash).write(self.download_address, program_data) {
-                    Ok(_) => {
-                        debug!("Flash write successful");
-                    },
-                    Err(_) => {
-                        warn!("Flash write failed");
-                        return self.create_negative_response(
-                            UDS_SID_TRANSFER_DATA, 
-                            UDS_NRC_GENERAL_PROGRAMMING_FAILURE
-                        );
-                    }
+            let gcm = unsafe { GcmDecryptor::new(&mut *cipher, &self.iv) };
+            self.gcm = Some(gcm.map_err(|_| UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?);
+        }
+        
+        let cipher = self.cipher.ok_or(UDS_NRC_CONDITIONS_NOT_CORRECT)?;
+        let mut buffer = [0u8; DECRYPT_BUFFER_SIZE];
+        
+        for chunk in data.chunks(DECRYPT_BUFFER_SIZE) {
+            let plain = &mut buffer[..chunk.len()];
+            plain.copy_from_slice(chunk);
+            
+            if let Some(gcm) = self.gcm.as_mut() {
+                // Safety: We know this pointer is valid
+                unsafe {
+                    gcm.decrypt(&mut *cipher, plain)
+                        .map_err(|_| UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?;
                 }
             }
+            
+            self.store(plain, address, remaining)?;
         }
         
-        // Update state
-        self.download_address += program_data.len() as u32;
-        self.download_size -= program_data.len() as u32;
-        self.block_counter = block_counter;
+        buffer.fill(0);
+        Ok(())
+    }
+    
+    /// Decompress download data if needed and program it
+    fn store(&mut self, data: &[u8], address: &mut u32, remaining: &mut u32) -> Result<(), u8> {
+        let flash = self.flash;
+        let old = self.delta_source();
+        let patcher = &mut self.patcher;
         
-        // Create positive response
-        response.push(UDS_SID_TRANSFER_DATA + UDS_RSP_POSITIVE);
-        response.push(block_counter);
+        match self.compression {
+            Compression::None => Self::program(flash, address, remaining, data),
+            Compression::Heatshrink => self.decoder.decode(data, |chunk| {
+                Self::program(flash, address, remaining, chunk)
+            }),
+            Compression::Delta => Self::patch(patcher, old, data, flash, address, remaining),
+            Compression::HeatshrinkDelta => self.decoder.decode(data, |chunk| {
+                Self::patch(patcher, old, chunk, flash, address, remaining)
+            }),
+        }
+    }
+    
+    /// Apply delta patch data and program the reconstructed image
+    fn patch(
+        patcher: &mut DeltaPatcher,
+        old: &[u8],
+        data: &[u8],
+        flash: Option<*mut Flash<dyn FlashPort>>,
+        address: &mut u32,
+        remaining: &mut u32,
+    ) -> Result<(), u8> {
+        patcher
+            .apply(old, data, |chunk| Self::program(flash, address, remaining, chunk))
+            .map_err(Self::patch_nrc)
+    }
+    
+    /// Map a patch error to the NRC to respond with
+    fn patch_nrc(error: PatchError<u8>) -> u8 {
+        match error {
+            PatchError::Output(nrc) => nrc,
+            PatchError::Delta(DeltaError::Truncated) => UDS_NRC_REQUEST_SEQUENCE_ERROR,
+            PatchError::Delta(error) => {
+                warn!("Delta patch rejected: {}", defmt::Debug2Format(&error));
+                UDS_NRC_GENERAL_PROGRAMMING_FAILURE
+            }
+        }
+    }
+    
+    /// Map a decompression error to the NRC to respond with
+    fn decode_nrc(error: DecodeError<u8>) -> u8 {
+        match error {
+            DecodeError::Output(nrc) => nrc,
+            DecodeError::Truncated => UDS_NRC_REQUEST_SEQUENCE_ERROR,
+        }
+    }
+    
+    /// Scratch partition address a delta image for `address..address + size` is reconstructed at
+    fn scratch_address(address: u32, size: u32) -> Option<u32> {
+        let application = partition::find(PartitionKind::Application)?;
+        let scratch = partition::find(PartitionKind::Scratch)?;
+        if !application.contains(address, size) {
+            return None;
+        }
         
-        response
+        let offset = address - application.start;
+        (offset + size <= scratch.size()).then_some(scratch.start + offset)
     }
     
-    /// Handle transfer exit
-    pub fn handle_transfer_exit(&mut self, _data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
+    /// Copy a verified delta image from the scratch partition over the installed one
+    fn activate_delta(&mut self) -> Result<(), u8> {
+        let (Some(target), Some(header)) = (self.delta_target.take(), self.patcher.header().copied()) else {
+            return Ok(());
+        };
+        let Some(flash) = self.flash else {
+            return Ok(());
+        };
+        
+        let size = target.end - target.start;
+        let source = Self::scratch_address(target.start, size).ok_or(UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?;
+        let mut buffer = [0u8; COPY_BUFFER_SIZE];
         
+        // Safety: We know this pointer is valid
+        unsafe {
+            (*flash).erase(target.start, size).map_err(|_| UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?;
+            
+            for offset in (0..size).step_by(COPY_BUFFER_SIZE) {
+                let chunk = &mut buffer[..core::cmp::min(COPY_BUFFER_SIZE as u32, size - offset) as usize];
+                memory::read_memory(source + offset, chunk);
+                (*flash).write(target.start + offset, chunk).map_err(|_| UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?;
+            }
+            
+            (*flash).finalize().map_err(|_| UDS_NRC_GENERAL_PROGRAMMING_FAILURE)?;
+        }
+        
+        // The copy must match the verified image
+        // Safety: the range lies in memory-mapped flash
+        let image = unsafe { core::slice::from_raw_parts(target.start as *const u8, size as usize) };
+        let hash: [u8; 32] = Sha256::digest(image).into();
+        if hash != header.new_hash {
+            warn!("Delta image copy does not match its hash");
+            return Err(UDS_NRC_GENERAL_PROGRAMMING_FAILURE);
+        }
+        
+        info!("Delta image activated at 0x{:08X}", target.start);
+        Ok(())
+    }
+    
+    /// Installed image delta patches are applied to
+    fn delta_source(&self) -> &'static [u8] {
+        // Safety: the region lies in memory-mapped flash, only written on activation
+        unsafe { core::slice::from_raw_parts(partition::application_start() as *const u8, self.delta_source_len as usize) }
+    }
+    
+    /// Handle transfer exit
+    pub fn handle_transfer_exit(&mut self, data: &[u8]) -> Vec<u8, 64> {
         // Check if transfer is active
         if !self.transfer_active {
             return self.create_negative_response(
@@ -215,7 +703,86 @@ impl TransferManager {
             );
         }
         
-        // Finalize flash operations
+        // Uploads leave flash untouched
+        if self.direction == TransferDirection::Upload {
+            self.transfer_active = false;
+            info!("Upload finished");
+            
+            return fixed_response([UDS_SID_REQUEST_TRANSFER_EXIT + UDS_RSP_POSITIVE]);
+        }
+        
+        // Encrypted downloads carry the authentication tag, plain ones nothing
+        let expected_length = match self.encryption {
+            Encryption::None => 0,
+            Encryption::Aes128Gcm => GCM_TAG_LENGTH,
+        };
+        if data.len() != expected_length {
+            return self.create_negative_response(
+                UDS_SID_REQUEST_TRANSFER_EXIT, 
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            );
+        }
+        
+        // Program the data still buffered in the decoder and patcher
+        let flash = self.flash;
+        let old = self.delta_source();
+        let patcher = &mut self.patcher;
+        let address = &mut self.transfer_address;
+        let remaining = &mut self.transfer_size;
+        let result = match self.compression {
+            Compression::None => Ok(()),
+            Compression::Heatshrink => self.decoder
+                .finish(|chunk| Self::program(flash, address, remaining, chunk))
+                .map_err(Self::decode_nrc),
+            Compression::Delta => Ok(()),
+            Compression::HeatshrinkDelta => self.decoder
+                .finish(|chunk| Self::patch(patcher, old, chunk, flash, address, remaining))
+                .map_err(Self::decode_nrc),
+        };
+        
+        // A delta image is only accepted if it matches the hash in the patch
+        let result = result.and_then(|_| match self.compression {
+            Compression::Delta | Compression::HeatshrinkDelta => self.patcher
+                .finish(|chunk| Self::program(flash, &mut self.transfer_address, &mut self.transfer_size, chunk))
+                .map_err(Self::patch_nrc),
+            _ => Ok(()),
+        });
+        
+        // All announced data must have been programmed
+        let result = result.and_then(|_| match self.transfer_size {
+            0 => Ok(()),
+            missing => {
+                warn!("Transfer exit with {} bytes missing", missing);
+                Err(UDS_NRC_REQUEST_SEQUENCE_ERROR)
+            }
+        });
+        
+        if let Err(nrc) = result {
+            // An incomplete download may still be continued
+            if nrc != UDS_NRC_REQUEST_SEQUENCE_ERROR {
+                self.transfer_active = false;
+            }
+            return self.create_negative_response(UDS_SID_REQUEST_TRANSFER_EXIT, nrc);
+        }
+        
+        // The image is only marked valid once the whole stream is authenticated
+        if self.encryption == Encryption::Aes128Gcm {
+            let authentic = match self.gcm.take() {
+                Some(gcm) => gcm.verify(data),
+                None => false,
+            };
+            
+            if !authentic {
+                warn!("Encrypted download failed authentication");
+                self.transfer_active = false;
+                return self.create_negative_response(
+                    UDS_SID_REQUEST_TRANSFER_EXIT, 
+                    UDS_NRC_GENERAL_PROGRAMMING_FAILURE
+                );
+            }
+        }
+        
+        // Program the data still buffered by the flash controller
         if let Some(flash) = self.flash {
             // Safety: We know this pointer is valid
             unsafe {
@@ -225,20 +792,7 @@ impl TransferManager {
                     },
                     Err(_) => {
                         warn!("Flash finalization failed");
-                        return self.create_negative_response(
-                            UDS_SID_REQUEST_TRANSFER_EXIT, 
-                            UDS_NRC_GENERAL_PROGRAMMING_FAILURE
-                        );
-                    }
-                }
-                
-                // Write firmware checksum
-                match (*flash).write_checksum() {
-                    Ok(_) => {
-                        debug!("Checksum write successful");
-                    },
-                    Err(_) => {
-                        warn!("Checksum write failed");
+                        self.transfer_active = false;
                         return self.create_negative_response(
                             UDS_SID_REQUEST_TRANSFER_EXIT, 
                             UDS_NRC_GENERAL_PROGRAMMING_FAILURE
@@ -248,45 +802,581 @@ impl TransferManager {
             }
         }
         
+        // A verified delta image replaces the installed one
+        if let Err(nrc) = self.activate_delta() {
+            self.transfer_active = false;
+            return self.create_negative_response(UDS_SID_REQUEST_TRANSFER_EXIT, nrc);
+        }
+        
+        // The image is marked valid by the dependency check after the last segment
+        self.download.complete_segment();
+        info!("Download segment {} complete", self.download.segments().len());
+        
         // Reset transfer state
         self.transfer_active = false;
         
         // Create positive response
-        response.push(UDS_SID_REQUEST_TRANSFER_EXIT + UDS_RSP_POSITIVE);
+        fixed_response([UDS_SID_REQUEST_TRANSFER_EXIT + UDS_RSP_POSITIVE])
+    }
+    
+    /// Check the downloaded segments and mark the image valid
+    ///
+    /// Called by the checkProgrammingDependencies routine once all segments
+    /// were downloaded. The download session is closed on success.
+    pub fn check_programming_dependencies(&mut self) -> Result<(), DownloadError> {
+        if self.transfer_active {
+            return Err(DownloadError::Incomplete);
+        }
+        
+        self.download.check_dependencies()?;
+        
+        // Write firmware checksum
+        if let Some(flash) = self.flash {
+            // Safety: We know this pointer is valid
+            unsafe {
+                if (*flash).write_checksum().is_err() {
+                    warn!("Checksum write failed");
+                    return Err(DownloadError::ProgrammingFailed);
+                }
+            }
+        }
+        
+        info!("Programming dependencies correct, {} segments", self.download.segments().len());
+        self.download.reset();
+        
+        Ok(())
+    }
+    
+    /// Handle read memory by address
+    pub fn handle_read_memory_by_address(&self, data: &[u8]) -> Vec<u8, 64> {
+        let (address, size) = match parse_address_and_length(data) {
+            Ok(range) => range,
+            Err(nrc) => return self.create_negative_response(UDS_SID_READ_MEMORY_BY_ADDRESS, nrc),
+        };
+        
+        // The whole read must fit into a single response
+        if size > READ_MEMORY_MAX_SIZE || !memory::is_readable(address, size) {
+            warn!("Memory read of 0x{:08X}+{} rejected", address, size);
+            return self.create_negative_response(
+                UDS_SID_READ_MEMORY_BY_ADDRESS, 
+                UDS_NRC_REQUEST_OUT_OF_RANGE
+            );
+        }
+        
+        let mut buffer = [0u8; READ_MEMORY_MAX_SIZE as usize];
+        memory::read_memory(address, &mut buffer[..size as usize]);
+        
+        let mut response = fixed_response([UDS_SID_READ_MEMORY_BY_ADDRESS + UDS_RSP_POSITIVE]);
+        if response.extend_from_slice(&buffer[..size as usize]).is_err() {
+            return self.create_negative_response(UDS_SID_READ_MEMORY_BY_ADDRESS, UDS_NRC_RESPONSE_TOO_LONG);
+        }
         
         response
     }
     
-    /// Validate memory address and size range
-    fn validate_memory_range(&self, address: u32, size: u32) -> bool {
-        // Check for overflow
-        if size > MAX_MEMORY_SIZE {
-            return false;
+    /// Program decoded download data and advance the transfer position
+    fn program(flash: Option<*mut Flash<dyn FlashPort>>, address: &mut u32, remaining: &mut u32, data: &[u8]) -> Result<(), u8> {
+        // More data than announced with RequestDownload
+        if data.len() as u32 > *remaining {
+            warn!("Download exceeds the requested size");
+            return Err(UDS_NRC_TRANSFER_DATA_SUSPENDED);
         }
         
-        // Check if end address would overflow
-        if address.checked_add(size).is_none() {
-            return false;
+        if let Some(flash) = flash {
+            // Safety: We know this pointer is valid
+            unsafe {
+                match (*flash).write(*address, data) {
+                    Ok(_) => {
+                        debug!("Flash write successful");
+                    },
+                    Err(_) => {
+                        warn!("Flash write failed");
+                        return Err(UDS_NRC_GENERAL_PROGRAMMING_FAILURE);
+                    }
+                }
+            }
         }
         
-        // Check that the range is within the allowed flash area
-        let app_start = 0x00008000; // Example value - adjust based on actual memory map
-        let flash_end = 0x00080000; // Example value - adjust based on actual flash size
+        *address += data.len() as u32;
+        *remaining -= data.len() as u32;
         
-        address >= app_start && (address + size) <= flash_end
+        Ok(())
+    }
+    
+    /// Validate memory address and size range
+    fn validate_memory_range(&self, address: u32, size: u32) -> bool {
+        // The range must lie within a single partition writable via UDS
+        partition::is_range_allowed(address, size, PARTITION_FLAG_WRITABLE)
     }
     
     /// Create a negative response
     fn create_negative_response(&self, sid: u8, nrc: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        response.push(UDS_SID_NEGATIVE_RESPONSE);
-        response.push(sid);
-        response.push(nrc);
-        
-        response
+        fixed_response([UDS_SID_NEGATIVE_RESPONSE, sid, nrc])
     }
 }
 
-// Additional UDS NRC for transfer functions
-const UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT: u8 = 0x13;
\ No newline at end of file
+impl Default for TransferManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::routine::{RoutineControl, ROUTINE_STATUS_CORRECT};
+    use super::*;
+    use crate::bootloader::flash::MockFlashPort;
+    use crate::bootloader::self_update::UpdateFlash;
+    use crate::crypto::aes::SoftwareAes128;
+    
+    /// Number of blocks sent, the counter wraps more than twice
+    const BLOCKS: usize = 600;
+    
+    fn manager() -> TransferManager {
+        let mut transfer = TransferManager::new();
+        transfer.init();
+        transfer
+    }
+    
+    /// Request a download of `size` bytes to the application start
+    fn request_download(transfer: &mut TransferManager, format: u8, size: u32) -> Vec<u8, 64> {
+        let mut request = std::vec![format, 0x44];
+        request.extend_from_slice(&partition::application_start().to_be_bytes());
+        request.extend_from_slice(&size.to_be_bytes());
+        transfer.handle_request_download(&request)
+    }
+    
+    fn transfer_data(transfer: &mut TransferManager, counter: u8, data: &[u8]) -> Vec<u8, 64> {
+        let mut request = std::vec![counter];
+        request.extend_from_slice(data);
+        transfer.handle_transfer_data(&request)
+    }
+    
+    fn negative(sid: u8, nrc: u8) -> [u8; 3] {
+        [UDS_SID_NEGATIVE_RESPONSE, sid, nrc]
+    }
+    
+    /// Register a flash image whose programming fails at `fail_at`
+    fn register_mock_flash(
+        transfer: &mut TransferManager,
+        fail_at: Option<u32>,
+    ) -> *mut Flash<MockFlashPort> {
+        let mut port = MockFlashPort::default();
+        port.fail_at = fail_at;
+        let flash: *mut Flash<MockFlashPort> = Box::leak(Box::new(Flash::with_port(port)));
+        transfer.register_flash(unsafe { &mut *flash });
+        flash
+    }
+    
+    /// GCM specification test case 3: key, IV, plaintext, ciphertext and tag
+    const GCM_KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const GCM_IV: &str = "cafebabefacedbaddecaf888";
+    const GCM_PLAINTEXT: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                                 1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255";
+    const GCM_CIPHERTEXT: &str = "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                                  21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985";
+    const GCM_TAG: &str = "4d5c2af327cd64a62cf35abd2ba6fab4";
+    
+    fn hex(text: &str) -> std::vec::Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+    
+    /// Start an encrypted download and send the IV and ciphertext, the IV
+    /// split across the first two blocks
+    fn encrypted_download() -> (TransferManager, *mut Flash<MockFlashPort>) {
+        let mut transfer = manager();
+        let flash = register_mock_flash(&mut transfer, None);
+        transfer.register_cipher(Box::leak(Box::new(SoftwareAes128::new(
+            &hex(GCM_KEY).try_into().unwrap(),
+        ))));
+        
+        let stream = [hex(GCM_IV), hex(GCM_CIPHERTEXT)].concat();
+        let size = hex(GCM_PLAINTEXT).len() as u32;
+        assert_eq!(
+            request_download(&mut transfer, UDS_DFI_ENCRYPTION_AES128_GCM, size)[0],
+            0x74
+        );
+        
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &stream[..5]),
+            [0x76, 0x01]
+        );
+        assert_eq!(
+            transfer_data(&mut transfer, 0x02, &stream[5..30]),
+            [0x76, 0x02]
+        );
+        assert_eq!(
+            transfer_data(&mut transfer, 0x03, &stream[30..]),
+            [0x76, 0x03]
+        );
+        assert_eq!(transfer.transfer_size, 0);
+        
+        (transfer, flash)
+    }
+    
+    /// Start the checkProgrammingDependencies routine
+    fn check_programming_dependencies(transfer: &mut TransferManager) -> Vec<u8, 64> {
+        let [high, low] = UDS_RID_CHECK_PROGRAMMING_DEPENDENCIES.to_be_bytes();
+        RoutineControl::new().handle_routine_control(&[UDS_ROUTINE_START, high, low], transfer)
+    }
+    
+    #[test]
+    fn block_sequence_every_counter() {
+        for received in 0..=0xFF {
+            let expected = if received == 0x01 {
+                BlockSequence::Next
+            } else {
+                BlockSequence::Wrong
+            };
+            assert_eq!(check_block_sequence(None, received), expected);
+        }
+        
+        for last in 0..=0xFFu8 {
+            let next = if last == 0xFF { 0x00 } else { last + 1 };
+            for received in 0..=0xFF {
+                let expected = match received {
+                    _ if received == next => BlockSequence::Next,
+                    _ if received == last => BlockSequence::Repeated,
+                    _ => BlockSequence::Wrong,
+                };
+                assert_eq!(
+                    check_block_sequence(Some(last), received),
+                    expected,
+                    "last {last}, received {received}"
+                );
+            }
+        }
+    }
+    
+    #[test]
+    fn download_wraps_block_counter() {
+        let mut transfer = manager();
+        let start = partition::application_start();
+        let block = [0xA5; 8];
+        assert_eq!(
+            request_download(&mut transfer, 0x00, (BLOCKS * block.len()) as u32)[0],
+            0x74
+        );
+        
+        for index in 0..BLOCKS {
+            let counter = (index + 1) as u8;
+            let offset = (index * block.len()) as u32;
+            let position = (start + offset, (BLOCKS * block.len()) as u32 - offset);
+            
+            // Every other counter is rejected without programming, the last
+            // one is acknowledged again
+            for other in (0..=0xFF).filter(|&other| other != counter) {
+                let response = transfer_data(&mut transfer, other, &block);
+                if index > 0 && other == counter.wrapping_sub(1) {
+                    assert_eq!(response, [0x76, other]);
+                } else {
+                    assert_eq!(
+                        response,
+                        negative(UDS_SID_TRANSFER_DATA, UDS_NRC_WRONG_BLOCK_SEQUENCE_COUNTER)
+                    );
+                }
+                assert_eq!(
+                    (transfer.transfer_address, transfer.transfer_size),
+                    position
+                );
+            }
+            
+            assert_eq!(
+                transfer_data(&mut transfer, counter, &block),
+                [0x76, counter]
+            );
+            assert_eq!(transfer.transfer_address, position.0 + block.len() as u32);
+            
+            // A retry after a lost response is not programmed again
+            assert_eq!(
+                transfer_data(&mut transfer, counter, &block),
+                [0x76, counter]
+            );
+            assert_eq!(transfer.transfer_address, position.0 + block.len() as u32);
+        }
+        
+        // Nothing is left of the requested size
+        let counter = (BLOCKS + 1) as u8;
+        assert_eq!(
+            transfer_data(&mut transfer, counter, &[0]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_TRANSFER_DATA_SUSPENDED)
+        );
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+        assert_eq!(transfer.check_programming_dependencies(), Ok(()));
+    }
+    
+    #[test]
+    fn failed_block_is_retried_at_the_same_offset() {
+        let mut transfer = manager();
+        let start = partition::application_start();
+        request_download(&mut transfer, 0x00, 10);
+        
+        assert_eq!(transfer_data(&mut transfer, 0x01, &[1; 4]), [0x76, 0x01]);
+        
+        // Exceeds the requested size, neither position nor counter advance
+        assert_eq!(
+            transfer_data(&mut transfer, 0x02, &[2; 8]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_TRANSFER_DATA_SUSPENDED)
+        );
+        assert_eq!(
+            (transfer.transfer_address, transfer.transfer_size),
+            (start + 4, 6)
+        );
+        assert_eq!(transfer.last_block_counter, Some(0x01));
+        
+        assert_eq!(transfer_data(&mut transfer, 0x02, &[2; 6]), [0x76, 0x02]);
+        assert_eq!(
+            (transfer.transfer_address, transfer.transfer_size),
+            (start + 10, 0)
+        );
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+    }
+    
+    #[test]
+    fn failed_compressed_block_aborts_the_download() {
+        let mut transfer = manager();
+        request_download(&mut transfer, UDS_DFI_COMPRESSION_HEATSHRINK << 4, 4);
+        
+        // Zero bits decode to single-byte back-references, more than 4 bytes
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0; 120]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_TRANSFER_DATA_SUSPENDED)
+        );
+        
+        // The decoder cannot be rewound for a retry
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0; 2]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_REQUEST_SEQUENCE_ERROR)
+        );
+    }
+    
+    #[test]
+    fn max_block_length_encoding() {
+        let encode = |length| {
+            let response = max_block_length_response(UDS_SID_REQUEST_DOWNLOAD, length);
+            std::vec::Vec::from(&response[1..])
+        };
+        
+        // The lengthFormatIdentifier counts the bytes that follow
+        assert_eq!(encode(0), [0x10, 0x00]);
+        assert_eq!(encode(0x40), [0x10, 0x40]);
+        assert_eq!(encode(0x400), [0x20, 0x04, 0x00]);
+        assert_eq!(encode(0xFFFF), [0x20, 0xFF, 0xFF]);
+        assert_eq!(encode(0x1_0000), [0x30, 0x01, 0x00, 0x00]);
+        assert_eq!(encode(0x0100_0000), [0x40, 0x01, 0x00, 0x00, 0x00]);
+        
+        let mut transfer = manager();
+        assert_eq!(
+            request_download(&mut transfer, 0x00, 0x10),
+            [0x74, 0x20, 0x04, 0x00]
+        );
+        
+        let mut transfer = manager();
+        let mut request = std::vec![0x00, 0x44];
+        request.extend_from_slice(&partition::application_start().to_be_bytes());
+        request.extend_from_slice(&0x10u32.to_be_bytes());
+        assert_eq!(transfer.handle_request_upload(&request), [0x75, 0x10, 0x40]);
+    }
+    
+    #[test]
+    fn block_longer_than_negotiated() {
+        let mut transfer = manager();
+        request_download(&mut transfer, 0x00, 2 * MAX_BLOCK_LENGTH as u32);
+        
+        // SID and counter count towards maxNumberOfBlockLength
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0; MAX_BLOCK_LENGTH - 1]),
+            negative(
+                UDS_SID_TRANSFER_DATA,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            )
+        );
+        assert_eq!(transfer.last_block_counter, None);
+        
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0; MAX_BLOCK_LENGTH - 2]),
+            [0x76, 0x01]
+        );
+        assert_eq!(transfer_data(&mut transfer, 0x02, &[]), [0x76, 0x02]);
+        assert_eq!(
+            transfer.handle_transfer_data(&[]),
+            negative(
+                UDS_SID_TRANSFER_DATA,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            )
+        );
+    }
+    
+    #[test]
+    fn download_larger_than_requested() {
+        let mut transfer = manager();
+        request_download(&mut transfer, 0x00, 100);
+        
+        assert_eq!(transfer_data(&mut transfer, 0x01, &[0; 60]), [0x76, 0x01]);
+        assert_eq!(
+            transfer_data(&mut transfer, 0x02, &[0; 41]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_TRANSFER_DATA_SUSPENDED)
+        );
+        assert_eq!(transfer_data(&mut transfer, 0x02, &[0; 40]), [0x76, 0x02]);
+        assert_eq!(
+            transfer_data(&mut transfer, 0x03, &[0]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_TRANSFER_DATA_SUSPENDED)
+        );
+    }
+    
+    #[test]
+    fn incomplete_download() {
+        let mut transfer = manager();
+        
+        // No transfer active
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0; 4]),
+            negative(UDS_SID_TRANSFER_DATA, UDS_NRC_REQUEST_SEQUENCE_ERROR)
+        );
+        assert_eq!(
+            transfer.handle_transfer_exit(&[]),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_REQUEST_SEQUENCE_ERROR
+            )
+        );
+        
+        // Exit with bytes missing, the download may still be completed
+        request_download(&mut transfer, 0x00, 100);
+        assert_eq!(transfer_data(&mut transfer, 0x01, &[0; 60]), [0x76, 0x01]);
+        assert_eq!(
+            transfer.handle_transfer_exit(&[]),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_REQUEST_SEQUENCE_ERROR
+            )
+        );
+        assert_eq!(
+            transfer.check_programming_dependencies(),
+            Err(DownloadError::Incomplete)
+        );
+        
+        assert_eq!(transfer_data(&mut transfer, 0x02, &[0; 40]), [0x76, 0x02]);
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+        assert_eq!(transfer.check_programming_dependencies(), Ok(()));
+        
+        // A truncated compressed stream is incomplete as well
+        request_download(&mut transfer, UDS_DFI_COMPRESSION_HEATSHRINK << 4, 1);
+        assert_eq!(transfer_data(&mut transfer, 0x01, &[0xAA]), [0x76, 0x01]);
+        assert_eq!(
+            transfer.handle_transfer_exit(&[]),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_REQUEST_SEQUENCE_ERROR
+            )
+        );
+        assert_eq!(transfer_data(&mut transfer, 0x02, &[0x80]), [0x76, 0x02]);
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+    }
+    
+    #[test]
+    fn dependency_check_writes_the_checksum() {
+        let mut transfer = manager();
+        let flash = register_mock_flash(&mut transfer, None);
+        let vectors: std::vec::Vec<u8> = (0..0x40u8).collect();
+        
+        assert_eq!(
+            request_download(&mut transfer, 0x00, vectors.len() as u32)[0],
+            0x74
+        );
+        assert_eq!(transfer_data(&mut transfer, 0x01, &vectors), [0x76, 0x01]);
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+        assert!(!unsafe { (*flash).verify_checksum() });
+        
+        assert_eq!(
+            check_programming_dependencies(&mut transfer),
+            [0x71, UDS_ROUTINE_START, 0xFF, 0x01, ROUTINE_STATUS_CORRECT]
+        );
+        assert!(unsafe { (*flash).verify_checksum() });
+        assert!(transfer.download.segments().is_empty());
+    }
+    
+    #[test]
+    fn dependency_check_checksum_write_failure() {
+        let mut transfer = manager();
+        let start = partition::application_start();
+        register_mock_flash(&mut transfer, Some(start + 0x3F8));
+        
+        // The segment itself is programmed in another sector
+        let mut request = std::vec![0x00, 0x44];
+        request.extend_from_slice(&(start + SECTOR_SIZE).to_be_bytes());
+        request.extend_from_slice(&0x10u32.to_be_bytes());
+        assert_eq!(transfer.handle_request_download(&request)[0], 0x74);
+        assert_eq!(
+            transfer_data(&mut transfer, 0x01, &[0x5A; 0x10]),
+            [0x76, 0x01]
+        );
+        assert_eq!(transfer.handle_transfer_exit(&[]), [0x77]);
+        
+        assert_eq!(
+            check_programming_dependencies(&mut transfer),
+            negative(UDS_SID_ROUTINE_CONTROL, UDS_NRC_GENERAL_PROGRAMMING_FAILURE)
+        );
+    }
+    
+    #[test]
+    fn encrypted_download_correct_tag() {
+        let (mut transfer, flash) = encrypted_download();
+        
+        assert_eq!(transfer.handle_transfer_exit(&hex(GCM_TAG)), [0x77]);
+        
+        // The IV is not programmed, the plaintext is
+        let mut programmed = std::vec![0; hex(GCM_PLAINTEXT).len()];
+        unsafe { (*flash).read(partition::application_start(), &mut programmed) };
+        assert_eq!(programmed, hex(GCM_PLAINTEXT));
+        assert_eq!(transfer.check_programming_dependencies(), Ok(()));
+    }
+    
+    #[test]
+    fn encrypted_download_wrong_tag() {
+        let (mut transfer, _) = encrypted_download();
+        let mut tag = hex(GCM_TAG);
+        tag[GCM_TAG_LENGTH - 1] ^= 0x80;
+        
+        assert_eq!(
+            transfer.handle_transfer_exit(&tag),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_GENERAL_PROGRAMMING_FAILURE
+            )
+        );
+        
+        // The segment is not complete, the image cannot be marked valid
+        assert_eq!(
+            transfer.check_programming_dependencies(),
+            Err(DownloadError::Incomplete)
+        );
+    }
+    
+    #[test]
+    fn encrypted_download_truncated_tag() {
+        let (mut transfer, _) = encrypted_download();
+        let tag = hex(GCM_TAG);
+        
+        assert_eq!(
+            transfer.handle_transfer_exit(&tag[..GCM_TAG_LENGTH - 1]),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            )
+        );
+        assert_eq!(
+            transfer.handle_transfer_exit(&[]),
+            negative(
+                UDS_SID_REQUEST_TRANSFER_EXIT,
+                UDS_NRC_INCORRECT_MESSAGE_LENGTH_OR_INVALID_FORMAT
+            )
+        );
+        
+        // The transfer is still active, the complete tag is accepted
+        assert_eq!(transfer.handle_transfer_exit(&tag), [0x77]);
+    }
+}
\ No newline at end of file
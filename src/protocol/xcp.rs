@@ -1,5 +1,7 @@
-use defmt::{debug, info, warn};
+use defmt::{debug, info};
 use heapless::Vec;
+use crate::config;
+use crate::protocol::fixed_response;
 
 // XCP Command codes
 const XCP_CMD_CONNECT: u8 = 0xFF;
@@ -23,27 +25,12 @@ const XCP_CMD_PROGRAM_RESET: u8 = 0xCF;
 // XCP Packet ID (Counter) position (first byte in XCP response)
 const XCP_PID_RESPONSE: u8 = 0xFF;
 const XCP_PID_ERROR: u8 = 0xFE;
-const XCP_PID_EVENT: u8 = 0xFD;
 
 // XCP Error codes
 const XCP_ERR_CMD_SYNCH: u8 = 0x00;
-const XCP_ERR_CMD_BUSY: u8 = 0x10;
-const XCP_ERR_DAQ_ACTIVE: u8 = 0x11;
-const XCP_ERR_PGM_ACTIVE: u8 = 0x12;
 const XCP_ERR_CMD_UNKNOWN: u8 = 0x20;
 const XCP_ERR_CMD_SYNTAX: u8 = 0x21;
-const XCP_ERR_OUT_OF_RANGE: u8 = 0x22;
-const XCP_ERR_WRITE_PROTECTED: u8 = 0x23;
 const XCP_ERR_ACCESS_DENIED: u8 = 0x24;
-const XCP_ERR_ACCESS_LOCKED: u8 = 0x25;
-const XCP_ERR_PAGE_NOT_VALID: u8 = 0x26;
-const XCP_ERR_MODE_NOT_VALID: u8 = 0x27;
-const XCP_ERR_SEGMENT_NOT_VALID: u8 = 0x28;
-const XCP_ERR_SEQUENCE: u8 = 0x29;
-const XCP_ERR_RESOURCE: u8 = 0x2A;
-const XCP_ERR_MEMORY_OVERFLOW: u8 = 0x30;
-const XCP_ERR_GENERIC: u8 = 0x31;
-const XCP_ERR_VERIFY: u8 = 0x32;
 
 /// XCP Protocol implementation (optional fallback to OpenBLT default)
 pub struct XcpProtocol {
@@ -113,8 +100,6 @@ impl XcpProtocol {
     
     /// Handle CONNECT command
     fn handle_connect(&mut self, data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Validate data length
         if data.is_empty() {
             return self.create_error_response(XCP_ERR_CMD_SYNTAX);
@@ -128,66 +113,51 @@ impl XcpProtocol {
         info!("XCP Connected (mode: 0x{:02X})", mode);
         
         // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        // Add resource availability info
-        response.push(0x01);  // CAL/PAG resource available
-        
-        // Communications mode info
-        response.push(0x00);  // COMM_MODE_BASIC
-        
-        // Max CTO size
-        response.push(8);     // 8 bytes (standard CAN)
-        
-        // Max DTO size
-        response.push(8);     // 8 bytes (standard CAN)
-        
-        // Protocol layer version
-        response.push(0x01);  // Version 1.0
-        
-        // Transport layer version
-        response.push(0x01);  // Version 1.0
-        
-        response
+        fixed_response([
+            XCP_PID_RESPONSE,
+            // Add resource availability info
+            0x01,  // CAL/PAG resource available
+            // Communications mode info
+            0x00,  // COMM_MODE_BASIC
+            // Max CTO size
+            8,     // 8 bytes (standard CAN)
+            // Max DTO size
+            8,     // 8 bytes (standard CAN)
+            // Protocol layer version
+            0x01,  // Version 1.0
+            // Transport layer version
+            0x01,  // Version 1.0
+        ])
     }
     
     /// Handle DISCONNECT command
     fn handle_disconnect(&mut self, _data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Set connection status
         self.connected = false;
         info!("XCP Disconnected");
         
         // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        response
+        fixed_response([XCP_PID_RESPONSE])
     }
     
     /// Handle GET_STATUS command
     fn handle_get_status(&self, _data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        // Build response
-        response.push(XCP_PID_RESPONSE);
-        
         // Status byte
         let status = if self.programming_mode { 0x01 } else { 0x00 };
-        response.push(status);
-        
-        // Protection status
-        response.push(0x00);
-        
-        // Reserved
-        response.push(0x00);
-        response.push(0x00);
-        
-        // Session configuration ID (vendor-specific)
-        response.push(0x01);
-        response.push(0x02);
         
-        response
+        // Build response
+        fixed_response([
+            XCP_PID_RESPONSE,
+            status,
+            // Protection status
+            0x00,
+            // Reserved
+            0x00,
+            0x00,
+            // Session configuration ID (vendor-specific)
+            0x01,
+            0x02,
+        ])
     }
     
     /// Handle SYNCH command
@@ -201,34 +171,25 @@ impl XcpProtocol {
     
     /// Handle GET_COMM_MODE_INFO command
     fn handle_get_comm_mode_info(&self, _data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        // Communication mode info
-        response.push(0x00);  // Reserved
-        
-        // Max BS (Block Size)
-        response.push(0x01);  // Single frame only
-        
-        // Min ST (Separation Time)
-        response.push(0x00);  // No separation time
-        
-        // Queue size
-        response.push(0x00);  // No queue
-        
-        // XCP Driver version
-        response.push(0x01);  // Version 1.0
-        response.push(0x00);  // Version 1.0
-        
-        response
+        fixed_response([
+            XCP_PID_RESPONSE,
+            // Communication mode info
+            0x00,  // Reserved
+            // Max BS (Block Size)
+            0x01,  // Single frame only
+            // Min ST (Separation Time)
+            0x00,  // No separation time
+            // Queue size
+            0x00,  // No queue
+            // XCP Driver version
+            0x01,  // Version 1.0
+            0x00,  // Version 1.0
+        ])
     }
     
     /// Handle GET_ID command
     fn handle_get_id(&self, data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Validate data length
         if data.is_empty() {
             return self.create_error_response(XCP_ERR_CMD_SYNTAX);
@@ -237,39 +198,35 @@ impl XcpProtocol {
         // Extract ID type
         let id_type = data[0];
         
-        // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        // Mode
-        response.push(0x01);  // Identification as ASCII text
-        
         // ID data length
         let id_text = match id_type {
-            0 => "Gridania S32K148 Bootloader",
-            1 => "S32K148",
-            2 => "v1.0.0",
+            0 => config::ECU_NAME,
+            1 => config::MCU_NAME,
+            2 => config::SOFTWARE_VERSION,
             _ => "",
         };
         
         let id_len = id_text.len() as u16;
-        response.push((id_len >> 8) as u8);
-        response.push(id_len as u8);
-        
-        // Reserved
-        response.push(0x00);
-        response.push(0x00);
-        response.push(0x00);
         
         // This requires a second frame to send the actual ID text
         // In a real implementation, this would be sent as a separate message
         
-        response
+        // Build response
+        fixed_response([
+            XCP_PID_RESPONSE,
+            // Mode
+            0x01,  // Identification as ASCII text
+            (id_len >> 8) as u8,
+            id_len as u8,
+            // Reserved
+            0x00,
+            0x00,
+            0x00,
+        ])
     }
     
     /// Handle SET_MTA command (Memory Transfer Address)
     fn handle_set_mta(&mut self, data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Validate data length
         if data.len() < 7 {
             return self.create_error_response(XCP_ERR_CMD_SYNTAX);
@@ -284,53 +241,40 @@ impl XcpProtocol {
         info!("XCP Set MTA: 0x{:08X} (ext: 0x{:02X})", addr, addr_ext);
         
         // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        response
+        fixed_response([XCP_PID_RESPONSE])
     }
     
     /// Handle PROGRAM_START command
     fn handle_program_start(&mut self, _data: &[u8]) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
         // Enter programming mode
         self.programming_mode = true;
         info!("XCP Programming mode started");
         
         // Build response
-        response.push(XCP_PID_RESPONSE);
-        
-        // Default communication mode
-        response.push(0x00);
-        
-        // Implementation-specific info
-        response.push(0x00);
-        response.push(0x00);
-        response.push(0x00);
-        response.push(0x00);
-        response.push(0x00);
-        
-        response
+        fixed_response([
+            XCP_PID_RESPONSE,
+            // Default communication mode
+            0x00,
+            // Implementation-specific info
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+        ])
     }
     
     /// Handle PROGRAM_CLEAR command
-    fn handle_program_clear(&mut self, data: &[u8]) -> Vec<u8, 64> {
+    fn handle_program_clear(&mut self, _data: &[u8]) -> Vec<u8, 64> {
         // The implementation of this would involve flash erase operations
         // For the skeleton, we'll just return a positive response
         
-        let mut response = Vec::new();
-        response.push(XCP_PID_RESPONSE);
-        response
+        fixed_response([XCP_PID_RESPONSE])
     }
     
     /// Create an error response
     fn create_error_response(&self, error_code: u8) -> Vec<u8, 64> {
-        let mut response = Vec::new();
-        
-        response.push(XCP_PID_ERROR);
-        response.push(error_code);
-        
-        response
+        fixed_response([XCP_PID_ERROR, error_code])
     }
     
     // Other handler methods would be implemented similarly
@@ -365,4 +309,10 @@ impl XcpProtocol {
     fn handle_program_reset(&self, _data: &[u8]) -> Vec<u8, 64> {
         self.create_error_response(XCP_ERR_CMD_UNKNOWN)
     }
+}
+
+impl Default for XcpProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,272 @@
+//! Build-time configuration of stage 0
+//!
+//! Reads the same `config/<variant>.toml` as the bootloader (variant from
+//! `ECU_VARIANT`, default `gridania-telematic`) and generates `config.rs`
+//! and `memory.x` in `OUT_DIR`. Only the sections stage 0 needs are read;
+//! the bootloader build validates the whole file. Root and production keys
+//! come from the key file named by `BOOTLOADER_KEYS` as for the bootloader.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use serde::Deserialize;
+
+/// Variant built when `ECU_VARIANT` is not set
+const DEFAULT_VARIANT: &str = "gridania-telematic";
+
+/// Number of key slots (`src/bootloader/keystore.rs`)
+const KEY_SLOT_COUNT: usize = 3;
+
+#[derive(Deserialize)]
+struct Config {
+    self_update: SelfUpdate,
+    #[serde(default)]
+    key: Vec<Key>,
+    memory: Memory,
+    partition: Vec<Partition>,
+}
+
+#[derive(Deserialize)]
+struct SelfUpdate {
+    security_version: u32,
+}
+
+#[derive(Deserialize)]
+struct Key {
+    slot: String,
+    id: u8,
+    public_key: String,
+}
+
+/// Key file named by `BOOTLOADER_KEYS`
+#[derive(Deserialize)]
+struct KeyFile {
+    key: Vec<Key>,
+}
+
+#[derive(Deserialize)]
+struct Memory {
+    ram_origin: u32,
+    ram_length: u32,
+    mailbox_length: u32,
+    handoff_length: u32,
+}
+
+#[derive(Deserialize)]
+struct Partition {
+    name: String,
+    kind: String,
+    start: u32,
+    end: u32,
+    flags: Vec<String>,
+    erase_size: u32,
+}
+
+fn main() {
+    let variant = env::var("ECU_VARIANT").unwrap_or_else(|_| String::from(DEFAULT_VARIANT));
+    let path = PathBuf::from("../config").join(format!("{variant}.toml"));
+    
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rerun-if-env-changed=ECU_VARIANT");
+    println!("cargo:rerun-if-env-changed=BOOTLOADER_KEYS");
+    
+    let text = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("cannot read configuration {}: {e}", path.display()));
+    let mut config: Config = toml::from_str(&text)
+        .unwrap_or_else(|e| panic!("invalid configuration {}: {e}", path.display()));
+    
+    if let Some(key) = config.key.iter().find(|key| key.slot != "development") {
+        panic!(
+            "invalid configuration {}: key {}: {} keys must come from BOOTLOADER_KEYS, not the repository",
+            path.display(),
+            key.id,
+            key.slot
+        );
+    }
+    
+    if let Ok(keys_path) = env::var("BOOTLOADER_KEYS") {
+        println!("cargo:rerun-if-changed={keys_path}");
+        let text = fs::read_to_string(&keys_path)
+            .unwrap_or_else(|e| panic!("cannot read key file {keys_path}: {e}"));
+        let keys: KeyFile = toml::from_str(&text)
+            .unwrap_or_else(|e| panic!("invalid key file {keys_path}: {e}"));
+        config.key.extend(keys.key);
+    }
+    
+    if let Err(message) = validate(&config) {
+        panic!("invalid configuration {}: {message}", path.display());
+    }
+    
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out.join("config.rs"), generate_config(&config, &variant)).unwrap();
+    fs::write(out.join("memory.x"), generate_memory_x(&config)).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+}
+
+/// Check what stage 0 relies on beyond the bootloader build's validation
+fn validate(config: &Config) -> Result<(), String> {
+    if find(config, "stage0").is_none() {
+        return Err(String::from("a stage0 partition is required to build stage 0"));
+    }
+    
+    for key in &config.key {
+        if key_slot(&key.slot).is_none() || parse_public_key(&key.public_key).is_none() {
+            return Err(format!("key {}: needs a known slot and an uncompressed P-256 public key", key.id));
+        }
+    }
+    
+    for partition in &config.partition {
+        if partition_kind(&partition.kind).is_none() {
+            return Err(format!("partition {}: unknown kind {}", partition.name, partition.kind));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Look up the partition of the given kind
+fn find<'a>(config: &'a Config, kind: &str) -> Option<&'a Partition> {
+    config.partition.iter().find(|partition| partition.kind == kind)
+}
+
+/// Index of a key slot in the key store
+fn key_slot(slot: &str) -> Option<usize> {
+    match slot {
+        "root" => Some(0),
+        "production" => Some(1),
+        "development" => Some(2),
+        _ => None,
+    }
+}
+
+/// Decode an uncompressed P-256 public key given as hex
+fn parse_public_key(text: &str) -> Option<Vec<u8>> {
+    if text.len() != 130 || !text.is_ascii() || !text.starts_with("04") {
+        return None;
+    }
+    
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Rust name of a partition kind
+fn partition_kind(kind: &str) -> Option<&'static str> {
+    match kind {
+        "bootloader" => Some("Bootloader"),
+        "application" => Some("Application"),
+        "calibration" => Some("Calibration"),
+        "nv-data" => Some("NvData"),
+        "staging" => Some("Staging"),
+        "stage0" => Some("Stage0"),
+        "scratch" => Some("Scratch"),
+        _ => None,
+    }
+}
+
+/// Rust name of a partition flag
+fn partition_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "writable" => Some("PARTITION_FLAG_WRITABLE"),
+        "executable" => Some("PARTITION_FLAG_EXECUTABLE"),
+        "readable" => Some("PARTITION_FLAG_READABLE"),
+        _ => None,
+    }
+}
+
+/// Generate the `config` module
+fn generate_config(config: &Config, variant: &str) -> String {
+    let mut out = String::new();
+    let memory = &config.memory;
+    
+    writeln!(out, "// Generated by build.rs from config/{variant}.toml, do not edit").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "use crate::bootloader::keystore::*;").unwrap();
+    writeln!(out, "use crate::bootloader::partition::*;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Lowest security version of the bootloader stage 0 starts").unwrap();
+    writeln!(out, "pub const BOOTLOADER_SECURITY_VERSION: u32 = {};", config.self_update.security_version).unwrap();
+    writeln!(out, "/// Key store in effect until the first key update (root, production, development)").unwrap();
+    writeln!(out, "pub const BUILTIN_KEYS: [Option<KeyEntry>; KEY_SLOT_COUNT] = [").unwrap();
+    for slot in 0..KEY_SLOT_COUNT {
+        match config.key.iter().find(|key| key_slot(&key.slot) == Some(slot)) {
+            Some(key) => {
+                let public_key = parse_public_key(&key.public_key).unwrap();
+                writeln!(out, "    Some(KeyEntry {{ id: {}, public_key: {:?} }}),", key.id, public_key).unwrap();
+            },
+            None => writeln!(out, "    None,").unwrap(),
+        }
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// First address of RAM").unwrap();
+    writeln!(out, "pub const RAM_START: u32 = 0x{:08X};", memory.ram_origin).unwrap();
+    writeln!(out, "/// First address after RAM").unwrap();
+    writeln!(out, "pub const RAM_END: u32 = 0x{:08X};", memory.ram_origin + memory.ram_length).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Partition table").unwrap();
+    writeln!(out, "pub static PARTITION_TABLE: &[Partition] = &[").unwrap();
+    for partition in &config.partition {
+        let flags: Vec<&str> = partition.flags.iter().filter_map(|flag| partition_flag(flag)).collect();
+        let flags = if flags.is_empty() { String::from("0") } else { flags.join(" | ") };
+        
+        writeln!(out, "    Partition {{").unwrap();
+        writeln!(out, "        name: {:?},", partition.name).unwrap();
+        writeln!(out, "        kind: PartitionKind::{},", partition_kind(&partition.kind).unwrap()).unwrap();
+        writeln!(out, "        start: 0x{:08X},", partition.start).unwrap();
+        writeln!(out, "        end: 0x{:08X},", partition.end).unwrap();
+        writeln!(out, "        flags: {flags},").unwrap();
+        writeln!(out, "        erase_size: {},", partition.erase_size).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    
+    out
+}
+
+/// Generate the linker memory layout
+///
+/// Stage 0 keeps off the mailbox, which must survive a software reset
+/// for the bootloader, and writes the handoff record for it.
+fn generate_memory_x(config: &Config) -> String {
+    let stage0 = find(config, "stage0").unwrap();
+    let memory = &config.memory;
+    let reserved = memory.mailbox_length + memory.handoff_length;
+    
+    format!(
+        "/* Generated by build.rs, do not edit */\n\
+         MEMORY\n\
+         {{\n  \
+           /* Stage 0 partition */\n  \
+           FLASH (rx) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           /* Mailbox between application and bootloader, left alone */\n  \
+           MAILBOX (rw) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           /* Record handed to the bootloader */\n  \
+           HANDOFF (rw) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n  \
+           RAM (rwx) : ORIGIN = 0x{:08X}, LENGTH = 0x{:X}\n\
+         }}\n\
+         \n\
+         PROVIDE(_stack_start = ORIGIN(RAM) + LENGTH(RAM));\n\
+         \n\
+         SECTIONS\n\
+         {{\n  \
+           .boot_handoff (NOLOAD) : ALIGN(4)\n  \
+           {{\n    \
+             KEEP(*(.boot_handoff .boot_handoff.*));\n    \
+             . = ALIGN(4);\n  \
+           }} > HANDOFF\n\
+         }}\n\
+         INSERT AFTER .bss;\n",
+        stage0.start,
+        stage0.end - stage0.start,
+        memory.ram_origin,
+        memory.mailbox_length,
+        memory.ram_origin + memory.mailbox_length,
+        memory.handoff_length,
+        memory.ram_origin + reserved,
+        memory.ram_length - reserved,
+    )
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+//! Modules shared with the bootloader
+//!
+//! Included from the bootloader sources so that stage 0 checks and
+//! installs images with exactly the code the bootloader arms them with.
+//! They only depend on `sha2`, `cortex-m` and the generated `config`.
+
+#[allow(dead_code)]
+#[path = "../../src/bootloader/boot_handoff.rs"]
+pub mod boot_handoff;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/handoff.rs"]
+pub mod handoff;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/image.rs"]
+pub mod image;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/keystore.rs"]
+pub mod keystore;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/nvm.rs"]
+pub mod nvm;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/partition.rs"]
+pub mod partition;
+#[allow(dead_code)]
+#[path = "../../src/bootloader/self_update.rs"]
+pub mod self_update;
\ No newline at end of file
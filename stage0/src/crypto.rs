@@ -0,0 +1,8 @@
+//! Signature verification shared with the bootloader
+
+#[allow(dead_code)]
+#[path = "../../src/crypto/der.rs"]
+pub mod der;
+#[allow(dead_code)]
+#[path = "../../src/crypto/ecdsa.rs"]
+pub mod ecdsa;
\ No newline at end of file
@@ -0,0 +1,5 @@
+//! Drivers shared with the bootloader
+
+#[allow(dead_code)]
+#[path = "../../src/drivers/eeprom.rs"]
+pub mod eeprom;
\ No newline at end of file
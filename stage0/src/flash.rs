@@ -0,0 +1,121 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::bootloader::partition::{self, PartitionKind};
+use crate::bootloader::self_update::{UpdateError, UpdateFlash};
+use crate::watchdog;
+
+/// FTFC registers
+const FTFC_FSTAT: u32 = 0x4002_0000;
+const FTFC_FCCOB3: u32 = 0x4002_0004;
+const FTFC_FCCOB2: u32 = 0x4002_0005;
+const FTFC_FCCOB1: u32 = 0x4002_0006;
+const FTFC_FCCOB0: u32 = 0x4002_0007;
+
+/// FCCOB4-FCCOBB in data byte order (each 32-bit group is big-endian)
+const FTFC_FCCOB_DATA: [u32; 8] = [
+    0x4002_000B, 0x4002_000A, 0x4002_0009, 0x4002_0008,
+    0x4002_000F, 0x4002_000E, 0x4002_000D, 0x4002_000C,
+];
+
+// FTFC_FSTAT bits
+const FSTAT_CCIF: u8 = 0x80;
+const FSTAT_RDCOLERR: u8 = 0x40;
+const FSTAT_ACCERR: u8 = 0x20;
+const FSTAT_FPVIOL: u8 = 0x10;
+const FSTAT_MGSTAT0: u8 = 0x01;
+
+// FTFC commands
+const FTFC_CMD_PROGRAM_PHRASE: u8 = 0x07;
+const FTFC_CMD_ERASE_SECTOR: u8 = 0x09;
+
+/// Size of a program phrase in bytes
+const PHRASE_SIZE: usize = 8;
+
+/// Program flash controller as far as stage 0 needs it
+///
+/// Only writes the bootloader and staging partitions, on behalf of the
+/// self-update. Stage 0 runs from the same flash block, so a command is
+/// launched and waited for from RAM.
+pub struct Flash;
+
+impl Flash {
+    /// Check that `address..address + length` may be written by the self-update
+    fn is_update_range(address: u32, length: u32) -> bool {
+        partition::find_containing(address, length)
+            .is_some_and(|partition| matches!(partition.kind, PartitionKind::Bootloader | PartitionKind::Staging))
+    }
+    
+    /// Load the command and address into FCCOB0-3
+    fn set_command(command: u8, address: u32) {
+        // Safety: fixed memory-mapped FTFC registers, no command is running
+        unsafe {
+            write_volatile(FTFC_FCCOB0 as *mut u8, command);
+            write_volatile(FTFC_FCCOB1 as *mut u8, (address >> 16) as u8);
+            write_volatile(FTFC_FCCOB2 as *mut u8, (address >> 8) as u8);
+            write_volatile(FTFC_FCCOB3 as *mut u8, address as u8);
+        }
+    }
+}
+
+/// Launch the loaded FTFC command and wait for its completion
+///
+/// Placed in `.data` so it runs from RAM while the flash is busy. Returns
+/// the error bits of FSTAT.
+#[inline(never)]
+#[link_section = ".data.stage0_ramfunc"]
+fn launch_command() -> u8 {
+    // Safety: fixed memory-mapped FTFC register, the command is loaded
+    unsafe {
+        while read_volatile(FTFC_FSTAT as *const u8) & FSTAT_CCIF == 0 {}
+        write_volatile(FTFC_FSTAT as *mut u8, FSTAT_RDCOLERR | FSTAT_ACCERR | FSTAT_FPVIOL);
+        write_volatile(FTFC_FSTAT as *mut u8, FSTAT_CCIF);
+        while read_volatile(FTFC_FSTAT as *const u8) & FSTAT_CCIF == 0 {}
+        
+        read_volatile(FTFC_FSTAT as *const u8) & (FSTAT_RDCOLERR | FSTAT_ACCERR | FSTAT_FPVIOL | FSTAT_MGSTAT0)
+    }
+}
+
+impl UpdateFlash for Flash {
+    fn read(&self, address: u32, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            // Safety: program flash is memory-mapped
+            *byte = unsafe { read_volatile((address + i as u32) as *const u8) };
+        }
+    }
+    
+    fn erase(&mut self, address: u32) -> Result<(), UpdateError> {
+        if !Self::is_update_range(address, 1) {
+            return Err(UpdateError::EraseFailed);
+        }
+        
+        watchdog::refresh();
+        Self::set_command(FTFC_CMD_ERASE_SECTOR, address);
+        
+        match launch_command() {
+            0 => Ok(()),
+            _ => Err(UpdateError::EraseFailed),
+        }
+    }
+    
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), UpdateError> {
+        let aligned = (address as usize).is_multiple_of(PHRASE_SIZE) && data.len().is_multiple_of(PHRASE_SIZE);
+        if !aligned || !Self::is_update_range(address, data.len() as u32) {
+            return Err(UpdateError::ProgramFailed);
+        }
+        
+        watchdog::refresh();
+        
+        for (i, phrase) in data.chunks(PHRASE_SIZE).enumerate() {
+            Self::set_command(FTFC_CMD_PROGRAM_PHRASE, address + (i * PHRASE_SIZE) as u32);
+            for (register, byte) in FTFC_FCCOB_DATA.iter().zip(phrase) {
+                // Safety: fixed memory-mapped FTFC registers
+                unsafe { write_volatile(*register as *mut u8, *byte) };
+            }
+            
+            if launch_command() != 0 {
+                return Err(UpdateError::ProgramFailed);
+            }
+        }
+        
+        Ok(())
+    }
+}
\ No newline at end of file
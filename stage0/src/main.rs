@@ -0,0 +1,122 @@
+//! Stage 0 of the Gridania Telematic bootloader
+//!
+//! Immutable code at the reset vector, programmed once in production and
+//! never written by the bootloader. At every start it:
+//!
+//! 1. finishes a bootloader self-update armed or interrupted in the
+//!    bootloader (`self_update::resume`), as the only code outside the
+//!    partition being rewritten,
+//! 2. checks the bootloader against the signed image header in the last
+//!    256 bytes of its partition (load address, size, security version,
+//!    signature with an image key of the key store and SHA-256 of the
+//!    image),
+//! 3. validates its vector table, writes the handoff record
+//!    (`boot_handoff`) and starts it.
+//!
+//! Nothing is started if a check fails: stage 0 waits for the watchdog,
+//! which resets the device and retries a pending update.
+
+#![no_std]
+#![no_main]
+
+use core::ptr::read_volatile;
+use cortex_m_rt::entry;
+use panic_halt as _;
+
+mod bootloader;
+mod config;
+mod crypto;
+mod drivers;
+mod flash;
+mod watchdog;
+
+use bootloader::boot_handoff::{self, BootHandoff, BootSlot};
+use bootloader::handoff::{self, VectorTable};
+use bootloader::image::{self, ImageHeader, ImagePolicy, SignatureCheck, IMAGE_HEADER_LENGTH};
+use bootloader::keystore::{self, KeyStore};
+use bootloader::partition::{self, Partition, PartitionKind};
+use bootloader::self_update::{self, UpdateFlash};
+use crypto::ecdsa;
+use drivers::eeprom::Eeprom;
+use flash::Flash;
+
+/// RCM system reset status register
+const RCM_SRS: u32 = 0x4007_F008;
+
+#[entry]
+fn main() -> ! {
+    // Sticky until the next reset, passed on for the bootloader's boot decision
+    // Safety: fixed memory-mapped RCM register
+    let reset_status = unsafe { read_volatile(RCM_SRS as *const u32) };
+    
+    watchdog::init();
+    
+    let mut flash = Flash;
+    let mut eeprom = Eeprom::new();
+    eeprom.init();
+    
+    // Keys installed by key updates, the built-in ones before the first
+    let keys = keystore::load(&eeprom, &KeyStore::builtin(config::BUILTIN_KEYS));
+    let verify = |key_id, message: &[u8], signature: &[u8]| verify_signature(&keys, key_id, message, signature);
+    
+    if let Some(layout) = partition::update_layout() {
+        // A failed update is retried at the next start, the checks below
+        // decide whether the bootloader can run meanwhile
+        let _ = self_update::resume(&mut flash, &mut eeprom, &layout, config::BOOTLOADER_SECURITY_VERSION, &verify);
+    }
+    
+    if let Some(bootloader) = partition::find(PartitionKind::Bootloader) {
+        let min_security_version = self_update::min_security_version(&eeprom, config::BOOTLOADER_SECURITY_VERSION);
+        
+        if let Ok(header) = verify_bootloader(&flash, bootloader, min_security_version, &verify) {
+            // Safety: the partition is memory-mapped flash
+            let table = unsafe { VectorTable::read(bootloader.start) };
+            
+            if handoff::validate_vector_table(&table, bootloader.start, bootloader, config::RAM_START, config::RAM_END).is_ok() {
+                boot_handoff::write(&BootHandoff {
+                    slot: BootSlot::Primary,
+                    reset_status,
+                    security_version: header.security_version,
+                    measurement: header.image_hash,
+                });
+                
+                // Safety: the image and its vector table were verified, stage 0
+                // leaves no peripheral but the watchdog configured
+                unsafe { handoff::jump_to_application(bootloader.start) }
+            }
+        }
+    }
+    
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Check the bootloader against the image header at the end of its partition
+fn verify_bootloader(
+    flash: &Flash,
+    bootloader: &Partition,
+    min_security_version: u32,
+    verify_signature: &SignatureCheck<'_>,
+) -> Result<ImageHeader, image::ImageError> {
+    let header_start = bootloader.end - IMAGE_HEADER_LENGTH as u32;
+    
+    let mut data = [0u8; IMAGE_HEADER_LENGTH];
+    flash.read(header_start, &mut data);
+    let header = ImageHeader::parse(&data)?;
+    
+    let policy = ImagePolicy {
+        load_address: bootloader.start,
+        max_size: header_start - bootloader.start,
+        min_security_version,
+    };
+    image::verify(&header, bootloader.start, &policy, &|address, buffer| flash.read(address, buffer), verify_signature)?;
+    
+    Ok(header)
+}
+
+/// Verify a signature over `message` with the image key `key_id`
+fn verify_signature(keys: &KeyStore, key_id: u8, message: &[u8], signature: &[u8]) -> bool {
+    keys.image_key(key_id)
+        .is_ok_and(|public_key| ecdsa::verify_raw(public_key, message, signature).is_ok())
+}
\ No newline at end of file
@@ -0,0 +1,41 @@
+use core::ptr::write_volatile;
+
+/// WDOG registers
+const WDOG_CS: u32 = 0x4005_2000;
+const WDOG_CNT: u32 = 0x4005_2004;
+const WDOG_TOVAL: u32 = 0x4005_2008;
+
+/// Unlock and refresh sequences (32-bit command mode)
+const WDOG_UNLOCK: u32 = 0xD928_C520;
+const WDOG_REFRESH: u32 = 0xB480_A602;
+
+// WDOG_CS bits
+const CS_UPDATE: u32 = 1 << 5;
+const CS_EN: u32 = 1 << 7;
+const CS_CLK_LPO: u32 = 1 << 8;
+const CS_PRES: u32 = 1 << 12;
+const CS_CMD32EN: u32 = 1 << 13;
+
+/// Timeout in LPO cycles divided by 256 (128 kHz / 256 = 500 Hz, 2 s)
+const TIMEOUT: u32 = 1000;
+
+/// Extend the watchdog timeout for stage 0
+///
+/// The watchdog runs out of reset with a timeout of a few milliseconds,
+/// too short for a signature check or a self-update. It stays enabled
+/// and updatable, so the bootloader configures it again.
+pub fn init() {
+    // Safety: fixed memory-mapped WDOG registers, the unlock sequence is
+    // followed by the reconfiguration within the unlock window
+    unsafe {
+        write_volatile(WDOG_CNT as *mut u32, WDOG_UNLOCK);
+        write_volatile(WDOG_TOVAL as *mut u32, TIMEOUT);
+        write_volatile(WDOG_CS as *mut u32, CS_CMD32EN | CS_PRES | CS_CLK_LPO | CS_EN | CS_UPDATE);
+    }
+}
+
+/// Refresh the watchdog
+pub fn refresh() {
+    // Safety: fixed memory-mapped WDOG register
+    unsafe { write_volatile(WDOG_CNT as *mut u32, WDOG_REFRESH) };
+}
\ No newline at end of file
@@ -0,0 +1,318 @@
+//! Attestation report verifier for the Gridania Telematic bootloader
+//!
+//! Checks the reports returned by the attestation routine (RoutineControl
+//! 0xF020) against the measurements read with routine 0xF021, the way the
+//! fleet backend does. Its tests (`cargo test`) run the measured boot with
+//! the bootloader's own measurement and CMAC code.
+//!
+//! ```text
+//! attest verify <key.hex> <nonce.hex> <report.hex> [<measurement.hex>...]
+//! ```
+//!
+//! `key.hex` holds the 16 byte device attestation key (CSEc `KEY_2`),
+//! `nonce.hex` the 16 byte nonce sent with the request. `report.hex` and
+//! `measurement.hex` are the routine status records as hex, the
+//! measurements in the order of their index.
+
+// Shared with the bootloader so the tests run the target code
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/measurement.rs"]
+mod measurement;
+mod crypto;
+
+use std::process::ExitCode;
+use std::{env, fs};
+
+use cmac::{Cmac, Mac};
+use measurement::{
+    AttestationReport, Measurement, ATTESTATION_MAC_LENGTH, ATTESTATION_NONCE_LENGTH, MEASUREMENT_LENGTH, REPORT_LENGTH,
+};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    
+    let result = match (args.first().map(|s| s.as_str()), args.len()) {
+        (Some("verify"), 4..) => run_verify(&args[1], &args[2], &args[3], &args[4..]),
+        _ => Err(String::from("usage: attest verify <key.hex> <nonce.hex> <report.hex> [<measurement.hex>...]")),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decode a hex argument, or the content of a file if it names one
+fn decode<const N: usize>(argument: &str, what: &str) -> Result<[u8; N], String> {
+    let text = fs::read_to_string(argument).unwrap_or_else(|_| argument.to_string());
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    
+    if text.len() != N * 2 || !text.is_ascii() {
+        return Err(format!("{what} must be {N} bytes as hex"));
+    }
+    
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| format!("{what} is not hex"))?;
+    }
+    
+    Ok(bytes)
+}
+
+/// Verifier side of the report MAC, independent of the bootloader's CMAC
+fn verify_mac(key: &[u8; 16]) -> impl Fn(&[u8], &[u8; ATTESTATION_MAC_LENGTH]) -> bool + '_ {
+    move |message, mac| {
+        let mut cmac = <Cmac<aes::Aes128> as Mac>::new_from_slice(key).unwrap();
+        cmac.update(message);
+        cmac.verify_slice(mac).is_ok()
+    }
+}
+
+fn run_verify(key: &str, nonce: &str, report: &str, measurements: &[String]) -> Result<(), String> {
+    let key = decode::<16>(key, "key")?;
+    let nonce = decode::<ATTESTATION_NONCE_LENGTH>(nonce, "nonce")?;
+    let report = AttestationReport::parse(&decode::<REPORT_LENGTH>(report, "report")?);
+    
+    let mut entries = Vec::new();
+    for (index, measurement) in measurements.iter().enumerate() {
+        let data = decode::<MEASUREMENT_LENGTH>(measurement, "measurement")?;
+        entries.push(Measurement::parse(&data).ok_or(format!("measurement {index} has an unknown stage"))?);
+    }
+    
+    report
+        .verify(&entries, &nonce, &verify_mac(&key))
+        .map_err(|error| format!("report rejected: {error:?}"))?;
+    
+    println!("report valid, chain {}", hex(&report.chain));
+    for (index, entry) in entries.iter().enumerate() {
+        println!(
+            "  {index}: {:?} security version {} sha256 {}",
+            entry.stage,
+            entry.security_version,
+            hex(&entry.digest)
+        );
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::aes::SoftwareAes128;
+    use crypto::cmac::MacGenerator;
+    use measurement::{
+        MeasuredStage, MeasurementError, MeasurementLog, LOG_RECORD_LENGTH, MAX_MEASUREMENTS,
+    };
+    
+    /// Largest UDS response of the bootloader
+    const MAX_RESPONSE_LENGTH: usize = 64;
+    
+    /// Positive RoutineControl response header (SID, subfunction, identifier)
+    const ROUTINE_RESPONSE_HEADER_LENGTH: usize = 4;
+    
+    // Routine responses fit a UDS response
+    const _: () = assert!(ROUTINE_RESPONSE_HEADER_LENGTH + REPORT_LENGTH <= MAX_RESPONSE_LENGTH);
+    const _: () =
+        assert!(ROUTINE_RESPONSE_HEADER_LENGTH + MEASUREMENT_LENGTH <= MAX_RESPONSE_LENGTH);
+    
+    /// Device attestation key
+    const KEY: [u8; 16] = [0x5A; 16];
+    
+    const NONCE: [u8; ATTESTATION_NONCE_LENGTH] = [0x33; ATTESTATION_NONCE_LENGTH];
+    
+    /// Measurement with a digest derived from `seed`
+    fn measurement(stage: MeasuredStage, security_version: u32, seed: u8) -> Measurement {
+        Measurement {
+            stage,
+            security_version,
+            digest: core::array::from_fn(|i| seed.wrapping_add(i as u8)),
+        }
+    }
+    
+    fn bootloader() -> Measurement {
+        measurement(MeasuredStage::Bootloader, 2, 0x10)
+    }
+    
+    fn application() -> Measurement {
+        measurement(MeasuredStage::Application, 0, 0x80)
+    }
+    
+    /// Log as built at startup and published to the application
+    fn log() -> MeasurementLog {
+        let mut log = MeasurementLog::new();
+        log.extend(bootloader()).unwrap();
+        log.extend(application()).unwrap();
+        log
+    }
+    
+    /// Report with the bootloader's CMAC
+    fn report() -> AttestationReport {
+        let mut device_key = SoftwareAes128::new(&KEY);
+        AttestationReport::create(&log(), &NONCE, &mut |message| {
+            device_key.generate_mac(message).ok()
+        })
+        .unwrap()
+    }
+    
+    /// AES-CMAC test vectors of RFC 4493 (key 2b7e1516 28aed2a6 abf71588 09cf4f3c)
+    #[test]
+    fn rfc4493_vectors() {
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let message = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a, 0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac,
+            0x45, 0xaf, 0x8e, 0x51, 0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb,
+            0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef, 0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17,
+            0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10,
+        ];
+        let vectors: [(usize, &str); 4] = [
+            (0, "bb1d6929e95937287fa37d129b756746"),
+            (16, "070a16b46b4d4144f79bdd9dd04a287c"),
+            (40, "dfa66747de9ae63030ca32611497c827"),
+            (64, "51f0bebf7e3b9d92fc49741779363cfe"),
+        ];
+        
+        let mut cipher = SoftwareAes128::new(&key);
+        for (length, expected) in vectors {
+            assert_eq!(
+                hex(&cipher.generate_mac(&message[..length]).unwrap()),
+                expected,
+                "{length} bytes"
+            );
+        }
+    }
+    
+    /// Every length around the block boundaries against the reference implementation
+    #[test]
+    fn cmac_matches_reference() {
+        let mut cipher = SoftwareAes128::new(&KEY);
+        let data: Vec<u8> = (0..100u8).map(|i| i.wrapping_mul(37)).collect();
+        for length in 0..=data.len() {
+            let mac = cipher.generate_mac(&data[..length]).unwrap();
+            assert!(verify_mac(&KEY)(&data[..length], &mac), "{length} bytes");
+        }
+    }
+    
+    #[test]
+    fn chain_replays_in_order() {
+        let log = log();
+        assert_eq!(
+            *log.chain(),
+            measurement::replay(&[bootloader(), application()])
+        );
+        assert_ne!(
+            measurement::replay(&[application(), bootloader()]),
+            *log.chain()
+        );
+    }
+    
+    #[test]
+    fn log_record() {
+        let log = log();
+        let record = log.to_record();
+        assert_eq!(MeasurementLog::parse(&record).as_ref(), Some(&log));
+        
+        for offset in 0..LOG_RECORD_LENGTH {
+            let mut corrupted = record;
+            corrupted[offset] ^= 0x01;
+            assert!(
+                MeasurementLog::parse(&corrupted).is_none(),
+                "byte {offset} flipped"
+            );
+        }
+        assert!(
+            MeasurementLog::parse(&[0u8; LOG_RECORD_LENGTH]).is_none(),
+            "empty RAM"
+        );
+    }
+    
+    #[test]
+    fn full_log() {
+        let mut full = MeasurementLog::new();
+        for _ in 0..MAX_MEASUREMENTS {
+            full.extend(application()).unwrap();
+        }
+        assert_eq!(full.extend(application()), Err(MeasurementError::LogFull));
+    }
+    
+    #[test]
+    fn report_accepted() {
+        let report = report();
+        assert_eq!(AttestationReport::parse(&report.to_bytes()), report);
+        assert_eq!(
+            report.verify(&[bootloader(), application()], &NONCE, &verify_mac(&KEY)),
+            Ok(())
+        );
+    }
+    
+    #[test]
+    fn report_rejected() {
+        let report = report();
+        let verify = verify_mac(&KEY);
+        let (bootloader, application) = (bootloader(), application());
+        
+        let rejections: [(&str, Result<(), MeasurementError>, MeasurementError); 6] = [
+            (
+                "other nonce (replayed report)",
+                report.verify(&[bootloader, application], &[0x34; 16], &verify),
+                MeasurementError::InvalidMac,
+            ),
+            (
+                "other key",
+                report.verify(&[bootloader, application], &NONCE, &verify_mac(&[0xA5; 16])),
+                MeasurementError::InvalidMac,
+            ),
+            (
+                "application measurement withheld",
+                report.verify(&[bootloader], &NONCE, &verify),
+                MeasurementError::ChainMismatch,
+            ),
+            (
+                "measurements reordered",
+                report.verify(&[application, bootloader], &NONCE, &verify),
+                MeasurementError::ChainMismatch,
+            ),
+            (
+                "other application",
+                report.verify(
+                    &[bootloader, measurement(MeasuredStage::Application, 0, 0x81)],
+                    &NONCE,
+                    &verify,
+                ),
+                MeasurementError::ChainMismatch,
+            ),
+            (
+                "other security version",
+                report.verify(
+                    &[measurement(MeasuredStage::Bootloader, 1, 0x10), application],
+                    &NONCE,
+                    &verify,
+                ),
+                MeasurementError::ChainMismatch,
+            ),
+        ];
+        for (case, result, expected) in rejections {
+            assert_eq!(result, Err(expected), "{case}");
+        }
+        
+        // Chain replaced without the key
+        let mut forged = report;
+        forged.chain = measurement::replay(&[bootloader]);
+        forged.count = 1;
+        assert_eq!(
+            forged.verify(&[bootloader], &NONCE, &verify),
+            Err(MeasurementError::InvalidMac)
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+//! Secure boot and lifecycle modules of the bootloader
+
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/lifecycle.rs"]
+pub mod lifecycle;
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/nvm.rs"]
+pub mod nvm;
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/secure_boot.rs"]
+pub mod secure_boot;
\ No newline at end of file
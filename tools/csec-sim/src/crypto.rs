@@ -0,0 +1,11 @@
+//! Crypto modules of the bootloader the CSEc driver builds on
+
+#[allow(dead_code)]
+#[path = "../../../src/crypto/aes.rs"]
+pub mod aes;
+#[allow(dead_code)]
+#[path = "../../../src/crypto/cmac.rs"]
+pub mod cmac;
+#[allow(dead_code)]
+#[path = "../../../src/crypto/entropy.rs"]
+pub mod entropy;
\ No newline at end of file
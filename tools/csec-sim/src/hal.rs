@@ -0,0 +1,9 @@
+//! CSEc driver of the bootloader, at its path in the bootloader
+
+#[allow(dead_code)]
+#[path = "../../../src/hal/s32k148/csec.rs"]
+pub mod csec;
+
+pub mod s32k148 {
+    pub use super::csec;
+}
\ No newline at end of file
@@ -0,0 +1,1151 @@
+//! CSEc driver simulation for the Gridania Telematic bootloader
+//!
+//! Generates the key update messages M1-M3 for the secure boot key
+//! routine (RoutineControl 0xF040) and the provisioning key routine
+//! (0xF050) with the M4 and M5 the device must return, and the BOOT_MAC
+//! of a new image for the code secure boot covers.
+//!
+//! Its tests (`cargo test`) run the bootloader's CSEc driver against a
+//! software model of the SHE engine: the key update protocol against the
+//! example of the SHE specification, ECB/CBC and CMAC against reference
+//! implementations over many page lengths, the key protection rules and
+//! secure boot, and the bootloader's secure boot and lifecycle modules on
+//! top of it.
+//!
+//! ```text
+//! csec-sim messages <auth-slot> <auth-key.hex> <slot> <key.hex> <counter> <flags> <uid.hex|wildcard>
+//! csec-sim boot-mac <boot-mac-key.hex> <image.bin> <size> <counter> <uid.hex|wildcard>
+//! ```
+//!
+//! Slots are named `master-ecu-key`, `boot-mac-key`, `boot-mac` or
+//! `key-1` to `key-11`. Flags are a comma separated list of
+//! `write-protection`, `boot-protection`, `debugger-protection`,
+//! `key-usage`, `wildcard` and `verify-only`, or `-` for none. The
+//! BOOT_MAC covers `size` bytes, the image padded with erased flash.
+
+mod bootloader;
+mod crypto;
+mod hal;
+mod she;
+
+use std::process::ExitCode;
+use std::{env, fs};
+
+use hal::s32k148::csec::*;
+use she::KeyUpdate;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    
+    let result = match (args.first().map(|s| s.as_str()), args.len()) {
+        (Some("messages"), 8) => run_messages(&args[1..]),
+        (Some("boot-mac"), 6) => run_boot_mac(&args[1..]),
+        _ => Err(String::from(
+            "usage: csec-sim messages <auth-slot> <auth-key.hex> <slot> <key.hex> <counter> <flags> <uid.hex|wildcard>\n       \
+             csec-sim boot-mac <boot-mac-key.hex> <image.bin> <size> <counter> <uid.hex|wildcard>",
+        )),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decode a hex argument, or the content of a file if it names one
+fn decode<const N: usize>(argument: &str, what: &str) -> Result<[u8; N], String> {
+    let text = fs::read_to_string(argument).unwrap_or_else(|_| argument.to_string());
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    
+    if text.len() != N * 2 || !text.is_ascii() {
+        return Err(format!("{what} must be {N} bytes as hex"));
+    }
+    
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| format!("{what} is not hex"))?;
+    }
+    
+    Ok(bytes)
+}
+
+/// Parse a number, decimal or hex with `0x`
+fn number(argument: &str, what: &str) -> Result<u32, String> {
+    let parsed = match argument.strip_prefix("0x") {
+        Some(digits) => u32::from_str_radix(digits, 16),
+        None => argument.parse(),
+    };
+    parsed.map_err(|_| format!("{what} is not a number"))
+}
+
+/// Key slot by name
+fn slot(argument: &str) -> Result<u8, String> {
+    match argument {
+        "master-ecu-key" => Ok(CSEC_MASTER_ECU_KEY),
+        "boot-mac-key" => Ok(CSEC_BOOT_MAC_KEY),
+        "boot-mac" => Ok(CSEC_BOOT_MAC),
+        _ => match argument.strip_prefix("key-").and_then(|index| index.parse::<u8>().ok()) {
+            Some(index @ 1..=11) => Ok(CSEC_KEY_1 + index - 1),
+            _ => Err(format!("unknown key slot {argument}")),
+        },
+    }
+}
+
+/// Key protection flags by name
+fn flags(argument: &str) -> Result<u8, String> {
+    if argument == "-" {
+        return Ok(0);
+    }
+    
+    argument.split(',').try_fold(0, |flags, name| {
+        let flag = match name {
+            "write-protection" => CSEC_FLAG_WRITE_PROTECTION,
+            "boot-protection" => CSEC_FLAG_BOOT_PROTECTION,
+            "debugger-protection" => CSEC_FLAG_DEBUGGER_PROTECTION,
+            "key-usage" => CSEC_FLAG_KEY_USAGE,
+            "wildcard" => CSEC_FLAG_WILDCARD,
+            "verify-only" => CSEC_FLAG_VERIFY_ONLY,
+            _ => return Err(format!("unknown key flag {name}")),
+        };
+        Ok(flags | flag)
+    })
+}
+
+/// Counter of a key update, 28 bits
+fn counter(argument: &str) -> Result<u32, String> {
+    let counter = number(argument, "counter")?;
+    if counter == 0 || counter >= 1 << 28 {
+        return Err(String::from("counter must be between 1 and 2^28 - 1"));
+    }
+    Ok(counter)
+}
+
+/// Device UID, `wildcard` for UID 0
+fn uid(argument: &str) -> Result<Option<[u8; CSEC_UID_LENGTH]>, String> {
+    if argument == "wildcard" {
+        return Ok(None);
+    }
+    decode(argument, "UID").map(Some)
+}
+
+/// Print the routine request and the proof the device must return
+fn print_update(update: &KeyUpdate, uid: Option<[u8; CSEC_UID_LENGTH]>) {
+    let messages = update.messages();
+    
+    println!("M1       {}", hex(&messages.m1));
+    println!("M2       {}", hex(&messages.m2));
+    println!("M3       {}", hex(&messages.m3));
+    println!("request  {}", hex(&messages.to_bytes()));
+    
+    // With a wildcard the device names itself in M4, so M5 is only known afterwards
+    match uid {
+        Some(uid) => {
+            let proof = update.proof(&uid);
+            println!("M4       {}", hex(&proof.m4));
+            println!("M5       {}", hex(&proof.m5));
+        }
+        None => println!("M4, M5   depend on the UID of the device (wildcard update)"),
+    }
+}
+
+fn run_messages(args: &[String]) -> Result<(), String> {
+    let uid = uid(&args[6])?;
+    let update = KeyUpdate {
+        uid: uid.unwrap_or([0; CSEC_UID_LENGTH]),
+        key_id: slot(&args[2])?,
+        auth_id: slot(&args[0])?,
+        auth_key: decode(&args[1], "authorizing key")?,
+        key: decode(&args[3], "key")?,
+        counter: counter(&args[4])?,
+        flags: flags(&args[5])?,
+    };
+    
+    print_update(&update, uid);
+    Ok(())
+}
+
+fn run_boot_mac(args: &[String]) -> Result<(), String> {
+    let boot_mac_key = decode(&args[0], "BOOT_MAC_KEY")?;
+    let mut code = fs::read(&args[1]).map_err(|error| format!("cannot read {}: {error}", args[1]))?;
+    let size = number(&args[2], "size")? as usize;
+    if code.len() > size {
+        return Err(format!("image of {} bytes larger than the secure boot size", code.len()));
+    }
+    code.resize(size, 0xFF);
+    
+    let boot_mac = she::boot_mac(&boot_mac_key, &code);
+    let uid = uid(&args[4])?;
+    let update = KeyUpdate {
+        uid: uid.unwrap_or([0; CSEC_UID_LENGTH]),
+        key_id: CSEC_BOOT_MAC,
+        auth_id: CSEC_BOOT_MAC_KEY,
+        auth_key: boot_mac_key,
+        key: boot_mac,
+        counter: counter(&args[3])?,
+        flags: 0,
+    };
+    
+    println!("BOOT_MAC {}", hex(&boot_mac));
+    print_update(&update, uid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+    use aes::Aes128;
+    use bootloader::lifecycle::{self, DeviceIdentity, LifecycleError, LifecycleState};
+    use bootloader::nvm::{NvStorage, NvmError, RamStorage, NVM_LIFECYCLE_OFFSET};
+    use bootloader::secure_boot::{self, SecureBootError, SecureBootState, SecureBootStatus};
+    use crypto::cmac::MacGenerator;
+    use crypto::entropy::EntropySource;
+    use she::SoftwareShe;
+    
+    /// UID of the example of the SHE specification
+    const EXAMPLE_UID: [u8; CSEC_UID_LENGTH] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+    
+    /// MASTER_ECU_KEY of the example of the SHE specification
+    const EXAMPLE_MASTER_KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    
+    /// User key slots the bootloader does not use
+    const KEY_3: u8 = 0x06;
+    const KEY_4: u8 = 0x07;
+    
+    /// KEY_1 loaded by the example of the SHE specification
+    const EXAMPLE_KEY: [u8; 16] = [
+        0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        0x00,
+    ];
+    
+    /// Data derived from `seed`, different for every length
+    fn pattern(length: usize, seed: u8) -> Vec<u8> {
+        (0..length)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect()
+    }
+    
+    /// Update of a key with the MASTER_ECU_KEY of the example
+    fn update(key_id: u8, key: [u8; 16], counter: u32, flags: u8) -> KeyUpdate {
+        KeyUpdate {
+            uid: EXAMPLE_UID,
+            key_id,
+            auth_id: CSEC_MASTER_ECU_KEY,
+            auth_key: EXAMPLE_MASTER_KEY,
+            key,
+            counter,
+            flags,
+        }
+    }
+    
+    /// Load a key and check the proof the engine returns
+    fn load(csec: &mut Csec<SoftwareShe>, update: &KeyUpdate) -> Result<(), CsecError> {
+        let uid = csec.port().uid();
+        let proof = csec.load_key(&update.messages())?;
+        if proof != update.proof(&uid) {
+            return Err(CsecError::GeneralError);
+        }
+        Ok(())
+    }
+    
+    /// Engine of the example with the MASTER_ECU_KEY loaded and started
+    fn provisioned() -> Csec<SoftwareShe> {
+        let mut csec = Csec::with_port(SoftwareShe::new(EXAMPLE_UID));
+        
+        // The erased MASTER_ECU_KEY authorizes its own first load
+        let master = KeyUpdate {
+            auth_id: CSEC_MASTER_ECU_KEY,
+            auth_key: [0xFF; 16],
+            ..update(CSEC_MASTER_ECU_KEY, EXAMPLE_MASTER_KEY, 1, 0)
+        };
+        load(&mut csec, &master).expect("first MASTER_ECU_KEY load");
+        csec.port().reset();
+        
+        csec
+    }
+    
+    /// Key update example of the SHE specification, through the driver
+    #[test]
+    fn she_key_update_example() {
+        let update = update(CSEC_KEY_1, EXAMPLE_KEY, 1, 0);
+        let messages = update.messages();
+        let proof = update.proof(&EXAMPLE_UID);
+        
+        assert_eq!(
+            hex(&she::kdf(
+                &EXAMPLE_MASTER_KEY,
+                &[0x01, 0x01, 0x53, 0x48, 0x45, 0x00, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0xB0]
+            )),
+            "118a46447a770d87828a69c222e2d17e",
+            "KDF of the example"
+        );
+        assert_eq!(
+            hex(&messages.m1),
+            "00000000000000000000000000000141",
+            "M1 of the example"
+        );
+        assert_eq!(
+            hex(&messages.m2),
+            "2b111e2d93f486566bcbba1d7f7a9797c94643b050fc5d4d7de14cff682203c3",
+            "M2 of the example"
+        );
+        assert_eq!(
+            hex(&messages.m3),
+            "b9d745e5ace7d41860bc63c2b9f5bb46",
+            "M3 of the example"
+        );
+        assert_eq!(
+            hex(&proof.m4),
+            "00000000000000000000000000000141b472e8d8727d70d57295e74849a27917",
+            "M4 of the example"
+        );
+        assert_eq!(
+            hex(&proof.m5),
+            "820d8d95dc11b4668878160cb2a4e23e",
+            "M5 of the example"
+        );
+        
+        let mut csec = provisioned();
+        let returned = csec.load_key(&messages).expect("example key update");
+        assert_eq!(returned, proof, "engine returns M4 and M5 of the example");
+        
+        let mut block = [0u8; 16];
+        csec.encrypt_ecb(CSEC_KEY_1, &mut block)
+            .expect("encrypt with KEY_1");
+        let mut reference = [0u8; 16];
+        Aes128::new(&EXAMPLE_KEY.into()).encrypt_block((&mut reference).into());
+        assert_eq!(block, reference, "KEY_1 holds the example key");
+    }
+    
+    /// Key update protocol rejections
+    #[test]
+    fn key_update_rules() {
+        let mut csec = provisioned();
+        let uid = csec.port().uid();
+        let key = [0x11; 16];
+        
+        load(&mut csec, &update(CSEC_KEY_1, key, 5, 0)).expect("KEY_1 update");
+        
+        let cases: [(&str, KeyUpdate, CsecError); 7] = [
+            (
+                "replayed counter",
+                update(CSEC_KEY_1, key, 5, 0),
+                CsecError::KeyUpdateError,
+            ),
+            (
+                "older counter",
+                update(CSEC_KEY_1, key, 4, 0),
+                CsecError::KeyUpdateError,
+            ),
+            (
+                "wrong authorizing key",
+                KeyUpdate {
+                    auth_key: [0x22; 16],
+                    ..update(CSEC_KEY_1, key, 6, 0)
+                },
+                CsecError::KeyUpdateError,
+            ),
+            (
+                "other device",
+                KeyUpdate {
+                    uid: [0x55; CSEC_UID_LENGTH],
+                    ..update(CSEC_KEY_1, key, 6, 0)
+                },
+                CsecError::KeyUpdateError,
+            ),
+            (
+                "wildcard UID without the wildcard flag",
+                KeyUpdate {
+                    uid: [0; CSEC_UID_LENGTH],
+                    ..update(CSEC_KEY_1, key, 6, 0)
+                },
+                CsecError::KeyUpdateError,
+            ),
+            (
+                "user key authorizing another key",
+                KeyUpdate {
+                    auth_id: CSEC_KEY_1,
+                    auth_key: key,
+                    ..update(CSEC_KEY_2, key, 1, 0)
+                },
+                CsecError::KeyInvalid,
+            ),
+            (
+                "empty key authorizing another key",
+                KeyUpdate {
+                    auth_id: CSEC_BOOT_MAC_KEY,
+                    ..update(CSEC_BOOT_MAC, key, 1, 0)
+                },
+                CsecError::KeyEmpty,
+            ),
+        ];
+        for (case, update, expected) in cases {
+            assert_eq!(
+                csec.load_key(&update.messages()),
+                Err(expected),
+                "key update rejected: {case}"
+            );
+        }
+        
+        let mut tampered = update(CSEC_KEY_1, key, 6, 0).messages();
+        tampered.m2[20] ^= 0x01;
+        assert_eq!(
+            csec.load_key(&tampered),
+            Err(CsecError::KeyUpdateError),
+            "key update rejected: M2 modified"
+        );
+        
+        // A key updates itself, and a wildcard key accepts UID 0
+        let own = KeyUpdate {
+            auth_id: CSEC_KEY_1,
+            auth_key: key,
+            ..update(CSEC_KEY_1, [0x12; 16], 6, CSEC_FLAG_WILDCARD)
+        };
+        load(&mut csec, &own).expect("key update authorized by the key itself");
+        let wildcard = KeyUpdate {
+            uid: [0; CSEC_UID_LENGTH],
+            ..update(CSEC_KEY_1, [0x13; 16], 7, 0)
+        };
+        let returned = csec
+            .load_key(&wildcard.messages())
+            .expect("wildcard key update");
+        assert_eq!(
+            returned,
+            wildcard.proof(&uid),
+            "wildcard proof names the device"
+        );
+        assert_eq!(
+            csec.load_key(
+                &KeyUpdate {
+                    uid: [0; CSEC_UID_LENGTH],
+                    ..update(CSEC_KEY_1, key, 8, 0)
+                }
+                .messages()
+            ),
+            Err(CsecError::KeyUpdateError),
+            "key update rejected: wildcard flag cleared by the last update"
+        );
+        
+        // Write protection is final
+        load(
+            &mut csec,
+            &update(KEY_3, key, 1, CSEC_FLAG_WRITE_PROTECTION),
+        )
+        .expect("write protected key");
+        assert_eq!(
+            csec.load_key(&update(KEY_3, key, 2, 0).messages()),
+            Err(CsecError::KeyWriteProtected),
+            "key update rejected: write protected"
+        );
+    }
+    
+    /// Reference AES-128 in ECB or CBC mode
+    fn reference_cipher(key: &[u8; 16], iv: Option<&[u8; 16]>, data: &mut [u8], encrypt: bool) {
+        let cipher = Aes128::new(key.into());
+        let mut chain = iv.copied();
+        
+        for block in data.as_chunks_mut::<16>().0 {
+            let input = *block;
+            match (&mut chain, encrypt) {
+                (None, true) => cipher.encrypt_block(block.into()),
+                (None, false) => cipher.decrypt_block(block.into()),
+                (Some(chain), true) => {
+                    for i in 0..16 {
+                        block[i] ^= chain[i];
+                    }
+                    cipher.encrypt_block(block.into());
+                    *chain = *block;
+                }
+                (Some(chain), false) => {
+                    cipher.decrypt_block(block.into());
+                    for i in 0..16 {
+                        block[i] ^= chain[i];
+                    }
+                    *chain = input;
+                }
+            }
+        }
+    }
+    
+    /// ECB and CBC over single and multi-call lengths
+    #[test]
+    fn ciphers() {
+        let mut csec = provisioned();
+        let key = [0x3C; 16];
+        let iv = [0xA7; 16];
+        load(&mut csec, &update(CSEC_KEY_1, key, 1, 0)).expect("cipher key");
+        
+        for blocks in 1..=40 {
+            let plain = pattern(blocks * 16, blocks as u8);
+            
+            for cbc in [false, true] {
+                let iv = cbc.then_some(&iv);
+                let mut expected = plain.clone();
+                reference_cipher(&key, iv, &mut expected, true);
+                
+                let mut data = plain.clone();
+                match iv {
+                    Some(iv) => csec
+                        .encrypt_cbc(CSEC_KEY_1, iv, &mut data)
+                        .expect("CBC encryption"),
+                    None => csec
+                        .encrypt_ecb(CSEC_KEY_1, &mut data)
+                        .expect("ECB encryption"),
+                }
+                assert_eq!(
+                    data,
+                    expected,
+                    "{} encryption of {blocks} blocks",
+                    if cbc { "CBC" } else { "ECB" }
+                );
+                
+                match iv {
+                    Some(iv) => csec
+                        .decrypt_cbc(CSEC_KEY_1, iv, &mut data)
+                        .expect("CBC decryption"),
+                    None => csec
+                        .decrypt_ecb(CSEC_KEY_1, &mut data)
+                        .expect("ECB decryption"),
+                }
+                assert_eq!(
+                    data,
+                    plain,
+                    "{} decryption of {blocks} blocks",
+                    if cbc { "CBC" } else { "ECB" }
+                );
+            }
+        }
+        
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_KEY_1, &mut [0u8; 20]),
+            Err(CsecError::InvalidLength),
+            "partial block rejected"
+        );
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_KEY_1, &mut []),
+            Err(CsecError::InvalidLength),
+            "empty data rejected"
+        );
+        assert_eq!(
+            csec.encrypt_ecb(KEY_4, &mut [0u8; 16]),
+            Err(CsecError::KeyEmpty),
+            "empty key rejected"
+        );
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_MASTER_ECU_KEY, &mut [0u8; 16]),
+            Err(CsecError::KeyInvalid),
+            "MASTER_ECU_KEY not usable for encryption"
+        );
+        
+        // Volatile RAM key, lost at reset
+        let ram_key = [0x5E; 16];
+        csec.load_plain_key(&ram_key).expect("plain RAM key");
+        let mut data = pattern(64, 9);
+        let mut expected = data.clone();
+        reference_cipher(&ram_key, None, &mut expected, true);
+        csec.encrypt_ecb(CSEC_RAM_KEY, &mut data)
+            .expect("encrypt with the RAM key");
+        assert_eq!(data, expected, "RAM key encryption");
+        csec.port().reset();
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_RAM_KEY, &mut data),
+            Err(CsecError::KeyEmpty),
+            "RAM key cleared at reset"
+        );
+    }
+    
+    /// MAC generation and verification over lengths across many pages
+    #[test]
+    fn macs() {
+        let mut csec = provisioned();
+        let key = [0xC3; 16];
+        load(&mut csec, &update(CSEC_KEY_2, key, 1, CSEC_FLAG_KEY_USAGE)).expect("MAC key");
+        load(
+            &mut csec,
+            &update(KEY_3, key, 1, CSEC_FLAG_KEY_USAGE | CSEC_FLAG_VERIFY_ONLY),
+        )
+        .expect("verify key");
+        
+        for length in 0..=300 {
+            let message = pattern(length, 0x40);
+            let expected = she::cmac(&key, &message);
+            
+            let mut mac = [0u8; 16];
+            csec.generate_mac(CSEC_KEY_2, &message, &mut mac)
+                .expect("MAC generation");
+            assert_eq!(mac, expected, "MAC of {length} bytes");
+            assert!(
+                csec.verify_mac(KEY_3, &message, &mac)
+                    .expect("MAC verification"),
+                "MAC of {length} bytes verified"
+            );
+            
+            mac[length % 16] ^= 0x80;
+            assert!(
+                !csec
+                    .verify_mac(CSEC_KEY_2, &message, &mac)
+                    .expect("MAC verification"),
+                "modified MAC of {length} bytes rejected"
+            );
+        }
+        
+        assert_eq!(
+            csec.generate_mac(KEY_3, b"message", &mut [0; 16]),
+            Err(CsecError::KeyInvalid),
+            "verify only key cannot generate"
+        );
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_KEY_2, &mut [0; 16]),
+            Err(CsecError::KeyInvalid),
+            "MAC key cannot encrypt"
+        );
+        assert_eq!(
+            csec.generate_mac(CSEC_KEY_1, b"message", &mut [0; 16]),
+            Err(CsecError::KeyEmpty),
+            "empty MAC key rejected"
+        );
+        
+        // Driver trait used by the bootloader's attestation
+        csec.set_mac_key(CSEC_KEY_2);
+        let mac = MacGenerator::generate_mac(&mut csec, b"attestation").unwrap();
+        assert_eq!(
+            mac,
+            she::cmac(&key, b"attestation"),
+            "MacGenerator of the driver"
+        );
+    }
+    
+    /// Random numbers and device identification
+    #[test]
+    fn random_numbers_and_identity() {
+        let mut csec = provisioned();
+        
+        csec.port().execute((CSEC_CMD_RND as u32) << 24);
+        assert_eq!(
+            csec.port().read_word(4) >> 16,
+            CSEC_ERC_RNG_SEED as u32,
+            "RND before INIT_RNG rejected"
+        );
+        csec.port()
+            .execute(((CSEC_CMD_ENC_ECB as u32) << 24) | ((CSEC_CALL_SEQ_SUBSEQUENT as u32) << 8));
+        assert_eq!(
+            csec.port().read_word(4) >> 16,
+            CSEC_ERC_SEQUENCE_ERROR as u32,
+            "subsequent call without a first call rejected"
+        );
+        
+        let mut first = [0u8; 40];
+        let mut second = [0u8; 40];
+        csec.fill_entropy(&mut first).expect("entropy");
+        csec.fill_entropy(&mut second).expect("entropy");
+        assert!(first != second && first != [0; 40], "random numbers differ");
+        assert!(csec.status() & CSEC_SREG_RND_INIT != 0, "RND_INIT set");
+        
+        let challenge = [0x6D; 16];
+        let id = csec.get_id(&challenge).expect("GET_ID");
+        assert_eq!(id.uid, EXAMPLE_UID, "GET_ID returns the UID");
+        assert_eq!(
+            id.status,
+            csec.status(),
+            "GET_ID returns the status register"
+        );
+        
+        let mut message = challenge.to_vec();
+        message.extend_from_slice(&id.uid);
+        message.push(id.status);
+        assert_eq!(
+            id.mac,
+            she::cmac(&EXAMPLE_MASTER_KEY, &message),
+            "GET_ID MAC with the MASTER_ECU_KEY"
+        );
+        
+        let mut blank = Csec::with_port(SoftwareShe::new(EXAMPLE_UID));
+        assert_eq!(
+            blank.get_id(&challenge).expect("GET_ID").mac,
+            [0; 16],
+            "GET_ID without MASTER_ECU_KEY has no MAC"
+        );
+    }
+    
+    /// Secure boot over the modelled flash and boot protected keys
+    #[test]
+    fn secure_boot() {
+        let image = pattern(4096, 0x77);
+        let boot_key = [0xB0; 16];
+        let attestation_key = [0xA5; 16];
+        
+        for flavor in [BootFlavor::Strict, BootFlavor::Serial, BootFlavor::Parallel] {
+            let mut csec = provisioned();
+            csec.port().program(&image);
+            
+            assert_eq!(
+                csec.boot_ok(),
+                Err(CsecError::NoSecureBoot),
+                "BOOT_OK without secure boot rejected"
+            );
+            load(&mut csec, &update(CSEC_BOOT_MAC_KEY, boot_key, 1, 0)).expect("BOOT_MAC_KEY");
+            load(
+                &mut csec,
+                &update(
+                    CSEC_KEY_2,
+                    attestation_key,
+                    1,
+                    CSEC_FLAG_KEY_USAGE | CSEC_FLAG_BOOT_PROTECTION,
+                ),
+            )
+            .expect("boot protected key");
+            csec.boot_define(image.len() as u32, flavor)
+                .expect("BOOT_DEFINE");
+            assert_eq!(
+                csec.boot_define(1024, BootFlavor::Serial),
+                Err(CsecError::SequenceError),
+                "second BOOT_DEFINE rejected"
+            );
+            
+            // First boot learns BOOT_MAC
+            csec.port().reset();
+            let status = csec.status();
+            assert!(
+                status & CSEC_SREG_SECURE_BOOT != 0
+                    && status & CSEC_SREG_BOOT_FINISHED != 0
+                    && status & CSEC_SREG_BOOT_OK == 0,
+                "first boot learns BOOT_MAC"
+            );
+            assert_eq!(
+                csec.generate_mac(CSEC_KEY_2, b"report", &mut [0; 16]),
+                Err(CsecError::KeyNotAvailable),
+                "boot protected key locked after learning"
+            );
+            
+            // Matching image: BOOT_OK, key available
+            csec.port().reset();
+            assert!(
+                csec.status() & CSEC_SREG_BOOT_OK != 0,
+                "{flavor:?} boot of the unchanged image succeeds"
+            );
+            let mut mac = [0u8; 16];
+            csec.generate_mac(CSEC_KEY_2, b"report", &mut mac)
+                .expect("boot protected key after a successful boot");
+            assert_eq!(
+                mac,
+                she::cmac(&attestation_key, b"report"),
+                "boot protected key MAC"
+            );
+            csec.boot_ok().expect("BOOT_OK");
+            assert!(
+                csec.status() & CSEC_SREG_BOOT_FINISHED != 0,
+                "BOOT_OK finishes the boot"
+            );
+            assert_eq!(
+                csec.boot_ok(),
+                Err(CsecError::SequenceError),
+                "second BOOT_OK rejected"
+            );
+            
+            // The bootloader reports a failed later stage
+            csec.port().reset();
+            csec.boot_failure().expect("BOOT_FAILURE");
+            assert_eq!(
+                csec.generate_mac(CSEC_KEY_2, b"report", &mut [0; 16]),
+                Err(CsecError::KeyNotAvailable),
+                "boot protected key locked after BOOT_FAILURE"
+            );
+            
+            // Modified image
+            let mut modified = image.clone();
+            modified[1000] ^= 0x01;
+            csec.port().program(&modified);
+            csec.port().reset();
+            if flavor == BootFlavor::Strict {
+                assert!(
+                    !csec.port().started(),
+                    "failed strict boot keeps the CPU in reset"
+                );
+                continue;
+            }
+            assert!(
+                csec.port().started(),
+                "failed {flavor:?} boot starts the CPU"
+            );
+            assert!(
+                csec.status() & CSEC_SREG_BOOT_OK == 0,
+                "{flavor:?} boot of a modified image fails"
+            );
+            assert_eq!(
+                csec.generate_mac(CSEC_KEY_2, b"report", &mut [0; 16]),
+                Err(CsecError::KeyNotAvailable),
+                "boot protected key locked after a failed boot"
+            );
+            assert_eq!(
+                csec.boot_ok(),
+                Err(CsecError::SequenceError),
+                "BOOT_OK after a failed boot rejected"
+            );
+            
+            // Bytes past BOOT_SIZE are not covered
+            let mut extended = image.clone();
+            extended.extend_from_slice(&[0xEE; 256]);
+            csec.port().program(&extended);
+            csec.port().reset();
+            assert!(
+                csec.status() & CSEC_SREG_BOOT_OK != 0,
+                "flash past BOOT_SIZE not checked"
+            );
+        }
+        
+        // Debugger protected key
+        let mut csec = provisioned();
+        load(
+            &mut csec,
+            &update(CSEC_KEY_1, [0xD0; 16], 1, CSEC_FLAG_DEBUGGER_PROTECTION),
+        )
+        .expect("debugger protected key");
+        csec.encrypt_ecb(CSEC_KEY_1, &mut [0; 16])
+            .expect("debugger protected key without debugger");
+        csec.port().set_debugger(true);
+        assert!(
+            csec.status() & CSEC_SREG_EXT_DEBUGGER != 0,
+            "debugger reported"
+        );
+        assert_eq!(
+            csec.encrypt_ecb(CSEC_KEY_1, &mut [0; 16]),
+            Err(CsecError::KeyNotAvailable),
+            "debugger protected key locked with a debugger"
+        );
+    }
+    
+    /// The bootloader's secure boot module over the engine
+    #[test]
+    fn secure_boot_module() {
+        let size = 0x2000;
+        let image = pattern(size, 0x21);
+        let boot_key = [0xB1; 16];
+        let flavor = BootFlavor::Serial;
+        
+        let mut csec = provisioned();
+        let uid = csec.port().uid();
+        csec.port().program(&image);
+        
+        let key_update = update(CSEC_BOOT_MAC_KEY, boot_key, 1, 0);
+        assert_eq!(
+            secure_boot::store_key(
+                &mut csec,
+                &update(CSEC_KEY_1, boot_key, 1, 0).messages(),
+                size as u32,
+                flavor
+            ),
+            Err(SecureBootError::WrongKeySlot),
+            "user key rejected by the secure boot key routine"
+        );
+        assert_eq!(
+            secure_boot::store_key(
+                &mut csec,
+                &key_update.messages(),
+                size as u32,
+                BootFlavor::NoBoot
+            ),
+            Err(SecureBootError::Disabled),
+            "secure boot key rejected without secure boot"
+        );
+        assert_eq!(
+            SecureBootStatus::read(&csec, flavor).state,
+            SecureBootState::Disabled,
+            "status before provisioning"
+        );
+        
+        // BOOT_MAC_KEY and BOOT_DEFINE, BOOT_MAC learned at the next reset
+        let proof = secure_boot::store_key(&mut csec, &key_update.messages(), size as u32, flavor)
+            .expect("BOOT_MAC_KEY");
+        assert_eq!(proof, key_update.proof(&uid), "BOOT_MAC_KEY proof");
+        csec.port().reset();
+        assert_eq!(
+            SecureBootStatus::read(&csec, flavor).state,
+            SecureBootState::Failed,
+            "learning boot reported as failed"
+        );
+        secure_boot::finish(&mut csec).expect("finish after learning");
+        
+        csec.port().reset();
+        let status = SecureBootStatus::read(&csec, flavor);
+        assert_eq!(
+            status.state,
+            SecureBootState::Verified,
+            "unchanged code verified"
+        );
+        assert_eq!(
+            status.to_bytes(),
+            [0x01, csec.status(), 0x01],
+            "status record"
+        );
+        secure_boot::finish(&mut csec).expect("finish");
+        assert!(
+            csec.status() & CSEC_SREG_BOOT_FINISHED != 0,
+            "finish closes the boot phase"
+        );
+        secure_boot::finish(&mut csec).expect("second finish");
+        
+        // New code: fails until its BOOT_MAC from the generator is stored
+        let mut modified = image.clone();
+        modified[..16].copy_from_slice(&[0x5A; 16]);
+        csec.port().program(&modified);
+        csec.port().reset();
+        assert_eq!(
+            SecureBootStatus::read(&csec, flavor).state,
+            SecureBootState::Failed,
+            "modified code fails"
+        );
+        
+        let boot_mac = KeyUpdate {
+            auth_id: CSEC_BOOT_MAC_KEY,
+            auth_key: boot_key,
+            ..update(CSEC_BOOT_MAC, she::boot_mac(&boot_key, &modified), 1, 0)
+        };
+        let forged = KeyUpdate {
+            auth_key: [0x99; 16],
+            ..boot_mac
+        };
+        assert_eq!(
+            secure_boot::store_key(&mut csec, &forged.messages(), size as u32, flavor),
+            Err(SecureBootError::KeyRejected),
+            "BOOT_MAC with a wrong BOOT_MAC_KEY rejected"
+        );
+        let proof = secure_boot::store_key(&mut csec, &boot_mac.messages(), size as u32, flavor)
+            .expect("BOOT_MAC");
+        assert_eq!(proof, boot_mac.proof(&uid), "BOOT_MAC proof");
+        csec.port().reset();
+        assert_eq!(
+            SecureBootStatus::read(&csec, flavor).state,
+            SecureBootState::Verified,
+            "new code verified"
+        );
+        
+        // A defined device keeps its flavor when BOOT_MAC_KEY changes
+        let rotated = update(CSEC_BOOT_MAC_KEY, [0xB2; 16], 2, 0);
+        secure_boot::store_key(
+            &mut csec,
+            &rotated.messages(),
+            size as u32,
+            BootFlavor::Parallel,
+        )
+        .expect("BOOT_MAC_KEY rotation");
+    }
+    
+    /// Storage dropping every write, as a worn out EEPROM
+    struct StuckStorage(RamStorage);
+    
+    impl NvStorage for StuckStorage {
+        fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError> {
+            self.0.read(offset, buffer)
+        }
+        
+        fn write(&mut self, _offset: u32, _data: &[u8]) -> Result<(), NvmError> {
+            Ok(())
+        }
+    }
+    
+    fn identity() -> DeviceIdentity {
+        DeviceIdentity {
+            serial_number: *b"GT-2026-00042   ",
+            configuration: [0x5C; 16],
+        }
+    }
+    
+    /// End of line provisioning and the lifecycle transitions
+    #[test]
+    fn provisioning() {
+        let mut storage = RamStorage::new();
+        let mut csec = Csec::with_port(SoftwareShe::new(EXAMPLE_UID));
+        let flavor = BootFlavor::Serial;
+        let image = pattern(0x2000, 0x42);
+        csec.port().program(&image);
+        
+        let provision = |storage: &RamStorage, csec: &mut Csec<SoftwareShe>, update: &KeyUpdate| {
+            let proof = lifecycle::provision_key(
+                storage,
+                csec,
+                &update.messages(),
+                image.len() as u32,
+                flavor,
+            )?;
+            if proof != update.proof(&EXAMPLE_UID) {
+                return Err(LifecycleError::KeyRejected);
+            }
+            Ok(())
+        };
+        
+        assert_eq!(
+            lifecycle::load_state(&storage),
+            LifecycleState::Development,
+            "erased device in development"
+        );
+        assert!(
+            lifecycle::load_identity(&storage).is_none(),
+            "erased device without identity"
+        );
+        
+        // Keys of the end of line: MASTER_ECU_KEY first, then the keys it authorizes
+        let master = KeyUpdate {
+            auth_key: [0xFF; 16],
+            ..update(CSEC_MASTER_ECU_KEY, EXAMPLE_MASTER_KEY, 1, 0)
+        };
+        provision(&storage, &mut csec, &master).expect("MASTER_ECU_KEY");
+        provision(
+            &storage,
+            &mut csec,
+            &update(
+                CSEC_KEY_2,
+                [0xA5; 16],
+                1,
+                CSEC_FLAG_KEY_USAGE | CSEC_FLAG_BOOT_PROTECTION,
+            ),
+        )
+        .expect("attestation key");
+        provision(
+            &storage,
+            &mut csec,
+            &update(CSEC_BOOT_MAC_KEY, [0xB3; 16], 1, 0),
+        )
+        .expect("BOOT_MAC_KEY");
+        assert_eq!(
+            provision(
+                &storage,
+                &mut csec,
+                &KeyUpdate {
+                    auth_key: [0x99; 16],
+                    ..update(CSEC_KEY_1, [0x11; 16], 1, 0)
+                }
+            ),
+            Err(LifecycleError::KeyRejected),
+            "forged key update rejected"
+        );
+        
+        // BOOT_MAC_KEY configured secure boot: learned at the first reset, verified at the second
+        csec.port().reset();
+        csec.port().reset();
+        assert_eq!(
+            SecureBootStatus::read(&csec, flavor).state,
+            SecureBootState::Verified,
+            "provisioned secure boot verified"
+        );
+        
+        // Development -> production -> locked, with the identity written before locking
+        let identity = identity();
+        assert_eq!(
+            lifecycle::advance(&mut storage, LifecycleState::Locked),
+            Err(LifecycleError::InvalidTransition),
+            "development cannot be locked directly"
+        );
+        lifecycle::advance(&mut storage, LifecycleState::Production).expect("production");
+        assert_eq!(
+            lifecycle::advance(&mut storage, LifecycleState::Production),
+            Err(LifecycleError::InvalidTransition),
+            "production entered once"
+        );
+        assert_eq!(
+            lifecycle::advance(&mut storage, LifecycleState::Locked),
+            Err(LifecycleError::NotPersonalized),
+            "locking needs the identity"
+        );
+        lifecycle::write_identity(
+            &mut storage,
+            &DeviceIdentity {
+                configuration: [0; 16],
+                ..identity
+            },
+        )
+        .expect("identity");
+        lifecycle::write_identity(&mut storage, &identity).expect("identity rewritten");
+        assert_eq!(
+            lifecycle::load_identity(&storage),
+            Some(identity),
+            "identity stored"
+        );
+        lifecycle::advance(&mut storage, LifecycleState::Locked).expect("locked");
+        
+        // Closed for good
+        assert_eq!(
+            lifecycle::load_state(&storage),
+            LifecycleState::Locked,
+            "lifecycle closed"
+        );
+        assert_eq!(
+            provision(&storage, &mut csec, &update(CSEC_KEY_1, [0x11; 16], 1, 0)),
+            Err(LifecycleError::Closed),
+            "key provisioning refused when locked"
+        );
+        assert_eq!(
+            lifecycle::write_identity(
+                &mut storage,
+                &DeviceIdentity {
+                    serial_number: [0x20; 16],
+                    ..identity
+                }
+            ),
+            Err(LifecycleError::Closed),
+            "identity refused when locked"
+        );
+        for target in [
+            LifecycleState::Development,
+            LifecycleState::Production,
+            LifecycleState::Locked,
+        ] {
+            assert_eq!(
+                lifecycle::advance(&mut storage, target),
+                Err(LifecycleError::Closed),
+                "lifecycle cannot be reopened"
+            );
+        }
+        assert_eq!(
+            lifecycle::load_identity(&storage),
+            Some(identity),
+            "identity kept when locked"
+        );
+    }
+    
+    /// Damaged records never reopen provisioning
+    #[test]
+    fn damaged_lifecycle_records() {
+        let cases: [(&str, [u8; 4]); 4] = [
+            ("wrong magic", [0x00, 0x00, 0x00, 0xFF]),
+            ("state and complement differ", [0x4C, 0x43, 0x00, 0xFE]),
+            ("unknown state", [0x4C, 0x43, 0x07, 0xF8]),
+            ("partly erased", [0x4C, 0x43, 0xFF, 0xFF]),
+        ];
+        for (case, record) in cases {
+            let mut damaged = RamStorage::new();
+            damaged.write(NVM_LIFECYCLE_OFFSET, &record).unwrap();
+            assert_eq!(
+                lifecycle::load_state(&damaged),
+                LifecycleState::Locked,
+                "damaged record locked: {case}"
+            );
+        }
+    }
+    
+    /// A write that does not stick is reported
+    #[test]
+    fn lost_lifecycle_writes() {
+        let identity = identity();
+        let mut stuck = StuckStorage(RamStorage::new());
+        assert_eq!(
+            lifecycle::advance(&mut stuck, LifecycleState::Production),
+            Err(LifecycleError::StorageError),
+            "lost lifecycle write reported"
+        );
+        assert_eq!(
+            lifecycle::write_identity(&mut stuck, &identity),
+            Err(LifecycleError::StorageError),
+            "lost identity write reported"
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,657 @@
+//! Software model of the CSEc (SHE) security engine
+//!
+//! Implements the command interface of the bootloader's CSEc driver on
+//! the host: parameter RAM, key slots with counters and protection
+//! flags, the SHE key update protocol (M1-M5), ECB/CBC, CMAC, the PRNG,
+//! GET_ID and secure boot over a modelled program flash.
+//!
+//! The model follows the SHE specification and the CSEc reference
+//! manual where they are explicit. Where they leave a detail open
+//! (which error code a combination returns, when a parallel boot check
+//! finishes) the model makes a plausible choice and documents it; it is
+//! a test double for the driver, not a reference for the silicon, and
+//! only built for the tests. The key update messages and BOOT_MAC
+//! computation are shared with the message generator.
+
+#[cfg(test)]
+use aes::cipher::BlockDecrypt;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+use cmac::{Cmac, Mac};
+
+use crate::hal::s32k148::csec::*;
+
+/// KDF constant deriving the M2 encryption key (SHE KEY_UPDATE_ENC_C)
+const KEY_UPDATE_ENC_C: [u8; 16] = [
+    0x01, 0x01, 0x53, 0x48, 0x45, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xB0,
+];
+
+/// KDF constant deriving the M3 MAC key (SHE KEY_UPDATE_MAC_C)
+const KEY_UPDATE_MAC_C: [u8; 16] = [
+    0x01, 0x02, 0x53, 0x48, 0x45, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xB0,
+];
+
+/// Value an empty key has when it authorizes its own first load
+#[cfg(test)]
+const EMPTY_KEY: [u8; 16] = [0xFF; 16];
+
+/// Highest non-volatile key slot (KEY_1 to KEY_11)
+#[cfg(test)]
+const LAST_KEY: u8 = 0x0E;
+
+/// Parameter RAM words (8 pages)
+#[cfg(test)]
+const PRAM_WORDS: usize = 32;
+
+fn encrypt(key: &[u8; 16], block: &mut [u8; 16]) {
+    Aes128::new(key.into()).encrypt_block(block.into());
+}
+
+#[cfg(test)]
+fn decrypt(key: &[u8; 16], block: &mut [u8; 16]) {
+    Aes128::new(key.into()).decrypt_block(block.into());
+}
+
+/// AES-128 CMAC
+pub fn cmac(key: &[u8; 16], message: &[u8]) -> [u8; 16] {
+    let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key).unwrap();
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// Miyaguchi-Preneel compression of whole blocks (SHE AES-MP)
+fn compress(blocks: &[[u8; 16]]) -> [u8; 16] {
+    let mut state = [0u8; 16];
+    
+    for block in blocks {
+        let mut output = *block;
+        encrypt(&state, &mut output);
+        for i in 0..16 {
+            state[i] ^= output[i] ^ block[i];
+        }
+    }
+    
+    state
+}
+
+/// SHE key derivation, the constants already carry the AES-MP padding
+pub fn kdf(key: &[u8; 16], constant: &[u8; 16]) -> [u8; 16] {
+    compress(&[*key, *constant])
+}
+
+/// BOOT_MAC over the first bytes of the program flash: CMAC(BOOT_MAC_KEY, 0(96) | size in bits(32) | code)
+pub fn boot_mac(key: &[u8; 16], code: &[u8]) -> [u8; 16] {
+    let mut message = vec![0u8; 16];
+    message[12..].copy_from_slice(&(code.len() as u32 * 8).to_be_bytes());
+    message.extend_from_slice(code);
+    cmac(key, &message)
+}
+
+/// Inputs of a key update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUpdate {
+    /// Device UID, all zero for a wildcard update
+    pub uid: [u8; CSEC_UID_LENGTH],
+    /// Key slot to update
+    pub key_id: u8,
+    /// Key slot authorizing the update
+    pub auth_id: u8,
+    /// Value of the authorizing key
+    pub auth_key: [u8; 16],
+    /// New key value
+    pub key: [u8; 16],
+    /// New counter, above the stored one
+    pub counter: u32,
+    /// New `CSEC_FLAG_*` bits
+    pub flags: u8,
+}
+
+impl KeyUpdate {
+    /// M1 = UID | ID | AuthID
+    fn m1(&self) -> [u8; 16] {
+        let mut m1 = [0u8; 16];
+        m1[..CSEC_UID_LENGTH].copy_from_slice(&self.uid);
+        m1[15] = (self.key_id << 4) | (self.auth_id & 0x0F);
+        m1
+    }
+    
+    /// Messages M1-M3 sent to the engine
+    pub fn messages(&self) -> KeyUpdateMessages {
+        let m1 = self.m1();
+        
+        // M2 = ENC_CBC(K1, IV = 0, counter(28) | flags(6) | 0(94) | key)
+        let k1 = kdf(&self.auth_key, &KEY_UPDATE_ENC_C);
+        let mut first = [0u8; 16];
+        let header = (self.counter << 4) | (self.flags >> 2) as u32;
+        first[..4].copy_from_slice(&header.to_be_bytes());
+        first[4] = (self.flags & 0x03) << 6;
+        encrypt(&k1, &mut first);
+        let mut second = self.key;
+        for i in 0..16 {
+            second[i] ^= first[i];
+        }
+        encrypt(&k1, &mut second);
+        
+        let mut m2 = [0u8; 32];
+        m2[..16].copy_from_slice(&first);
+        m2[16..].copy_from_slice(&second);
+        
+        // M3 = CMAC(K2, M1 | M2)
+        let k2 = kdf(&self.auth_key, &KEY_UPDATE_MAC_C);
+        let mut message = [0u8; 48];
+        message[..16].copy_from_slice(&m1);
+        message[16..].copy_from_slice(&m2);
+        
+        KeyUpdateMessages { m1, m2, m3: cmac(&k2, &message) }
+    }
+    
+    /// Messages M4-M5 the engine returns once it stored the key
+    pub fn proof(&self, uid: &[u8; CSEC_UID_LENGTH]) -> KeyUpdateProof {
+        let mut m1 = self.m1();
+        m1[..CSEC_UID_LENGTH].copy_from_slice(uid);
+        proof(&m1, &self.key, self.counter)
+    }
+}
+
+/// M4 = M1 with the device UID | ENC_ECB(K3, counter(28) | 1 | 0(99)), M5 = CMAC(K4, M4)
+fn proof(m1: &[u8; 16], key: &[u8; 16], counter: u32) -> KeyUpdateProof {
+    let mut block = [0u8; 16];
+    block[..4].copy_from_slice(&((counter << 4) | 0x08).to_be_bytes());
+    encrypt(&kdf(key, &KEY_UPDATE_ENC_C), &mut block);
+    
+    let mut m4 = [0u8; 32];
+    m4[..16].copy_from_slice(m1);
+    m4[16..].copy_from_slice(&block);
+    
+    KeyUpdateProof { m4, m5: cmac(&kdf(key, &KEY_UPDATE_MAC_C), &m4) }
+}
+
+/// Non-volatile key slot
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+struct KeySlot {
+    value: [u8; 16],
+    counter: u32,
+    flags: u8,
+    empty: bool,
+}
+
+#[cfg(test)]
+impl KeySlot {
+    const EMPTY: Self = Self { value: EMPTY_KEY, counter: 0, flags: 0, empty: true };
+}
+
+/// Use of a key by a command
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyUse {
+    Cipher,
+    GenerateMac,
+    VerifyMac,
+}
+
+/// Command split over several calls
+#[cfg(test)]
+#[derive(Debug, Clone)]
+enum Sequence {
+    Cipher { command: u8, key: [u8; 16], chain: [u8; 16], blocks: usize },
+    Mac { command: u8, key: [u8; 16], pages: usize, message_length: usize, mac_length: usize, data: Vec<u8> },
+}
+
+/// Modelled CSEc engine
+///
+/// Secure boot runs at [`SoftwareShe::reset`]: with BOOT_DEFINE issued
+/// and BOOT_MAC_KEY loaded, BOOT_MAC = CMAC(BOOT_MAC_KEY, 0(96) | size
+/// in bits(32) | flash[..size]). An empty BOOT_MAC is learned and the
+/// boot finishes without BOOT_OK. A match sets BOOT_OK and leaves the
+/// boot open for BOOT_OK/BOOT_FAILURE of the bootloader, a mismatch
+/// finishes the boot without BOOT_OK. The model completes a parallel
+/// check before the first command and keeps the CPU in reset after a
+/// failed strict boot.
+#[cfg(test)]
+pub struct SoftwareShe {
+    pram: [u32; PRAM_WORDS],
+    uid: [u8; CSEC_UID_LENGTH],
+    keys: [KeySlot; LAST_KEY as usize + 1],
+    ram_key: Option<[u8; 16]>,
+    status: u8,
+    debugger: bool,
+    boot: Option<(u32, BootFlavor)>,
+    flash: Vec<u8>,
+    halted: bool,
+    rng_state: [u8; 16],
+    sequence: Option<Sequence>,
+}
+
+#[cfg(test)]
+impl SoftwareShe {
+    /// Engine with empty key slots and no secure boot
+    pub fn new(uid: [u8; CSEC_UID_LENGTH]) -> Self {
+        Self {
+            pram: [0; PRAM_WORDS],
+            uid,
+            keys: [KeySlot::EMPTY; LAST_KEY as usize + 1],
+            ram_key: None,
+            status: 0,
+            debugger: false,
+            boot: None,
+            flash: Vec::new(),
+            halted: false,
+            rng_state: [0; 16],
+            sequence: None,
+        }
+    }
+    
+    /// Device UID
+    pub fn uid(&self) -> [u8; CSEC_UID_LENGTH] {
+        self.uid
+    }
+    
+    /// Replace the program flash checked by secure boot
+    pub fn program(&mut self, image: &[u8]) {
+        self.flash = image.to_vec();
+    }
+    
+    /// Attach or detach an external debugger
+    pub fn set_debugger(&mut self, attached: bool) {
+        self.debugger = attached;
+    }
+    
+    /// Whether the CPU left reset (false after a failed strict boot)
+    pub fn started(&self) -> bool {
+        !self.halted
+    }
+    
+    /// Power-on reset: clears the volatile state and runs secure boot
+    pub fn reset(&mut self) {
+        self.pram = [0; PRAM_WORDS];
+        self.ram_key = None;
+        self.status = 0;
+        self.halted = false;
+        self.sequence = None;
+        
+        let Some((size, flavor)) = self.boot else { return };
+        let boot_mac_key = self.keys[CSEC_BOOT_MAC_KEY as usize];
+        if flavor == BootFlavor::NoBoot || boot_mac_key.empty {
+            return;
+        }
+        
+        let length = (size as usize).min(self.flash.len());
+        let mac = boot_mac(&boot_mac_key.value, &self.flash[..length]);
+        
+        self.status = CSEC_SREG_SECURE_BOOT | CSEC_SREG_BOOT_INIT;
+        let boot_mac = &mut self.keys[CSEC_BOOT_MAC as usize];
+        if boot_mac.empty {
+            boot_mac.value = mac;
+            boot_mac.empty = false;
+            self.status |= CSEC_SREG_BOOT_FINISHED;
+        } else if boot_mac.value == mac {
+            self.status |= CSEC_SREG_BOOT_OK;
+        } else {
+            self.status |= CSEC_SREG_BOOT_FINISHED;
+            self.halted = flavor == BootFlavor::Strict;
+        }
+    }
+    
+    fn read_page(&self, page: usize) -> [u8; 16] {
+        let mut data = [0u8; 16];
+        for (i, chunk) in data.chunks_mut(4).enumerate() {
+            chunk.copy_from_slice(&self.pram[page * 4 + i].to_be_bytes());
+        }
+        data
+    }
+    
+    fn write_page(&mut self, page: usize, data: &[u8; 16]) {
+        for (i, chunk) in data.chunks(4).enumerate() {
+            self.pram[page * 4 + i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+    }
+    
+    /// Key of a slot for a command, with its usage and protection checks
+    fn key(&self, key_id: u8, usage: KeyUse) -> Result<[u8; 16], u16> {
+        if key_id == CSEC_RAM_KEY {
+            return self.ram_key.ok_or(CSEC_ERC_KEY_EMPTY);
+        }
+        if !(CSEC_KEY_1..=LAST_KEY).contains(&key_id) {
+            return Err(CSEC_ERC_KEY_INVALID);
+        }
+        
+        let slot = self.keys[key_id as usize];
+        if slot.empty {
+            return Err(CSEC_ERC_KEY_EMPTY);
+        }
+        
+        let mac_key = slot.flags & CSEC_FLAG_KEY_USAGE != 0;
+        let verify_only = slot.flags & CSEC_FLAG_VERIFY_ONLY != 0;
+        let allowed = match usage {
+            KeyUse::Cipher => !mac_key,
+            KeyUse::GenerateMac => mac_key && !verify_only,
+            KeyUse::VerifyMac => mac_key,
+        };
+        if !allowed {
+            return Err(CSEC_ERC_KEY_INVALID);
+        }
+        
+        if slot.flags & CSEC_FLAG_BOOT_PROTECTION != 0 && self.status & CSEC_SREG_BOOT_OK == 0 {
+            return Err(CSEC_ERC_KEY_NOT_AVAILABLE);
+        }
+        if slot.flags & CSEC_FLAG_DEBUGGER_PROTECTION != 0 && self.debugger {
+            return Err(CSEC_ERC_KEY_NOT_AVAILABLE);
+        }
+        
+        Ok(slot.value)
+    }
+    
+    fn run(&mut self, command: u8, call_seq: u8, key_id: u8) -> Result<(), u16> {
+        if call_seq == CSEC_CALL_SEQ_SUBSEQUENT {
+            return match self.sequence.take() {
+                Some(sequence) => self.resume(command, sequence),
+                None => Err(CSEC_ERC_SEQUENCE_ERROR),
+            };
+        }
+        
+        // A first call abandons any unfinished sequence
+        self.sequence = None;
+        
+        match command {
+            CSEC_CMD_ENC_ECB | CSEC_CMD_ENC_CBC | CSEC_CMD_DEC_ECB | CSEC_CMD_DEC_CBC => {
+                let key = self.key(key_id, KeyUse::Cipher)?;
+                let blocks = self.pram[(CSEC_PAGE_LENGTH_OFFSET / 4) as usize] as usize;
+                if blocks == 0 {
+                    return Err(CSEC_ERC_GENERAL_ERROR);
+                }
+                
+                let cbc = command == CSEC_CMD_ENC_CBC || command == CSEC_CMD_DEC_CBC;
+                let chain = if cbc { self.read_page(1) } else { [0; 16] };
+                self.cipher(Sequence::Cipher { command, key, chain, blocks }, if cbc { 2 } else { 1 })
+            }
+            CSEC_CMD_GENERATE_MAC | CSEC_CMD_VERIFY_MAC => {
+                let usage = if command == CSEC_CMD_GENERATE_MAC { KeyUse::GenerateMac } else { KeyUse::VerifyMac };
+                let key = self.key(key_id, usage)?;
+                
+                // Whole bytes only, the driver never passes bit lengths
+                let bits = self.pram[(CSEC_MESSAGE_LENGTH_OFFSET / 4) as usize] as usize;
+                if !bits.is_multiple_of(8) {
+                    return Err(CSEC_ERC_GENERAL_ERROR);
+                }
+                let mac_length = if command == CSEC_CMD_VERIFY_MAC {
+                    let mac_bits = self.pram[(CSEC_MAC_LENGTH_OFFSET / 4) as usize] as usize;
+                    if mac_bits == 0 || mac_bits > 128 || !mac_bits.is_multiple_of(8) {
+                        return Err(CSEC_ERC_GENERAL_ERROR);
+                    }
+                    mac_bits / 8
+                } else {
+                    0
+                };
+                
+                let message_length = bits / 8;
+                let pages = message_length.div_ceil(16) + (command == CSEC_CMD_VERIFY_MAC) as usize;
+                self.mac(Sequence::Mac { command, key, pages, message_length, mac_length, data: Vec::new() }, 2)
+            }
+            CSEC_CMD_LOAD_KEY => self.load_key(key_id),
+            CSEC_CMD_LOAD_PLAIN_KEY => {
+                if key_id != CSEC_RAM_KEY {
+                    return Err(CSEC_ERC_KEY_INVALID);
+                }
+                self.ram_key = Some(self.read_page(1));
+                Ok(())
+            }
+            CSEC_CMD_INIT_RNG => {
+                // Seed from the UID and the key material, fixed per device in the model
+                let mut seed = [0u8; 16];
+                seed[..CSEC_UID_LENGTH].copy_from_slice(&self.uid);
+                self.rng_state = cmac(&self.keys[CSEC_MASTER_ECU_KEY as usize].value, &seed);
+                self.status |= CSEC_SREG_RND_INIT;
+                Ok(())
+            }
+            CSEC_CMD_RND => {
+                if self.status & CSEC_SREG_RND_INIT == 0 {
+                    return Err(CSEC_ERC_RNG_SEED);
+                }
+                let mut output = [0u8; 16];
+                encrypt(&self.rng_state, &mut output);
+                self.rng_state = kdf(&self.rng_state, &output);
+                self.write_page(1, &output);
+                Ok(())
+            }
+            CSEC_CMD_GET_ID => {
+                let challenge = self.read_page(1);
+                let mut page = [0u8; 16];
+                page[..CSEC_UID_LENGTH].copy_from_slice(&self.uid);
+                page[CSEC_UID_LENGTH] = self.status();
+                
+                let master = self.keys[CSEC_MASTER_ECU_KEY as usize];
+                let mac = if master.empty {
+                    [0; 16]
+                } else {
+                    let mut message = [0u8; 32];
+                    message[..16].copy_from_slice(&challenge);
+                    message[16..].copy_from_slice(&page);
+                    cmac(&master.value, &message)
+                };
+                
+                self.write_page(2, &page);
+                self.write_page(3, &mac);
+                Ok(())
+            }
+            CSEC_CMD_BOOT_DEFINE => {
+                if self.boot.is_some() {
+                    return Err(CSEC_ERC_SEQUENCE_ERROR);
+                }
+                let size = self.pram[(CSEC_BOOT_SIZE_OFFSET / 4) as usize];
+                let flavor = (self.pram[(CSEC_BOOT_FLAVOR_OFFSET / 4) as usize] & 0xFF) as u8;
+                let flavor = BootFlavor::from_byte(flavor).ok_or(CSEC_ERC_GENERAL_ERROR)?;
+                self.boot = Some((size, flavor));
+                Ok(())
+            }
+            CSEC_CMD_BOOT_OK | CSEC_CMD_BOOT_FAILURE => {
+                if self.status & CSEC_SREG_SECURE_BOOT == 0 {
+                    return Err(CSEC_ERC_NO_SECURE_BOOT);
+                }
+                if self.status & CSEC_SREG_BOOT_INIT == 0 || self.status & CSEC_SREG_BOOT_FINISHED != 0 {
+                    return Err(CSEC_ERC_SEQUENCE_ERROR);
+                }
+                if command == CSEC_CMD_BOOT_FAILURE {
+                    self.status &= !CSEC_SREG_BOOT_OK;
+                }
+                self.status |= CSEC_SREG_BOOT_FINISHED;
+                Ok(())
+            }
+            _ => Err(CSEC_ERC_GENERAL_ERROR),
+        }
+    }
+    
+    /// Subsequent call of a split command, with the command of the first call
+    fn resume(&mut self, command: u8, sequence: Sequence) -> Result<(), u16> {
+        match sequence {
+            Sequence::Cipher { command: first, .. } | Sequence::Mac { command: first, .. } if first != command => {
+                Err(CSEC_ERC_SEQUENCE_ERROR)
+            }
+            Sequence::Cipher { .. } => self.cipher(sequence, 1),
+            Sequence::Mac { .. } => self.mac(sequence, 1),
+        }
+    }
+    
+    /// Process the data pages of an ECB or CBC call in place
+    fn cipher(&mut self, sequence: Sequence, first_page: usize) -> Result<(), u16> {
+        let Sequence::Cipher { command, key, mut chain, mut blocks } = sequence else { unreachable!() };
+        
+        for page in first_page..=CSEC_LAST_DATA_PAGE as usize {
+            if blocks == 0 {
+                break;
+            }
+            
+            let input = self.read_page(page);
+            let mut output = input;
+            match command {
+                CSEC_CMD_ENC_ECB => encrypt(&key, &mut output),
+                CSEC_CMD_DEC_ECB => decrypt(&key, &mut output),
+                CSEC_CMD_ENC_CBC => {
+                    for i in 0..16 {
+                        output[i] ^= chain[i];
+                    }
+                    encrypt(&key, &mut output);
+                    chain = output;
+                }
+                _ => {
+                    decrypt(&key, &mut output);
+                    for i in 0..16 {
+                        output[i] ^= chain[i];
+                    }
+                    chain = input;
+                }
+            }
+            self.write_page(page, &output);
+            blocks -= 1;
+        }
+        
+        if blocks > 0 {
+            self.sequence = Some(Sequence::Cipher { command, key, chain, blocks });
+        }
+        
+        Ok(())
+    }
+    
+    /// Collect the pages of a MAC call and finish once all arrived
+    fn mac(&mut self, sequence: Sequence, first_page: usize) -> Result<(), u16> {
+        let Sequence::Mac { command, key, pages, message_length, mac_length, mut data } = sequence else {
+            unreachable!()
+        };
+        
+        for page in first_page..=CSEC_LAST_DATA_PAGE as usize {
+            if data.len() == pages * 16 {
+                break;
+            }
+            data.extend_from_slice(&self.read_page(page));
+        }
+        
+        if data.len() < pages * 16 {
+            self.sequence = Some(Sequence::Mac { command, key, pages, message_length, mac_length, data });
+            return Ok(());
+        }
+        
+        let mac = cmac(&key, &data[..message_length]);
+        if command == CSEC_CMD_GENERATE_MAC {
+            self.write_page(2, &mac);
+        } else {
+            let expected = &data[message_length.div_ceil(16) * 16..][..mac_length];
+            let status = (expected != &mac[..mac_length]) as u32;
+            self.pram[(CSEC_VERIFICATION_STATUS_OFFSET / 4) as usize] = status << 16;
+        }
+        
+        Ok(())
+    }
+    
+    /// LOAD_KEY: check M1-M3, store the key and return M4-M5
+    fn load_key(&mut self, key_id: u8) -> Result<(), u16> {
+        let m1 = self.read_page(1);
+        let mut m2 = [0u8; 32];
+        m2[..16].copy_from_slice(&self.read_page(2));
+        m2[16..].copy_from_slice(&self.read_page(3));
+        let m3 = self.read_page(4);
+        
+        let id = m1[15] >> 4;
+        let auth_id = m1[15] & 0x0F;
+        if id != key_id || !(CSEC_MASTER_ECU_KEY..=LAST_KEY).contains(&id) {
+            return Err(CSEC_ERC_KEY_INVALID);
+        }
+        
+        let authorized = auth_id == CSEC_MASTER_ECU_KEY
+            || auth_id == id
+            || (id == CSEC_BOOT_MAC && auth_id == CSEC_BOOT_MAC_KEY);
+        if !authorized || auth_id > LAST_KEY {
+            return Err(CSEC_ERC_KEY_INVALID);
+        }
+        
+        let slot = self.keys[id as usize];
+        if !slot.empty && slot.flags & CSEC_FLAG_WRITE_PROTECTION != 0 {
+            return Err(CSEC_ERC_KEY_WRITE_PROTECTED);
+        }
+        
+        // An empty key authorizes only its own first load, with its erased value
+        let auth = self.keys[auth_id as usize];
+        if auth.empty && auth_id != id {
+            return Err(CSEC_ERC_KEY_EMPTY);
+        }
+        if auth.flags & CSEC_FLAG_DEBUGGER_PROTECTION != 0 && self.debugger {
+            return Err(CSEC_ERC_KEY_NOT_AVAILABLE);
+        }
+        
+        let mut message = [0u8; 48];
+        message[..16].copy_from_slice(&m1);
+        message[16..].copy_from_slice(&m2);
+        if cmac(&kdf(&auth.value, &KEY_UPDATE_MAC_C), &message) != m3 {
+            return Err(CSEC_ERC_KEY_UPDATE_ERROR);
+        }
+        
+        let wildcard = m1[..CSEC_UID_LENGTH].iter().all(|&byte| byte == 0)
+            && !slot.empty
+            && slot.flags & CSEC_FLAG_WILDCARD != 0;
+        if m1[..CSEC_UID_LENGTH] != self.uid && !wildcard {
+            return Err(CSEC_ERC_KEY_UPDATE_ERROR);
+        }
+        
+        let k1 = kdf(&auth.value, &KEY_UPDATE_ENC_C);
+        let mut first: [u8; 16] = m2[..16].try_into().unwrap();
+        let mut key: [u8; 16] = m2[16..].try_into().unwrap();
+        decrypt(&k1, &mut key);
+        for i in 0..16 {
+            key[i] ^= m2[i];
+        }
+        decrypt(&k1, &mut first);
+        
+        let header = u32::from_be_bytes([first[0], first[1], first[2], first[3]]);
+        let counter = header >> 4;
+        let flags = (((header & 0x0F) << 2) as u8) | (first[4] >> 6);
+        if !slot.empty && counter <= slot.counter {
+            return Err(CSEC_ERC_KEY_UPDATE_ERROR);
+        }
+        
+        self.keys[id as usize] = KeySlot { value: key, counter, flags, empty: false };
+        
+        // The proof names this device even for a wildcard update
+        let mut named = m1;
+        named[..CSEC_UID_LENGTH].copy_from_slice(&self.uid);
+        let proof = proof(&named, &key, counter);
+        self.write_page(5, proof.m4[..16].try_into().unwrap());
+        self.write_page(6, proof.m4[16..].try_into().unwrap());
+        self.write_page(7, &proof.m5);
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl CsecPort for SoftwareShe {
+    fn write_word(&mut self, offset: u32, value: u32) {
+        self.pram[(offset / 4) as usize] = value;
+    }
+    
+    fn read_word(&self, offset: u32) -> u32 {
+        self.pram[(offset / 4) as usize]
+    }
+    
+    fn execute(&mut self, header: u32) {
+        let command = (header >> 24) as u8;
+        let call_seq = (header >> 8) as u8;
+        let key_id = header as u8;
+        
+        self.pram[0] = header;
+        let erc = if self.halted {
+            CSEC_ERC_GENERAL_ERROR
+        } else {
+            match self.run(command, call_seq, key_id) {
+                Ok(()) => CSEC_ERC_NO_ERROR,
+                Err(erc) => {
+                    self.sequence = None;
+                    erc
+                }
+            }
+        };
+        self.pram[1] = (erc as u32) << 16;
+    }
+    
+    fn status(&self) -> u8 {
+        self.status | if self.debugger { CSEC_SREG_EXT_DEBUGGER } else { 0 }
+    }
+}
\ No newline at end of file
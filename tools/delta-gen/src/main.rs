@@ -0,0 +1,459 @@
+//! Delta patch generator for the Gridania Telematic bootloader
+//!
+//! Produces patches in the format applied by `compression::delta` on the
+//! target and checks every patch by applying it with the bootloader's own
+//! patcher (and heatshrink decoder) before writing it.
+//!
+//! ```text
+//! delta-gen diff <old.bin> <new.bin> <patch.bin> [--compress]
+//! delta-gen apply <old.bin> <patch.bin> <new.bin> [--compressed]
+//! ```
+//!
+//! `--compress` heatshrink-compresses the patch (window 8, lookahead 4) for
+//! download with compressionMethod 0x3 instead of 0x2.
+
+// Shared with the bootloader so the round trip runs the target code
+#[allow(dead_code)]
+#[path = "../../../src/compression/delta.rs"]
+mod delta;
+#[allow(dead_code)]
+#[path = "../../../src/compression/heatshrink.rs"]
+mod heatshrink;
+
+use std::collections::HashMap;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use delta::{DeltaHeader, DeltaPatcher, PatchError};
+use heatshrink::{DecodeError, HeatshrinkDecoder, HEATSHRINK_LOOKAHEAD_BITS, HEATSHRINK_WINDOW_BITS};
+use sha2::{Digest, Sha256};
+
+/// Length of the blocks used to find matches in the old image
+const MATCH_BLOCK: usize = 8;
+
+/// Candidate positions kept per block
+const MAX_CANDIDATES: usize = 32;
+
+/// Score drop after which an approximate match stops growing
+const MAX_SCORE_DROP: i64 = 16;
+
+/// A region of the new image derived from the old image
+#[derive(Clone, Copy)]
+struct Match {
+    new_start: usize,
+    old_start: usize,
+    length: usize,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    
+    let result = match (files.first().map(|s| s.as_str()), files.len()) {
+        (Some("diff"), 4) => run_diff(files[1], files[2], files[3], flag("--compress")),
+        (Some("apply"), 4) => run_apply(files[1], files[2], files[3], flag("--compressed")),
+        _ => Err(String::from(
+            "usage: delta-gen diff <old.bin> <new.bin> <patch.bin> [--compress]\n       \
+             delta-gen apply <old.bin> <patch.bin> <new.bin> [--compressed]",
+        )),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))
+}
+
+fn write(path: &str, data: &[u8]) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| format!("cannot write {path}: {e}"))
+}
+
+fn run_diff(old_path: &str, new_path: &str, patch_path: &str, compress: bool) -> Result<(), String> {
+    let old = read(old_path)?;
+    let new = read(new_path)?;
+    
+    let patch = diff(&old, &new)?;
+    let mut output = patch.clone();
+    if compress {
+        output = heatshrink_encode(&patch);
+    }
+    
+    // Round trip through the bootloader's own decoder before shipping the patch
+    let rebuilt = apply(&old, &output, compress)?;
+    if rebuilt != new {
+        return Err(String::from("round trip produced a different image"));
+    }
+    
+    write(patch_path, &output)?;
+    println!(
+        "{}: {} -> {} bytes, patch {} bytes ({:.1}%)",
+        patch_path,
+        old.len(),
+        new.len(),
+        output.len(),
+        100.0 * output.len() as f64 / new.len().max(1) as f64
+    );
+    
+    Ok(())
+}
+
+fn run_apply(old_path: &str, patch_path: &str, new_path: &str, compressed: bool) -> Result<(), String> {
+    let old = read(old_path)?;
+    let patch = read(patch_path)?;
+    let new = apply(&old, &patch, compressed)?;
+    write(new_path, &new)
+}
+
+/// Apply a patch with the bootloader's patcher
+fn apply(old: &[u8], patch: &[u8], compressed: bool) -> Result<Vec<u8>, String> {
+    let mut patch = patch.to_vec();
+    if compressed {
+        let mut decoder = HeatshrinkDecoder::new();
+        let mut decoded = Vec::new();
+        let mut sink = |chunk: &[u8]| -> Result<(), ()> {
+            decoded.extend_from_slice(chunk);
+            Ok(())
+        };
+        decoder.decode(&patch, &mut sink).map_err(|()| "output failed")?;
+        decoder.finish(&mut sink).map_err(|error| match error {
+            DecodeError::Truncated => "compressed patch truncated",
+            DecodeError::Output(()) => "output failed",
+        })?;
+        patch = decoded;
+    }
+    
+    let mut patcher = DeltaPatcher::new();
+    let mut new = Vec::new();
+    let mut sink = |chunk: &[u8]| -> Result<(), ()> {
+        new.extend_from_slice(chunk);
+        Ok(())
+    };
+    
+    // Feed the patch in CAN-sized pieces like the bootloader receives it
+    for chunk in patch.chunks(61) {
+        patcher.apply(old, chunk, &mut sink).map_err(describe)?;
+    }
+    patcher.finish(&mut sink).map_err(describe)?;
+    
+    Ok(new)
+}
+
+fn describe(error: PatchError<()>) -> String {
+    match error {
+        PatchError::Delta(error) => format!("patch rejected: {error:?}"),
+        PatchError::Output(()) => String::from("output failed"),
+    }
+}
+
+/// Generate a patch turning `old` into `new`
+fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>, String> {
+    let header = DeltaHeader {
+        old_size: u32::try_from(old.len()).map_err(|_| "old image too large")?,
+        new_size: u32::try_from(new.len()).map_err(|_| "new image too large")?,
+        old_hash: Sha256::digest(old).into(),
+        new_hash: Sha256::digest(new).into(),
+    };
+    
+    let mut patch = header.to_bytes().to_vec();
+    let matches = find_matches(old, new);
+    
+    // Records: diff over the previous match, literal bytes up to the next
+    // match, then the jump to the next match's old position
+    let mut previous = Match { new_start: 0, old_start: 0, length: 0 };
+    for index in 0..=matches.len() {
+        let next = matches.get(index);
+        let diff_end = previous.new_start + previous.length;
+        let old_end = previous.old_start + previous.length;
+        let extra_end = next.map_or(new.len(), |m| m.new_start);
+        let seek = next.map_or(0, |m| m.old_start as i64 - old_end as i64);
+        
+        patch.extend_from_slice(&(previous.length as u32).to_le_bytes());
+        patch.extend_from_slice(&((extra_end - diff_end) as u32).to_le_bytes());
+        patch.extend_from_slice(&(seek as i32).to_le_bytes());
+        patch.extend(
+            (0..previous.length).map(|k| new[previous.new_start + k].wrapping_sub(old[previous.old_start + k])),
+        );
+        patch.extend_from_slice(&new[diff_end..extra_end]);
+        
+        if let Some(&next) = next {
+            previous = next;
+        }
+    }
+    
+    Ok(patch)
+}
+
+/// Find approximate matches of `new` regions in `old`, in new image order
+fn find_matches(old: &[u8], new: &[u8]) -> Vec<Match> {
+    let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    if old.len() >= MATCH_BLOCK {
+        for position in 0..=old.len() - MATCH_BLOCK {
+            let candidates = index.entry(&old[position..position + MATCH_BLOCK]).or_default();
+            if candidates.len() < MAX_CANDIDATES {
+                candidates.push(position);
+            }
+        }
+    }
+    
+    let mut matches = Vec::new();
+    let mut displacement: Option<i64> = None;
+    let mut i = 0;
+    
+    while i + MATCH_BLOCK <= new.len() {
+        let mut best: Option<(usize, usize, i64)> = None;
+        
+        // Continue at the same displacement as the previous match, then
+        // try every position with the same leading block
+        let continuation = displacement
+            .map(|d| i as i64 + d)
+            .filter(|&p| p >= 0 && (p as usize) < old.len())
+            .map(|p| p as usize);
+        let candidates = index.get(&new[i..i + MATCH_BLOCK]).map(|c| c.as_slice()).unwrap_or(&[]);
+        
+        for &position in continuation.iter().chain(candidates.iter()) {
+            let (length, score) = extend(old, position, new, i);
+            if best.is_none_or(|(_, _, s)| score > s) {
+                best = Some((position, length, score));
+            }
+        }
+        
+        match best {
+            Some((position, length, score)) if score >= MATCH_BLOCK as i64 => {
+                matches.push(Match { new_start: i, old_start: position, length });
+                displacement = Some(position as i64 - i as i64);
+                i += length;
+            },
+            _ => i += 1,
+        }
+    }
+    
+    matches
+}
+
+/// Grow a match forward while it stays mostly equal
+///
+/// Returns the length with the highest score, where equal bytes count +1
+/// and differing bytes -2, so small changes (e.g. moved addresses) inside
+/// an otherwise equal region become part of the diff block.
+fn extend(old: &[u8], old_start: usize, new: &[u8], new_start: usize) -> (usize, i64) {
+    let mut score = 0i64;
+    let mut best = (0usize, 0i64);
+    let mut length = 0;
+    
+    while old_start + length < old.len() && new_start + length < new.len() {
+        score += if old[old_start + length] == new[new_start + length] { 1 } else { -2 };
+        length += 1;
+        
+        if score > best.1 {
+            best = (length, score);
+        } else if score < best.1 - MAX_SCORE_DROP {
+            break;
+        }
+    }
+    
+    best
+}
+
+/// Heatshrink encoder matching the bootloader decoder parameters
+fn heatshrink_encode(data: &[u8]) -> Vec<u8> {
+    let window = 1usize << HEATSHRINK_WINDOW_BITS;
+    let lookahead = 1usize << HEATSHRINK_LOOKAHEAD_BITS;
+    let mut writer = BitWriter::default();
+    let mut i = 0;
+    
+    while i < data.len() {
+        let mut best = (0usize, 0usize);
+        for offset in 1..=window.min(i) {
+            let mut length = 0;
+            while length < lookahead && i + length < data.len() && data[i + length] == data[i + length - offset] {
+                length += 1;
+            }
+            if length > best.1 {
+                best = (offset, length);
+            }
+        }
+        
+        // A back-reference (13 bits) beats two literals (18 bits)
+        if best.1 >= 2 {
+            writer.push(0, 1);
+            writer.push((best.0 - 1) as u32, HEATSHRINK_WINDOW_BITS);
+            writer.push((best.1 - 1) as u32, HEATSHRINK_LOOKAHEAD_BITS);
+            i += best.1;
+        } else {
+            writer.push(1, 1);
+            writer.push(data[i] as u32, 8);
+            i += 1;
+        }
+    }
+    
+    writer.finish()
+}
+
+/// MSB-first bit writer
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u8,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, bits: u8) {
+        for bit in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> bit) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+    
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.bytes.push(self.current << (8 - self.used));
+        }
+        self.bytes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delta::DELTA_HEADER_LENGTH;
+    
+    /// Deterministic pseudo-random image
+    fn image(seed: u32, length: usize) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+    
+    /// `old` with a few bytes changed, a block inserted and one removed,
+    /// like a rebuilt firmware with moved code
+    fn modified(old: &[u8]) -> Vec<u8> {
+        let mut new = old.to_vec();
+        for position in (100..new.len()).step_by(997) {
+            new[position] ^= 0x5A;
+        }
+        new.splice(old.len() / 3..old.len() / 3, image(7, 300));
+        new.drain(old.len() / 2..old.len() / 2 + 200);
+        new
+    }
+    
+    fn round_trip(old: &[u8], new: &[u8]) {
+        let patch = diff(old, new).unwrap();
+        assert_eq!(apply(old, &patch, false).unwrap(), new);
+        
+        let compressed = heatshrink_encode(&patch);
+        assert_eq!(apply(old, &compressed, true).unwrap(), new);
+    }
+    
+    #[test]
+    fn round_trips() {
+        let old = image(1, 20_000);
+        
+        round_trip(&old, &old);
+        round_trip(&old, &modified(&old));
+        round_trip(&old, &image(2, 5_000));
+        round_trip(&old, &[]);
+        round_trip(&[], &old);
+        round_trip(&old[..MATCH_BLOCK - 1], &old[..3]);
+    }
+    
+    #[test]
+    fn similar_images_give_small_patches() {
+        let old = image(3, 50_000);
+        let patch = heatshrink_encode(&diff(&old, &modified(&old)).unwrap());
+        
+        assert!(patch.len() < old.len() / 5, "patch {} bytes", patch.len());
+    }
+    
+    #[test]
+    fn patch_is_split_independent() {
+        let old = image(4, 10_000);
+        let new = modified(&old);
+        let patch = diff(&old, &new).unwrap();
+        
+        for size in [1, 7, 64, 1023] {
+            let mut patcher = DeltaPatcher::new();
+            let mut output = Vec::new();
+            let mut sink = |chunk: &[u8]| -> Result<(), ()> {
+                output.extend_from_slice(chunk);
+                Ok(())
+            };
+            for chunk in patch.chunks(size) {
+                patcher.apply(&old, chunk, &mut sink).unwrap();
+            }
+            patcher.finish(&mut sink).unwrap();
+            
+            assert_eq!(output, new);
+        }
+    }
+    
+    #[test]
+    fn wrong_old_image() {
+        let old = image(5, 4_000);
+        let new = modified(&old);
+        let patch = diff(&old, &new).unwrap();
+        
+        let mut other = old.clone();
+        other[10] ^= 1;
+        assert_eq!(apply(&other, &patch, false).unwrap_err(), "patch rejected: OldImageMismatch");
+        assert_eq!(apply(&old[..3_999], &patch, false).unwrap_err(), "patch rejected: OldImageMismatch");
+        
+        // A longer installed region is fine, the patch covers its old size only
+        let mut longer = old.clone();
+        longer.extend_from_slice(&[0xFF; 100]);
+        assert_eq!(apply(&longer, &patch, false).unwrap(), new);
+    }
+    
+    #[test]
+    fn damaged_patches() {
+        let old = image(6, 4_000);
+        let new = modified(&old);
+        let patch = diff(&old, &new).unwrap();
+        
+        for length in [0, 40, DELTA_HEADER_LENGTH + 5, patch.len() - 1] {
+            assert_eq!(apply(&old, &patch[..length], false).unwrap_err(), "patch rejected: Truncated");
+        }
+        
+        // A changed data byte passes the patcher but not the image hash
+        let mut damaged = patch.clone();
+        *damaged.last_mut().unwrap() ^= 0x01;
+        assert_eq!(apply(&old, &damaged, false).unwrap_err(), "patch rejected: HashMismatch");
+        
+        let mut damaged = patch.clone();
+        damaged[0] = b'X';
+        assert_eq!(apply(&old, &damaged, false).unwrap_err(), "patch rejected: InvalidHeader");
+        
+        // More output than the header announces
+        let mut damaged = patch.clone();
+        damaged.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0xAA]);
+        assert_eq!(apply(&old, &damaged, false).unwrap_err(), "patch rejected: Corrupt");
+        
+        // Cut inside a heatshrink element or on an element boundary
+        let compressed = heatshrink_encode(&patch);
+        let errors: Vec<String> = (1..=8)
+            .map(|cut| apply(&old, &compressed[..compressed.len() - cut], true).unwrap_err())
+            .collect();
+        assert!(errors.iter().any(|error| error == "compressed patch truncated"));
+        assert!(errors.iter().all(|error| error.contains("truncated") || error.contains("Truncated")));
+    }
+}
@@ -0,0 +1,290 @@
+//! ECDSA P-256 check of the Gridania Telematic bootloader
+//!
+//! Runs the bootloader's signature verification (`crypto::ecdsa`), which
+//! checks signed images and key updates (`verify_raw`) and authentication
+//! certificates and proofs (`verify_der`), against the Wycheproof ECDSA
+//! P-256/SHA-256 vectors: valid signatures, malformed and non-minimal DER
+//! encodings, out of range values and edge cases of the arithmetic.
+//!
+//! ```text
+//! ecdsa-check wycheproof [<vectors.blb>]
+//! ```
+//!
+//! Without an argument the vectors in `data/ecdsa-p256-sha256.blb` are
+//! used (the set the `p256` crate is tested with, blobby format: public
+//! key x, y, message, DER signature, valid flag). Each vector is checked
+//! with `verify_der` and with the `p256` crate as reference. Every valid
+//! signature is also checked in the raw (r || s) form of the image header
+//! with `verify_raw`, and each form must fail with the other function.
+//! Re-encodings DER forbids must fail as well.
+//! Verification runs on a thread with `STACK_SIZE` bytes of stack, a
+//! verification needing more aborts the tool.
+
+mod crypto;
+
+use std::process::ExitCode;
+use std::{env, fs, thread};
+
+use crypto::ecdsa::{self, EcdsaError, P256_PUBLIC_KEY_LENGTH};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+
+/// Wycheproof ECDSA P-256/SHA-256 vectors
+const VECTORS: &[u8] = include_bytes!("../data/ecdsa-p256-sha256.blb");
+
+/// Stack of the verifying thread, well below the RAM of the target
+const STACK_SIZE: usize = 16 * 1024;
+
+/// Length of a P-256 coordinate
+const COORDINATE_LENGTH: usize = 32;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    
+    let result = match (args.first().map(|s| s.as_str()), args.len()) {
+        (Some("wycheproof"), 1) => run_wycheproof(VECTORS.to_vec()),
+        (Some("wycheproof"), 2) => fs::read(&args[1])
+            .map_err(|e| format!("cannot read {}: {e}", args[1]))
+            .and_then(run_wycheproof),
+        _ => Err(String::from("usage: ecdsa-check wycheproof [<vectors.blb>]")),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decode a blobby file: an index of shared blobs, then the blobs
+///
+/// Lengths and references are variable length quantities; the lowest bit
+/// of an entry marks a reference into the index.
+fn decode_blobs(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    fn vlq(data: &[u8], position: &mut usize) -> Result<usize, String> {
+        let mut value = 0usize;
+        for i in 0..4 {
+            let byte = *data.get(*position).ok_or("truncated length")?;
+            *position += 1;
+            
+            value = if i == 0 { (byte & 0x7F) as usize } else { ((value + 1) << 7) + (byte & 0x7F) as usize };
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(String::from("length too long"))
+    }
+    
+    fn take<'a>(data: &'a [u8], position: &mut usize, length: usize) -> Result<&'a [u8], String> {
+        let blob = data.get(*position..*position + length).ok_or("truncated blob")?;
+        *position += length;
+        Ok(blob)
+    }
+    
+    let mut position = 0;
+    let mut index = Vec::new();
+    for _ in 0..vlq(data, &mut position)? {
+        let length = vlq(data, &mut position)?;
+        index.push(take(data, &mut position, length)?);
+    }
+    
+    let mut blobs = Vec::new();
+    while position < data.len() {
+        let entry = vlq(data, &mut position)?;
+        blobs.push(match entry & 1 {
+            1 => *index.get(entry >> 1).ok_or("reference outside the index")?,
+            _ => take(data, &mut position, entry >> 1)?,
+        });
+    }
+    
+    Ok(blobs)
+}
+
+/// Wycheproof test vector
+struct Vector<'a> {
+    public_key: [u8; P256_PUBLIC_KEY_LENGTH],
+    message: &'a [u8],
+    signature: &'a [u8],
+    valid: bool,
+}
+
+/// Coordinate given with leading zeros or without its leading zero bytes
+fn coordinate(data: &[u8]) -> Result<[u8; COORDINATE_LENGTH], String> {
+    let significant = &data[data.len().saturating_sub(COORDINATE_LENGTH)..];
+    if data[..data.len() - significant.len()].iter().any(|&byte| byte != 0) {
+        return Err(format!("coordinate {} too large", hex(data)));
+    }
+    
+    let mut coordinate = [0u8; COORDINATE_LENGTH];
+    coordinate[COORDINATE_LENGTH - significant.len()..].copy_from_slice(significant);
+    Ok(coordinate)
+}
+
+fn parse_vectors(data: &[u8]) -> Result<Vec<Vector<'_>>, String> {
+    let blobs = decode_blobs(data)?;
+    if blobs.len() % 5 != 0 {
+        return Err(format!("{} blobs are not a set of 5 per vector", blobs.len()));
+    }
+    
+    blobs
+        .chunks(5)
+        .map(|row| {
+            let mut public_key = [0x04; P256_PUBLIC_KEY_LENGTH];
+            public_key[1..33].copy_from_slice(&coordinate(row[0])?);
+            public_key[33..].copy_from_slice(&coordinate(row[1])?);
+            
+            let valid = match row[4] {
+                [0] => false,
+                [1] => true,
+                flag => return Err(format!("invalid valid flag {}", hex(flag))),
+            };
+            
+            Ok(Vector { public_key, message: row[2], signature: row[3], valid })
+        })
+        .collect()
+}
+
+/// Verification of the `p256` crate, as in its own Wycheproof test
+fn reference(vector: &Vector) -> bool {
+    let key = VerifyingKey::from_sec1_bytes(&vector.public_key);
+    let signature = Signature::from_der(vector.signature);
+    matches!((key, signature), (Ok(key), Ok(signature)) if key.verify(vector.message, &signature).is_ok())
+}
+
+/// DER INTEGER of a non-negative big-endian number, `redundant_zero` adds a forbidden leading zero
+fn der_integer(value: &[u8], redundant_zero: bool) -> Vec<u8> {
+    let value = &value[value.iter().position(|&byte| byte != 0).unwrap_or(value.len() - 1)..];
+    
+    let mut content = Vec::new();
+    if redundant_zero {
+        content.push(0x00);
+    }
+    if value[0] & 0x80 != 0 {
+        content.push(0x00);
+    }
+    content.extend_from_slice(value);
+    
+    let mut integer = vec![0x02, content.len() as u8];
+    integer.extend_from_slice(&content);
+    integer
+}
+
+/// Encodings of a valid signature that must be rejected
+fn invalid_encodings(r: &[u8], s: &[u8]) -> [(&'static str, Vec<u8>); 4] {
+    let sequence = |content: Vec<u8>, long_form: bool| {
+        let mut der = vec![0x30];
+        if long_form {
+            der.push(0x81);
+        }
+        der.push(content.len() as u8);
+        der.extend_from_slice(&content);
+        der
+    };
+    let content = [der_integer(r, false), der_integer(s, false)].concat();
+    
+    [
+        ("redundant zero in r", sequence([der_integer(r, true), der_integer(s, false)].concat(), false)),
+        ("redundant zero in s", sequence([der_integer(r, false), der_integer(s, true)].concat(), false)),
+        ("long form length", sequence(content.clone(), true)),
+        ("trailing byte", [sequence(content, false), vec![0x00]].concat()),
+    ]
+}
+
+/// Check the other encodings of a valid signature, returning the problems found
+fn check_encodings(vector: &Vector) -> Vec<String> {
+    let raw = Signature::from_der(vector.signature).expect("valid vector").to_bytes();
+    let (r, s) = raw.split_at(COORDINATE_LENGTH);
+    let mut problems = Vec::new();
+    
+    if ecdsa::verify_raw(&vector.public_key, vector.message, &raw).is_err() {
+        problems.push(String::from("raw (r || s) form rejected"));
+    }
+    
+    // Each function only takes its own form
+    if ecdsa::verify_der(&vector.public_key, vector.message, &raw).is_ok() {
+        problems.push(String::from("raw (r || s) form accepted as DER"));
+    }
+    if ecdsa::verify_raw(&vector.public_key, vector.message, vector.signature).is_ok() {
+        problems.push(String::from("DER form accepted as raw (r || s)"));
+    }
+    
+    for (name, encoding) in invalid_encodings(r, s) {
+        if ecdsa::verify_der(&vector.public_key, vector.message, &encoding).is_ok() {
+            problems.push(format!("{name} accepted: {}", hex(&encoding)));
+        }
+    }
+    
+    problems
+}
+
+fn run_wycheproof(data: Vec<u8>) -> Result<(), String> {
+    // On its own thread to bound the stack the verification may use
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || check_vectors(&data))
+        .map_err(|e| format!("cannot start the verification thread: {e}"))?
+        .join()
+        .map_err(|_| String::from("FAILED: verification thread panicked"))?
+}
+
+fn check_vectors(data: &[u8]) -> Result<(), String> {
+    let vectors = parse_vectors(data)?;
+    
+    let mut failures = 0;
+    let mut rejected = [0usize; 3];
+    for (number, vector) in vectors.iter().enumerate() {
+        let result = ecdsa::verify_der(&vector.public_key, vector.message, vector.signature);
+        
+        let mut problems = Vec::new();
+        if result.is_ok() != vector.valid {
+            problems.push(String::from(if vector.valid { "valid signature rejected" } else { "invalid signature accepted" }));
+        }
+        if reference(vector) != vector.valid {
+            problems.push(String::from("reference disagrees with the vector"));
+        }
+        if vector.valid {
+            problems.extend(check_encodings(vector));
+        }
+        
+        if let Err(error) = result {
+            rejected[match error {
+                EcdsaError::InvalidPublicKey => 0,
+                EcdsaError::InvalidSignature => 1,
+                EcdsaError::VerificationFailed => 2,
+            }] += 1;
+        }
+        
+        if !problems.is_empty() {
+            failures += 1;
+            println!(
+                "FAILED vector {number}: {} ({result:?})\n  key {}\n  message {}\n  signature {}",
+                problems.join(", "),
+                hex(&vector.public_key),
+                hex(vector.message),
+                hex(vector.signature)
+            );
+        }
+    }
+    
+    if failures > 0 {
+        return Err(format!("FAILED: {failures} of {} vectors", vectors.len()));
+    }
+    
+    let valid = vectors.iter().filter(|vector| vector.valid).count();
+    println!(
+        "ok  {} Wycheproof vectors ({valid} valid; rejected: {} public key, {} encoding, {} verification) on a {} KiB stack",
+        vectors.len(),
+        rejected[0],
+        rejected[1],
+        rejected[2],
+        STACK_SIZE / 1024
+    );
+    println!("ok  {valid} valid signatures accepted raw, rejected with {} non-DER encodings each", invalid_encodings(&[1], &[1]).len());
+    Ok(())
+}
\ No newline at end of file
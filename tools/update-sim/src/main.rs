@@ -0,0 +1,1102 @@
+//! Bootloader self-update packer and simulator for the Gridania Telematic bootloader
+//!
+//! Packs signed updater images in the format installed by
+//! `bootloader::self_update`. Its tests (`cargo test`) run the whole update
+//! flow on a simulated flash and emulated EEPROM, cutting the power at every
+//! flash and journal operation to check that the update always completes
+//! after a restart.
+//!
+//! ```text
+//! update-sim pack <bootloader.bin> <key.hex> <key-id> <security-version> <updater.bin>
+//! update-sim place <updater.bin> <partition.bin>
+//! update-sim keygen <key.hex>
+//! update-sim pubkey <key.hex>
+//! update-sim key-update <root.hex> <root-id> <sequence> <slot> <key-id> <public-key> <revoke> <update.bin>
+//! ```
+//!
+//! `key.hex` holds the 32 byte P-256 private key as hex, `key-id` the ID of
+//! the key in the key store. `keygen` creates a new private key and
+//! `pubkey` prints the public key for a `[[key]]` of the ECU configuration
+//! or of the `BOOTLOADER_KEYS` key file. Private keys never belong in the
+//! repository. `place` turns an updater image into the
+//! content of the bootloader partition as stage 0 expects it (image, then
+//! the header at the end), for programming a device in production.
+//!
+//! `key-update` signs a key update with the root key for the key update
+//! routine (RoutineControl 0xF030): `slot` is `root`, `production` or
+//! `development`, `key-id` and `public-key` (hex) the new key of the slot
+//! or `none` and `-` to empty it, `revoke` the key IDs to revoke as a
+//! comma separated list or `-`.
+
+// Shared with the bootloader so the tests run the target code
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/image.rs"]
+mod image;
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/keystore.rs"]
+mod keystore;
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/nvm.rs"]
+mod nvm;
+#[allow(dead_code)]
+#[path = "../../../src/bootloader/self_update.rs"]
+mod self_update;
+
+use std::io::Write as _;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use image::{ImageHeader, SignatureAlgorithm, IMAGE_HEADER_LENGTH};
+use keystore::{KeyEntry, KeySlot, KeyUpdate, PUBLIC_KEY_LENGTH};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+
+/// Bootloader partition of the default configuration
+const TARGET_START: u32 = 0x0000_8000;
+const TARGET_END: u32 = 0x0001_0000;
+
+/// Largest bootloader image, the image header takes the end of the partition
+const MAX_IMAGE_SIZE: usize = (TARGET_END - TARGET_START) as usize - IMAGE_HEADER_LENGTH;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    
+    let result = match (args.first().map(|s| s.as_str()), args.len()) {
+        (Some("pack"), 6) => run_pack(&args[1], &args[2], &args[3], &args[4], &args[5]),
+        (Some("place"), 3) => run_place(&args[1], &args[2]),
+        (Some("keygen"), 2) => run_keygen(&args[1]),
+        (Some("pubkey"), 2) => run_pubkey(&args[1]),
+        (Some("key-update"), 9) => run_key_update(&args[1..]),
+        _ => Err(String::from(
+            "usage: update-sim pack <bootloader.bin> <key.hex> <key-id> <security-version> <updater.bin>\n       \
+             update-sim place <updater.bin> <partition.bin>\n       \
+             update-sim keygen <key.hex>\n       \
+             update-sim pubkey <key.hex>\n       \
+             update-sim key-update <root.hex> <root-id> <sequence> <slot> <key-id> <public-key> <revoke> <update.bin>",
+        )),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))
+}
+
+fn write(path: &str, data: &[u8]) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| format!("cannot write {path}: {e}"))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn read_key(path: &str) -> Result<SigningKey, String> {
+    let text = String::from_utf8(read(path)?).map_err(|_| format!("{path} is not hex"))?;
+    let text = text.trim();
+    
+    if text.len() != 64 || !text.is_ascii() {
+        return Err(format!("{path} must hold 32 bytes as hex"));
+    }
+    
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| format!("{path} is not hex"))?;
+    }
+    
+    SigningKey::from_bytes(&bytes.into()).map_err(|_| format!("{path} is not a valid P-256 private key"))
+}
+
+/// Build a signed updater image
+fn pack(image: &[u8], key: &SigningKey, key_id: u8, security_version: u32, load_address: u32) -> Vec<u8> {
+    let mut header = ImageHeader {
+        key_id,
+        algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+        image_size: image.len() as u32,
+        security_version,
+        load_address,
+        image_hash: Sha256::digest(image).into(),
+        signature: [0; 64],
+    };
+    
+    let signature: Signature = key.sign(&header.signed_data());
+    header.signature.copy_from_slice(&signature.to_bytes());
+    
+    let mut updater = header.to_bytes().to_vec();
+    updater.extend_from_slice(image);
+    updater
+}
+
+fn run_pack(image_path: &str, key_path: &str, key_id: &str, version: &str, updater_path: &str) -> Result<(), String> {
+    let image = read(image_path)?;
+    let key = read_key(key_path)?;
+    let key_id = parse_key_id(key_id)?;
+    let security_version: u32 = version.parse().map_err(|_| format!("invalid security version {version}"))?;
+    
+    if image.is_empty() || image.len() > MAX_IMAGE_SIZE {
+        return Err(format!("{image_path} does not fit the bootloader partition"));
+    }
+    
+    let updater = pack(&image, &key, key_id, security_version, TARGET_START);
+    write(updater_path, &updater)?;
+    
+    println!(
+        "{} bytes bootloader, security version {}, {} bytes updater image",
+        image.len(),
+        security_version,
+        updater.len()
+    );
+    Ok(())
+}
+
+fn run_place(updater_path: &str, partition_path: &str) -> Result<(), String> {
+    let updater = read(updater_path)?;
+    
+    let header = updater
+        .get(..IMAGE_HEADER_LENGTH)
+        .and_then(|data| ImageHeader::parse(data.try_into().unwrap()).ok())
+        .ok_or_else(|| format!("{updater_path} is not an updater image"))?;
+    if header.load_address != TARGET_START || updater.len() != IMAGE_HEADER_LENGTH + header.image_size as usize {
+        return Err(format!("{updater_path} does not match the bootloader partition"));
+    }
+    
+    write(partition_path, &installed(&updater))?;
+    println!("{} bytes bootloader partition at 0x{TARGET_START:08X}", TARGET_END - TARGET_START);
+    Ok(())
+}
+
+fn run_keygen(key_path: &str) -> Result<(), String> {
+    let key = SigningKey::random(&mut OsRng);
+    
+    // Never replace an existing key, and keep it private to the user
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(key_path)
+        .map_err(|e| format!("cannot create {key_path}: {e}"))?;
+    writeln!(file, "{}", hex(&key.to_bytes())).map_err(|e| format!("cannot write {key_path}: {e}"))?;
+    
+    println!("{}", hex(key.verifying_key().to_encoded_point(false).as_bytes()));
+    Ok(())
+}
+
+fn run_pubkey(key_path: &str) -> Result<(), String> {
+    let key = read_key(key_path)?;
+    println!("{}", hex(key.verifying_key().to_encoded_point(false).as_bytes()));
+    Ok(())
+}
+
+fn parse_key_id(text: &str) -> Result<u8, String> {
+    text.parse().ok().filter(|id| *id <= keystore::MAX_KEY_ID).ok_or_else(|| format!("invalid key ID {text}"))
+}
+
+/// Build a key update signed with the root key
+fn sign_key_update(
+    root: &SigningKey,
+    root_id: u8,
+    sequence: u32,
+    slot: KeySlot,
+    key: Option<KeyEntry>,
+    revoke: u32,
+) -> KeyUpdate {
+    let mut update = KeyUpdate {
+        slot,
+        key,
+        signer_id: root_id,
+        sequence,
+        revoke,
+        signature: [0; 64],
+    };
+    
+    let signature: Signature = root.sign(&update.signed_data());
+    update.signature.copy_from_slice(&signature.to_bytes());
+    update
+}
+
+fn run_key_update(args: &[String]) -> Result<(), String> {
+    let [root_path, root_id, sequence, slot, key_id, public_key, revoke, update_path] = args else {
+        return Err(String::from("key-update takes 8 arguments"));
+    };
+    
+    let root = read_key(root_path)?;
+    let root_id = parse_key_id(root_id)?;
+    let sequence: u32 = sequence.parse().map_err(|_| format!("invalid sequence {sequence}"))?;
+    let slot = match slot.as_str() {
+        "root" => KeySlot::Root,
+        "production" => KeySlot::Production,
+        "development" => KeySlot::Development,
+        _ => return Err(format!("unknown slot {slot}")),
+    };
+    
+    let key = match (key_id.as_str(), public_key.as_str()) {
+        ("none", "-") => None,
+        (key_id, public_key) => {
+            let bytes: Option<Vec<u8>> = (0..public_key.len())
+                .step_by(2)
+                .map(|i| public_key.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect();
+            let public_key: [u8; PUBLIC_KEY_LENGTH] = bytes
+                .and_then(|bytes| bytes.try_into().ok())
+                .filter(|bytes: &[u8; PUBLIC_KEY_LENGTH]| VerifyingKey::from_sec1_bytes(bytes).is_ok())
+                .ok_or_else(|| String::from("public key must be an uncompressed P-256 key as hex"))?;
+            Some(KeyEntry { id: parse_key_id(key_id)?, public_key })
+        }
+    };
+    
+    let mut revoked = 0u32;
+    if revoke != "-" {
+        for id in revoke.split(',') {
+            revoked |= 1 << parse_key_id(id)?;
+        }
+    }
+    
+    let update = sign_key_update(&root, root_id, sequence, slot, key, revoked);
+    write(update_path, &update.to_bytes())?;
+    
+    println!("key update {sequence} for the {slot:?} slot, revoking 0x{revoked:08X}");
+    Ok(())
+}
+
+/// Content of the bootloader partition after installing `updater`
+fn installed(updater: &[u8]) -> Vec<u8> {
+    let (header, image) = updater.split_at(IMAGE_HEADER_LENGTH);
+    let mut content = image.to_vec();
+    content.resize((TARGET_END - TARGET_START) as usize - IMAGE_HEADER_LENGTH, 0xFF);
+    content.extend_from_slice(header);
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImagePolicy;
+    use keystore::{KeyError, KeyStore};
+    use nvm::{NvStorage, NvmError, RamStorage};
+    use p256::ecdsa::signature::Verifier;
+    use self_update::{UpdateError, UpdateFlash, UpdateLayout, UpdateState};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    
+    /// Staging partition of the default configuration
+    const STAGING_START: u32 = 0x0006_6000;
+    const STAGING_END: u32 = 0x0007_0000;
+    
+    /// P-Flash sector size
+    const SECTOR_SIZE: u32 = 0x1000;
+    
+    /// Security version of the simulated running bootloader
+    const FLOOR: u32 = 1;
+    
+    /// Key IDs of the simulated built-in key store
+    const ROOT_KEY_ID: u8 = 0;
+    const PRODUCTION_KEY_ID: u8 = 1;
+    
+    /// Outcome of a flash or storage operation under the simulated supply
+    enum Supply {
+        /// The operation completes
+        On,
+        /// The power fails during the operation
+        Cut,
+        /// The power failed before, nothing happens
+        Off,
+    }
+    
+    /// Power supply failing after a number of operations
+    struct Power {
+        remaining: Cell<Option<usize>>,
+        failed: Cell<bool>,
+        operations: Cell<usize>,
+    }
+    
+    impl Power {
+        fn new() -> Rc<Self> {
+            Rc::new(Self {
+                remaining: Cell::new(None),
+                failed: Cell::new(false),
+                operations: Cell::new(0),
+            })
+        }
+        
+        /// Fail during the operation after `operations` more completed ones
+        fn cut_after(&self, operations: usize) {
+            self.remaining.set(Some(operations));
+        }
+        
+        /// Power the device up again
+        fn restore(&self) {
+            self.remaining.set(None);
+            self.failed.set(false);
+        }
+        
+        fn consume(&self) -> Supply {
+            if self.failed.get() {
+                return Supply::Off;
+            }
+            
+            self.operations.set(self.operations.get() + 1);
+            match self.remaining.get() {
+                Some(0) => {
+                    self.failed.set(true);
+                    Supply::Cut
+                }
+                Some(n) => {
+                    self.remaining.set(Some(n - 1));
+                    Supply::On
+                }
+                None => Supply::On,
+            }
+        }
+    }
+    
+    /// NOR flash holding the bootloader and staging partitions
+    struct SimFlash {
+        memory: Vec<u8>,
+        power: Rc<Power>,
+    }
+    
+    impl UpdateFlash for SimFlash {
+        fn read(&self, address: u32, buffer: &mut [u8]) {
+            let start = address as usize;
+            buffer.copy_from_slice(&self.memory[start..start + buffer.len()]);
+        }
+        
+        fn erase(&mut self, address: u32) -> Result<(), UpdateError> {
+            let start = address as usize;
+            let sector = &mut self.memory[start..start + SECTOR_SIZE as usize];
+            
+            match self.power.consume() {
+                Supply::On => {
+                    sector.fill(0xFF);
+                    Ok(())
+                }
+                Supply::Cut => {
+                    sector[..SECTOR_SIZE as usize / 2].fill(0xFF);
+                    Err(UpdateError::EraseFailed)
+                }
+                Supply::Off => Err(UpdateError::EraseFailed),
+            }
+        }
+        
+        fn program(&mut self, address: u32, data: &[u8]) -> Result<(), UpdateError> {
+            let start = address as usize;
+            let (length, result) = match self.power.consume() {
+                Supply::On => (data.len(), Ok(())),
+                Supply::Cut => (data.len() / 2, Err(UpdateError::ProgramFailed)),
+                Supply::Off => (0, Err(UpdateError::ProgramFailed)),
+            };
+            
+            // Programming can only clear bits
+            for (cell, byte) in self.memory[start..start + length].iter_mut().zip(data) {
+                *cell &= byte;
+            }
+            
+            result
+        }
+    }
+    
+    /// Emulated EEPROM holding the journal
+    struct SimStorage {
+        storage: RamStorage,
+        power: Rc<Power>,
+    }
+    
+    impl NvStorage for SimStorage {
+        fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), NvmError> {
+            self.storage.read(offset, buffer)
+        }
+        
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), NvmError> {
+            match self.power.consume() {
+                Supply::On => self.storage.write(offset, data),
+                Supply::Cut => {
+                    let _ = self.storage.write(offset, &data[..data.len() / 2]);
+                    Err(NvmError::WriteError)
+                }
+                Supply::Off => Err(NvmError::WriteError),
+            }
+        }
+    }
+    
+    /// Simulated ECU
+    struct Device {
+        flash: SimFlash,
+        storage: SimStorage,
+        power: Rc<Power>,
+        builtin_keys: KeyStore,
+    }
+    
+    impl Device {
+        /// Device with the bootloader `partition` content and `updater` downloaded to the staging partition
+        fn new(partition: &[u8], updater: &[u8], builtin_keys: KeyStore) -> Self {
+            let power = Power::new();
+            let mut memory = vec![0xFF; STAGING_END as usize];
+            memory[TARGET_START as usize..TARGET_START as usize + partition.len()]
+                .copy_from_slice(partition);
+            memory[STAGING_START as usize..STAGING_START as usize + updater.len()]
+                .copy_from_slice(updater);
+            
+            Self {
+                flash: SimFlash {
+                    memory,
+                    power: power.clone(),
+                },
+                storage: SimStorage {
+                    storage: RamStorage::new(),
+                    power: power.clone(),
+                },
+                power,
+                builtin_keys,
+            }
+        }
+        
+        fn layout() -> UpdateLayout {
+            UpdateLayout {
+                target_start: TARGET_START,
+                target_end: TARGET_END,
+                staging_start: STAGING_START,
+                staging_end: STAGING_END,
+                sector_size: SECTOR_SIZE,
+            }
+        }
+        
+        /// What `verification::verify_image_signature` does with the installed key store
+        fn verify(&self) -> impl Fn(u8, &[u8], &[u8]) -> bool {
+            let keys = keystore::load(&self.storage, &self.builtin_keys);
+            move |key_id: u8, message: &[u8], signature: &[u8]| {
+                keys.image_key(key_id)
+                    .is_ok_and(|public_key| verify_signature(public_key, message, signature))
+            }
+        }
+        
+        /// What the key update routine does
+        fn install_keys(&mut self, update: &KeyUpdate) -> Result<KeyStore, KeyError> {
+            keystore::install(
+                &mut self.storage,
+                &self.builtin_keys,
+                update,
+                &verify_signature,
+            )
+        }
+        
+        /// What the install routine does: verify the staged image and arm the update
+        fn arm(&mut self) -> Result<(), UpdateError> {
+            let min_security_version = self_update::min_security_version(&self.storage, FLOOR);
+            let header = self_update::verify_staged(
+                &self.flash,
+                &Self::layout(),
+                min_security_version,
+                &self.verify(),
+            )?;
+            self_update::arm(&mut self.storage, &header)
+        }
+        
+        /// What stage 0 does first at every start
+        fn start(&mut self) -> Result<Option<u32>, UpdateError> {
+            let verify = self.verify();
+            self_update::resume(
+                &mut self.flash,
+                &mut self.storage,
+                &Self::layout(),
+                FLOOR,
+                &verify,
+            )
+        }
+        
+        fn bootloader(&self) -> &[u8] {
+            &self.flash.memory[TARGET_START as usize..TARGET_END as usize]
+        }
+        
+        /// What stage 0 checks before starting the bootloader, returns its security version
+        fn stage0_check(&self) -> Option<u32> {
+            let header_start = TARGET_END - IMAGE_HEADER_LENGTH as u32;
+            let mut data = [0u8; IMAGE_HEADER_LENGTH];
+            self.flash.read(header_start, &mut data);
+            let header = ImageHeader::parse(&data).ok()?;
+            
+            let policy = ImagePolicy {
+                load_address: TARGET_START,
+                max_size: header_start - TARGET_START,
+                min_security_version: self_update::min_security_version(&self.storage, FLOOR),
+            };
+            let read = |address, buffer: &mut [u8]| self.flash.read(address, buffer);
+            image::verify(&header, TARGET_START, &policy, &read, &self.verify()).ok()?;
+            
+            Some(header.security_version)
+        }
+    }
+    
+    /// ECDSA P-256 verification as done by `crypto::ecdsa`
+    fn verify_signature(
+        public_key: &[u8; PUBLIC_KEY_LENGTH],
+        message: &[u8],
+        signature: &[u8],
+    ) -> bool {
+        let key = VerifyingKey::from_sec1_bytes(public_key);
+        let signature = Signature::from_slice(signature);
+        matches!((key, signature), (Ok(key), Ok(signature)) if key.verify(message, &signature).is_ok())
+    }
+    
+    /// Key store entry of a signing key
+    fn entry(id: u8, key: &SigningKey) -> KeyEntry {
+        let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+        public_key.copy_from_slice(key.verifying_key().to_encoded_point(false).as_bytes());
+        KeyEntry { id, public_key }
+    }
+    
+    /// Deterministic test image
+    fn image(size: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9E37_79B9) | 1;
+        (0..size)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+    
+    /// Signing key of the production slot of the built-in key store
+    fn production_key() -> SigningKey {
+        SigningKey::from_bytes(&[0x42; 32].into()).unwrap()
+    }
+    
+    fn builtin_keys() -> KeyStore {
+        let root_key = SigningKey::from_bytes(&[0x66; 32].into()).unwrap();
+        KeyStore::builtin([
+            Some(entry(ROOT_KEY_ID, &root_key)),
+            Some(entry(PRODUCTION_KEY_ID, &production_key())),
+            None,
+        ])
+    }
+    
+    /// Bootloader partition running security version 1
+    fn old() -> Vec<u8> {
+        installed(&pack(
+            &image(0x7C00, 1),
+            &production_key(),
+            PRODUCTION_KEY_ID,
+            1,
+            TARGET_START,
+        ))
+    }
+    
+    /// Updater image of security version 2
+    fn updater() -> Vec<u8> {
+        pack(
+            &image(0x6A40, 2),
+            &production_key(),
+            PRODUCTION_KEY_ID,
+            2,
+            TARGET_START,
+        )
+    }
+    
+    /// Device with `staged` in the staging partition and the update armed
+    fn armed(staged: &[u8]) -> Device {
+        let mut device = Device::new(&old(), staged, builtin_keys());
+        device.arm().unwrap();
+        device
+    }
+    
+    /// Restart until the update reports completion, power stays on
+    fn restart_until_installed(device: &mut Device, version: u32, context: &str) {
+        for _ in 0..3 {
+            device.power.restore();
+            match device.start() {
+                Ok(Some(installed)) if installed == version => return,
+                Ok(other) => {
+                    panic!("start returned {other:?} instead of version {version} ({context})")
+                }
+                Err(_) => {}
+            }
+        }
+        panic!("update not completed after 3 restarts ({context})");
+    }
+    
+    /// Check the state after a completed update
+    fn check_installed(device: &mut Device, updater: &[u8], version: u32, context: &str) {
+        assert!(
+            device.bootloader() == installed(updater).as_slice(),
+            "bootloader partition holds the new image ({context})"
+        );
+        assert_eq!(
+            device.stage0_check(),
+            Some(version),
+            "stage 0 accepts the new bootloader ({context})"
+        );
+        
+        let journal = self_update::load_journal(&device.storage);
+        assert_eq!(
+            journal.state,
+            UpdateState::Idle,
+            "journal is idle ({context})"
+        );
+        assert_eq!(
+            journal.min_security_version, version,
+            "downgrade protection raised ({context})"
+        );
+        
+        let mut header = [0u8; IMAGE_HEADER_LENGTH];
+        device.flash.read(STAGING_START, &mut header);
+        assert!(
+            header.iter().all(|&byte| byte == 0xFF),
+            "staged image consumed ({context})"
+        );
+        
+        assert_eq!(
+            device.start(),
+            Ok(None),
+            "nothing to do on the next start ({context})"
+        );
+    }
+    
+    /// Flash and journal operations of an uninterrupted update
+    fn update_operations() -> usize {
+        let mut device = armed(&updater());
+        let armed = device.power.operations.get();
+        assert_eq!(device.start(), Ok(Some(2)));
+        device.power.operations.get() - armed
+    }
+    
+    #[test]
+    fn uninterrupted_update() {
+        let updater = updater();
+        let mut device = Device::new(&old(), &updater, builtin_keys());
+        assert_eq!(
+            device.stage0_check(),
+            Some(1),
+            "stage 0 accepts the running bootloader"
+        );
+        
+        device.arm().unwrap();
+        assert_eq!(device.start(), Ok(Some(2)));
+        check_installed(&mut device, &updater, 2, "uninterrupted update");
+    }
+    
+    #[test]
+    fn power_cut_at_every_operation() {
+        let old = old();
+        let updater = updater();
+        
+        // Power cut at every operation, and a second one during the recovery
+        for cut in 0..update_operations() {
+            for second_cut in [None, Some(cut / 2)] {
+                let context =
+                    format!("power cut after {cut} operations, second cut {second_cut:?}");
+                let mut device = armed(&updater);
+                
+                device.power.cut_after(cut);
+                assert!(device.start().is_err(), "power cut reported ({context})");
+                
+                // Stage 0 only ever starts a complete bootloader, old or new
+                let started = match device.stage0_check() {
+                    Some(1) => device.bootloader() == old.as_slice(),
+                    Some(2) => device.bootloader() == installed(&updater).as_slice(),
+                    Some(_) => false,
+                    None => true,
+                };
+                assert!(
+                    started,
+                    "stage 0 rejects a partially written bootloader ({context})"
+                );
+                
+                // The recovery may complete before the second cut is reached
+                let mut completed = false;
+                if let Some(second_cut) = second_cut {
+                    device.power.restore();
+                    device.power.cut_after(second_cut);
+                    completed = device.start() == Ok(Some(2));
+                }
+                
+                if !completed {
+                    restart_until_installed(&mut device, 2, &context);
+                }
+                check_installed(&mut device, &updater, 2, &context);
+            }
+        }
+    }
+    
+    #[test]
+    fn power_cut_while_arming() {
+        let old = old();
+        let mut device = Device::new(&old, &updater(), builtin_keys());
+        
+        device.power.cut_after(0);
+        assert_eq!(device.arm(), Err(UpdateError::JournalWriteFailed));
+        device.power.restore();
+        assert_eq!(
+            device.start(),
+            Ok(None),
+            "no update after an interrupted arming"
+        );
+        assert!(
+            device.bootloader() == old.as_slice(),
+            "bootloader untouched"
+        );
+    }
+    
+    #[test]
+    fn rejected_staged_images() {
+        let key = production_key();
+        let other_key = SigningKey::from_bytes(&[0x24; 32].into()).unwrap();
+        let new = image(0x6A40, 2);
+        
+        let mut tampered = updater();
+        tampered[IMAGE_HEADER_LENGTH + 0x100] ^= 0x01;
+        let mut other_algorithm = updater();
+        other_algorithm[6] = 0x01;
+        let too_large = image(MAX_IMAGE_SIZE + 1, 3);
+        let cases: [(&str, Vec<u8>, UpdateError); 6] = [
+            ("tampered image", tampered, UpdateError::HashMismatch),
+            (
+                "unknown signature algorithm",
+                other_algorithm,
+                UpdateError::UnsupportedAlgorithm,
+            ),
+            (
+                "foreign key",
+                pack(&new, &other_key, PRODUCTION_KEY_ID, 2, TARGET_START),
+                UpdateError::InvalidSignature,
+            ),
+            (
+                "below running version",
+                pack(&new, &key, PRODUCTION_KEY_ID, 0, TARGET_START),
+                UpdateError::Downgrade,
+            ),
+            (
+                "built for stage 0",
+                pack(&new, &key, PRODUCTION_KEY_ID, 2, 0x0000_0000),
+                UpdateError::WrongLoadAddress,
+            ),
+            (
+                "larger than the partition",
+                pack(&too_large, &key, PRODUCTION_KEY_ID, 2, TARGET_START),
+                UpdateError::InvalidSize,
+            ),
+        ];
+        for (name, staged, expected) in cases {
+            let mut device = Device::new(&old(), &staged, builtin_keys());
+            assert_eq!(device.arm(), Err(expected), "{name}");
+            assert_eq!(device.start(), Ok(None), "{name}");
+        }
+    }
+    
+    #[test]
+    fn downgrade_after_update() {
+        let mut device = armed(&updater());
+        assert_eq!(device.start(), Ok(Some(2)));
+        
+        // The update raised the protection above the running version
+        let older = pack(
+            &image(0x7C00, 1),
+            &production_key(),
+            PRODUCTION_KEY_ID,
+            1,
+            TARGET_START,
+        );
+        device.flash.memory[STAGING_START as usize..STAGING_START as usize + older.len()]
+            .copy_from_slice(&older);
+        assert_eq!(device.arm(), Err(UpdateError::Downgrade));
+    }
+    
+    #[test]
+    fn arming_twice() {
+        let mut device = armed(&updater());
+        assert_eq!(device.arm(), Err(UpdateError::Busy));
+    }
+    
+    #[test]
+    fn staged_image_replaced_after_arming() {
+        let mut device = armed(&updater());
+        
+        let replaced = pack(
+            &image(0x6A40, 4),
+            &production_key(),
+            PRODUCTION_KEY_ID,
+            2,
+            TARGET_START,
+        );
+        device.flash.memory[STAGING_START as usize..STAGING_START as usize + replaced.len()]
+            .copy_from_slice(&replaced);
+        assert_eq!(device.start(), Err(UpdateError::StagingChanged));
+        assert_eq!(device.start(), Ok(None), "update abandoned");
+        assert!(
+            device.bootloader() == old().as_slice(),
+            "bootloader untouched"
+        );
+    }
+    /// Signing key of the root slot of the built-in key store
+    fn root_key() -> SigningKey {
+        SigningKey::from_bytes(&[0x66; 32].into()).unwrap()
+    }
+    
+    /// Production key replacing the built-in one
+    fn rotated_key() -> SigningKey {
+        SigningKey::from_bytes(&[0x77; 32].into()).unwrap()
+    }
+    
+    fn development_key() -> SigningKey {
+        SigningKey::from_bytes(&[0x99; 32].into()).unwrap()
+    }
+    
+    /// Production key rotated, the old key revoked with the same update
+    fn rotation() -> KeyUpdate {
+        sign_key_update(
+            &root_key(),
+            ROOT_KEY_ID,
+            1,
+            KeySlot::Production,
+            Some(entry(2, &rotated_key())),
+            1 << PRODUCTION_KEY_ID,
+        )
+    }
+    
+    /// Device with `staged` in the staging partition and the rotation installed
+    fn rotated(staged: &[u8]) -> Device {
+        let mut device = Device::new(&old(), staged, builtin_keys());
+        assert!(
+            device.install_keys(&rotation()).is_ok(),
+            "rotation installed"
+        );
+        device
+    }
+    
+    #[test]
+    fn key_update_round_trip() {
+        assert_eq!(KeyUpdate::parse(&rotation().to_bytes()), Ok(rotation()));
+    }
+    
+    #[test]
+    fn production_key_rotated() {
+        let rotated = pack(&image(0x6A40, 2), &rotated_key(), 2, 2, TARGET_START);
+        let mut device = Device::new(&old(), &rotated, builtin_keys());
+        assert_eq!(
+            device.stage0_check(),
+            Some(1),
+            "running bootloader accepted before the rotation"
+        );
+        
+        assert!(
+            device.install_keys(&rotation()).is_ok(),
+            "rotation installed"
+        );
+        assert_eq!(
+            device.stage0_check(),
+            None,
+            "image signed with the revoked key rejected"
+        );
+        device.arm().unwrap();
+        assert_eq!(
+            device.start(),
+            Ok(Some(2)),
+            "image signed with the new key installed"
+        );
+        assert_eq!(
+            device.stage0_check(),
+            Some(2),
+            "stage 0 accepts the new bootloader"
+        );
+    }
+    
+    #[test]
+    fn image_signed_with_revoked_key() {
+        let mut device = rotated(&updater());
+        assert_eq!(device.arm(), Err(UpdateError::InvalidSignature));
+    }
+    
+    #[test]
+    fn rejected_key_updates() {
+        let root_key = root_key();
+        let rotated_key = rotated_key();
+        let development_key = development_key();
+        let mut device = rotated(&updater());
+        
+        let mut tampered = rotation();
+        tampered.sequence = 2;
+        let restore = sign_key_update(
+            &root_key,
+            ROOT_KEY_ID,
+            2,
+            KeySlot::Production,
+            Some(entry(PRODUCTION_KEY_ID, &production_key())),
+            0,
+        );
+        let cases: [(&str, KeyUpdate, KeyError); 8] = [
+            ("replayed", rotation(), KeyError::Replayed),
+            ("tampered", tampered, KeyError::InvalidSignature),
+            (
+                "signed by an image key",
+                sign_key_update(&rotated_key, 2, 2, KeySlot::Development, None, 0),
+                KeyError::UnknownKey,
+            ),
+            (
+                "signed by a foreign key as root",
+                sign_key_update(&rotated_key, ROOT_KEY_ID, 2, KeySlot::Development, None, 0),
+                KeyError::InvalidSignature,
+            ),
+            ("revoked key restored", restore, KeyError::Revoked),
+            (
+                "key ID in use",
+                sign_key_update(
+                    &root_key,
+                    ROOT_KEY_ID,
+                    2,
+                    KeySlot::Development,
+                    Some(entry(2, &development_key)),
+                    0,
+                ),
+                KeyError::DuplicateKeyId,
+            ),
+            (
+                "root slot emptied",
+                sign_key_update(&root_key, ROOT_KEY_ID, 2, KeySlot::Root, None, 0),
+                KeyError::RootRequired,
+            ),
+            (
+                "root key revoked",
+                sign_key_update(
+                    &root_key,
+                    ROOT_KEY_ID,
+                    2,
+                    KeySlot::Development,
+                    None,
+                    1 << ROOT_KEY_ID,
+                ),
+                KeyError::RootRequired,
+            ),
+        ];
+        for (name, update, expected) in cases {
+            assert_eq!(device.install_keys(&update), Err(expected), "{name}");
+        }
+    }
+    
+    #[test]
+    fn root_key_rotated() {
+        let root_key = root_key();
+        let new_root_key = SigningKey::from_bytes(&[0x88; 32].into()).unwrap();
+        let development_key = development_key();
+        let mut device = rotated(&updater());
+        
+        // The old root revokes itself
+        let root_rotation = sign_key_update(
+            &root_key,
+            ROOT_KEY_ID,
+            2,
+            KeySlot::Root,
+            Some(entry(3, &new_root_key)),
+            1 << ROOT_KEY_ID,
+        );
+        assert!(device.install_keys(&root_rotation).is_ok(), "root rotated");
+        let development = sign_key_update(
+            &root_key,
+            ROOT_KEY_ID,
+            3,
+            KeySlot::Development,
+            Some(entry(4, &development_key)),
+            0,
+        );
+        assert_eq!(
+            device.install_keys(&development),
+            Err(KeyError::UnknownKey),
+            "old root rejected"
+        );
+        let development = sign_key_update(
+            &new_root_key,
+            3,
+            3,
+            KeySlot::Development,
+            Some(entry(4, &development_key)),
+            0,
+        );
+        assert!(
+            device.install_keys(&development).is_ok(),
+            "development key added by the new root"
+        );
+        
+        let keys = keystore::load(&device.storage, &builtin_keys());
+        assert!(keys.image_key(4).is_ok(), "development key signs images");
+        assert_eq!(
+            keys.image_key(3),
+            Err(KeyError::UnknownKey),
+            "root key does not sign images"
+        );
+        assert_eq!(
+            keys.image_key(PRODUCTION_KEY_ID),
+            Err(KeyError::Revoked),
+            "revocation kept"
+        );
+        assert_eq!(
+            keys.image_key(9),
+            Err(KeyError::UnknownKey),
+            "unknown key ID"
+        );
+        
+        let signed_by_root = pack(&image(0x6A40, 2), &new_root_key, 3, 2, TARGET_START);
+        device.flash.memory[STAGING_START as usize..STAGING_START as usize + signed_by_root.len()]
+            .copy_from_slice(&signed_by_root);
+        assert_eq!(
+            device.arm(),
+            Err(UpdateError::InvalidSignature),
+            "image signed with the root key"
+        );
+    }
+    
+    #[test]
+    fn power_cut_while_storing_key_update() {
+        let builtin_keys = builtin_keys();
+        let installed_keys = keystore::load(&rotated(&updater()).storage, &builtin_keys);
+        let development = sign_key_update(
+            &root_key(),
+            ROOT_KEY_ID,
+            2,
+            KeySlot::Development,
+            Some(entry(4, &development_key())),
+            0,
+        );
+        
+        for cut in [Some(0), None] {
+            let mut device = rotated(&updater());
+            
+            // The previous key store is kept
+            if let Some(cut) = cut {
+                device.power.cut_after(cut);
+                assert_eq!(
+                    device.install_keys(&development),
+                    Err(KeyError::StorageError),
+                    "power cut reported"
+                );
+                device.power.restore();
+                assert!(
+                    keystore::load(&device.storage, &builtin_keys) == installed_keys,
+                    "previous key store kept"
+                );
+            }
+            
+            assert!(
+                device.install_keys(&development).is_ok(),
+                "key update installed after the restart"
+            );
+            assert_eq!(
+                keystore::load(&device.storage, &builtin_keys).sequence,
+                2,
+                "newest key store loaded"
+            );
+            assert_eq!(
+                device.stage0_check(),
+                None,
+                "revocation survives the next record"
+            );
+        }
+    }
+}
\ No newline at end of file